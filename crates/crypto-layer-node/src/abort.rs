@@ -0,0 +1,107 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex, PoisonError, Weak};
+
+use neon::prelude::*;
+use neon::types::Deferred;
+
+use crate::runtime;
+
+/// Deferred of an operation, which is taken by whoever settles it first: the operation or [AbortToken::abort].
+pub(crate) type DeferredSlot = Arc<Mutex<Option<Deferred>>>;
+
+/// Flag shared between JS and an operation running on the runtime, which signals that the operation was aborted.
+#[derive(Clone, Default)]
+pub(crate) struct AbortToken {
+    aborted: Arc<AtomicBool>,
+    /// Deferreds rejected by [AbortToken::abort], which are dropped once their operation settled them.
+    deferreds: Arc<Mutex<Vec<Weak<Mutex<Option<Deferred>>>>>>,
+}
+
+impl AbortToken {
+    /// Marks the operations as aborted and rejects their promises, which are not settled yet, with an `AbortError`.
+    ///
+    /// Results of operations, which are still running, are dropped once they finish.
+    pub(crate) fn abort<'a, C: Context<'a>>(&self, cx: &mut C) -> NeonResult<()> {
+        self.aborted.store(true, Ordering::Release);
+        let deferreds = std::mem::take(
+            &mut *self
+                .deferreds
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner),
+        );
+        for slot in deferreds.iter().filter_map(Weak::upgrade) {
+            let deferred = slot.lock().unwrap_or_else(PoisonError::into_inner).take();
+            if let Some(deferred) = deferred {
+                let err = abort_error(cx)?;
+                deferred.reject(cx, err);
+            }
+        }
+        Ok(())
+    }
+
+    pub(crate) fn is_aborted(&self) -> bool {
+        self.aborted.load(Ordering::Acquire)
+    }
+
+    /// Lets [AbortToken::abort] reject the deferred in `slot`, unless it was taken before.
+    pub(crate) fn reject_on_abort(&self, slot: &DeferredSlot) {
+        let mut deferreds = self
+            .deferreds
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner);
+        deferreds.retain(|deferred| deferred.strong_count() > 0);
+        deferreds.push(Arc::downgrade(slot));
+    }
+}
+
+impl Finalize for AbortToken {}
+
+type JsAbortToken = JsBox<AbortToken>;
+
+/// Returns the [AbortToken] given as last argument of the function call, if there is one.
+pub(crate) fn abort_token_from_last_argument(cx: &mut FunctionContext) -> Option<AbortToken> {
+    let last_index = cx.len().checked_sub(1)?;
+    let last = cx.argument::<JsValue>(last_index).ok()?;
+    let token = last.downcast::<JsAbortToken, _>(cx).ok()?;
+    Some((**token).clone())
+}
+
+/// Creates an `Error` with the name `AbortError`.
+fn abort_error<'a, C: Context<'a>>(cx: &mut C) -> JsResult<'a, JsError> {
+    let err = cx.error("The operation was aborted.")?;
+    let name = cx.string("AbortError");
+    err.set(cx, "name", name)?;
+    Ok(err)
+}
+
+/// Throws an `Error` with the name `AbortError`.
+pub(crate) fn throw_abort_error<'a, C: Context<'a>, V: Value>(cx: &mut C) -> JsResult<'a, V> {
+    let err = abort_error(cx)?;
+    cx.throw(err)
+}
+
+/// Creates a new abort token.
+///
+/// # Returns
+/// * `{}` - bare abort token
+pub fn export_create_abort_token(mut cx: FunctionContext) -> JsResult<JsAbortToken> {
+    Ok(cx.boxed(AbortToken::default()))
+}
+
+/// Aborts the operations the token was given to.
+///
+/// Their promises are rejected with an `AbortError` right away, also while they are still running.
+/// Operations, which did not start yet, are removed from the queue right away, so that they do not
+/// hold a slot of `maxQueued` until a worker reaches them.
+///
+/// # Arguments
+/// * **token**: `{}` - bare abort token
+///
+/// # Returns
+/// * `undefined`
+pub fn export_abort(mut cx: FunctionContext) -> JsResult<JsUndefined> {
+    let token = cx.argument::<JsAbortToken>(0)?;
+    token.abort(&mut cx)?;
+    runtime::cancel_aborted();
+    Ok(cx.undefined())
+}
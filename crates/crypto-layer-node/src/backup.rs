@@ -0,0 +1,759 @@
+use std::collections::{HashMap, HashSet};
+use std::str::FromStr;
+
+use crypto_layer::common::config::Spec;
+use crypto_layer::common::error::CalError;
+use crypto_layer::prelude::*;
+use neon::prelude::*;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use zeroize::{Zeroize, Zeroizing};
+
+use crate::commitment::{
+    constant_time_eq, decrypt_committing, encrypt_committing, CommitmentError,
+};
+use crate::metadata::{now_millis, KeyMetadata, MetadataError, MetadataStore};
+use crate::tojs::wrap_string_array;
+
+/// `code` of the error thrown when keys of a backup already exist in the provider.
+pub(crate) const BACKUP_CONFLICT_ERROR_CODE: &str = "ERR_BACKUP_CONFLICT";
+
+/// `code` of the error thrown when a backup cannot be decrypted or was modified.
+pub(crate) const BACKUP_AUTHENTICATION_ERROR_CODE: &str = "ERR_BACKUP_AUTHENTICATION";
+
+/// First bytes of every archive created by [export_backup].
+const ARCHIVE_MAGIC: &[u8; 4] = b"CLBK";
+
+/// Version of the archive layout following [ARCHIVE_MAGIC].
+const ARCHIVE_FORMAT_VERSION: u8 = 1;
+
+/// Archive protected by a key derived from a password.
+const PASSWORD_PROTECTION: u8 = 0;
+
+/// Archive protected by a key handle supplied by the caller.
+const KEY_PROTECTION: u8 = 1;
+
+/// Length of the random salt of password protected archives.
+const SALT_LEN: usize = 16;
+
+/// Largest Argon2 memory in KiB accepted from an archive, 1 GiB.
+///
+/// The kdf parameters are read from the unauthenticated header before the archive key is derived,
+/// so without limits a crafted archive could make the import allocate and compute without bound.
+const MAX_ARGON2_MEMORY: u32 = 1 << 20;
+
+/// Largest Argon2 iteration count accepted from an archive.
+const MAX_ARGON2_ITERATIONS: u32 = 64;
+
+/// Largest Argon2 parallelism accepted from an archive.
+const MAX_ARGON2_PARALLELISM: u32 = 16;
+
+/// Separates fingerprints of key material from other digests.
+const FINGERPRINT_DOMAIN: &[u8] = b"crypto-layer-node key fingerprint v1";
+
+#[derive(thiserror::Error, Debug)]
+pub(crate) enum BackupError {
+    #[error(transparent)]
+    Cal(#[from] CalError),
+    #[error(transparent)]
+    Metadata(#[from] MetadataError),
+    #[error("Failed serializing the backup: {0}")]
+    Serialization(#[from] serde_json::Error),
+    #[error("The backup is malformed: {0}")]
+    Malformed(&'static str),
+    #[error("The backup is protected by a {0}, which was not given.")]
+    WrongProtection(&'static str),
+    #[error("The backup was modified or the password or key is wrong.")]
+    AuthenticationFailed,
+    #[error("The keys {} of the backup already exist in the provider.", .0.join(", "))]
+    Conflict(Vec<String>),
+}
+
+impl From<CommitmentError> for BackupError {
+    fn from(err: CommitmentError) -> Self {
+        match err {
+            CommitmentError::CommitmentMismatch => BackupError::AuthenticationFailed,
+            CommitmentError::UnsupportedVersion(_) => {
+                BackupError::Malformed("unsupported key commitment version")
+            }
+            CommitmentError::Cal(err) => BackupError::Cal(err),
+        }
+    }
+}
+
+/// Secret protecting an archive.
+pub(crate) enum BackupProtection {
+    /// Password from which the archive key is derived with the kdf stored in the archive.
+    Password(Zeroizing<String>),
+    /// Key handle, which directly encrypts the archive.
+    Key(KeyHandle),
+}
+
+/// How [import_backup] treats keys, whose key material already exists in the provider.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub(crate) enum ConflictStrategy {
+    /// Restores nothing, if any key exists.
+    #[default]
+    Fail,
+    /// Keeps the existing keys and restores the others.
+    Skip,
+    /// Restores every key, existing ones as additional copies.
+    Duplicate,
+}
+
+impl ConflictStrategy {
+    pub(crate) const VARIANTS: &'static [&'static str] = &["fail", "skip", "duplicate"];
+
+    pub(crate) fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "fail" => Some(ConflictStrategy::Fail),
+            "skip" => Some(ConflictStrategy::Skip),
+            "duplicate" => Some(ConflictStrategy::Duplicate),
+            _ => None,
+        }
+    }
+}
+
+/// Result of [export_backup].
+pub(crate) struct ExportedBackup {
+    pub archive: Vec<u8>,
+    /// Ids of the keys, which are not exportable and thus missing in the archive.
+    pub skipped: Vec<String>,
+}
+
+/// Id of a key in the archive and the id of the key it was restored as or matched in the provider.
+pub(crate) struct RestoredKey {
+    pub original_id: String,
+    pub id: String,
+}
+
+/// Result of [import_backup].
+pub(crate) struct ImportedBackup {
+    pub restored: Vec<RestoredKey>,
+    /// Keys, which already existed and were kept with [ConflictStrategy::Skip], with the existing id.
+    pub skipped: Vec<RestoredKey>,
+}
+
+/// Spec of a backed up key with algorithms stored by name.
+///
+/// Restored keys are neither ephemeral nor non exportable, as such keys are never backed up.
+#[derive(PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(tag = "kind")]
+enum BackupSpec {
+    #[serde(rename_all = "camelCase")]
+    Key {
+        cipher: String,
+        signing_hash: String,
+    },
+    #[serde(rename_all = "camelCase")]
+    KeyPair {
+        asym_spec: String,
+        cipher: Option<String>,
+        signing_hash: String,
+    },
+}
+
+impl BackupSpec {
+    fn new(spec: Spec) -> Self {
+        fn name(variant: impl Into<&'static str>) -> String {
+            variant.into().to_owned()
+        }
+
+        match spec {
+            Spec::KeySpec(spec) => BackupSpec::Key {
+                cipher: name(spec.cipher),
+                signing_hash: name(spec.signing_hash),
+            },
+            Spec::KeyPairSpec(spec) => BackupSpec::KeyPair {
+                asym_spec: name(spec.asym_spec),
+                cipher: spec.cipher.map(name),
+                signing_hash: name(spec.signing_hash),
+            },
+        }
+    }
+
+    fn spec(&self) -> Result<Spec, BackupError> {
+        fn parse<T: FromStr>(name: &str) -> Result<T, BackupError> {
+            name.parse().map_err(|_| BackupError::Malformed("unknown algorithm"))
+        }
+
+        Ok(match self {
+            BackupSpec::Key {
+                cipher,
+                signing_hash,
+            } => Spec::KeySpec(KeySpec {
+                cipher: parse(cipher)?,
+                signing_hash: parse(signing_hash)?,
+                ephemeral: false,
+                non_exportable: false,
+            }),
+            BackupSpec::KeyPair {
+                asym_spec,
+                cipher,
+                signing_hash,
+            } => Spec::KeyPairSpec(KeyPairSpec {
+                asym_spec: parse(asym_spec)?,
+                cipher: cipher.as_deref().map(parse).transpose()?,
+                signing_hash: parse(signing_hash)?,
+                ephemeral: false,
+                non_exportable: false,
+            }),
+        })
+    }
+}
+
+/// Key of an archive together with its spec and metadata.
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct BackupEntry {
+    id: String,
+    spec: BackupSpec,
+    /// Symmetric key or private key.
+    secret: Vec<u8>,
+    /// Public key of key pairs.
+    public_key: Option<Vec<u8>>,
+    metadata: Option<KeyMetadata>,
+}
+
+impl Drop for BackupEntry {
+    fn drop(&mut self) {
+        self.secret.zeroize();
+    }
+}
+
+/// Encrypted content of an archive.
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Payload {
+    /// SHA-256 of the unencrypted header, which thereby cannot be modified unnoticed.
+    header_digest: Vec<u8>,
+    /// Milliseconds since the unix epoch.
+    created_at: u64,
+    keys: Vec<BackupEntry>,
+}
+
+/// Protection recorded in the unencrypted header of an archive.
+enum HeaderProtection {
+    Password { kdf: KDF, salt: Vec<u8> },
+    Key,
+}
+
+/// Archive created by [export_backup].
+///
+/// Layout: magic (4 bytes), format version (1 byte), protection (1 byte),
+/// for passwords the kdf (1 byte), its memory, iterations and parallelism (4 bytes each, big endian),
+/// salt length (1 byte) and salt, then the iv length (1 byte), iv and the committing ciphertext of the payload.
+/// The header is everything before the iv, which is bound to the key by the commitment itself.
+struct Archive<'a> {
+    header: &'a [u8],
+    protection: HeaderProtection,
+    iv: &'a [u8],
+    ciphertext: &'a [u8],
+}
+
+impl<'a> Archive<'a> {
+    fn header(protection: &HeaderProtection) -> Vec<u8> {
+        let mut header = ARCHIVE_MAGIC.to_vec();
+        header.push(ARCHIVE_FORMAT_VERSION);
+        match protection {
+            HeaderProtection::Password { kdf, salt } => {
+                header.push(PASSWORD_PROTECTION);
+                let (variant, options) = match kdf {
+                    KDF::Argon2d(options) => (0, options),
+                    KDF::Argon2id(options) => (1, options),
+                    KDF::Argon2i(options) => (2, options),
+                };
+                header.push(variant);
+                header.extend_from_slice(&options.memory.to_be_bytes());
+                header.extend_from_slice(&options.iterations.to_be_bytes());
+                header.extend_from_slice(&options.parallelism.to_be_bytes());
+                header.push(salt.len() as u8);
+                header.extend_from_slice(salt);
+            }
+            HeaderProtection::Key => header.push(KEY_PROTECTION),
+        }
+        header
+    }
+
+    fn seal(mut header: Vec<u8>, iv: &[u8], ciphertext: &[u8]) -> Result<Vec<u8>, BackupError> {
+        let iv_len = u8::try_from(iv.len()).map_err(|_| BackupError::Malformed("iv too long"))?;
+        header.reserve(1 + iv.len() + ciphertext.len());
+        header.push(iv_len);
+        header.extend_from_slice(iv);
+        header.extend_from_slice(ciphertext);
+        Ok(header)
+    }
+
+    fn open(archive: &'a [u8]) -> Result<Self, BackupError> {
+        let rest = archive
+            .strip_prefix(ARCHIVE_MAGIC.as_slice())
+            .ok_or(BackupError::Malformed("missing header"))?;
+        let (&format_version, rest) = rest
+            .split_first()
+            .ok_or(BackupError::Malformed("truncated header"))?;
+        if format_version != ARCHIVE_FORMAT_VERSION {
+            return Err(BackupError::Malformed("unsupported format version"));
+        }
+
+        let (&protection, mut rest) = rest
+            .split_first()
+            .ok_or(BackupError::Malformed("truncated header"))?;
+        let protection = match protection {
+            PASSWORD_PROTECTION => {
+                let (&variant, parameters) = rest
+                    .split_first()
+                    .ok_or(BackupError::Malformed("truncated header"))?;
+                let (parameters, salt) = parameters
+                    .split_first_chunk::<12>()
+                    .ok_or(BackupError::Malformed("truncated kdf parameters"))?;
+                let parameter = |i: usize| {
+                    u32::from_be_bytes([
+                        parameters[i],
+                        parameters[i + 1],
+                        parameters[i + 2],
+                        parameters[i + 3],
+                    ])
+                };
+                let options = Argon2Options {
+                    memory: parameter(0),
+                    iterations: parameter(4),
+                    parallelism: parameter(8),
+                };
+                if options.memory > MAX_ARGON2_MEMORY
+                    || options.iterations > MAX_ARGON2_ITERATIONS
+                    || options.parallelism > MAX_ARGON2_PARALLELISM
+                {
+                    return Err(BackupError::Malformed("kdf parameters exceed the limits"));
+                }
+                let kdf = match variant {
+                    0 => KDF::Argon2d(options),
+                    1 => KDF::Argon2id(options),
+                    2 => KDF::Argon2i(options),
+                    _ => return Err(BackupError::Malformed("unknown kdf")),
+                };
+
+                let (&salt_len, salt) = salt
+                    .split_first()
+                    .ok_or(BackupError::Malformed("truncated header"))?;
+                if salt.len() < salt_len as usize {
+                    return Err(BackupError::Malformed("truncated salt"));
+                }
+                let (salt, after_salt) = salt.split_at(salt_len as usize);
+                rest = after_salt;
+                HeaderProtection::Password {
+                    kdf,
+                    salt: salt.to_vec(),
+                }
+            }
+            KEY_PROTECTION => HeaderProtection::Key,
+            _ => return Err(BackupError::Malformed("unknown protection")),
+        };
+        let header = &archive[..archive.len() - rest.len()];
+
+        let (&iv_len, rest) = rest
+            .split_first()
+            .ok_or(BackupError::Malformed("truncated header"))?;
+        if rest.len() < iv_len as usize {
+            return Err(BackupError::Malformed("truncated iv"));
+        }
+        let (iv, ciphertext) = rest.split_at(iv_len as usize);
+
+        Ok(Self {
+            header,
+            protection,
+            iv,
+            ciphertext,
+        })
+    }
+}
+
+/// Argon2id with the parameters recommended by OWASP, used if no kdf is given.
+pub(crate) fn default_kdf() -> KDF {
+    KDF::Argon2id(Argon2Options {
+        memory: 19456,
+        iterations: 2,
+        parallelism: 1,
+    })
+}
+
+/// Derives the AES-256-GCM key encrypting a password protected archive.
+fn derive_archive_key(
+    provider: &Provider,
+    password: &str,
+    salt: &[u8],
+    kdf: KDF,
+) -> Result<KeyHandle, BackupError> {
+    let spec = KeySpec {
+        cipher: Cipher::AesGcm256,
+        signing_hash: CryptoHash::Sha2_256,
+        ephemeral: true,
+        non_exportable: true,
+    };
+    Ok(provider.derive_key_from_password(password, salt, spec, kdf)?)
+}
+
+/// Digest identifying key material independent of the id it is stored under.
+///
+/// `material` is the symmetric key for keys and the public key for key pairs.
+fn fingerprint(key_pair: bool, material: &[u8]) -> Vec<u8> {
+    let mut hasher = Sha256::new();
+    hasher.update(FINGERPRINT_DOMAIN);
+    hasher.update([key_pair as u8]);
+    hasher.update(material);
+    hasher.finalize().to_vec()
+}
+
+impl BackupEntry {
+    fn fingerprint(&self) -> Result<Vec<u8>, BackupError> {
+        Ok(match self.spec {
+            BackupSpec::Key { .. } => fingerprint(false, &self.secret),
+            BackupSpec::KeyPair { .. } => {
+                let public_key = self
+                    .public_key
+                    .as_deref()
+                    .ok_or(BackupError::Malformed("missing public key"))?;
+                fingerprint(true, public_key)
+            }
+        })
+    }
+}
+
+/// Keys of the provider, which already hold keys of a backup.
+struct ExistingKeys {
+    /// Ids of keys of the backup, which the provider stores under the same id and spec.
+    same_ids: HashSet<String>,
+    /// Fingerprints of the other keys of the provider, whose spec matches a key of the backup.
+    fingerprints: HashMap<Vec<u8>, String>,
+}
+
+impl ExistingKeys {
+    /// Compares the keys of the provider with the keys of the backup.
+    ///
+    /// Keys with the id and spec of a key of the backup are taken to be that key.
+    /// Only the material of keys with the spec of a key of the backup is read,
+    /// key pairs are identified by their public key, symmetric keys by their secret,
+    /// so non exportable symmetric keys are never recognized.
+    fn find(provider: &mut Provider, keys: &[BackupEntry]) -> Result<Self, BackupError> {
+        let backup_specs: HashMap<&str, &BackupSpec> =
+            keys.iter().map(|key| (key.id.as_str(), &key.spec)).collect();
+        let specs: HashSet<&BackupSpec> = keys.iter().map(|key| &key.spec).collect();
+
+        let mut same_ids = HashSet::new();
+        let mut candidates = vec![];
+        for (id, spec) in provider.get_all_keys()? {
+            if matches!(&spec, Spec::KeySpec(spec) if spec.ephemeral || spec.non_exportable) {
+                continue;
+            }
+            let key_pair = matches!(spec, Spec::KeyPairSpec(_));
+            let spec = BackupSpec::new(spec);
+            if backup_specs.get(id.as_str()) == Some(&&spec) {
+                same_ids.insert(id);
+            } else if specs.contains(&spec) {
+                candidates.push((id, key_pair));
+            }
+        }
+
+        let mut fingerprints = HashMap::new();
+        for (id, key_pair) in candidates {
+            let material = if key_pair {
+                provider
+                    .load_key_pair(id.clone())
+                    .and_then(|handle| handle.get_public_key())
+                    .map(|public_key| fingerprint(true, &public_key))
+            } else {
+                provider
+                    .load_key(id.clone())
+                    .and_then(|handle| handle.extract_key())
+                    .map(|secret| fingerprint(false, &Zeroizing::new(secret)))
+            };
+            match material {
+                Ok(fingerprint) => {
+                    fingerprints.entry(fingerprint).or_insert(id);
+                }
+                Err(err) => {
+                    tracing::warn!(
+                        error = %err,
+                        id = %id,
+                        "Skipping key in backup conflict check."
+                    );
+                }
+            }
+        }
+        Ok(Self {
+            same_ids,
+            fingerprints,
+        })
+    }
+
+    /// Id of the key of the provider holding `entry`.
+    fn get(&self, entry: &BackupEntry) -> Result<Option<String>, BackupError> {
+        if self.same_ids.contains(&entry.id) {
+            return Ok(Some(entry.id.clone()));
+        }
+        Ok(self.fingerprints.get(&entry.fingerprint()?).cloned())
+    }
+}
+
+/// Reads the key `id` with its secret, or returns `None` if it cannot be exported.
+fn backup_entry(
+    provider: &mut Provider,
+    store: &MetadataStore,
+    id: String,
+    spec: Spec,
+) -> Result<Option<BackupEntry>, BackupError> {
+    let (secret, public_key) = match &spec {
+        Spec::KeySpec(key_spec) => {
+            if key_spec.ephemeral || key_spec.non_exportable {
+                return Ok(None);
+            }
+            let handle = provider.load_key(id.clone())?;
+            (handle.extract_key(), None)
+        }
+        Spec::KeyPairSpec(key_pair_spec) => {
+            if key_pair_spec.ephemeral || key_pair_spec.non_exportable {
+                return Ok(None);
+            }
+            let handle = provider.load_key_pair(id.clone())?;
+            let public_key = handle.get_public_key()?;
+            (handle.extract_key(), Some(public_key))
+        }
+    };
+    let secret = match secret {
+        Ok(secret) => secret,
+        Err(err) => {
+            tracing::warn!(error = %err, id = %id, "Skipping key, which cannot be extracted.");
+            return Ok(None);
+        }
+    };
+
+    Ok(Some(BackupEntry {
+        metadata: store.get(&id),
+        id,
+        spec: BackupSpec::new(spec),
+        secret,
+        public_key,
+    }))
+}
+
+/// Serializes every exportable key of the provider with its spec and metadata into an encrypted archive.
+///
+/// The archive is encrypted with key commitment by the key of `protection`,
+/// for passwords a key derived with `kdf` or, if `None`, with [default_kdf] and a random salt.
+/// Ephemeral and non exportable keys are skipped.
+pub(crate) fn export_backup(
+    provider: &mut Provider,
+    store: &MetadataStore,
+    protection: &BackupProtection,
+    kdf: Option<KDF>,
+) -> Result<ExportedBackup, BackupError> {
+    let mut keys = vec![];
+    let mut skipped = vec![];
+    for (id, spec) in provider.get_all_keys()? {
+        match backup_entry(provider, store, id.clone(), spec)? {
+            Some(entry) => keys.push(entry),
+            None => skipped.push(id),
+        }
+    }
+
+    let (key, header) = match protection {
+        BackupProtection::Password(password) => {
+            let kdf = kdf.unwrap_or_else(default_kdf);
+            let salt = provider.get_random(SALT_LEN);
+            let header = Archive::header(&HeaderProtection::Password {
+                kdf: kdf.clone(),
+                salt: salt.clone(),
+            });
+            (derive_archive_key(provider, password, &salt, kdf)?, header)
+        }
+        BackupProtection::Key(handle) => (handle.clone(), Archive::header(&HeaderProtection::Key)),
+    };
+
+    let payload = Payload {
+        header_digest: Sha256::digest(&header).to_vec(),
+        created_at: now_millis(),
+        keys,
+    };
+    let plaintext = Zeroizing::new(serde_json::to_vec(&payload)?);
+    let (ciphertext, iv) = encrypt_committing(&key, &plaintext)?;
+
+    Ok(ExportedBackup {
+        archive: Archive::seal(header, &iv, &ciphertext)?,
+        skipped,
+    })
+}
+
+/// Key created by [import_backup], deleted again if the import fails.
+enum RestoredHandle {
+    Key(KeyHandle),
+    KeyPair(KeyPairHandle),
+}
+
+impl RestoredHandle {
+    fn delete(self) {
+        let result = match self {
+            RestoredHandle::Key(handle) => handle.delete(),
+            RestoredHandle::KeyPair(handle) => handle.delete(),
+        };
+        if let Err(err) = result {
+            tracing::error!(error = %err, "Failed deleting key of failed backup import.");
+        }
+    }
+}
+
+/// Imports the keys of `entries` and stores their metadata.
+///
+/// Key families are kept intact by replacing family ids, which name restored keys, with their new ids
+/// and family ids, which name `skipped` keys, with the ids of the existing keys.
+fn restore_entries(
+    provider: &mut Provider,
+    store: &MetadataStore,
+    entries: &[&BackupEntry],
+    skipped: &[RestoredKey],
+    handles: &mut Vec<RestoredHandle>,
+) -> Result<Vec<RestoredKey>, BackupError> {
+    let mut restored = Vec::with_capacity(entries.len());
+    for entry in entries {
+        let id = match entry.spec.spec()? {
+            Spec::KeySpec(spec) => {
+                let handle = provider.import_key(spec, &entry.secret)?;
+                let id = handle.id()?;
+                handles.push(RestoredHandle::Key(handle));
+                id
+            }
+            Spec::KeyPairSpec(spec) => {
+                let public_key = entry
+                    .public_key
+                    .as_deref()
+                    .ok_or(BackupError::Malformed("missing public key"))?;
+                let handle = provider.import_key_pair(spec, public_key, &entry.secret)?;
+                let id = handle.id()?;
+                handles.push(RestoredHandle::KeyPair(handle));
+                id
+            }
+        };
+        restored.push(RestoredKey {
+            original_id: entry.id.clone(),
+            id,
+        });
+    }
+
+    let new_ids: HashMap<&str, &str> = skipped
+        .iter()
+        .chain(&restored)
+        .map(|key| (key.original_id.as_str(), key.id.as_str()))
+        .collect();
+    let updates = entries
+        .iter()
+        .zip(&restored)
+        .map(|(entry, key)| {
+            let mut metadata = entry.metadata.clone().unwrap_or_else(|| KeyMetadata {
+                created_at: now_millis(),
+                ..Default::default()
+            });
+            if let Some(family) = &mut metadata.family {
+                if let Some(new_id) = new_ids.get(family.family_id.as_str()) {
+                    family.family_id = (*new_id).to_owned();
+                }
+            }
+            (key.id.clone(), metadata)
+        })
+        .collect();
+    store.replace_all(updates)?;
+
+    Ok(restored)
+}
+
+/// Decrypts an archive of [export_backup] and imports its keys with their metadata.
+///
+/// Ids are not preserved, as crypto-layer assigns the id of every imported key:
+/// imported keys get new ids, which are returned together with the ids in the archive.
+/// Keys of the archive, which exist in the provider, are handled according to `on_conflict`,
+/// skipped keys are returned with the id of the existing key. See [ExistingKeys::find].
+/// Either all keys are restored or, on failure, none.
+pub(crate) fn import_backup(
+    provider: &mut Provider,
+    store: &MetadataStore,
+    archive: &[u8],
+    protection: &BackupProtection,
+    on_conflict: ConflictStrategy,
+) -> Result<ImportedBackup, BackupError> {
+    let archive = Archive::open(archive)?;
+    let key = match (&archive.protection, protection) {
+        (HeaderProtection::Password { kdf, salt }, BackupProtection::Password(password)) => {
+            derive_archive_key(provider, password, salt, kdf.clone())?
+        }
+        (HeaderProtection::Key, BackupProtection::Key(handle)) => handle.clone(),
+        (HeaderProtection::Password { .. }, _) => {
+            return Err(BackupError::WrongProtection("password"))
+        }
+        (HeaderProtection::Key, _) => return Err(BackupError::WrongProtection("key")),
+    };
+
+    let plaintext = Zeroizing::new(
+        // Failed decryptions of the provider are authentication failures, too.
+        decrypt_committing(&key, archive.ciphertext, archive.iv).map_err(|err| match err {
+            CommitmentError::UnsupportedVersion(_) => BackupError::from(err),
+            _ => BackupError::AuthenticationFailed,
+        })?,
+    );
+    let payload: Payload = serde_json::from_slice(&plaintext)
+        .map_err(|_| BackupError::Malformed("invalid payload"))?;
+    if !constant_time_eq(&payload.header_digest, &Sha256::digest(archive.header)) {
+        return Err(BackupError::AuthenticationFailed);
+    }
+
+    let mut entries = vec![];
+    let mut skipped = vec![];
+    if on_conflict == ConflictStrategy::Duplicate {
+        entries.extend(&payload.keys);
+    } else {
+        let existing = ExistingKeys::find(provider, &payload.keys)?;
+        for entry in &payload.keys {
+            match existing.get(entry)? {
+                Some(id) => skipped.push(RestoredKey {
+                    original_id: entry.id.clone(),
+                    id,
+                }),
+                None => entries.push(entry),
+            }
+        }
+    }
+    if on_conflict == ConflictStrategy::Fail && !skipped.is_empty() {
+        let ids = skipped.into_iter().map(|key| key.original_id).collect();
+        return Err(BackupError::Conflict(ids));
+    }
+
+    let mut handles = vec![];
+    match restore_entries(provider, store, &entries, &skipped, &mut handles) {
+        Ok(restored) => Ok(ImportedBackup { restored, skipped }),
+        Err(err) => {
+            handles.into_iter().for_each(RestoredHandle::delete);
+            Err(err)
+        }
+    }
+}
+
+/// Throws an `Error` with the name `BackupError`.
+///
+/// Conflicts have the `code` [BACKUP_CONFLICT_ERROR_CODE] and the conflicting `keyIds`,
+/// archives, which cannot be decrypted, the `code` [BACKUP_AUTHENTICATION_ERROR_CODE].
+pub(crate) fn throw_backup_error<'a, C: Context<'a>, V: Value>(
+    cx: &mut C,
+    err: &BackupError,
+) -> JsResult<'a, V> {
+    let js_err = cx.error(err.to_string())?;
+    let code = match err {
+        BackupError::Conflict(ids) => {
+            let key_ids = wrap_string_array(cx, ids.clone())?;
+            js_err.set(cx, "keyIds", key_ids)?;
+            BACKUP_CONFLICT_ERROR_CODE
+        }
+        BackupError::AuthenticationFailed => BACKUP_AUTHENTICATION_ERROR_CODE,
+        _ => return cx.throw(js_err),
+    };
+    let name = cx.string("BackupError");
+    js_err.set(cx, "name", name)?;
+    let code = cx.string(code);
+    js_err.set(cx, "code", code)?;
+    cx.throw(js_err)
+}
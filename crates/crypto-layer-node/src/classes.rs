@@ -0,0 +1,325 @@
+use std::sync::{Arc, RwLock};
+
+use crypto_layer::prelude::*;
+use neon::context::CallKind;
+use neon::handle::Root;
+use neon::prelude::*;
+use neon::thread::LocalKey;
+
+use crate::common::Finalized;
+use crate::metadata::MetadataStore;
+use crate::provider::ProviderState;
+use crate::tombstone::{Deletable, KeyState, Tombstoned};
+
+/// Content of the [JsBox] held by every instance of a native class.
+pub(crate) type Boxed<T> = Arc<RwLock<Finalized<<T as NativeClass>::Content>>>;
+
+/// Signature of the asynchronous functions placed on the prototype of a native class.
+pub(crate) type Method = for<'a> fn(FunctionContext<'a>) -> JsResult<'a, JsPromise>;
+
+/// Signature of the synchronous functions placed on the prototype of a native class.
+pub(crate) type SyncMethod = for<'a> fn(FunctionContext<'a>) -> JsResult<'a, JsValue>;
+
+/// `crypto-layer` type, which is exposed to JS as instance of a native class.
+pub(crate) trait NativeClass: Send + Sync + 'static {
+    /// Name of the JS class.
+    const NAME: &'static str;
+
+    /// Type held by the box of instances.
+    type Content: From<Self> + Send + Sync + 'static;
+
+    fn constructor(classes: &Classes) -> &Root<JsFunction>;
+
+    /// Text returned by `toString()` and `util.inspect()`.
+    fn describe(content: &Self::Content) -> String;
+}
+
+/// Constructors of the native classes of this addon instance.
+pub(crate) struct Classes {
+    provider: Root<JsFunction>,
+    key_handle: Root<JsFunction>,
+    key_pair_handle: Root<JsFunction>,
+    dh_exchange: Root<JsFunction>,
+    /// `WeakMap` from instances to their box, which is never exposed to JS.
+    boxes: Root<JsObject>,
+    /// `WeakMap.prototype.get` and `set` at load time, so that patching the prototype does not affect the addon.
+    boxes_get: Root<JsFunction>,
+    boxes_set: Root<JsFunction>,
+}
+
+static CLASSES: LocalKey<Classes> = LocalKey::new();
+
+impl NativeClass for Provider {
+    const NAME: &'static str = "Provider";
+
+    type Content = ProviderState;
+
+    fn constructor(classes: &Classes) -> &Root<JsFunction> {
+        &classes.provider
+    }
+
+    fn describe(content: &ProviderState) -> String {
+        format!("Provider {{ name: {:?} }}", content.provider_name())
+    }
+}
+
+impl NativeClass for KeyHandle {
+    const NAME: &'static str = "KeyHandle";
+
+    type Content = Tombstoned<Self>;
+
+    fn constructor(classes: &Classes) -> &Root<JsFunction> {
+        &classes.key_handle
+    }
+
+    fn describe(content: &Tombstoned<Self>) -> String {
+        match &content.state {
+            KeyState::Live(handle) => match handle.id() {
+                Ok(id) => format!("KeyHandle {{ id: {:?}, spec: {:?} }}", id, handle.spec()),
+                Err(_) => format!("KeyHandle {{ spec: {:?} }}", handle.spec()),
+            },
+            KeyState::Deleted { id } => format!("KeyHandle {{ id: {:?}, deleted: true }}", id),
+        }
+    }
+}
+
+impl NativeClass for KeyPairHandle {
+    const NAME: &'static str = "KeyPairHandle";
+
+    type Content = Tombstoned<Self>;
+
+    fn constructor(classes: &Classes) -> &Root<JsFunction> {
+        &classes.key_pair_handle
+    }
+
+    fn describe(content: &Tombstoned<Self>) -> String {
+        match &content.state {
+            KeyState::Live(handle) => match handle.id() {
+                Ok(id) => format!(
+                    "KeyPairHandle {{ id: {:?}, spec: {:?} }}",
+                    id,
+                    handle.spec()
+                ),
+                Err(_) => format!("KeyPairHandle {{ spec: {:?} }}", handle.spec()),
+            },
+            KeyState::Deleted { id } => {
+                format!("KeyPairHandle {{ id: {:?}, deleted: true }}", id)
+            }
+        }
+    }
+}
+
+impl NativeClass for DHExchange {
+    const NAME: &'static str = "DHExchange";
+
+    type Content = Self;
+
+    fn constructor(classes: &Classes) -> &Root<JsFunction> {
+        &classes.dh_exchange
+    }
+
+    fn describe(_content: &Self) -> String {
+        "DHExchange {}".to_owned()
+    }
+}
+
+fn classes<'a, C: Context<'a>>(cx: &mut C) -> NeonResult<&'a Classes> {
+    match CLASSES.get(cx) {
+        Some(classes) => Ok(classes),
+        None => cx.throw_error("Native classes are not initialized."),
+    }
+}
+
+/// Returns the value stored for `instance` in the `WeakMap` of boxes, `undefined` if there is none.
+fn get_box<'a, C: Context<'a>>(
+    cx: &mut C,
+    instance: Handle<'a, JsObject>,
+) -> JsResult<'a, JsValue> {
+    let classes = classes(cx)?;
+    let boxes = classes.boxes.to_inner(cx);
+    let boxes_get = classes.boxes_get.to_inner(cx);
+    boxes_get.call_with(cx).this(boxes).arg(instance).apply(cx)
+}
+
+/// Stores `boxed` for `instance` in the `WeakMap` of boxes.
+fn set_box<'a, C: Context<'a>>(
+    cx: &mut C,
+    instance: Handle<'a, JsObject>,
+    boxed: Handle<'a, JsValue>,
+) -> NeonResult<()> {
+    let classes = classes(cx)?;
+    let boxes = classes.boxes.to_inner(cx);
+    let boxes_set = classes.boxes_set.to_inner(cx);
+    boxes_set.call_with(cx).this(boxes).arg(instance).arg(boxed).exec(cx)
+}
+
+/// Constructor of native classes.
+///
+/// Instances can only be created by the addon, as only the addon is able to create the [JsBox] passed as argument.
+fn construct<T: NativeClass>(mut cx: FunctionContext) -> JsResult<JsUndefined> {
+    if !matches!(cx.kind(), CallKind::Construct) {
+        return cx.throw_type_error(format!(
+            "Class constructor {} cannot be invoked without 'new'.",
+            T::NAME
+        ));
+    }
+
+    let boxed = match cx.argument_opt(0) {
+        Some(value) => value.downcast::<JsBox<Boxed<T>>, _>(&mut cx).ok(),
+        None => None,
+    };
+    let Some(boxed) = boxed else {
+        return cx.throw_type_error(format!(
+            "Illegal constructor. Instances of {} are only created by the addon.",
+            T::NAME
+        ));
+    };
+
+    let this = cx.this::<JsObject>()?;
+    set_box(&mut cx, this, boxed.upcast())?;
+    Ok(cx.undefined())
+}
+
+/// Returns the box of `value`, if it is an instance of the native class of `T`.
+pub(crate) fn boxed_from_instance<'a, T: NativeClass>(
+    cx: &mut impl Context<'a>,
+    value: Handle<'a, JsValue>,
+) -> NeonResult<Option<Boxed<T>>> {
+    let Ok(instance) = value.downcast::<JsObject, _>(cx) else {
+        return Ok(None);
+    };
+    let boxed = get_box(cx, instance)?;
+    Ok(boxed
+        .downcast::<JsBox<Boxed<T>>, _>(cx)
+        .ok()
+        .map(|boxed| (**boxed).clone()))
+}
+
+/// Returns the box of `this`.
+///
+/// # Throws
+/// * `TypeError` if `this` is not an instance of the native class of `T`.
+pub(crate) fn boxed_this<T: NativeClass>(cx: &mut FunctionContext) -> NeonResult<Boxed<T>> {
+    let this = cx.this::<JsValue>()?;
+    match boxed_from_instance::<T>(cx, this)? {
+        Some(boxed) => Ok(boxed),
+        None => cx.throw_type_error(format!(
+            "Illegal invocation. The receiver is not an instance of {}.",
+            T::NAME
+        )),
+    }
+}
+
+/// Wraps `value` into the content of the box of instances.
+pub(crate) fn new_boxed<T: NativeClass>(value: T) -> Boxed<T> {
+    boxed_from_content::<T>(T::Content::from(value))
+}
+
+/// Boxes `content`, which holds more state than can be derived from `T` alone.
+pub(crate) fn boxed_from_content<T: NativeClass>(content: T::Content) -> Boxed<T> {
+    Arc::new(RwLock::new(Finalized::new(content)))
+}
+
+/// Creates an instance of the native class of `T`.
+pub(crate) fn new_instance<'a, T: NativeClass>(
+    cx: &mut impl Context<'a>,
+    value: T,
+) -> JsResult<'a, JsObject> {
+    instance_from_boxed::<T>(cx, new_boxed(value))
+}
+
+/// Creates an instance of the native class of the handle `T` obtained from the provider storage with the metadata `store`.
+///
+/// The handle only checks deletions of its key in `store`, see [Tombstoned::stored].
+pub(crate) fn new_stored_instance<'a, T>(
+    cx: &mut impl Context<'a>,
+    handle: T,
+    store: Arc<MetadataStore>,
+) -> JsResult<'a, JsObject>
+where
+    T: NativeClass<Content = Tombstoned<T>> + Deletable,
+{
+    instance_from_boxed::<T>(cx, boxed_from_content::<T>(Tombstoned::stored(handle, store)))
+}
+
+/// Creates an instance of the native class of `T`, which shares `boxed` with other instances.
+pub(crate) fn instance_from_boxed<'a, T: NativeClass>(
+    cx: &mut impl Context<'a>,
+    boxed: Boxed<T>,
+) -> JsResult<'a, JsObject> {
+    let boxed = JsBox::new(cx, boxed);
+    let constructor = T::constructor(classes(cx)?).to_inner(cx);
+    constructor.construct_with(cx).arg(boxed).apply(cx)
+}
+
+fn describe_this<T: NativeClass>(mut cx: FunctionContext) -> JsResult<JsString> {
+    let boxed = boxed_this::<T>(&mut cx)?;
+    let description = match boxed.read() {
+        Ok(content) => T::describe(&content),
+        Err(_) => format!("{} {{ <poisoned> }}", T::NAME),
+    };
+    Ok(cx.string(description))
+}
+
+/// Defines the native class of `T` with `methods` and `sync_methods` on its prototype.
+///
+/// `toString()` and `util.inspect()` of instances return [NativeClass::describe].
+pub(crate) fn define_class<'a, T: NativeClass>(
+    cx: &mut ModuleContext<'a>,
+    methods: &[(&str, Method)],
+    sync_methods: &[(&str, SyncMethod)],
+) -> JsResult<'a, JsFunction> {
+    let constructor = JsFunction::with_name(cx, T::NAME, construct::<T>)?;
+    let prototype = constructor.get::<JsObject, _, _>(cx, "prototype")?;
+
+    for (name, method) in methods {
+        let method_js = JsFunction::with_name(cx, name, *method)?;
+        prototype.set(cx, *name, method_js)?;
+    }
+    for (name, method) in sync_methods {
+        let method_js = JsFunction::with_name(cx, name, *method)?;
+        prototype.set(cx, *name, method_js)?;
+    }
+
+    let to_string_js = JsFunction::with_name(cx, "toString", describe_this::<T>)?;
+    prototype.set(cx, "toString", to_string_js)?;
+
+    let symbol = cx.global::<JsFunction>("Symbol")?;
+    let symbol_for = symbol.get::<JsFunction, _, _>(cx, "for")?;
+    let inspect_key_name = cx.string("nodejs.util.inspect.custom");
+    let inspect_key = symbol_for
+        .call_with(cx)
+        .this(symbol)
+        .arg(inspect_key_name)
+        .apply::<JsValue, _>(cx)?;
+    prototype.set(cx, inspect_key, to_string_js)?;
+
+    Ok(constructor)
+}
+
+/// Stores the constructors of the native classes, so that the addon is able to create instances.
+pub(crate) fn init_classes<'a>(
+    cx: &mut ModuleContext<'a>,
+    provider: Handle<'a, JsFunction>,
+    key_handle: Handle<'a, JsFunction>,
+    key_pair_handle: Handle<'a, JsFunction>,
+    dh_exchange: Handle<'a, JsFunction>,
+) -> NeonResult<()> {
+    let weak_map = cx.global::<JsFunction>("WeakMap")?;
+    let boxes = weak_map.construct_with(cx).apply::<JsObject, _>(cx)?;
+    let weak_map_prototype = weak_map.get::<JsObject, _, _>(cx, "prototype")?;
+    let boxes_get = weak_map_prototype.get::<JsFunction, _, _>(cx, "get")?;
+    let boxes_set = weak_map_prototype.get::<JsFunction, _, _>(cx, "set")?;
+
+    let classes = Classes {
+        provider: provider.root(cx),
+        key_handle: key_handle.root(cx),
+        key_pair_handle: key_pair_handle.root(cx),
+        dh_exchange: dh_exchange.root(cx),
+        boxes: boxes.root(cx),
+        boxes_get: boxes_get.root(cx),
+        boxes_set: boxes_set.root(cx),
+    };
+    CLASSES.get_or_init(cx, || classes);
+    Ok(())
+}
@@ -0,0 +1,79 @@
+use crypto_layer::common::error::CalError;
+use crypto_layer::prelude::*;
+
+/// Separates the key commitment from other HMACs of the key.
+///
+/// Must change together with [COMMITMENT_FORMAT_VERSION], whenever the commitment is computed differently.
+const COMMITMENT_DOMAIN: &[u8] = b"crypto-layer-node key commitment v1";
+
+/// Version of the commitment, the first byte of committing ciphertexts.
+const COMMITMENT_FORMAT_VERSION: u8 = 1;
+
+#[derive(thiserror::Error, Debug)]
+pub(crate) enum CommitmentError {
+    #[error("The ciphertext was not encrypted with this key.")]
+    CommitmentMismatch,
+    #[error("The key commitment version {0} is not supported.")]
+    UnsupportedVersion(u8),
+    #[error(transparent)]
+    Cal(#[from] CalError),
+}
+
+/// Commitment to the key of `handle` for the ciphertext with `iv`.
+///
+/// An HMAC of the domain and the iv with the key, whose length depends on the signing hash of its spec.
+fn commitment(handle: &KeyHandle, iv: &[u8]) -> Result<Vec<u8>, CalError> {
+    let mut input = Vec::with_capacity(COMMITMENT_DOMAIN.len() + 8 + iv.len());
+    input.extend_from_slice(COMMITMENT_DOMAIN);
+    input.extend_from_slice(&(iv.len() as u64).to_be_bytes());
+    input.extend_from_slice(iv);
+    handle.hmac(&input)
+}
+
+/// Encrypts `data` and prepends a commitment to the key.
+///
+/// AEAD ciphers like AES-GCM and ChaCha20-Poly1305 are not key committing:
+/// a ciphertext can be crafted to decrypt validly under several keys.
+/// The commitment is an HMAC of the iv with the key,
+/// which [decrypt_committing] verifies before decrypting, so only the key used for encryption accepts the ciphertext.
+///
+/// Returns the commitment version, the commitment and the ciphertext, followed by the iv.
+pub(crate) fn encrypt_committing(
+    handle: &KeyHandle,
+    data: &[u8],
+) -> Result<(Vec<u8>, Vec<u8>), CommitmentError> {
+    let (ciphertext, iv) = handle.encrypt(data)?;
+    let mut committed = vec![COMMITMENT_FORMAT_VERSION];
+    committed.extend_from_slice(&commitment(handle, &iv)?);
+    committed.extend_from_slice(&ciphertext);
+    Ok((committed, iv))
+}
+
+/// Verifies the commitment of a ciphertext of [encrypt_committing] and decrypts it.
+pub(crate) fn decrypt_committing(
+    handle: &KeyHandle,
+    committed: &[u8],
+    iv: &[u8],
+) -> Result<Vec<u8>, CommitmentError> {
+    let Some((&version, rest)) = committed.split_first() else {
+        return Err(CommitmentError::CommitmentMismatch);
+    };
+    if version != COMMITMENT_FORMAT_VERSION {
+        return Err(CommitmentError::UnsupportedVersion(version));
+    }
+
+    let expected = commitment(handle, iv)?;
+    if rest.len() < expected.len() {
+        return Err(CommitmentError::CommitmentMismatch);
+    }
+    let (received, ciphertext) = rest.split_at(expected.len());
+    if !constant_time_eq(&expected, received) {
+        return Err(CommitmentError::CommitmentMismatch);
+    }
+    Ok(handle.decrypt_data(ciphertext, iv)?)
+}
+
+/// Compares `a` and `b` in time independent of their content.
+pub(crate) fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0u8, |diff, (a, b)| diff | (a ^ b)) == 0
+}
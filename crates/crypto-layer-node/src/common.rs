@@ -1,12 +1,16 @@
 use std::convert::From;
+use std::fmt::Display;
 use std::ops::{Deref, DerefMut};
-use std::sync::{Arc, RwLock};
+use std::sync::{Arc, Mutex, PoisonError};
 
-use blocking::unblock;
-use crypto_layer::common::error::CalError;
 use neon::prelude::*;
 
+use crate::abort::{abort_token_from_last_argument, throw_abort_error, AbortToken, DeferredSlot};
+use crate::classes::{new_instance, new_stored_instance, NativeClass};
 use crate::fromjs::error::unwrap_or_throw;
+use crate::metadata::MetadataStore;
+use crate::runtime;
+use crate::tombstone::{Deletable, Tombstoned};
 
 /// Wrapper for empty [Finalize] trait implementation.
 pub(crate) struct Finalized<T> {
@@ -41,17 +45,27 @@ impl<T> From<T> for Finalized<T> {
     }
 }
 
-pub(crate) fn box_if_ok<'a, T>(
+/// Creates an instance of the native class of `T` on success or throws the error.
+pub(crate) fn box_if_ok<'a, T: NativeClass, E: Display>(
     cx: &mut impl Context<'a>,
-    result_to_be_boxed: Result<T, CalError>,
-) -> NeonResult<Handle<'a, JsBox<Arc<RwLock<Finalized<T>>>>>> {
-    Ok(JsBox::new(
-        cx,
-        Arc::new(RwLock::new(Finalized::new(unwrap_or_throw!(
-            cx,
-            result_to_be_boxed
-        )))),
-    ))
+    result_to_be_boxed: Result<T, E>,
+) -> JsResult<'a, JsObject> {
+    let content = unwrap_or_throw!(cx, result_to_be_boxed);
+    new_instance(cx, content)
+}
+
+/// Like [box_if_ok] for handles obtained from the provider storage with the metadata `store`.
+pub(crate) fn box_stored_if_ok<'a, T, E>(
+    cx: &mut impl Context<'a>,
+    result_to_be_boxed: Result<T, E>,
+    store: Arc<MetadataStore>,
+) -> JsResult<'a, JsObject>
+where
+    T: NativeClass<Content = Tombstoned<T>> + Deletable,
+    E: Display,
+{
+    let handle = unwrap_or_throw!(cx, result_to_be_boxed);
+    new_stored_instance(cx, handle, store)
 }
 
 macro_rules! arc_or_poisoned_error_deferred {
@@ -73,17 +87,85 @@ macro_rules! arc_or_poisoned_error_deferred {
 
 pub(crate) use arc_or_poisoned_error_deferred;
 
+/// [neon::types::Deferred], which rejects with an `AbortError` instead of settling, once its [AbortToken] was aborted.
+///
+/// [AbortToken::abort] rejects it right away, even while the operation is still running.
+pub(crate) struct AbortableDeferred {
+    deferred: DeferredSlot,
+    token: Option<AbortToken>,
+}
+
+impl AbortableDeferred {
+    pub(crate) fn is_aborted(&self) -> bool {
+        self.token.as_ref().is_some_and(AbortToken::is_aborted)
+    }
+
+    /// Settles the promise with the result of `complete` or rejects it with an `AbortError`.
+    ///
+    /// `complete` is not executed if the operation was aborted, thus no handles are boxed for aborted operations.
+    /// It is dropped right away, if [AbortToken::abort] already rejected the promise.
+    pub(crate) fn settle_with<V, F>(self, channel: &Channel, complete: F)
+    where
+        V: Value,
+        F: FnOnce(TaskContext) -> JsResult<V> + Send + 'static,
+    {
+        let deferred = self
+            .deferred
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .take();
+        let Some(deferred) = deferred else {
+            return;
+        };
+        let token = self.token;
+        deferred.settle_with(channel, move |mut cx| {
+            if token.as_ref().is_some_and(AbortToken::is_aborted) {
+                return throw_abort_error(&mut cx);
+            }
+            complete(cx)
+        });
+    }
+}
+
+/// Spawns `func` on the runtime and returns a promise, which is settled by `func`.
+///
+/// If the last argument of the function call is an [AbortToken], the promise is rejected with an `AbortError`
+/// as soon as the token is aborted, the operation is skipped, if it did not start yet,
+/// and its result is dropped otherwise.
 pub(crate) fn spawn_promise<'a, F>(
-    cx: &mut impl Context<'a>,
+    cx: &mut FunctionContext<'a>,
     func: F,
 ) -> NeonResult<Handle<'a, JsPromise>>
 where
-    F: Fn(Channel, neon::types::Deferred) -> () + Send + Sync + 'static,
+    F: FnOnce(Channel, AbortableDeferred) -> () + Send + 'static,
 {
+    let token = abort_token_from_last_argument(cx);
     let channel = cx.channel();
     let (deferred, promise) = cx.promise();
 
-    unblock(move || func(channel, deferred)).detach();
+    // The deferred is only moved into the task after a slot was reserved,
+    // thus it can still be rejected here if the queue is full.
+    match runtime::reserve() {
+        Ok(reservation) => {
+            let deferred: DeferredSlot = Arc::new(Mutex::new(Some(deferred)));
+            if let Some(token) = &token {
+                token.reject_on_abort(&deferred);
+            }
+            reservation.spawn_abortable(token.clone(), move || {
+                let deferred = AbortableDeferred { deferred, token };
+                if deferred.is_aborted() {
+                    // Rejects with an `AbortError`, unless the token already did.
+                    deferred.settle_with(&channel, |mut cx| Ok(cx.undefined()));
+                    return;
+                }
+                func(channel, deferred)
+            })
+        }
+        Err(msg) => {
+            let err = cx.error(msg)?;
+            deferred.reject(cx, err);
+        }
+    }
 
     Ok(promise)
 }
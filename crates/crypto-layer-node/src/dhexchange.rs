@@ -1,12 +1,14 @@
+use crypto_layer::prelude::DHExchange;
 use neon::prelude::*;
+use zeroize::Zeroizing;
 
+use crate::classes::{boxed_this, new_instance};
 use crate::common::{arc_or_poisoned_error_deferred, box_if_ok, spawn_promise};
 use crate::fromjs::error::unwrap_or_throw;
 use crate::fromjs::vec_from_uint_8_array;
 use crate::tojs::{
-    js_array_from_vec, uint_8_array_from_vec_u8, uint_8_array_tuple_from_vec_u8_tuple,
+    js_array_from_vec, uint_8_array_from_vec_u8, uint_8_array_tuple_from_secret_tuple,
 };
-use crate::JsDhExchange;
 
 /// Wraps `get_public_key` function.
 ///
@@ -18,7 +20,7 @@ use crate::JsDhExchange;
 /// # Throws
 /// * When failing to get public key.
 pub fn export_get_public_key(mut cx: FunctionContext) -> JsResult<JsPromise> {
-    let handle_arc = (**cx.this::<JsDhExchange>()?).clone();
+    let handle_arc = boxed_this::<DHExchange>(&mut cx)?;
 
     spawn_promise(&mut cx, move |channel, deferred| {
         let handle = arc_or_poisoned_error_deferred!(&channel, deferred, handle_arc.read());
@@ -44,7 +46,7 @@ pub fn export_get_public_key(mut cx: FunctionContext) -> JsResult<JsPromise> {
 /// # Throws
 /// * When failing to execute.
 pub fn export_add_external(mut cx: FunctionContext) -> JsResult<JsPromise> {
-    let handle_arc = (**cx.this::<JsDhExchange>()?).clone();
+    let handle_arc = boxed_this::<DHExchange>(&mut cx)?;
     let raw_public_key_js = cx.argument::<JsUint8Array>(0)?;
     let raw_public_key = vec_from_uint_8_array(&mut cx, raw_public_key_js);
 
@@ -71,7 +73,7 @@ pub fn export_add_external(mut cx: FunctionContext) -> JsResult<JsPromise> {
 /// # Throws
 /// * When failing to execute.
 pub fn export_add_external_final(mut cx: FunctionContext) -> JsResult<JsPromise> {
-    let handle_arc = (**cx.this::<JsDhExchange>()?).clone();
+    let handle_arc = boxed_this::<DHExchange>(&mut cx)?;
     let raw_public_key_js = cx.argument::<JsUint8Array>(0)?;
     let raw_public_key = vec_from_uint_8_array(&mut cx, raw_public_key_js);
 
@@ -96,19 +98,21 @@ pub fn export_add_external_final(mut cx: FunctionContext) -> JsResult<JsPromise>
 /// # Throws
 /// * When failing to execute.
 pub fn export_derive_client_session_keys(mut cx: FunctionContext) -> JsResult<JsPromise> {
-    let handle_arc = (**cx.this::<JsDhExchange>()?).clone();
+    let handle_arc = boxed_this::<DHExchange>(&mut cx)?;
     let server_pk_js = cx.argument::<JsUint8Array>(0)?;
     let server_pk = vec_from_uint_8_array(&mut cx, server_pk_js);
 
     spawn_promise(&mut cx, move |channel, deferred| {
         let mut handle = arc_or_poisoned_error_deferred!(&channel, deferred, handle_arc.write());
 
-        let client_session_keys = handle.derive_client_session_keys(&server_pk);
+        let client_session_keys = handle
+            .derive_client_session_keys(&server_pk)
+            .map(|(rx, tx)| (Zeroizing::new(rx), Zeroizing::new(tx)));
 
         deferred.settle_with(&channel, |mut cx| {
             let client_session_keys = unwrap_or_throw!(cx, client_session_keys);
             let client_session_keys_js =
-                uint_8_array_tuple_from_vec_u8_tuple(&mut cx, client_session_keys)?;
+                uint_8_array_tuple_from_secret_tuple(&mut cx, client_session_keys)?;
             Ok(client_session_keys_js)
         });
     })
@@ -125,19 +129,21 @@ pub fn export_derive_client_session_keys(mut cx: FunctionContext) -> JsResult<Js
 /// # Throws
 /// * When failing to execute.
 pub fn export_derive_server_session_keys(mut cx: FunctionContext) -> JsResult<JsPromise> {
-    let handle_arc = (**cx.this::<JsDhExchange>()?).clone();
+    let handle_arc = boxed_this::<DHExchange>(&mut cx)?;
     let client_pk_js = cx.argument::<JsUint8Array>(0)?;
     let client_pk = vec_from_uint_8_array(&mut cx, client_pk_js);
 
     spawn_promise(&mut cx, move |channel, deferred| {
         let mut handle = arc_or_poisoned_error_deferred!(&channel, deferred, handle_arc.write());
 
-        let client_session_keys = handle.derive_server_session_keys(&client_pk);
+        let client_session_keys = handle
+            .derive_server_session_keys(&client_pk)
+            .map(|(rx, tx)| (Zeroizing::new(rx), Zeroizing::new(tx)));
 
         deferred.settle_with(&channel, |mut cx| {
             let server_session_keys = unwrap_or_throw!(cx, client_session_keys);
             let server_session_keys_js =
-                uint_8_array_tuple_from_vec_u8_tuple(&mut cx, server_session_keys)?;
+                uint_8_array_tuple_from_secret_tuple(&mut cx, server_session_keys)?;
             Ok(server_session_keys_js)
         });
     })
@@ -154,7 +160,7 @@ pub fn export_derive_server_session_keys(mut cx: FunctionContext) -> JsResult<Js
 /// # Throws
 /// * When failing to execute.
 pub fn export_derive_client_key_handles(mut cx: FunctionContext) -> JsResult<JsPromise> {
-    let handle_arc = (**cx.this::<JsDhExchange>()?).clone();
+    let handle_arc = boxed_this::<DHExchange>(&mut cx)?;
     let server_pk_js = cx.argument::<JsUint8Array>(0)?;
     let server_pk = vec_from_uint_8_array(&mut cx, server_pk_js);
 
@@ -168,7 +174,7 @@ pub fn export_derive_client_key_handles(mut cx: FunctionContext) -> JsResult<JsP
             let client_session_keys_js = js_array_from_vec(
                 &mut cx,
                 vec![client_session_keys.0, client_session_keys.1],
-                |cx, e| Ok(box_if_ok(cx, Ok(e))?.upcast()),
+                |cx, e| Ok(new_instance(cx, e)?.upcast()),
             )?;
             Ok(client_session_keys_js)
         });
@@ -186,7 +192,7 @@ pub fn export_derive_client_key_handles(mut cx: FunctionContext) -> JsResult<JsP
 /// # Throws
 /// * When failing to execute.
 pub fn export_derive_server_key_handles(mut cx: FunctionContext) -> JsResult<JsPromise> {
-    let handle_arc = (**cx.this::<JsDhExchange>()?).clone();
+    let handle_arc = boxed_this::<DHExchange>(&mut cx)?;
     let client_pk_js = cx.argument::<JsUint8Array>(0)?;
     let client_pk = vec_from_uint_8_array(&mut cx, client_pk_js);
 
@@ -200,7 +206,7 @@ pub fn export_derive_server_key_handles(mut cx: FunctionContext) -> JsResult<JsP
             let server_session_keys_js = js_array_from_vec(
                 &mut cx,
                 vec![server_session_keys.0, server_session_keys.1],
-                |cx, e| Ok(box_if_ok(cx, Ok(e))?.upcast()),
+                |cx, e| Ok(new_instance(cx, e)?.upcast()),
             )?;
             Ok(server_session_keys_js)
         });
@@ -0,0 +1,86 @@
+use crypto_layer::prelude::*;
+use neon::prelude::*;
+use zeroize::Zeroizing;
+
+use super::error::{
+    bad_parameter, downcast_value, optional_field, rw_lock_poisoned, ConversionError,
+};
+use super::kdf::kdf_from_object;
+use crate::backup::{BackupProtection, ConflictStrategy};
+use crate::classes::boxed_from_instance;
+
+/// Reads the password or key handle protecting a backup, given as argument at `index`.
+///
+/// # Example Input Type
+/// ```ts
+/// type Protection = string | KeyHandle;
+/// ```
+pub(crate) fn backup_protection_from_argument(
+    cx: &mut FunctionContext,
+    index: usize,
+) -> Result<BackupProtection, ConversionError> {
+    let protection = match cx.argument_opt(index) {
+        Some(protection) => protection,
+        None => cx.undefined().upcast(),
+    };
+    if let Ok(password) = protection.downcast::<JsString, _>(cx) {
+        return Ok(BackupProtection::Password(Zeroizing::new(password.value(cx))));
+    }
+
+    let Some(boxed) = bad_parameter(boxed_from_instance::<KeyHandle>(cx, protection))? else {
+        return Err(ConversionError::invalid_value(cx, protection, "string or KeyHandle"));
+    };
+    let handle = rw_lock_poisoned(boxed.read())?;
+    let handle = bad_parameter(handle.live())?;
+    Ok(BackupProtection::Key(handle.clone()))
+}
+
+/// Reads the optional `{ kdf?: KDF }` of `exportBackup` given as argument at `index`.
+///
+/// Returns `None` if no kdf is given.
+pub(crate) fn export_backup_options_from_argument(
+    cx: &mut FunctionContext,
+    index: usize,
+) -> Result<Option<KDF>, ConversionError> {
+    let Some(options) = cx.argument_opt(index) else {
+        return Ok(None);
+    };
+    if options.is_a::<JsUndefined, _>(cx) {
+        return Ok(None);
+    }
+    let options = downcast_value::<JsObject>(cx, options, "object")?;
+
+    optional_field::<JsObject>(cx, options, "kdf", "object")?
+        .map(|kdf_js| kdf_from_object(cx, kdf_js).map_err(|err| err.at("kdf")))
+        .transpose()
+}
+
+/// Reads the optional `{ onConflict?: "fail" | "skip" | "duplicate" }` of `importBackup`
+/// given as argument at `index`.
+///
+/// `onConflict` defaults to [ConflictStrategy::Fail].
+pub(crate) fn import_backup_options_from_argument(
+    cx: &mut FunctionContext,
+    index: usize,
+) -> Result<ConflictStrategy, ConversionError> {
+    let Some(options) = cx.argument_opt(index) else {
+        return Ok(ConflictStrategy::default());
+    };
+    if options.is_a::<JsUndefined, _>(cx) {
+        return Ok(ConflictStrategy::default());
+    }
+    let options = downcast_value::<JsObject>(cx, options, "object")?;
+
+    let Some(on_conflict_js) = optional_field::<JsValue>(cx, options, "onConflict", "string")?
+    else {
+        return Ok(ConflictStrategy::default());
+    };
+    let on_conflict = on_conflict_js
+        .downcast::<JsString, _>(cx)
+        .ok()
+        .and_then(|on_conflict| ConflictStrategy::from_name(&on_conflict.value(cx)));
+    on_conflict.ok_or_else(|| {
+        ConversionError::variant_not_found(cx, on_conflict_js, ConflictStrategy::VARIANTS)
+            .at("onConflict")
+    })
+}
@@ -1,65 +1,159 @@
+use std::path::PathBuf;
+
 use crypto_layer::common::config::AdditionalConfigDiscriminants;
 use crypto_layer::prelude::*;
 use neon::prelude::*;
+use zeroize::Zeroizing;
 
-use super::error::{bad_parameter, js_result, rw_lock_poisoned, ConversionError};
+use super::error::{
+    bad_parameter, downcast_value, field, js_result, optional_field, rw_lock_poisoned,
+    ConversionError,
+};
+use super::kdf::kdf_from_object;
 use super::{from_wrapped_enum, from_wrapped_simple_enum, wrapped_array_to_hash_set};
-use crate::{BoxedKeyHandle, BoxedKeyPairHandle, JsKeyHandle, JsKeyPairHandle};
+use crate::backup::default_kdf;
+use crate::classes::boxed_from_instance;
+use crate::spec::{PartialKeyPairSpec, PartialKeySpec, PartialSpec};
+use crate::storage_password::{PendingImplConfig, StoragePassword};
+use crate::{BoxedKeyHandle, BoxedKeyPairHandle};
+
+/// Variants of `AdditionalConfig` accepted by [from_wrapped_additional_config] and `StoragePassword`.
+const SUPPORTED_ADDITIONAL_CONFIGS: [&str; 6] = [
+    "FileStoreConfig",
+    "StorageConfigHMAC",
+    "StorageConfigDSA",
+    "StorageConfigSymmetricEncryption",
+    "StorageConfigAsymmetricEncryption",
+    "StoragePassword",
+];
 
 /// Converts `ProviderConfig` from `crypto-layer-ts-types` to `ProviderConfig` from `crypto-layer`.
+///
+/// Paths of errors are relative to `wrapped`.
 #[tracing::instrument(level = "trace", skip_all)]
 pub fn from_wrapped_provider_config<'a>(
-    cx: &mut FunctionContext,
-    wrapped: Handle<JsObject>,
+    cx: &mut FunctionContext<'a>,
+    wrapped: Handle<'a, JsObject>,
 ) -> Result<ProviderConfig, ConversionError> {
-    let max_security_level_string =
-        js_result(wrapped.get::<JsValue, _, _>(cx, "max_security_level"))?;
-    let min_security_level_string =
-        js_result(wrapped.get::<JsValue, _, _>(cx, "min_security_level"))?;
-    let supported_hashes_arr = js_result(wrapped.get::<JsArray, _, _>(cx, "supported_hashes"))?;
-    let supported_ciphers_arr = js_result(wrapped.get::<JsArray, _, _>(cx, "supported_ciphers"))?;
-    let supported_asym_spec_arr =
-        js_result(wrapped.get::<JsArray, _, _>(cx, "supported_asym_spec"))?;
+    let max_security_level_string = field::<JsValue>(cx, wrapped, "max_security_level", "string")?;
+    let min_security_level_string = field::<JsValue>(cx, wrapped, "min_security_level", "string")?;
+    let supported_hashes_arr = field::<JsArray>(cx, wrapped, "supported_hashes", "array")?;
+    let supported_ciphers_arr = field::<JsArray>(cx, wrapped, "supported_ciphers", "array")?;
+    let supported_asym_spec_arr = field::<JsArray>(cx, wrapped, "supported_asym_spec", "array")?;
 
     Ok(ProviderConfig {
-        max_security_level: from_wrapped_simple_enum(cx, max_security_level_string)?,
-        min_security_level: from_wrapped_simple_enum(cx, min_security_level_string)?,
+        max_security_level: from_wrapped_simple_enum(cx, max_security_level_string)
+            .map_err(|err| err.at("max_security_level"))?,
+        min_security_level: from_wrapped_simple_enum(cx, min_security_level_string)
+            .map_err(|err| err.at("min_security_level"))?,
         supported_hashes: wrapped_array_to_hash_set(
             cx,
             supported_hashes_arr,
             from_wrapped_simple_enum,
-        )?,
+        )
+        .map_err(|err| err.at("supported_hashes"))?,
         supported_ciphers: wrapped_array_to_hash_set(
             cx,
             supported_ciphers_arr,
             from_wrapped_simple_enum,
-        )?,
+        )
+        .map_err(|err| err.at("supported_ciphers"))?,
         supported_asym_spec: wrapped_array_to_hash_set(
             cx,
             supported_asym_spec_arr,
             from_wrapped_simple_enum,
-        )?,
+        )
+        .map_err(|err| err.at("supported_asym_spec"))?,
     })
 }
 
 /// Converts `ProviderImplConfig` from `crypto-layer-ts-types` to `ProviderImplConfig` from `crypto-layer`.
+///
+/// `additional_config` may also hold one `StoragePassword` (see [from_wrapped_storage_password]),
+/// whose key is derived by [PendingImplConfig::resolve].
+/// Paths of errors are relative to `wrapped`.
 #[tracing::instrument(level = "trace", skip_all)]
 pub fn from_wrapped_provider_impl_config<'a>(
-    cx: &mut FunctionContext,
-    wrapped: Handle<JsObject>,
-) -> Result<ProviderImplConfig, ConversionError> {
-    let additional_config_js_arr: Handle<'_, JsArray> =
-        js_result(wrapped.get(cx, "additional_config"))?;
+    cx: &mut FunctionContext<'a>,
+    wrapped: Handle<'a, JsObject>,
+) -> Result<PendingImplConfig, ConversionError> {
+    let additional_config_js_arr = field::<JsArray>(cx, wrapped, "additional_config", "array")?;
     let additional_config_arr = js_result(additional_config_js_arr.to_vec(cx))?;
 
     let mut res = vec![];
-    for additional_config in additional_config_arr {
-        let additional_config_obj = js_result(additional_config.downcast::<JsObject, _>(cx))?;
-        res.push(from_wrapped_additional_config(cx, additional_config_obj)?);
+    let mut storage_password = None;
+    for (i, additional_config) in additional_config_arr.into_iter().enumerate() {
+        let at = |err: ConversionError| err.at(format!("[{i}]")).at("additional_config");
+        let obj = downcast_value::<JsObject>(cx, additional_config, "object").map_err(at)?;
+
+        let Some(storage_password_js) =
+            optional_field::<JsObject>(cx, obj, "StoragePassword", "object").map_err(at)?
+        else {
+            res.push(from_wrapped_additional_config(cx, obj).map_err(at)?);
+            continue;
+        };
+        if storage_password.is_some() {
+            return Err(at(ConversionError::invalid_value(
+                cx,
+                obj.upcast(),
+                "at most one `StoragePassword`",
+            )));
+        }
+        storage_password = Some(
+            from_wrapped_storage_password(cx, storage_password_js)
+                .map_err(|err| at(err.at("StoragePassword")))?,
+        );
+    }
+
+    let has_storage_key = res.iter().any(|additional_config| {
+        matches!(additional_config, AdditionalConfig::StorageConfigSymmetricEncryption(_))
+    });
+    if storage_password.is_some() && has_storage_key {
+        return Err(ConversionError::invalid_value(
+            cx,
+            additional_config_js_arr.upcast(),
+            "either `StoragePassword` or `StorageConfigSymmetricEncryption`",
+        )
+        .at("additional_config"));
     }
 
-    Ok(ProviderImplConfig {
-        additional_config: res,
+    Ok(PendingImplConfig {
+        impl_config: ProviderImplConfig {
+            additional_config: res,
+        },
+        storage_password,
+    })
+}
+
+/// Converts the `StoragePassword` entry of `additional_config` to a [StoragePassword].
+///
+/// `kdf` defaults to Argon2id with the parameters recommended by OWASP.
+/// Paths of errors are relative to `wrapped`.
+///
+/// # Example Input Type
+/// ```ts
+/// type StoragePassword = {
+///     password: string;
+///     kdf?: KDF;
+///     salt_path: string;
+/// };
+/// ```
+#[tracing::instrument(level = "trace", skip_all)]
+fn from_wrapped_storage_password<'a>(
+    cx: &mut FunctionContext<'a>,
+    wrapped: Handle<'a, JsObject>,
+) -> Result<StoragePassword, ConversionError> {
+    let password_js = field::<JsString>(cx, wrapped, "password", "string")?;
+    let salt_path_js = field::<JsString>(cx, wrapped, "salt_path", "string")?;
+    let kdf = optional_field::<JsObject>(cx, wrapped, "kdf", "object")?
+        .map(|kdf_js| kdf_from_object(cx, kdf_js).map_err(|err| err.at("kdf")))
+        .transpose()?
+        .unwrap_or_else(default_kdf);
+
+    Ok(StoragePassword {
+        password: Zeroizing::new(password_js.value(cx)),
+        kdf,
+        salt_path: PathBuf::from(salt_path_js.value(cx)),
     })
 }
 
@@ -67,77 +161,99 @@ fn boxed_key_handle_from_node_key_handle(
     cx: &mut FunctionContext,
     obj: Handle<JsObject>,
 ) -> Result<BoxedKeyHandle, ConversionError> {
-    Ok((**bad_parameter(obj.get::<JsKeyHandle, _, _>(cx, "keyHandle"))?).clone())
+    let instance = bad_parameter(obj.get_value(cx, "keyHandle"))?;
+    match bad_parameter(boxed_from_instance::<KeyHandle>(cx, instance))? {
+        Some(boxed) => Ok(boxed),
+        None => Err(ConversionError::invalid_value(cx, instance, "KeyHandle").at("keyHandle")),
+    }
 }
 
 fn boxed_key_pair_handle_from_node_key_pair_handle(
     cx: &mut FunctionContext,
     obj: Handle<JsObject>,
 ) -> Result<BoxedKeyPairHandle, ConversionError> {
-    Ok((**bad_parameter(obj.get::<JsKeyPairHandle, _, _>(cx, "keyPairHandle"))?).clone())
+    let instance = bad_parameter(obj.get_value(cx, "keyPairHandle"))?;
+    match bad_parameter(boxed_from_instance::<KeyPairHandle>(cx, instance))? {
+        Some(boxed) => Ok(boxed),
+        None => Err(
+            ConversionError::invalid_value(cx, instance, "KeyPairHandle").at("keyPairHandle"),
+        ),
+    }
 }
 
 /// Converts `AdditionalConfig` from `rs-crypto-types` to `AdditionalConfig` from `crypto-layer`.
 ///
 /// # Errors
-/// * `KVStoreConfig` is currently not supported and will crash the program with `unimplemented!()`.
+/// * [ConversionError::Unsupported] for `KVStoreConfig`, which cannot be served by JS callbacks.
 #[tracing::instrument(level = "trace", skip_all)]
-pub fn from_wrapped_additional_config(
-    cx: &mut FunctionContext,
-    wrapped: Handle<JsObject>,
+pub fn from_wrapped_additional_config<'a>(
+    cx: &mut FunctionContext<'a>,
+    wrapped: Handle<'a, JsObject>,
 ) -> Result<AdditionalConfig, ConversionError> {
     let (additional_config, obj_option): (AdditionalConfigDiscriminants, _) =
         from_wrapped_enum(cx, wrapped.upcast())?;
 
-    if obj_option.is_none() {
-        tracing::error!("Value is not of type object or string.");
-        return Err(ConversionError::BadParameter);
-    }
-
-    let obj = obj_option.unwrap();
-    let obj = bad_parameter(obj.downcast::<JsObject, _>(cx))?;
+    let variant = format!("{additional_config:?}");
+    let Some(obj) = obj_option else {
+        return Err(ConversionError::invalid_value(
+            cx,
+            wrapped.upcast(),
+            format!("object with the key `{variant}` holding an object"),
+        ));
+    };
+    let obj = downcast_value::<JsObject>(cx, obj, "object").map_err(|err| err.at(&variant))?;
 
     let result = match additional_config {
         AdditionalConfigDiscriminants::FileStoreConfig => {
-            let db_path_js = bad_parameter(obj.get::<JsString, _, _>(cx, "db_dir"))?;
+            let db_path_js = field::<JsString>(cx, obj, "db_dir", "string")
+                .map_err(|err| err.at(&variant))?;
 
             AdditionalConfig::FileStoreConfig {
                 db_dir: db_path_js.value(cx),
             }
         }
         AdditionalConfigDiscriminants::KVStoreConfig => {
-            // Implementing this is problematic:
-            // There is only one node thread running.
-            // Meaning that to call methods given to rust, rust queues theses method calls for node to run, when the thread
-            // is available.
-            // Rust waits for the call to finish. It never does, as the call can only execute, when the thread is free.
-            unimplemented!()
+            // The callbacks of a key value store can only run on the JS thread.
+            // crypto-layer calls them synchronously from a worker, while the JS thread may wait for
+            // that worker, thus the call might never finish.
+            return Err(
+                ConversionError::unsupported(&variant, SUPPORTED_ADDITIONAL_CONFIGS.to_vec())
+                    .at(&variant),
+            );
         }
         AdditionalConfigDiscriminants::StorageConfigHMAC => {
-            let boxed_key_handle = boxed_key_handle_from_node_key_handle(cx, obj)?;
+            let boxed_key_handle =
+                boxed_key_handle_from_node_key_handle(cx, obj).map_err(|err| err.at(&variant))?;
 
             let key_handle = rw_lock_poisoned(boxed_key_handle.read())?;
+            let key_handle = bad_parameter(key_handle.live())?;
 
             AdditionalConfig::StorageConfigHMAC(key_handle.clone())
         }
         AdditionalConfigDiscriminants::StorageConfigDSA => {
-            let key_pair_handle_js = boxed_key_pair_handle_from_node_key_pair_handle(cx, obj)?;
+            let key_pair_handle_js = boxed_key_pair_handle_from_node_key_pair_handle(cx, obj)
+                .map_err(|err| err.at(&variant))?;
 
             let key_pair_handle = rw_lock_poisoned(key_pair_handle_js.read())?;
+            let key_pair_handle = bad_parameter(key_pair_handle.live())?;
 
             AdditionalConfig::StorageConfigDSA(key_pair_handle.clone())
         }
         AdditionalConfigDiscriminants::StorageConfigSymmetricEncryption => {
-            let key_handle_js = boxed_key_handle_from_node_key_handle(cx, obj)?;
+            let key_handle_js =
+                boxed_key_handle_from_node_key_handle(cx, obj).map_err(|err| err.at(&variant))?;
 
             let key_handle = rw_lock_poisoned(key_handle_js.read())?;
+            let key_handle = bad_parameter(key_handle.live())?;
 
             AdditionalConfig::StorageConfigSymmetricEncryption(key_handle.clone())
         }
         AdditionalConfigDiscriminants::StorageConfigAsymmetricEncryption => {
-            let key_pair_handle_js = boxed_key_pair_handle_from_node_key_pair_handle(cx, obj)?;
+            let key_pair_handle_js = boxed_key_pair_handle_from_node_key_pair_handle(cx, obj)
+                .map_err(|err| err.at(&variant))?;
 
             let key_pair_handle = rw_lock_poisoned(key_pair_handle_js.read())?;
+            let key_pair_handle = bad_parameter(key_pair_handle.live())?;
 
             AdditionalConfig::StorageConfigAsymmetricEncryption(key_pair_handle.clone())
         }
@@ -147,47 +263,145 @@ pub fn from_wrapped_additional_config(
 }
 
 /// Converts `KeySpec` from `crypto-layer-ts-types` to `KeySpec` from `crypto-layer`.
+///
+/// Paths of errors are relative to `wrapped`.
 #[tracing::instrument(level = "trace", skip_all)]
-pub(crate) fn from_wrapped_key_spec(
-    cx: &mut FunctionContext,
-    wrapped: Handle<JsObject>,
+pub(crate) fn from_wrapped_key_spec<'a>(
+    cx: &mut FunctionContext<'a>,
+    wrapped: Handle<'a, JsObject>,
 ) -> Result<KeySpec, ConversionError> {
-    let cipher_js = js_result(wrapped.get(cx, "cipher"))?;
-    let signing_hash_js = js_result(wrapped.get(cx, "signing_hash"))?;
-    let ephemeral_js = js_result(wrapped.get::<JsBoolean, _, _>(cx, "ephemeral"))?;
-    let non_exportable_js = js_result(wrapped.get::<JsBoolean, _, _>(cx, "non_exportable"))?;
+    let cipher_js = field::<JsValue>(cx, wrapped, "cipher", "string")?;
+    let signing_hash_js = field::<JsValue>(cx, wrapped, "signing_hash", "string")?;
+    let ephemeral_js = field::<JsBoolean>(cx, wrapped, "ephemeral", "boolean")?;
+    let non_exportable_js = field::<JsBoolean>(cx, wrapped, "non_exportable", "boolean")?;
 
     Ok(KeySpec {
-        cipher: from_wrapped_simple_enum(cx, cipher_js)?,
-        signing_hash: from_wrapped_simple_enum(cx, signing_hash_js)?,
+        cipher: from_wrapped_simple_enum(cx, cipher_js).map_err(|err| err.at("cipher"))?,
+        signing_hash: from_wrapped_simple_enum(cx, signing_hash_js)
+            .map_err(|err| err.at("signing_hash"))?,
         ephemeral: ephemeral_js.value(cx),
         non_exportable: non_exportable_js.value(cx),
     })
 }
 
 /// Converts `KeyPairSpec` from `crypto-layer-ts-types` to `KeyPairSpec` from `crypto-layer`.
+///
+/// Paths of errors are relative to `wrapped`.
 #[tracing::instrument(level = "trace", skip_all)]
-pub(crate) fn from_wrapped_key_pair_spec(
-    cx: &mut FunctionContext,
-    wrapped: Handle<JsObject>,
+pub(crate) fn from_wrapped_key_pair_spec<'a>(
+    cx: &mut FunctionContext<'a>,
+    wrapped: Handle<'a, JsObject>,
 ) -> Result<KeyPairSpec, ConversionError> {
-    let asym_spec_js = js_result(wrapped.get(cx, "asym_spec"))?;
-    let cipher_js = js_result(wrapped.get::<JsValue, _, _>(cx, "cipher"))?;
-    let signing_hash_js = js_result(wrapped.get(cx, "signing_hash"))?;
-    let ephemeral_js = js_result(wrapped.get::<JsBoolean, _, _>(cx, "ephemeral"))?;
-    let non_exportable_js = js_result(wrapped.get::<JsBoolean, _, _>(cx, "non_exportable"))?;
-
-    let cipher = if let Ok(cipher_js_str) = cipher_js.downcast::<JsString, _>(cx) {
-        Some(from_wrapped_simple_enum(cx, cipher_js_str.upcast())?)
-    } else {
+    let asym_spec_js = field::<JsValue>(cx, wrapped, "asym_spec", "string")?;
+    let cipher_js = field::<JsValue>(cx, wrapped, "cipher", "string or null")?;
+    let signing_hash_js = field::<JsValue>(cx, wrapped, "signing_hash", "string")?;
+    let ephemeral_js = field::<JsBoolean>(cx, wrapped, "ephemeral", "boolean")?;
+    let non_exportable_js = field::<JsBoolean>(cx, wrapped, "non_exportable", "boolean")?;
+
+    let cipher = if cipher_js.is_a::<JsNull, _>(cx) || cipher_js.is_a::<JsUndefined, _>(cx) {
         None
+    } else {
+        Some(from_wrapped_simple_enum(cx, cipher_js).map_err(|err| err.at("cipher"))?)
     };
 
     Ok(KeyPairSpec {
-        asym_spec: from_wrapped_simple_enum(cx, asym_spec_js)?,
+        asym_spec: from_wrapped_simple_enum(cx, asym_spec_js)
+            .map_err(|err| err.at("asym_spec"))?,
         cipher,
-        signing_hash: from_wrapped_simple_enum(cx, signing_hash_js)?,
+        signing_hash: from_wrapped_simple_enum(cx, signing_hash_js)
+            .map_err(|err| err.at("signing_hash"))?,
         ephemeral: ephemeral_js.value(cx),
         non_exportable: non_exportable_js.value(cx),
     })
 }
+
+/// Converts a `Partial<KeySpec>` to a [PartialKeySpec].
+///
+/// Fields, which are `undefined`, are left to [crate::spec::complete_key_spec].
+/// Paths of errors are relative to `wrapped`.
+#[tracing::instrument(level = "trace", skip_all)]
+pub(crate) fn from_wrapped_partial_key_spec<'a>(
+    cx: &mut FunctionContext<'a>,
+    wrapped: Handle<'a, JsObject>,
+) -> Result<PartialKeySpec, ConversionError> {
+    let cipher_js = optional_field::<JsValue>(cx, wrapped, "cipher", "string")?;
+    let signing_hash_js = optional_field::<JsValue>(cx, wrapped, "signing_hash", "string")?;
+    let ephemeral_js = optional_field::<JsBoolean>(cx, wrapped, "ephemeral", "boolean")?;
+    let non_exportable_js =
+        optional_field::<JsBoolean>(cx, wrapped, "non_exportable", "boolean")?;
+
+    Ok(PartialKeySpec {
+        cipher: cipher_js
+            .map(|js| from_wrapped_simple_enum(cx, js).map_err(|err| err.at("cipher")))
+            .transpose()?,
+        signing_hash: signing_hash_js
+            .map(|js| from_wrapped_simple_enum(cx, js).map_err(|err| err.at("signing_hash")))
+            .transpose()?,
+        ephemeral: ephemeral_js.map(|js| js.value(cx)),
+        non_exportable: non_exportable_js.map(|js| js.value(cx)),
+    })
+}
+
+/// Converts a `Partial<KeyPairSpec>` to a [PartialKeyPairSpec].
+///
+/// Fields, which are `undefined`, are left to [crate::spec::complete_key_pair_spec].
+/// Paths of errors are relative to `wrapped`.
+#[tracing::instrument(level = "trace", skip_all)]
+pub(crate) fn from_wrapped_partial_key_pair_spec<'a>(
+    cx: &mut FunctionContext<'a>,
+    wrapped: Handle<'a, JsObject>,
+) -> Result<PartialKeyPairSpec, ConversionError> {
+    let asym_spec_js = optional_field::<JsValue>(cx, wrapped, "asym_spec", "string")?;
+    let cipher_js = optional_field::<JsValue>(cx, wrapped, "cipher", "string or null")?;
+    let signing_hash_js = optional_field::<JsValue>(cx, wrapped, "signing_hash", "string")?;
+    let ephemeral_js = optional_field::<JsBoolean>(cx, wrapped, "ephemeral", "boolean")?;
+    let non_exportable_js =
+        optional_field::<JsBoolean>(cx, wrapped, "non_exportable", "boolean")?;
+
+    let cipher = match cipher_js {
+        Some(js) if js.is_a::<JsNull, _>(cx) => Some(None),
+        Some(js) => Some(Some(
+            from_wrapped_simple_enum(cx, js).map_err(|err| err.at("cipher"))?,
+        )),
+        None => None,
+    };
+
+    Ok(PartialKeyPairSpec {
+        asym_spec: asym_spec_js
+            .map(|js| from_wrapped_simple_enum(cx, js).map_err(|err| err.at("asym_spec")))
+            .transpose()?,
+        cipher,
+        signing_hash: signing_hash_js
+            .map(|js| from_wrapped_simple_enum(cx, js).map_err(|err| err.at("signing_hash")))
+            .transpose()?,
+        ephemeral: ephemeral_js.map(|js| js.value(cx)),
+        non_exportable: non_exportable_js.map(|js| js.value(cx)),
+    })
+}
+
+/// Converts `{ KeySpec: Partial<KeySpec> } | { KeyPairSpec: Partial<KeyPairSpec> }` to a [PartialSpec].
+///
+/// Paths of errors are relative to `wrapped`.
+#[tracing::instrument(level = "trace", skip_all)]
+pub(crate) fn from_wrapped_partial_spec<'a>(
+    cx: &mut FunctionContext<'a>,
+    wrapped: Handle<'a, JsObject>,
+) -> Result<PartialSpec, ConversionError> {
+    if let Some(key_spec_js) = optional_field::<JsObject>(cx, wrapped, "KeySpec", "object")? {
+        let partial =
+            from_wrapped_partial_key_spec(cx, key_spec_js).map_err(|err| err.at("KeySpec"))?;
+        return Ok(PartialSpec::KeySpec(partial));
+    }
+    if let Some(key_pair_spec_js) =
+        optional_field::<JsObject>(cx, wrapped, "KeyPairSpec", "object")?
+    {
+        let partial = from_wrapped_partial_key_pair_spec(cx, key_pair_spec_js)
+            .map_err(|err| err.at("KeyPairSpec"))?;
+        return Ok(PartialSpec::KeyPairSpec(partial));
+    }
+    Err(ConversionError::invalid_value(
+        cx,
+        wrapped.upcast(),
+        "object with the key `KeySpec` or `KeyPairSpec`",
+    ))
+}
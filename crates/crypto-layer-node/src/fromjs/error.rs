@@ -1,5 +1,14 @@
+use std::fmt;
+
+use neon::prelude::*;
 use tracing::error;
 
+/// `code` of errors thrown for invalid arguments.
+pub(crate) const INVALID_ARGUMENT_ERROR_CODE: &str = "ERR_INVALID_ARG_VALUE";
+
+/// `code` of errors thrown for specs using algorithms the provider does not support.
+pub(crate) const UNSUPPORTED_ERROR_CODE: &str = "ERR_UNSUPPORTED_BY_PROVIDER";
+
 #[derive(thiserror::Error, Debug)]
 pub(crate) enum ConversionError {
     #[error("The string given does not convert to the enum requested.")]
@@ -10,6 +19,214 @@ pub(crate) enum ConversionError {
     JsError,
     #[error("RwLock is poisoned.")]
     RwLockPoisoned,
+    #[error("{0}")]
+    InvalidValue(Box<InvalidValue>),
+    /// Well formed value, which is not supported by the provider.
+    #[error("{}", .0.describe("Unsupported value"))]
+    Unsupported(Box<InvalidValue>),
+}
+
+/// Details of a value, which does not match the type expected at its position.
+#[derive(Debug, Default)]
+pub(crate) struct InvalidValue {
+    /// Segments of the path from the argument to the value, e.g. `["spec", "asym_spec"]`.
+    pub path: Vec<String>,
+    /// JS type of the received value and, for primitives, the value itself.
+    pub received: String,
+    /// Description of the expected type.
+    pub expected: String,
+    /// Accepted variant names, if an enum is expected.
+    pub accepted: Vec<&'static str>,
+}
+
+impl InvalidValue {
+    /// Path as written in JS, e.g. `impl_config.additional_config[0]`.
+    pub fn path_string(&self) -> String {
+        let mut res = String::new();
+        for segment in &self.path {
+            if !res.is_empty() && !segment.starts_with('[') {
+                res.push('.');
+            }
+            res.push_str(segment);
+        }
+        res
+    }
+
+    /// Message starting with `prefix` followed by the path, the expected and the received value.
+    pub fn describe(&self, prefix: &str) -> String {
+        let mut res = prefix.to_owned();
+        if !self.path.is_empty() {
+            res.push_str(&format!(" at `{}`", self.path_string()));
+        }
+        if self.accepted.is_empty() {
+            res.push_str(&format!(": expected {}", self.expected));
+        } else {
+            let accepted: Vec<String> = self.accepted.iter().map(|v| format!("{v:?}")).collect();
+            res.push_str(&format!(": expected one of {}", accepted.join(", ")));
+        }
+        res.push_str(&format!(", received {}.", self.received));
+        res
+    }
+}
+
+impl fmt::Display for InvalidValue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.describe("Invalid value"))
+    }
+}
+
+impl ConversionError {
+    /// Creates an [ConversionError::InvalidValue] for `value` at the current position.
+    pub fn invalid_value<'a>(
+        cx: &mut impl Context<'a>,
+        value: Handle<JsValue>,
+        expected: impl Into<String>,
+    ) -> Self {
+        ConversionError::InvalidValue(Box::new(InvalidValue {
+            path: vec![],
+            received: describe_js_value(cx, value),
+            expected: expected.into(),
+            accepted: vec![],
+        }))
+    }
+
+    /// Creates an [ConversionError::InvalidValue] for `value`, which is not one of the `accepted` variants.
+    pub fn variant_not_found<'a>(
+        cx: &mut impl Context<'a>,
+        value: Handle<JsValue>,
+        accepted: &[&'static str],
+    ) -> Self {
+        ConversionError::InvalidValue(Box::new(InvalidValue {
+            path: vec![],
+            received: describe_js_value(cx, value),
+            expected: "string".to_owned(),
+            accepted: accepted.to_vec(),
+        }))
+    }
+
+    /// Creates an [ConversionError::Unsupported] for the `received` variant, which is not one of the `supported` variants.
+    pub fn unsupported(received: &str, supported: Vec<&'static str>) -> Self {
+        ConversionError::Unsupported(Box::new(InvalidValue {
+            path: vec![],
+            received: format!("string {received:?}"),
+            expected: "variant supported by the provider".to_owned(),
+            accepted: supported,
+        }))
+    }
+
+    /// Prepends `segment` to the path of an [ConversionError::InvalidValue] or [ConversionError::Unsupported].
+    ///
+    /// Field names are given as is, array indices as `[i]`. Other variants are returned unchanged.
+    pub fn at(self, segment: impl Into<String>) -> Self {
+        match self {
+            ConversionError::InvalidValue(mut invalid) => {
+                invalid.path.insert(0, segment.into());
+                ConversionError::InvalidValue(invalid)
+            }
+            ConversionError::Unsupported(mut invalid) => {
+                invalid.path.insert(0, segment.into());
+                ConversionError::Unsupported(invalid)
+            }
+            other => other,
+        }
+    }
+}
+
+/// Describes the JS type of `value` and, for primitives, the value itself, e.g. `string "P257"`.
+pub(crate) fn describe_js_value<'a>(cx: &mut impl Context<'a>, value: Handle<JsValue>) -> String {
+    if value.is_a::<JsUndefined, _>(cx) {
+        "undefined".to_owned()
+    } else if value.is_a::<JsNull, _>(cx) {
+        "null".to_owned()
+    } else if let Ok(s) = value.downcast::<JsString, _>(cx) {
+        format!("string {:?}", s.value(cx))
+    } else if let Ok(n) = value.downcast::<JsNumber, _>(cx) {
+        format!("number {}", n.value(cx))
+    } else if let Ok(b) = value.downcast::<JsBoolean, _>(cx) {
+        format!("boolean {}", b.value(cx))
+    } else if value.is_a::<JsArray, _>(cx) {
+        "array".to_owned()
+    } else if value.is_a::<JsFunction, _>(cx) {
+        "function".to_owned()
+    } else if value.is_a::<JsObject, _>(cx) {
+        "object".to_owned()
+    } else {
+        "unknown".to_owned()
+    }
+}
+
+/// Downcasts `value` or returns an [ConversionError::InvalidValue] naming the `expected` type.
+pub(crate) fn downcast_value<'a, V: Value>(
+    cx: &mut impl Context<'a>,
+    value: Handle<'a, JsValue>,
+    expected: &str,
+) -> Result<Handle<'a, V>, ConversionError> {
+    match value.downcast::<V, _>(cx) {
+        Ok(v) => Ok(v),
+        Err(_) => Err(ConversionError::invalid_value(cx, value, expected)),
+    }
+}
+
+/// Reads the field `key` of `obj` as `V`.
+///
+/// A missing field or a field of another type results in an [ConversionError::InvalidValue] with the path `key`.
+pub(crate) fn field<'a, V: Value>(
+    cx: &mut impl Context<'a>,
+    obj: Handle<'a, JsObject>,
+    key: &str,
+    expected: &str,
+) -> Result<Handle<'a, V>, ConversionError> {
+    let value = js_result(obj.get_value(cx, key))?;
+    downcast_value(cx, value, expected).map_err(|err| err.at(key))
+}
+
+/// Optional variant of [field], which returns `None` if the field is `undefined`.
+pub(crate) fn optional_field<'a, V: Value>(
+    cx: &mut impl Context<'a>,
+    obj: Handle<'a, JsObject>,
+    key: &str,
+    expected: &str,
+) -> Result<Option<Handle<'a, V>>, ConversionError> {
+    let value = js_result(obj.get_value(cx, key))?;
+    if value.is_a::<JsUndefined, _>(cx) {
+        return Ok(None);
+    }
+    downcast_value(cx, value, expected)
+        .map(Some)
+        .map_err(|err| err.at(key))
+}
+
+/// Throws a `TypeError` for `err`.
+///
+/// For [ConversionError::InvalidValue] the error has the `code` [INVALID_ARGUMENT_ERROR_CODE],
+/// for [ConversionError::Unsupported] the `code` [UNSUPPORTED_ERROR_CODE].
+/// Both carry `path`, `received`, `expected` and `accepted`.
+pub(crate) fn throw_conversion_error<'a, C: Context<'a>, V: Value>(
+    cx: &mut C,
+    err: &ConversionError,
+) -> JsResult<'a, V> {
+    let (invalid, code) = match err {
+        ConversionError::InvalidValue(invalid) => (invalid, INVALID_ARGUMENT_ERROR_CODE),
+        ConversionError::Unsupported(invalid) => (invalid, UNSUPPORTED_ERROR_CODE),
+        _ => return cx.throw_error(err.to_string()),
+    };
+
+    let js_err = cx.type_error(err.to_string())?;
+    let code = cx.string(code);
+    js_err.set(cx, "code", code)?;
+    let path = cx.string(invalid.path_string());
+    js_err.set(cx, "path", path)?;
+    let received = cx.string(&invalid.received);
+    js_err.set(cx, "received", received)?;
+    let expected = cx.string(&invalid.expected);
+    js_err.set(cx, "expected", expected)?;
+    let accepted = JsArray::new(cx, invalid.accepted.len());
+    for (i, variant) in invalid.accepted.iter().enumerate() {
+        let variant = cx.string(variant);
+        accepted.set(cx, i as u32, variant)?;
+    }
+    js_err.set(cx, "accepted", accepted)?;
+    cx.throw(js_err)
 }
 
 /// Used for errors which stem from internal logic (casting up and down).
@@ -58,3 +275,39 @@ macro_rules! unwrap_or_throw {
 }
 
 pub(crate) use unwrap_or_throw;
+
+/// Like [unwrap_or_throw], but prepends `root` to the path of a [ConversionError] and throws it with [throw_conversion_error].
+macro_rules! unwrap_or_throw_conversion {
+    ($cx:ident, $root:expr, $e:expr) => {
+        match $e {
+            Ok(res) => res,
+            Err(err) => {
+                let err: crate::fromjs::error::ConversionError = err;
+                return crate::fromjs::error::throw_conversion_error(&mut $cx, &err.at($root));
+            }
+        }
+    };
+}
+
+pub(crate) use unwrap_or_throw_conversion;
+
+/// Returns the value or rejects the deferred with [throw_conversion_error] and returns from the current function.
+///
+/// `root` is prepended to the path of the error.
+macro_rules! conversion_or_error_deferred {
+    ($channel:expr, $deferred:expr, $root:expr, $e:expr) => {{
+        match $e {
+            Ok(res) => res,
+            Err(err) => {
+                let err: crate::fromjs::error::ConversionError = err;
+                let err = err.at($root);
+                $deferred.settle_with($channel, move |mut cx| {
+                    crate::fromjs::error::throw_conversion_error::<_, JsValue>(&mut cx, &err)
+                });
+                return ();
+            }
+        }
+    }};
+}
+
+pub(crate) use conversion_or_error_deferred;
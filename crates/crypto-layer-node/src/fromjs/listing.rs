@@ -0,0 +1,126 @@
+use neon::prelude::*;
+
+use super::error::{downcast_value, optional_field, ConversionError};
+use super::from_wrapped_simple_enum;
+use crate::listing::{KeyFilter, KeyKind, DEFAULT_PAGE_LIMIT};
+
+/// Options of `listKeys`.
+#[derive(Debug, Default)]
+pub(crate) struct ListKeysOptions {
+    pub filter: KeyFilter,
+    pub cursor: Option<String>,
+    pub limit: usize,
+}
+
+/// Converts a `KeyFilter` to a [KeyFilter].
+///
+/// Paths of errors are relative to `wrapped`.
+///
+/// # Example Input Type
+/// ```ts
+/// type KeyFilter = {
+///     kind?: "Key" | "KeyPair";
+///     cipher?: Cipher;
+///     asymSpec?: AsymmetricKeySpec;
+///     tag?: string;
+///     ephemeral?: boolean;
+/// };
+/// ```
+pub(crate) fn from_wrapped_key_filter<'a>(
+    cx: &mut FunctionContext<'a>,
+    wrapped: Handle<'a, JsObject>,
+) -> Result<KeyFilter, ConversionError> {
+    let kind_js = optional_field::<JsValue>(cx, wrapped, "kind", "string")?;
+    let cipher_js = optional_field::<JsValue>(cx, wrapped, "cipher", "string")?;
+    let asym_spec_js = optional_field::<JsValue>(cx, wrapped, "asymSpec", "string")?;
+    let tag_js = optional_field::<JsString>(cx, wrapped, "tag", "string")?;
+    let ephemeral_js = optional_field::<JsBoolean>(cx, wrapped, "ephemeral", "boolean")?;
+
+    let kind = match kind_js {
+        Some(kind_js) => {
+            let kind = kind_js
+                .downcast::<JsString, _>(cx)
+                .ok()
+                .and_then(|kind| KeyKind::from_name(&kind.value(cx)));
+            match kind {
+                Some(kind) => Some(kind),
+                None => {
+                    return Err(
+                        ConversionError::variant_not_found(cx, kind_js, KeyKind::VARIANTS)
+                            .at("kind"),
+                    )
+                }
+            }
+        }
+        None => None,
+    };
+
+    Ok(KeyFilter {
+        kind,
+        cipher: cipher_js
+            .map(|js| from_wrapped_simple_enum(cx, js).map_err(|err| err.at("cipher")))
+            .transpose()?,
+        asym_spec: asym_spec_js
+            .map(|js| from_wrapped_simple_enum(cx, js).map_err(|err| err.at("asymSpec")))
+            .transpose()?,
+        tag: tag_js.map(|js| js.value(cx)),
+        ephemeral: ephemeral_js.map(|js| js.value(cx)),
+    })
+}
+
+/// Reads an optional `KeyFilter` given as argument at `index`.
+///
+/// Returns a filter matching every key, if the argument is missing.
+pub(crate) fn key_filter_from_argument(
+    cx: &mut FunctionContext,
+    index: usize,
+) -> Result<KeyFilter, ConversionError> {
+    let Some(filter) = cx.argument_opt(index) else {
+        return Ok(KeyFilter::default());
+    };
+    if filter.is_a::<JsUndefined, _>(cx) {
+        return Ok(KeyFilter::default());
+    }
+    let filter = downcast_value::<JsObject>(cx, filter, "object")?;
+    from_wrapped_key_filter(cx, filter)
+}
+
+/// Reads optional `{ filter?: KeyFilter, cursor?: string, limit?: number }` given as argument at `index`.
+///
+/// `limit` defaults to [DEFAULT_PAGE_LIMIT] and must be a positive integer.
+/// Paths of errors are relative to the options object.
+pub(crate) fn list_keys_options_from_argument(
+    cx: &mut FunctionContext,
+    index: usize,
+) -> Result<ListKeysOptions, ConversionError> {
+    let mut res = ListKeysOptions {
+        limit: DEFAULT_PAGE_LIMIT,
+        ..Default::default()
+    };
+    let Some(options) = cx.argument_opt(index) else {
+        return Ok(res);
+    };
+    if options.is_a::<JsUndefined, _>(cx) {
+        return Ok(res);
+    }
+    let options = downcast_value::<JsObject>(cx, options, "object")?;
+
+    if let Some(filter_js) = optional_field::<JsObject>(cx, options, "filter", "object")? {
+        res.filter = from_wrapped_key_filter(cx, filter_js).map_err(|err| err.at("filter"))?;
+    }
+    if let Some(cursor_js) = optional_field::<JsString>(cx, options, "cursor", "string")? {
+        res.cursor = Some(cursor_js.value(cx));
+    }
+    if let Some(limit_js) = optional_field::<JsNumber>(cx, options, "limit", "number")? {
+        let limit = limit_js.value(cx);
+        if limit.fract() != 0.0 || limit < 1.0 || limit > u32::MAX as f64 {
+            return Err(
+                ConversionError::invalid_value(cx, limit_js.upcast(), "positive integer")
+                    .at("limit"),
+            );
+        }
+        res.limit = limit as usize;
+    }
+
+    Ok(res)
+}
@@ -0,0 +1,271 @@
+use neon::prelude::*;
+
+use super::error::{downcast_value, js_result, optional_field, ConversionError};
+use crate::metadata::{NewKeyMetadata, Validity};
+use crate::nonce::EncryptionLimits;
+use crate::policy::KeyOperation;
+
+/// Option key of create and import functions holding the metadata of the new key.
+const METADATA_OPTION: &str = "metadata";
+
+/// Option key of create and import functions holding the validity period of the new key.
+const VALIDITY_OPTION: &str = "validity";
+
+/// Option key of create and import functions holding the usages the new key is restricted to.
+const USAGES_OPTION: &str = "usages";
+
+/// Option key of create and import functions holding the encryption limits of the new key.
+const ENCRYPTION_LIMITS_OPTION: &str = "encryptionLimits";
+
+/// Reads `metadata`, `validity`, `usages` and `encryptionLimits` from an optional options object
+/// given as argument at `index`.
+///
+/// Returns `None` if the options object or all of these options are missing.
+/// Paths of errors are relative to the options object, e.g. `metadata.tags[0]`.
+///
+/// # Example Input Type
+/// ```ts
+/// type Options = {
+///     metadata?: {
+///         label?: string;
+///         tags?: string[];
+///         // `Date` or milliseconds since the unix epoch
+///         expiresAt?: Date | number;
+///     };
+///     validity?: {
+///         notBefore?: Date | number;
+///         notAfter?: Date | number;
+///         // milliseconds
+///         gracePeriod?: number;
+///     };
+///     usages?: ("encrypt" | "decrypt" | "sign" | "verify" | "deriveKey" | "dhExchange")[];
+///     encryptionLimits?: {
+///         maxEncryptions?: number;
+///         warnAt?: number;
+///         strictIvCheck?: boolean;
+///     };
+/// };
+/// ```
+pub(crate) fn metadata_from_options_argument<'a>(
+    cx: &mut FunctionContext<'a>,
+    index: usize,
+) -> Result<Option<NewKeyMetadata>, ConversionError> {
+    let Some(options) = cx.argument_opt(index) else {
+        return Ok(None);
+    };
+    if options.is_a::<JsUndefined, _>(cx) {
+        return Ok(None);
+    }
+    let options = downcast_value::<JsObject>(cx, options, "object")?;
+    let metadata_js = optional_field::<JsObject>(cx, options, METADATA_OPTION, "object")?;
+    let validity_js = optional_field::<JsObject>(cx, options, VALIDITY_OPTION, "object")?;
+    let usages_js = optional_field::<JsArray>(cx, options, USAGES_OPTION, "array of strings")?;
+    let encryption_limits_js =
+        optional_field::<JsObject>(cx, options, ENCRYPTION_LIMITS_OPTION, "object")?;
+    if metadata_js.is_none()
+        && validity_js.is_none()
+        && usages_js.is_none()
+        && encryption_limits_js.is_none()
+    {
+        return Ok(None);
+    }
+
+    let mut metadata = match metadata_js {
+        Some(metadata_js) => from_wrapped_new_key_metadata(cx, metadata_js)
+            .map_err(|err| err.at(METADATA_OPTION))?,
+        None => NewKeyMetadata::default(),
+    };
+    if let Some(validity_js) = validity_js {
+        metadata.validity =
+            from_wrapped_validity(cx, validity_js).map_err(|err| err.at(VALIDITY_OPTION))?;
+    }
+    if let Some(usages_js) = usages_js {
+        metadata.usages =
+            Some(from_wrapped_usages(cx, usages_js).map_err(|err| err.at(USAGES_OPTION))?);
+    }
+    if let Some(encryption_limits_js) = encryption_limits_js {
+        metadata.encryption_limits = Some(
+            from_wrapped_encryption_limits(cx, encryption_limits_js)
+                .map_err(|err| err.at(ENCRYPTION_LIMITS_OPTION))?,
+        );
+    }
+    Ok(Some(metadata))
+}
+
+fn from_wrapped_new_key_metadata<'a>(
+    cx: &mut FunctionContext<'a>,
+    wrapped: Handle<'a, JsObject>,
+) -> Result<NewKeyMetadata, ConversionError> {
+    let label = optional_field::<JsString>(cx, wrapped, "label", "string")?.map(|s| s.value(cx));
+
+    let tags = match optional_field::<JsArray>(cx, wrapped, "tags", "array of strings")? {
+        Some(tags_js) => {
+            let mut tags = vec![];
+            for (i, tag_js) in js_result(tags_js.to_vec(cx))?.into_iter().enumerate() {
+                let tag = downcast_value::<JsString>(cx, tag_js, "string")
+                    .map_err(|err| err.at(format!("[{i}]")).at("tags"))?;
+                tags.push(tag.value(cx));
+            }
+            tags
+        }
+        None => vec![],
+    };
+
+    let expires_at_js = optional_field::<JsValue>(cx, wrapped, "expiresAt", "Date or number")?;
+    let expires_at = match expires_at_js {
+        Some(expires_at_js) => Some(
+            millis_from_date_or_number(cx, expires_at_js).map_err(|err| err.at("expiresAt"))?,
+        ),
+        None => None,
+    };
+
+    Ok(NewKeyMetadata {
+        label,
+        tags,
+        expires_at,
+        validity: Validity::default(),
+        usages: None,
+        encryption_limits: None,
+    })
+}
+
+fn from_wrapped_validity<'a>(
+    cx: &mut FunctionContext<'a>,
+    wrapped: Handle<'a, JsObject>,
+) -> Result<Validity, ConversionError> {
+    let not_before_js = optional_field::<JsValue>(cx, wrapped, "notBefore", "Date or number")?;
+    let not_after_js = optional_field::<JsValue>(cx, wrapped, "notAfter", "Date or number")?;
+
+    let not_before = not_before_js
+        .map(|js| millis_from_date_or_number(cx, js).map_err(|err| err.at("notBefore")))
+        .transpose()?;
+    let not_after = match not_after_js {
+        Some(js) => {
+            let not_after =
+                millis_from_date_or_number(cx, js).map_err(|err| err.at("notAfter"))?;
+            if not_before.is_some_and(|not_before| not_after < not_before) {
+                return Err(ConversionError::invalid_value(
+                    cx,
+                    js,
+                    "Date or number not before `notBefore`",
+                )
+                .at("notAfter"));
+            }
+            Some(not_after)
+        }
+        None => None,
+    };
+
+    let grace_period = match optional_field::<JsNumber>(cx, wrapped, "gracePeriod", "number")? {
+        Some(grace_period_js) => {
+            let grace_period = grace_period_js.value(cx);
+            if !grace_period.is_finite() || grace_period < 0.0 {
+                return Err(ConversionError::invalid_value(
+                    cx,
+                    grace_period_js.upcast(),
+                    "non negative milliseconds",
+                )
+                .at("gracePeriod"));
+            }
+            Some(grace_period.trunc() as u64)
+        }
+        None => None,
+    };
+
+    Ok(Validity {
+        not_before,
+        not_after,
+        grace_period,
+    })
+}
+
+fn from_wrapped_usages<'a>(
+    cx: &mut FunctionContext<'a>,
+    wrapped: Handle<'a, JsArray>,
+) -> Result<Vec<KeyOperation>, ConversionError> {
+    let mut usages = vec![];
+    for (i, usage_js) in js_result(wrapped.to_vec(cx))?.into_iter().enumerate() {
+        let usage = usage_js
+            .downcast::<JsString, _>(cx)
+            .ok()
+            .and_then(|usage| KeyOperation::from_name(&usage.value(cx)));
+        let Some(usage) = usage else {
+            return Err(
+                ConversionError::variant_not_found(cx, usage_js, KeyOperation::VARIANTS)
+                    .at(format!("[{i}]")),
+            );
+        };
+        if !usages.contains(&usage) {
+            usages.push(usage);
+        }
+    }
+    Ok(usages)
+}
+
+fn from_wrapped_encryption_limits<'a>(
+    cx: &mut FunctionContext<'a>,
+    wrapped: Handle<'a, JsObject>,
+) -> Result<EncryptionLimits, ConversionError> {
+    let max_encryptions = positive_integer_field(cx, wrapped, "maxEncryptions")?;
+    let warn_at = positive_integer_field(cx, wrapped, "warnAt")?;
+    let strict_iv_check = optional_field::<JsBoolean>(cx, wrapped, "strictIvCheck", "boolean")?
+        .is_some_and(|strict_iv_check| strict_iv_check.value(cx));
+
+    if let (Some(max_encryptions), Some(warn_at)) = (max_encryptions, warn_at) {
+        if warn_at > max_encryptions {
+            let warn_at_js = cx.number(warn_at as f64).upcast();
+            return Err(ConversionError::invalid_value(
+                cx,
+                warn_at_js,
+                "positive integer not above `maxEncryptions`",
+            )
+            .at("warnAt"));
+        }
+    }
+
+    Ok(EncryptionLimits {
+        max_encryptions,
+        warn_at,
+        strict_iv_check,
+    })
+}
+
+fn positive_integer_field<'a>(
+    cx: &mut FunctionContext<'a>,
+    wrapped: Handle<'a, JsObject>,
+    key: &str,
+) -> Result<Option<u64>, ConversionError> {
+    let Some(number_js) = optional_field::<JsNumber>(cx, wrapped, key, "positive integer")? else {
+        return Ok(None);
+    };
+    let number = number_js.value(cx);
+    if number.fract() != 0.0 || number < 1.0 || number > u64::MAX as f64 {
+        return Err(
+            ConversionError::invalid_value(cx, number_js.upcast(), "positive integer").at(key),
+        );
+    }
+    Ok(Some(number as u64))
+}
+
+/// Converts a `Date` or a number of milliseconds since the unix epoch to milliseconds.
+fn millis_from_date_or_number<'a>(
+    cx: &mut FunctionContext<'a>,
+    value: Handle<'a, JsValue>,
+) -> Result<u64, ConversionError> {
+    let millis = if let Ok(date) = value.downcast::<JsDate, _>(cx) {
+        date.value(cx)
+    } else if let Ok(number) = value.downcast::<JsNumber, _>(cx) {
+        number.value(cx)
+    } else {
+        return Err(ConversionError::invalid_value(cx, value, "Date or number"));
+    };
+
+    if !millis.is_finite() || millis < 0.0 {
+        return Err(ConversionError::invalid_value(
+            cx,
+            value,
+            "valid Date or non negative milliseconds since the unix epoch",
+        ));
+    }
+    Ok(millis.trunc() as u64)
+}
@@ -0,0 +1,50 @@
+use crypto_layer::prelude::*;
+use neon::prelude::*;
+
+use super::error::{bad_parameter, downcast_value, optional_field, ConversionError};
+use super::listing::from_wrapped_key_filter;
+use crate::classes::{boxed_from_instance, Boxed};
+use crate::migration::MigrationOptions;
+
+/// Reads the bare provider given as argument at `index`.
+pub(crate) fn boxed_provider_from_argument(
+    cx: &mut FunctionContext,
+    index: usize,
+) -> Result<Boxed<Provider>, ConversionError> {
+    let provider = match cx.argument_opt(index) {
+        Some(provider) => provider,
+        None => cx.undefined().upcast(),
+    };
+    match bad_parameter(boxed_from_instance::<Provider>(cx, provider))? {
+        Some(boxed) => Ok(boxed),
+        None => Err(ConversionError::invalid_value(cx, provider, "Provider")),
+    }
+}
+
+/// Reads optional `{ filter?: KeyFilter, deleteSource?: boolean }` given as argument at `index`.
+///
+/// Paths of errors are relative to the options object.
+pub(crate) fn migration_options_from_argument(
+    cx: &mut FunctionContext,
+    index: usize,
+) -> Result<MigrationOptions, ConversionError> {
+    let mut res = MigrationOptions::default();
+    let Some(options) = cx.argument_opt(index) else {
+        return Ok(res);
+    };
+    if options.is_a::<JsUndefined, _>(cx) {
+        return Ok(res);
+    }
+    let options = downcast_value::<JsObject>(cx, options, "object")?;
+
+    if let Some(filter_js) = optional_field::<JsObject>(cx, options, "filter", "object")? {
+        res.filter = from_wrapped_key_filter(cx, filter_js).map_err(|err| err.at("filter"))?;
+    }
+    if let Some(delete_source_js) =
+        optional_field::<JsBoolean>(cx, options, "deleteSource", "boolean")?
+    {
+        res.delete_source = delete_source_js.value(cx);
+    }
+
+    Ok(res)
+}
@@ -1,6 +1,10 @@
+pub(crate) mod backup;
 pub(crate) mod config;
 pub(crate) mod error;
 pub(crate) mod kdf;
+pub(crate) mod listing;
+pub(crate) mod metadata;
+pub(crate) mod migration;
 
 use std::any::type_name;
 use std::cmp::Eq;
@@ -11,7 +15,9 @@ use std::str::FromStr;
 use neon::prelude::*;
 use neon::types::buffer::TypedArray;
 use num::{cast, PrimInt};
+use strum::VariantNames;
 use tracing::error;
+use zeroize::{Zeroize, Zeroizing};
 
 use error::{bad_parameter, js_result, ConversionError};
 
@@ -35,6 +41,8 @@ pub(crate) fn from_wrapped_string_vec<'a>(
 }
 
 /// Converts any `Uint8Array` into a `Vec<u8>`.
+///
+/// The data is copied, as the operation runs on another thread while JS may still mutate or detach the buffer.
 pub(crate) fn vec_from_uint_8_array(
     cx: &mut FunctionContext,
     typed_js_array: Handle<JsUint8Array>,
@@ -47,6 +55,78 @@ pub(crate) fn vec_from_uint_8_array(
     }
 }
 
+/// Converts a `Uint8Array` holding secret material into a `Vec<u8>`, which is wiped on drop.
+pub(crate) fn secret_from_uint_8_array(
+    cx: &mut FunctionContext,
+    typed_js_array: Handle<JsUint8Array>,
+) -> Zeroizing<Vec<u8>> {
+    Zeroizing::new(vec_from_uint_8_array(cx, typed_js_array))
+}
+
+/// Converts a JS Array of `Uint8Array` into a `Vec<Vec<u8>>`.
+pub(crate) fn vec_from_uint_8_array_array(
+    cx: &mut FunctionContext,
+    wrapped: Handle<JsArray>,
+) -> Result<Vec<Vec<u8>>, ConversionError> {
+    let arr = js_result(wrapped.to_vec(cx))?;
+    let mut res = Vec::with_capacity(arr.len());
+
+    for elem in arr {
+        let typed_js_array = bad_parameter(elem.downcast::<JsUint8Array, _>(cx))?;
+        res.push(vec_from_uint_8_array(cx, typed_js_array));
+    }
+
+    Ok(res)
+}
+
+/// Converts a JS Array of `[Uint8Array, Uint8Array]` tuples into a `Vec<(Vec<u8>, Vec<u8>)>`.
+pub(crate) fn vec_from_uint_8_array_tuple_array(
+    cx: &mut FunctionContext,
+    wrapped: Handle<JsArray>,
+) -> Result<Vec<(Vec<u8>, Vec<u8>)>, ConversionError> {
+    let arr = js_result(wrapped.to_vec(cx))?;
+    let mut res = Vec::with_capacity(arr.len());
+
+    for elem in arr {
+        let tuple = bad_parameter(elem.downcast::<JsArray, _>(cx))?;
+        let first = bad_parameter(tuple.get::<JsUint8Array, _, _>(cx, 0))?;
+        let second = bad_parameter(tuple.get::<JsUint8Array, _, _>(cx, 1))?;
+        res.push((
+            vec_from_uint_8_array(cx, first),
+            vec_from_uint_8_array(cx, second),
+        ));
+    }
+
+    Ok(res)
+}
+
+/// Overwrites the contents of a `Uint8Array` with zeros.
+pub(crate) fn zeroize_uint_8_array(cx: &mut FunctionContext, typed_js_array: Handle<JsUint8Array>) {
+    if typed_js_array.len(cx) != 0 {
+        // `as_mut_slice` method panics on empty array.
+        typed_js_array.as_mut_slice(cx).zeroize();
+    }
+}
+
+/// Reads a boolean flag from an optional options object given as argument at `index`.
+///
+/// Returns `false` if the options object or the flag is missing.
+pub(crate) fn flag_from_options_argument(
+    cx: &mut FunctionContext,
+    index: usize,
+    key: &str,
+) -> Result<bool, ConversionError> {
+    let Some(options) = cx.argument_opt(index) else {
+        return Ok(false);
+    };
+    if options.is_a::<JsUndefined, _>(cx) {
+        return Ok(false);
+    }
+    let options = bad_parameter(options.downcast::<JsObject, _>(cx))?;
+    let flag = bad_parameter(options.get_opt::<JsBoolean, _, _>(cx, key))?;
+    Ok(flag.map(|flag| flag.value(cx)).unwrap_or(false))
+}
+
 /// Returns all keys of an JS Object.
 pub(crate) fn object_keys<'a>(
     cx: &mut impl Context<'a>,
@@ -59,22 +139,30 @@ pub(crate) fn object_keys<'a>(
 
 /// Converts an JS `string` to a rust type implementing `FromStr` trait.
 ///
+/// Errors list the variant names accepted by `T`.
+///
 /// # Example Input Type
 /// ```ts
 /// type Cipher =
 ///   | "AesGcm128"
 ///   | "AesGcm256";
 /// ```
-pub(crate) fn from_wrapped_simple_enum<T: FromStr>(
+pub(crate) fn from_wrapped_simple_enum<T: FromStr + VariantNames>(
     cx: &mut FunctionContext,
     wrapped_enum: Handle<JsValue>,
 ) -> Result<T, ConversionError> {
-    let wrapped_as_str = js_result(wrapped_enum.downcast::<JsString, _>(cx))?;
+    let Ok(wrapped_as_str) = wrapped_enum.downcast::<JsString, _>(cx) else {
+        return Err(ConversionError::variant_not_found(
+            cx,
+            wrapped_enum,
+            T::VARIANTS,
+        ));
+    };
     let enum_str = wrapped_as_str.value(cx);
-    Ok(T::from_str(&enum_str).map_err(|_| {
+    T::from_str(&enum_str).map_err(|_| {
         error!("Failed constructing {} from {}", type_name::<T>(), enum_str);
-        ConversionError::EnumVariantNotFound
-    })?)
+        ConversionError::variant_not_found(cx, wrapped_enum, T::VARIANTS)
+    })
 }
 
 /// Converts an JS `string` or `object` to a rust enum, which implements the `FromStr` trait.
@@ -102,7 +190,7 @@ pub(crate) fn from_wrapped_enum<'a, T: FromStr>(
         let value = s.value(cx);
         let res = T::from_str(&value).map_err(|_| {
             error!("Failed constructing {} from {}", type_name::<T>(), value);
-            ConversionError::EnumVariantNotFound
+            ConversionError::invalid_value(cx, wrapped_enum, "variant name")
         })?;
         Ok((res, None))
     } else if let Ok(o) = wrapped_enum.downcast::<JsObject, _>(cx) {
@@ -113,9 +201,17 @@ pub(crate) fn from_wrapped_enum<'a, T: FromStr>(
                 return Ok((res, Some(value)));
             }
         }
-        Err(ConversionError::EnumVariantNotFound)
+        Err(ConversionError::invalid_value(
+            cx,
+            wrapped_enum,
+            "object with a variant name as key",
+        ))
     } else {
-        Err(ConversionError::BadParameter)
+        Err(ConversionError::invalid_value(
+            cx,
+            wrapped_enum,
+            "string or object",
+        ))
     }
 }
 
@@ -136,7 +232,7 @@ pub(crate) fn wrapped_array_to_hash_set<
 
     for i in 0..count {
         let val = js_result(arr.get::<JsValue, _, _>(cx, i))?;
-        res.insert(convert(cx, val)?);
+        res.insert(convert(cx, val).map_err(|err| err.at(format!("[{i}]")))?);
     }
 
     Ok(res)
@@ -1,11 +1,34 @@
+use crypto_layer::prelude::KeyHandle;
 use neon::prelude::*;
+use neon::types::buffer::TypedArray;
+use zeroize::Zeroizing;
 
+use crate::classes::boxed_this;
+use crate::commitment::{decrypt_committing, encrypt_committing};
 use crate::common::{arc_or_poisoned_error_deferred, spawn_promise};
-use crate::fromjs::error::unwrap_or_throw;
-use crate::fromjs::vec_from_uint_8_array;
+use crate::fromjs::error::{rw_lock_poisoned, unwrap_or_throw};
+use crate::fromjs::{
+    flag_from_options_argument, vec_from_uint_8_array, vec_from_uint_8_array_array,
+    vec_from_uint_8_array_tuple_array,
+};
+use crate::nonce::{counted_or_error_deferred, record_encryptions};
+use crate::policy::{check_key_policy, permitted_or_error_deferred, KeyOperation};
+use crate::runtime::parallel_map;
+use crate::tombstone::{live_or_deleted_error_deferred, throw_key_deleted_error};
 use crate::tojs::config::wrap_key_spec;
-use crate::tojs::uint_8_array_from_vec_u8;
-use crate::{box_if_ok, JsKeyHandle};
+use crate::tojs::metadata::wrap_optional_key_metadata;
+use crate::tojs::{
+    check_output_len, copy_into_uint_8_array, uint_8_array_from_secret, uint_8_array_from_vec_u8,
+    uint_8_array_tuple_from_vec_u8_tuple, wrap_batch_results,
+};
+use crate::box_if_ok;
+
+/// Option key of `encrypt` and `decryptData`, which prepends and verifies a key commitment.
+const COMMITTING_OPTION: &str = "committing";
+
+/// Bytes a cipher text is longer than its plain text: the tag of AEAD ciphers
+/// or at most the padding of block ciphers.
+const CIPHERTEXT_OVERHEAD: usize = 16;
 
 /// Wraps `id` function.
 ///
@@ -17,10 +40,11 @@ use crate::{box_if_ok, JsKeyHandle};
 /// # Throws
 /// * When failing to execute.
 pub fn export_id(mut cx: FunctionContext) -> JsResult<JsPromise> {
-    let handle_arc = (**cx.this::<JsKeyHandle>()?).clone();
+    let handle_arc = boxed_this::<KeyHandle>(&mut cx)?;
 
     spawn_promise(&mut cx, move |channel, deferred| {
         let handle = arc_or_poisoned_error_deferred!(&channel, deferred, handle_arc.read());
+        let handle = live_or_deleted_error_deferred!(&channel, deferred, handle.live());
 
         let id = handle.id();
 
@@ -28,8 +52,62 @@ pub fn export_id(mut cx: FunctionContext) -> JsResult<JsPromise> {
     })
 }
 
+/// Synchronous variant of [export_id], which reads the id on the main thread.
+///
+/// # Arguments
+///
+/// # Returns
+/// * `string` - id of key
+///
+/// # Throws
+/// * When the lock of the handle is poisoned.
+/// * When failing to execute.
+pub fn export_id_sync(mut cx: FunctionContext) -> JsResult<JsValue> {
+    let handle_arc = boxed_this::<KeyHandle>(&mut cx)?;
+    let handle = unwrap_or_throw!(cx, rw_lock_poisoned(handle_arc.read()));
+    let handle = match handle.live() {
+        Ok(handle) => handle,
+        Err(err) => return throw_key_deleted_error(&mut cx, &err),
+    };
+
+    let id = unwrap_or_throw!(cx, handle.id());
+
+    Ok(cx.string(id).upcast())
+}
+
+/// Returns the metadata stored with the key on creation or import.
+///
+/// # Arguments
+///
+/// # Returns
+/// * `KeyMetadata` - label, tags and timestamps
+/// * `null` - if the key was created without metadata
+///
+/// # Throws
+/// * When failing to execute.
+/// * `KeyDeletedError` when the key was deleted.
+pub fn export_metadata(mut cx: FunctionContext) -> JsResult<JsPromise> {
+    let handle_arc = boxed_this::<KeyHandle>(&mut cx)?;
+
+    spawn_promise(&mut cx, move |channel, deferred| {
+        let state = arc_or_poisoned_error_deferred!(&channel, deferred, handle_arc.read());
+        let handle = live_or_deleted_error_deferred!(&channel, deferred, state.live());
+
+        let metadata = handle
+            .id()
+            .map(|id| state.store.as_ref().and_then(|store| store.get(&id)));
+
+        deferred.settle_with(&channel, |mut cx| {
+            let metadata = unwrap_or_throw!(cx, metadata);
+            wrap_optional_key_metadata(&mut cx, metadata)
+        });
+    })
+}
+
 /// Wraps `delete` function.
 ///
+/// Afterwards every call on this handle and on other handles of the same key rejects with a `KeyDeletedError`.
+///
 /// # Arguments
 ///
 /// # Returns
@@ -37,13 +115,14 @@ pub fn export_id(mut cx: FunctionContext) -> JsResult<JsPromise> {
 ///
 /// # Throws
 /// * When failing to execute.
+/// * `KeyDeletedError` when the key was already deleted.
 pub fn export_delete(mut cx: FunctionContext) -> JsResult<JsPromise> {
-    let handle_arc = (**cx.this::<JsKeyHandle>()?).clone();
+    let handle_arc = boxed_this::<KeyHandle>(&mut cx)?;
 
     spawn_promise(&mut cx, move |channel, deferred| {
-        let handle = arc_or_poisoned_error_deferred!(&channel, deferred, handle_arc.read());
+        let mut handle = arc_or_poisoned_error_deferred!(&channel, deferred, handle_arc.write());
 
-        let result = handle.clone().delete();
+        let result = live_or_deleted_error_deferred!(&channel, deferred, handle.delete());
 
         deferred.settle_with(&channel, |mut cx| {
             unwrap_or_throw!(cx, result);
@@ -64,56 +143,135 @@ pub fn export_delete(mut cx: FunctionContext) -> JsResult<JsPromise> {
 /// # Throws
 /// * When failing to execute.
 pub fn export_encrypt_data(mut cx: FunctionContext) -> JsResult<JsPromise> {
-    let handle_arc = (**cx.this::<JsKeyHandle>()?).clone();
+    let handle_arc = boxed_this::<KeyHandle>(&mut cx)?;
     let data_js = cx.argument::<JsUint8Array>(0)?;
     let data = vec_from_uint_8_array(&mut cx, data_js);
     let iv_js = cx.argument::<JsUint8Array>(1)?;
     let iv = vec_from_uint_8_array(&mut cx, iv_js);
 
     spawn_promise(&mut cx, move |channel, deferred| {
-        let handle = arc_or_poisoned_error_deferred!(&channel, deferred, handle_arc.read());
+        let state = arc_or_poisoned_error_deferred!(&channel, deferred, handle_arc.read());
+        let handle = live_or_deleted_error_deferred!(&channel, deferred, state.live());
+        permitted_or_error_deferred!(
+            &channel,
+            deferred,
+            check_key_policy(handle, state.store.as_deref(), KeyOperation::Encrypt)
+        );
+
+        counted_or_error_deferred!(
+            &channel,
+            deferred,
+            record_encryptions(handle, state.store.as_deref(), 1, Some(&iv))
+        );
 
         let result = handle.encrypt_data(&data, &iv);
 
         deferred.settle_with(&channel, |mut cx| {
-            let (encrypted_data, iv) = unwrap_or_throw!(cx, result);
-
-            let arr = cx.empty_array();
-            let encrypted_data_js = JsUint8Array::from_slice(&mut cx, &encrypted_data)?;
-            arr.set(&mut cx, 0, encrypted_data_js)?;
-            let iv_js = uint_8_array_from_vec_u8(&mut cx, iv)?;
-            arr.set(&mut cx, 1, iv_js)?;
-            Ok(arr)
+            let encrypted_data_and_iv = unwrap_or_throw!(cx, result);
+            uint_8_array_tuple_from_vec_u8_tuple(&mut cx, encrypted_data_and_iv)
         });
     })
 }
 
 /// Wraps `encrypt` function.
 ///
+/// With `committing` a commitment to the key is prepended to the ciphertext, see [encrypt_committing].
+///
 /// # Arguments
 /// * **data**: `Uint8Array`
+/// * **options**: `{ committing?: boolean }` - optional
 ///
 /// # Returns
 /// * `[Uint8Array, Uint8Array]` - on success
 ///
 /// # Throws
+/// * When one of the inputs is incorrect.
 /// * When failing to execute.
 pub fn export_encrypt(mut cx: FunctionContext) -> JsResult<JsPromise> {
-    let handle_arc = (**cx.this::<JsKeyHandle>()?).clone();
+    let handle_arc = boxed_this::<KeyHandle>(&mut cx)?;
     let data_js = cx.argument::<JsUint8Array>(0)?;
     let data = vec_from_uint_8_array(&mut cx, data_js);
+    let committing =
+        unwrap_or_throw!(cx, flag_from_options_argument(&mut cx, 1, COMMITTING_OPTION));
 
     spawn_promise(&mut cx, move |channel, deferred| {
-        let handle = arc_or_poisoned_error_deferred!(&channel, deferred, handle_arc.read());
+        let state = arc_or_poisoned_error_deferred!(&channel, deferred, handle_arc.read());
+        let handle = live_or_deleted_error_deferred!(&channel, deferred, state.live());
+        permitted_or_error_deferred!(
+            &channel,
+            deferred,
+            check_key_policy(handle, state.store.as_deref(), KeyOperation::Encrypt)
+        );
+
+        counted_or_error_deferred!(
+            &channel,
+            deferred,
+            record_encryptions(handle, state.store.as_deref(), 1, None)
+        );
+
+        let result = if committing {
+            encrypt_committing(handle, &data).map_err(|err| err.to_string())
+        } else {
+            handle.encrypt(&data).map_err(|err| err.to_string())
+        };
+
+        deferred.settle_with(&channel, |mut cx| {
+            let encrypted_data_and_iv = unwrap_or_throw!(cx, result);
+            uint_8_array_tuple_from_vec_u8_tuple(&mut cx, encrypted_data_and_iv)
+        });
+    })
+}
+
+/// Wraps `encrypt` function and writes the cipher text into a caller supplied buffer.
+///
+/// `data` is copied before the call returns, as JS may modify, transfer or detach its buffer meanwhile.
+/// Only the allocation of the cipher text is saved.
+///
+/// # Arguments
+/// * **data**: `Uint8Array`
+/// * **out**: `Uint8Array` - buffer the cipher text is written to,
+///   at least [CIPHERTEXT_OVERHEAD] bytes longer than `data`
+///
+/// # Returns
+/// * `[number, Uint8Array]` - bytes written to `out` and iv on success
+///
+/// # Throws
+/// * When failing to execute.
+/// * `RangeError` before encrypting, when `out` is too small.
+pub fn export_encrypt_into(mut cx: FunctionContext) -> JsResult<JsPromise> {
+    let handle_arc = boxed_this::<KeyHandle>(&mut cx)?;
+    let data_js = cx.argument::<JsUint8Array>(0)?;
+    let out_js = cx.argument::<JsUint8Array>(1)?;
+    check_output_len(&mut cx, out_js, data_js.len(&mut cx) + CIPHERTEXT_OVERHEAD)?;
+    let data = vec_from_uint_8_array(&mut cx, data_js);
+    let out_root = out_js.root(&mut cx);
+
+    spawn_promise(&mut cx, move |channel, deferred| {
+        let state = arc_or_poisoned_error_deferred!(&channel, deferred, handle_arc.read());
+        let handle = live_or_deleted_error_deferred!(&channel, deferred, state.live());
+        permitted_or_error_deferred!(
+            &channel,
+            deferred,
+            check_key_policy(handle, state.store.as_deref(), KeyOperation::Encrypt)
+        );
+
+        counted_or_error_deferred!(
+            &channel,
+            deferred,
+            record_encryptions(handle, state.store.as_deref(), 1, None)
+        );
 
         let result = handle.encrypt(&data);
 
-        deferred.settle_with(&channel, |mut cx| {
+        deferred.settle_with(&channel, move |mut cx| {
+            let out = out_root.into_inner(&mut cx);
             let (encrypted_data, iv) = unwrap_or_throw!(cx, result);
 
+            let written = copy_into_uint_8_array(&mut cx, out, &encrypted_data)?;
+
             let arr = cx.empty_array();
-            let encrypted_data_js = JsUint8Array::from_slice(&mut cx, &encrypted_data)?;
-            arr.set(&mut cx, 0, encrypted_data_js)?;
+            let written_js = cx.number(written as f64);
+            arr.set(&mut cx, 0, written_js)?;
             let iv_js = uint_8_array_from_vec_u8(&mut cx, iv)?;
             arr.set(&mut cx, 1, iv_js)?;
             Ok(arr)
@@ -133,14 +291,26 @@ pub fn export_encrypt(mut cx: FunctionContext) -> JsResult<JsPromise> {
 /// # Throws
 /// * When failing to execute.
 pub fn export_encrypt_with_iv(mut cx: FunctionContext) -> JsResult<JsPromise> {
-    let handle_arc = (**cx.this::<JsKeyHandle>()?).clone();
+    let handle_arc = boxed_this::<KeyHandle>(&mut cx)?;
     let data_js = cx.argument::<JsUint8Array>(0)?;
     let data = vec_from_uint_8_array(&mut cx, data_js);
     let iv_js = cx.argument::<JsUint8Array>(1)?;
     let iv = vec_from_uint_8_array(&mut cx, iv_js);
 
     spawn_promise(&mut cx, move |channel, deferred| {
-        let handle = arc_or_poisoned_error_deferred!(&channel, deferred, handle_arc.read());
+        let state = arc_or_poisoned_error_deferred!(&channel, deferred, handle_arc.read());
+        let handle = live_or_deleted_error_deferred!(&channel, deferred, state.live());
+        permitted_or_error_deferred!(
+            &channel,
+            deferred,
+            check_key_policy(handle, state.store.as_deref(), KeyOperation::Encrypt)
+        );
+
+        counted_or_error_deferred!(
+            &channel,
+            deferred,
+            record_encryptions(handle, state.store.as_deref(), 1, Some(&iv))
+        );
 
         let result = handle.encrypt_with_iv(&data, &iv);
 
@@ -154,26 +324,44 @@ pub fn export_encrypt_with_iv(mut cx: FunctionContext) -> JsResult<JsPromise> {
 
 /// Wraps `decrypt_data` function.
 ///
+/// With `committing` the key commitment prepended by [export_encrypt] is verified before decrypting,
+/// see [decrypt_committing].
+///
 /// # Arguments
 /// * **encryptedData**: `Uint8Array`
 /// * **iv**: `Uint8Array`
+/// * **options**: `{ committing?: boolean }` - optional
 ///
 /// # Returns
 /// * `Uint8Array` - decrypted data on success
 ///
 /// # Throws
+/// * When one of the inputs is incorrect.
+/// * When the ciphertext was not encrypted with this key in committing mode.
 /// * When failing to execute.
 pub fn export_decrypt_data(mut cx: FunctionContext) -> JsResult<JsPromise> {
-    let handle_arc = (**cx.this::<JsKeyHandle>()?).clone();
+    let handle_arc = boxed_this::<KeyHandle>(&mut cx)?;
     let data_js = cx.argument::<JsUint8Array>(0)?;
     let data = vec_from_uint_8_array(&mut cx, data_js);
     let iv_js = cx.argument::<JsUint8Array>(1)?;
     let iv = vec_from_uint_8_array(&mut cx, iv_js);
+    let committing =
+        unwrap_or_throw!(cx, flag_from_options_argument(&mut cx, 2, COMMITTING_OPTION));
 
     spawn_promise(&mut cx, move |channel, deferred| {
-        let handle = arc_or_poisoned_error_deferred!(&channel, deferred, handle_arc.read());
-
-        let decrypted_data = handle.decrypt_data(&data, &iv);
+        let state = arc_or_poisoned_error_deferred!(&channel, deferred, handle_arc.read());
+        let handle = live_or_deleted_error_deferred!(&channel, deferred, state.live());
+        permitted_or_error_deferred!(
+            &channel,
+            deferred,
+            check_key_policy(handle, state.store.as_deref(), KeyOperation::Decrypt)
+        );
+
+        let decrypted_data = if committing {
+            decrypt_committing(handle, &data, &iv).map_err(|err| err.to_string())
+        } else {
+            handle.decrypt_data(&data, &iv).map_err(|err| err.to_string())
+        };
 
         deferred.settle_with(&channel, |mut cx| {
             let decrypted_data = unwrap_or_throw!(cx, decrypted_data);
@@ -182,6 +370,135 @@ pub fn export_decrypt_data(mut cx: FunctionContext) -> JsResult<JsPromise> {
     })
 }
 
+/// Wraps `decrypt_data` function and writes the plain text into a caller supplied buffer.
+///
+/// `encryptedData` is copied before the call returns, as JS may modify, transfer or detach its buffer meanwhile.
+/// Only the allocation of the plain text is saved.
+///
+/// # Arguments
+/// * **encryptedData**: `Uint8Array`
+/// * **iv**: `Uint8Array`
+/// * **out**: `Uint8Array` - buffer the plain text is written to,
+///   at least as long as `encryptedData` without [CIPHERTEXT_OVERHEAD] bytes
+///
+/// # Returns
+/// * `number` - bytes written to `out` on success
+///
+/// # Throws
+/// * When failing to execute.
+/// * `RangeError` before decrypting, when `out` is too small.
+pub fn export_decrypt_into(mut cx: FunctionContext) -> JsResult<JsPromise> {
+    let handle_arc = boxed_this::<KeyHandle>(&mut cx)?;
+    let data_js = cx.argument::<JsUint8Array>(0)?;
+    let iv_js = cx.argument::<JsUint8Array>(1)?;
+    let iv = vec_from_uint_8_array(&mut cx, iv_js);
+    let out_js = cx.argument::<JsUint8Array>(2)?;
+    let required = data_js.len(&mut cx).saturating_sub(CIPHERTEXT_OVERHEAD);
+    check_output_len(&mut cx, out_js, required)?;
+    let data = vec_from_uint_8_array(&mut cx, data_js);
+    let out_root = out_js.root(&mut cx);
+
+    spawn_promise(&mut cx, move |channel, deferred| {
+        let state = arc_or_poisoned_error_deferred!(&channel, deferred, handle_arc.read());
+        let handle = live_or_deleted_error_deferred!(&channel, deferred, state.live());
+        permitted_or_error_deferred!(
+            &channel,
+            deferred,
+            check_key_policy(handle, state.store.as_deref(), KeyOperation::Decrypt)
+        );
+
+        let decrypted_data = handle.decrypt_data(&data, &iv);
+
+        deferred.settle_with(&channel, move |mut cx| {
+            let out = out_root.into_inner(&mut cx);
+            let decrypted_data = unwrap_or_throw!(cx, decrypted_data);
+
+            let written = copy_into_uint_8_array(&mut cx, out, &decrypted_data)?;
+            Ok(cx.number(written as f64))
+        });
+    })
+}
+
+/// Wraps `encrypt` function for many messages at once.
+///
+/// The handle is locked once and the messages are encrypted in parallel.
+///
+/// # Arguments
+/// * **data**: `Uint8Array[]`
+///
+/// # Returns
+/// * `BatchResult<[Uint8Array, Uint8Array]>[]` - per message result
+///
+/// # Throws
+/// * When one of the inputs is incorrect.
+pub fn export_encrypt_many(mut cx: FunctionContext) -> JsResult<JsPromise> {
+    let handle_arc = boxed_this::<KeyHandle>(&mut cx)?;
+    let items_js = cx.argument::<JsArray>(0)?;
+    let items = unwrap_or_throw!(cx, vec_from_uint_8_array_array(&mut cx, items_js));
+
+    spawn_promise(&mut cx, move |channel, deferred| {
+        let state = arc_or_poisoned_error_deferred!(&channel, deferred, handle_arc.read());
+        let handle = live_or_deleted_error_deferred!(&channel, deferred, state.live());
+        permitted_or_error_deferred!(
+            &channel,
+            deferred,
+            check_key_policy(handle, state.store.as_deref(), KeyOperation::Encrypt)
+        );
+
+        counted_or_error_deferred!(
+            &channel,
+            deferred,
+            record_encryptions(handle, state.store.as_deref(), items.len() as u64, None)
+        );
+
+        let handle = handle.clone();
+        let results = parallel_map(items, move |data| handle.encrypt(&data));
+
+        deferred.settle_with(&channel, |mut cx| {
+            wrap_batch_results(&mut cx, results, |cx, encrypted_data_and_iv| {
+                Ok(uint_8_array_tuple_from_vec_u8_tuple(cx, encrypted_data_and_iv)?.upcast())
+            })
+        });
+    })
+}
+
+/// Wraps `decrypt_data` function for many messages at once.
+///
+/// The handle is locked once and the messages are decrypted in parallel.
+///
+/// # Arguments
+/// * **items**: `[Uint8Array, Uint8Array][]` - encrypted data and iv
+///
+/// # Returns
+/// * `BatchResult<Uint8Array>[]` - per message result
+///
+/// # Throws
+/// * When one of the inputs is incorrect.
+pub fn export_decrypt_many(mut cx: FunctionContext) -> JsResult<JsPromise> {
+    let handle_arc = boxed_this::<KeyHandle>(&mut cx)?;
+    let items_js = cx.argument::<JsArray>(0)?;
+    let items = unwrap_or_throw!(cx, vec_from_uint_8_array_tuple_array(&mut cx, items_js));
+
+    spawn_promise(&mut cx, move |channel, deferred| {
+        let state = arc_or_poisoned_error_deferred!(&channel, deferred, handle_arc.read());
+        let handle = live_or_deleted_error_deferred!(&channel, deferred, state.live());
+        permitted_or_error_deferred!(
+            &channel,
+            deferred,
+            check_key_policy(handle, state.store.as_deref(), KeyOperation::Decrypt)
+        );
+
+        let handle = handle.clone();
+        let results = parallel_map(items, move |(data, iv)| handle.decrypt_data(&data, &iv));
+
+        deferred.settle_with(&channel, |mut cx| {
+            wrap_batch_results(&mut cx, results, |cx, decrypted_data| {
+                Ok(uint_8_array_from_vec_u8(cx, decrypted_data)?.upcast())
+            })
+        });
+    })
+}
+
 /// Wraps `extract_key` function.
 ///
 /// # Arguments
@@ -192,16 +509,17 @@ pub fn export_decrypt_data(mut cx: FunctionContext) -> JsResult<JsPromise> {
 /// # Throws
 /// * When failing to execute.
 pub fn export_extract_key(mut cx: FunctionContext) -> JsResult<JsPromise> {
-    let handle_arc = (**cx.this::<JsKeyHandle>()?).clone();
+    let handle_arc = boxed_this::<KeyHandle>(&mut cx)?;
 
     spawn_promise(&mut cx, move |channel, deferred| {
         let handle = arc_or_poisoned_error_deferred!(&channel, deferred, handle_arc.read());
+        let handle = live_or_deleted_error_deferred!(&channel, deferred, handle.live());
 
-        let key = handle.extract_key();
+        let key = handle.extract_key().map(Zeroizing::new);
 
         deferred.settle_with(&channel, |mut cx| {
             let key = unwrap_or_throw!(cx, key);
-            Ok(uint_8_array_from_vec_u8(&mut cx, key)?)
+            Ok(uint_8_array_from_secret(&mut cx, key)?)
         });
     })
 }
@@ -215,10 +533,11 @@ pub fn export_extract_key(mut cx: FunctionContext) -> JsResult<JsPromise> {
 ///
 /// # Throws
 pub fn export_spec(mut cx: FunctionContext) -> JsResult<JsPromise> {
-    let handle_arc = (**cx.this::<JsKeyHandle>()?).clone();
+    let handle_arc = boxed_this::<KeyHandle>(&mut cx)?;
 
     spawn_promise(&mut cx, move |channel, deferred| {
         let handle = arc_or_poisoned_error_deferred!(&channel, deferred, handle_arc.read());
+        let handle = live_or_deleted_error_deferred!(&channel, deferred, handle.live());
 
         let spec = handle.spec();
 
@@ -226,13 +545,41 @@ pub fn export_spec(mut cx: FunctionContext) -> JsResult<JsPromise> {
     })
 }
 
+/// Synchronous variant of [export_spec], which reads the spec on the main thread.
+///
+/// # Arguments
+///
+/// # Returns
+/// * `KeySpec` - spec of key
+///
+/// # Throws
+/// * When the lock of the handle is poisoned.
+pub fn export_spec_sync(mut cx: FunctionContext) -> JsResult<JsValue> {
+    let handle_arc = boxed_this::<KeyHandle>(&mut cx)?;
+    let handle = unwrap_or_throw!(cx, rw_lock_poisoned(handle_arc.read()));
+    let handle = match handle.live() {
+        Ok(handle) => handle,
+        Err(err) => return throw_key_deleted_error(&mut cx, &err),
+    };
+
+    let spec = handle.spec();
+
+    Ok(wrap_key_spec(&mut cx, spec)?.upcast())
+}
+
 pub fn export_derive_key(mut cx: FunctionContext) -> JsResult<JsPromise> {
-    let handle_arc = (**cx.this::<JsKeyHandle>()?).clone();
+    let handle_arc = boxed_this::<KeyHandle>(&mut cx)?;
     let nonce_js = cx.argument::<JsUint8Array>(0)?;
     let nonce = vec_from_uint_8_array(&mut cx, nonce_js);
 
     spawn_promise(&mut cx, move |channel, deferred| {
-        let handle = arc_or_poisoned_error_deferred!(&channel, deferred, handle_arc.read());
+        let state = arc_or_poisoned_error_deferred!(&channel, deferred, handle_arc.read());
+        let handle = live_or_deleted_error_deferred!(&channel, deferred, state.live());
+        permitted_or_error_deferred!(
+            &channel,
+            deferred,
+            check_key_policy(handle, state.store.as_deref(), KeyOperation::DeriveKey)
+        );
 
         let derived_key = handle.derive_key(&nonce);
 
@@ -1,11 +1,20 @@
+use crypto_layer::prelude::KeyPairHandle;
 use neon::prelude::*;
+use zeroize::Zeroizing;
 
+use crate::classes::boxed_this;
 use crate::common::{arc_or_poisoned_error_deferred, spawn_promise};
-use crate::error::unwrap_or_throw;
-use crate::fromjs::vec_from_uint_8_array;
+use crate::error::{rw_lock_poisoned, unwrap_or_throw};
+use crate::fromjs::{
+    vec_from_uint_8_array, vec_from_uint_8_array_array, vec_from_uint_8_array_tuple_array,
+};
+use crate::policy::{check_key_policy, permitted_or_error_deferred, KeyOperation};
+use crate::runtime::parallel_map;
+use crate::tombstone::{live_or_deleted_error_deferred, throw_key_deleted_error};
 use crate::tojs::config::wrap_key_pair_spec;
-use crate::tojs::uint_8_array_from_vec_u8;
-use crate::{box_if_ok, JsKeyPairHandle};
+use crate::tojs::metadata::wrap_optional_key_metadata;
+use crate::tojs::{uint_8_array_from_secret, uint_8_array_from_vec_u8, wrap_batch_results};
+use crate::box_if_ok;
 
 /// Wraps `sign_data` function.
 ///
@@ -18,13 +27,19 @@ use crate::{box_if_ok, JsKeyPairHandle};
 /// # Throws
 /// * When failing to execute.
 pub fn export_sign_data(mut cx: FunctionContext) -> JsResult<JsPromise> {
-    let handle_arc = (**cx.this::<JsKeyPairHandle>()?).clone();
+    let handle_arc = boxed_this::<KeyPairHandle>(&mut cx)?;
 
     let data_js = cx.argument::<JsUint8Array>(0)?;
     let data = vec_from_uint_8_array(&mut cx, data_js);
 
     spawn_promise(&mut cx, move |channel, deferred| {
-        let handle = arc_or_poisoned_error_deferred!(&channel, deferred, handle_arc.read());
+        let state = arc_or_poisoned_error_deferred!(&channel, deferred, handle_arc.read());
+        let handle = live_or_deleted_error_deferred!(&channel, deferred, state.live());
+        permitted_or_error_deferred!(
+            &channel,
+            deferred,
+            check_key_policy(handle, state.store.as_deref(), KeyOperation::Sign)
+        );
 
         let signature = handle.sign_data(&data);
 
@@ -47,7 +62,7 @@ pub fn export_sign_data(mut cx: FunctionContext) -> JsResult<JsPromise> {
 /// # Throws
 /// * When failing to execute.
 pub fn export_verify_data(mut cx: FunctionContext) -> JsResult<JsPromise> {
-    let handle_arc = (**cx.this::<JsKeyPairHandle>()?).clone();
+    let handle_arc = boxed_this::<KeyPairHandle>(&mut cx)?;
     let data_js = cx.argument::<JsUint8Array>(0)?;
     let signature_js = cx.argument::<JsUint8Array>(1)?;
 
@@ -55,7 +70,13 @@ pub fn export_verify_data(mut cx: FunctionContext) -> JsResult<JsPromise> {
     let signature = vec_from_uint_8_array(&mut cx, signature_js);
 
     spawn_promise(&mut cx, move |channel, deferred| {
-        let handle = arc_or_poisoned_error_deferred!(&channel, deferred, handle_arc.read());
+        let state = arc_or_poisoned_error_deferred!(&channel, deferred, handle_arc.read());
+        let handle = live_or_deleted_error_deferred!(&channel, deferred, state.live());
+        permitted_or_error_deferred!(
+            &channel,
+            deferred,
+            check_key_policy(handle, state.store.as_deref(), KeyOperation::Verify)
+        );
 
         let res = handle.verify_signature(&data, &signature);
 
@@ -66,6 +87,82 @@ pub fn export_verify_data(mut cx: FunctionContext) -> JsResult<JsPromise> {
     })
 }
 
+/// Wraps `sign_data` function for many messages at once.
+///
+/// The handle is locked once and the messages are signed in parallel.
+///
+/// # Arguments
+/// * **data**: `Uint8Array[]`
+///
+/// # Returns
+/// * `BatchResult<Uint8Array>[]` - per message result
+///
+/// # Throws
+/// * When one of the inputs is incorrect.
+pub fn export_sign_many(mut cx: FunctionContext) -> JsResult<JsPromise> {
+    let handle_arc = boxed_this::<KeyPairHandle>(&mut cx)?;
+    let items_js = cx.argument::<JsArray>(0)?;
+    let items = unwrap_or_throw!(cx, vec_from_uint_8_array_array(&mut cx, items_js));
+
+    spawn_promise(&mut cx, move |channel, deferred| {
+        let state = arc_or_poisoned_error_deferred!(&channel, deferred, handle_arc.read());
+        let handle = live_or_deleted_error_deferred!(&channel, deferred, state.live());
+        permitted_or_error_deferred!(
+            &channel,
+            deferred,
+            check_key_policy(handle, state.store.as_deref(), KeyOperation::Sign)
+        );
+
+        let handle = handle.clone();
+        let results = parallel_map(items, move |data| handle.sign_data(&data));
+
+        deferred.settle_with(&channel, |mut cx| {
+            wrap_batch_results(&mut cx, results, |cx, signature| {
+                Ok(uint_8_array_from_vec_u8(cx, signature)?.upcast())
+            })
+        });
+    })
+}
+
+/// Wraps `verify_signature` function for many messages at once.
+///
+/// The handle is locked once and the signatures are verified in parallel.
+///
+/// # Arguments
+/// * **items**: `[Uint8Array, Uint8Array][]` - data and signature
+///
+/// # Returns
+/// * `BatchResult<boolean>[]` - per message result
+///
+/// # Throws
+/// * When one of the inputs is incorrect.
+pub fn export_verify_many(mut cx: FunctionContext) -> JsResult<JsPromise> {
+    let handle_arc = boxed_this::<KeyPairHandle>(&mut cx)?;
+    let items_js = cx.argument::<JsArray>(0)?;
+    let items = unwrap_or_throw!(cx, vec_from_uint_8_array_tuple_array(&mut cx, items_js));
+
+    spawn_promise(&mut cx, move |channel, deferred| {
+        let state = arc_or_poisoned_error_deferred!(&channel, deferred, handle_arc.read());
+        let handle = live_or_deleted_error_deferred!(&channel, deferred, state.live());
+        permitted_or_error_deferred!(
+            &channel,
+            deferred,
+            check_key_policy(handle, state.store.as_deref(), KeyOperation::Verify)
+        );
+
+        let handle = handle.clone();
+        let results = parallel_map(items, move |(data, signature)| {
+            handle.verify_signature(&data, &signature)
+        });
+
+        deferred.settle_with(&channel, |mut cx| {
+            wrap_batch_results(&mut cx, results, |cx, verified| {
+                Ok(cx.boolean(verified).upcast())
+            })
+        });
+    })
+}
+
 /// Wraps `id` function.
 ///
 /// # Arguments
@@ -76,10 +173,11 @@ pub fn export_verify_data(mut cx: FunctionContext) -> JsResult<JsPromise> {
 /// # Throws
 /// * When failing to execute.
 pub fn export_id(mut cx: FunctionContext) -> JsResult<JsPromise> {
-    let handle_arc = (**cx.this::<JsKeyPairHandle>()?).clone();
+    let handle_arc = boxed_this::<KeyPairHandle>(&mut cx)?;
 
     spawn_promise(&mut cx, move |channel, deferred| {
         let handle = arc_or_poisoned_error_deferred!(&channel, deferred, handle_arc.read());
+        let handle = live_or_deleted_error_deferred!(&channel, deferred, handle.live());
 
         let id = handle.id();
 
@@ -90,8 +188,62 @@ pub fn export_id(mut cx: FunctionContext) -> JsResult<JsPromise> {
     })
 }
 
+/// Synchronous variant of [export_id], which reads the id on the main thread.
+///
+/// # Arguments
+///
+/// # Returns
+/// * `string` - id of key pair
+///
+/// # Throws
+/// * When the lock of the handle is poisoned.
+/// * When failing to execute.
+pub fn export_id_sync(mut cx: FunctionContext) -> JsResult<JsValue> {
+    let handle_arc = boxed_this::<KeyPairHandle>(&mut cx)?;
+    let handle = unwrap_or_throw!(cx, rw_lock_poisoned(handle_arc.read()));
+    let handle = match handle.live() {
+        Ok(handle) => handle,
+        Err(err) => return throw_key_deleted_error(&mut cx, &err),
+    };
+
+    let id = unwrap_or_throw!(cx, handle.id());
+
+    Ok(cx.string(id).upcast())
+}
+
+/// Returns the metadata stored with the key pair on creation or import.
+///
+/// # Arguments
+///
+/// # Returns
+/// * `KeyMetadata` - label, tags and timestamps
+/// * `null` - if the key pair was created without metadata
+///
+/// # Throws
+/// * When failing to execute.
+/// * `KeyDeletedError` when the key was deleted.
+pub fn export_metadata(mut cx: FunctionContext) -> JsResult<JsPromise> {
+    let handle_arc = boxed_this::<KeyPairHandle>(&mut cx)?;
+
+    spawn_promise(&mut cx, move |channel, deferred| {
+        let state = arc_or_poisoned_error_deferred!(&channel, deferred, handle_arc.read());
+        let handle = live_or_deleted_error_deferred!(&channel, deferred, state.live());
+
+        let metadata = handle
+            .id()
+            .map(|id| state.store.as_ref().and_then(|store| store.get(&id)));
+
+        deferred.settle_with(&channel, |mut cx| {
+            let metadata = unwrap_or_throw!(cx, metadata);
+            wrap_optional_key_metadata(&mut cx, metadata)
+        });
+    })
+}
+
 /// Wraps `delete` function.
 ///
+/// Afterwards every call on this handle and on other handles of the same key rejects with a `KeyDeletedError`.
+///
 /// # Arguments
 ///
 /// # Returns
@@ -99,13 +251,14 @@ pub fn export_id(mut cx: FunctionContext) -> JsResult<JsPromise> {
 ///
 /// # Throws
 /// * When failing to execute.
+/// * `KeyDeletedError` when the key was already deleted.
 pub fn export_delete(mut cx: FunctionContext) -> JsResult<JsPromise> {
-    let handle_arc = (**cx.this::<JsKeyPairHandle>()?).clone();
+    let handle_arc = boxed_this::<KeyPairHandle>(&mut cx)?;
 
     spawn_promise(&mut cx, move |channel, deferred| {
-        let handle = arc_or_poisoned_error_deferred!(&channel, deferred, handle_arc.read());
+        let mut handle = arc_or_poisoned_error_deferred!(&channel, deferred, handle_arc.write());
 
-        let result = handle.clone().delete();
+        let result = live_or_deleted_error_deferred!(&channel, deferred, handle.delete());
 
         deferred.settle_with(&channel, |mut cx| {
             unwrap_or_throw!(cx, result);
@@ -125,12 +278,18 @@ pub fn export_delete(mut cx: FunctionContext) -> JsResult<JsPromise> {
 /// # Throws
 /// * When failing to execute.
 pub fn export_encrypt_data(mut cx: FunctionContext) -> JsResult<JsPromise> {
-    let handle_arc = (**cx.this::<JsKeyPairHandle>()?).clone();
+    let handle_arc = boxed_this::<KeyPairHandle>(&mut cx)?;
     let data_js = cx.argument::<JsUint8Array>(0)?;
     let data = vec_from_uint_8_array(&mut cx, data_js);
 
     spawn_promise(&mut cx, move |channel, deferred| {
-        let handle = arc_or_poisoned_error_deferred!(&channel, deferred, handle_arc.read());
+        let state = arc_or_poisoned_error_deferred!(&channel, deferred, handle_arc.read());
+        let handle = live_or_deleted_error_deferred!(&channel, deferred, state.live());
+        permitted_or_error_deferred!(
+            &channel,
+            deferred,
+            check_key_policy(handle, state.store.as_deref(), KeyOperation::Encrypt)
+        );
 
         let encrypted_data = handle.encrypt_data(&data);
 
@@ -152,12 +311,18 @@ pub fn export_encrypt_data(mut cx: FunctionContext) -> JsResult<JsPromise> {
 /// # Throws
 /// * When failing to execute.
 pub fn export_decrypt_data(mut cx: FunctionContext) -> JsResult<JsPromise> {
-    let handle_arc = (**cx.this::<JsKeyPairHandle>()?).clone();
+    let handle_arc = boxed_this::<KeyPairHandle>(&mut cx)?;
     let data_js = cx.argument::<JsUint8Array>(0)?;
     let data = vec_from_uint_8_array(&mut cx, data_js);
 
     spawn_promise(&mut cx, move |channel, deferred| {
-        let handle = arc_or_poisoned_error_deferred!(&channel, deferred, handle_arc.read());
+        let state = arc_or_poisoned_error_deferred!(&channel, deferred, handle_arc.read());
+        let handle = live_or_deleted_error_deferred!(&channel, deferred, state.live());
+        permitted_or_error_deferred!(
+            &channel,
+            deferred,
+            check_key_policy(handle, state.store.as_deref(), KeyOperation::Decrypt)
+        );
 
         let decrypted_data = handle.decrypt_data(&data);
 
@@ -178,10 +343,11 @@ pub fn export_decrypt_data(mut cx: FunctionContext) -> JsResult<JsPromise> {
 /// # Throws
 /// * When failing to execute.
 pub fn export_get_public_key(mut cx: FunctionContext) -> JsResult<JsPromise> {
-    let handle_arc = (**cx.this::<JsKeyPairHandle>()?).clone();
+    let handle_arc = boxed_this::<KeyPairHandle>(&mut cx)?;
 
     spawn_promise(&mut cx, move |channel, deferred| {
         let handle = arc_or_poisoned_error_deferred!(&channel, deferred, handle_arc.read());
+        let handle = live_or_deleted_error_deferred!(&channel, deferred, handle.live());
 
         let public_key = handle.get_public_key();
 
@@ -202,16 +368,17 @@ pub fn export_get_public_key(mut cx: FunctionContext) -> JsResult<JsPromise> {
 /// # Throws
 /// * When failing to execute.
 pub fn export_extract_key(mut cx: FunctionContext) -> JsResult<JsPromise> {
-    let handle_arc = (**cx.this::<JsKeyPairHandle>()?).clone();
+    let handle_arc = boxed_this::<KeyPairHandle>(&mut cx)?;
 
     spawn_promise(&mut cx, move |channel, deferred| {
         let handle = arc_or_poisoned_error_deferred!(&channel, deferred, handle_arc.read());
+        let handle = live_or_deleted_error_deferred!(&channel, deferred, handle.live());
 
-        let private_key = handle.extract_key();
+        let private_key = handle.extract_key().map(Zeroizing::new);
 
         deferred.settle_with(&channel, |mut cx| {
             let private_key = unwrap_or_throw!(cx, private_key);
-            Ok(uint_8_array_from_vec_u8(&mut cx, private_key)?)
+            Ok(uint_8_array_from_secret(&mut cx, private_key)?)
         });
     })
 }
@@ -225,10 +392,11 @@ pub fn export_extract_key(mut cx: FunctionContext) -> JsResult<JsPromise> {
 ///
 /// # Throws
 pub fn export_spec(mut cx: FunctionContext) -> JsResult<JsPromise> {
-    let handle_arc = (**cx.this::<JsKeyPairHandle>()?).clone();
+    let handle_arc = boxed_this::<KeyPairHandle>(&mut cx)?;
 
     spawn_promise(&mut cx, move |channel, deferred| {
         let handle = arc_or_poisoned_error_deferred!(&channel, deferred, handle_arc.read());
+        let handle = live_or_deleted_error_deferred!(&channel, deferred, handle.live());
 
         let spec = handle.spec();
 
@@ -236,6 +404,28 @@ pub fn export_spec(mut cx: FunctionContext) -> JsResult<JsPromise> {
     })
 }
 
+/// Synchronous variant of [export_spec], which reads the spec on the main thread.
+///
+/// # Arguments
+///
+/// # Returns
+/// * `KeyPairSpec` - spec of key pair
+///
+/// # Throws
+/// * When the lock of the handle is poisoned.
+pub fn export_spec_sync(mut cx: FunctionContext) -> JsResult<JsValue> {
+    let handle_arc = boxed_this::<KeyPairHandle>(&mut cx)?;
+    let handle = unwrap_or_throw!(cx, rw_lock_poisoned(handle_arc.read()));
+    let handle = match handle.live() {
+        Ok(handle) => handle,
+        Err(err) => return throw_key_deleted_error(&mut cx, &err),
+    };
+
+    let spec = handle.spec();
+
+    Ok(wrap_key_pair_spec(&mut cx, spec)?.upcast())
+}
+
 /// Wraps `start_dh_exchange` function.
 ///
 /// # Arguments
@@ -246,10 +436,16 @@ pub fn export_spec(mut cx: FunctionContext) -> JsResult<JsPromise> {
 /// # Throws
 /// * When failing to start the dh exchange.
 pub fn export_start_dh_exchange(mut cx: FunctionContext) -> JsResult<JsPromise> {
-    let handle_arc = (**cx.this::<JsKeyPairHandle>()?).clone();
+    let handle_arc = boxed_this::<KeyPairHandle>(&mut cx)?;
 
     spawn_promise(&mut cx, move |channel, deferred| {
-        let handle = arc_or_poisoned_error_deferred!(&channel, deferred, handle_arc.read());
+        let state = arc_or_poisoned_error_deferred!(&channel, deferred, handle_arc.read());
+        let handle = live_or_deleted_error_deferred!(&channel, deferred, state.live());
+        permitted_or_error_deferred!(
+            &channel,
+            deferred,
+            check_key_policy(handle, state.store.as_deref(), KeyOperation::DhExchange)
+        );
 
         let dh_exchange = handle.start_dh_exchange();
 
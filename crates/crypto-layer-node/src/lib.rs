@@ -1,5 +1,3 @@
-use std::sync::{Arc, RwLock};
-
 use crypto_layer::prelude::*;
 use neon::prelude::*;
 use tracing_subscriber::{
@@ -8,27 +6,45 @@ use tracing_subscriber::{
     fmt::format::FmtSpan,
 };
 
+pub(crate) mod abort;
+pub(crate) mod backup;
+pub(crate) mod classes;
+pub(crate) mod commitment;
 pub(crate) mod common;
 pub(crate) mod dhexchange;
 pub(crate) mod fromjs;
 pub(crate) mod keyhandle;
 pub(crate) mod keypairhandle;
+pub(crate) mod listing;
+pub(crate) mod metadata;
+pub(crate) mod migration;
+pub(crate) mod nonce;
+pub(crate) mod policy;
 pub(crate) mod provider;
+pub(crate) mod registry;
+pub(crate) mod rotation;
+pub(crate) mod runtime;
+pub(crate) mod selection;
+pub(crate) mod spec;
+pub(crate) mod storage_password;
 pub(crate) mod tojs;
+pub(crate) mod tombstone;
+pub(crate) mod verification;
 
-use crate::common::{box_if_ok, spawn_promise, Finalized};
-use crate::fromjs::error::unwrap_or_throw;
+use crate::classes::{define_class, init_classes, instance_from_boxed, Boxed};
+use crate::common::spawn_promise;
+use crate::fromjs::error::{unwrap_or_throw, unwrap_or_throw_conversion};
+use crate::provider::new_boxed_provider;
+use crate::registry::acquire_provider;
+use crate::selection::{rank_providers, throw_no_matching_provider_error};
+use crate::storage_password::resolve_or_throw_deferred;
 use fromjs::config::*;
 use fromjs::*;
-use tojs::config::wrap_provider_config;
+use tojs::config::{wrap_provider_config, wrap_provider_evaluation};
 use tojs::*;
 
-type BoxedKeyHandle = Arc<RwLock<Finalized<KeyHandle>>>;
-type JsKeyHandle = JsBox<BoxedKeyHandle>;
-type BoxedKeyPairHandle = Arc<RwLock<Finalized<KeyPairHandle>>>;
-type JsKeyPairHandle = JsBox<BoxedKeyPairHandle>;
-type JsProvider = JsBox<Arc<RwLock<Finalized<Provider>>>>;
-type JsDhExchange = JsBox<Arc<RwLock<Finalized<DHExchange>>>>;
+type BoxedKeyHandle = Boxed<KeyHandle>;
+type BoxedKeyPairHandle = Boxed<KeyPairHandle>;
 
 /// Wraps `get_all_providers` function.
 ///
@@ -48,6 +64,7 @@ fn export_get_all_providers(mut cx: FunctionContext) -> JsResult<JsPromise> {
 /// # Arguments
 /// * **config**: `ProviderConfig`
 /// * **impl_config**: `ProviderImplConfig`
+/// * **options**: `{ explain?: boolean } | undefined`
 ///
 /// # Returns
 /// * `{}` - bare provider on success
@@ -55,20 +72,52 @@ fn export_get_all_providers(mut cx: FunctionContext) -> JsResult<JsPromise> {
 ///
 /// # Throws
 /// * When one of the inputs is incorrect.
+/// * When the storage key of a `StoragePassword` cannot be derived.
+/// * When the key metadata file of the storage fails verification with the storage key.
+/// * `ProviderSelectionError` with the ranked evaluations of all providers,
+///   if no provider matches and `explain` is set.
 #[tracing::instrument(level = "trace", skip(cx))]
 fn export_create_provider(mut cx: FunctionContext) -> JsResult<JsPromise> {
     let config_js = cx.argument::<JsObject>(0)?;
     let impl_config_js = cx.argument::<JsObject>(1)?;
 
-    let config = unwrap_or_throw!(cx, from_wrapped_provider_config(&mut cx, config_js));
-    let impl_config = unwrap_or_throw!(
+    let config = unwrap_or_throw_conversion!(
+        cx,
+        "config",
+        from_wrapped_provider_config(&mut cx, config_js)
+    );
+    let impl_config = unwrap_or_throw_conversion!(
         cx,
+        "impl_config",
         from_wrapped_provider_impl_config(&mut cx, impl_config_js)
     );
+    let explain = unwrap_or_throw!(cx, flag_from_options_argument(&mut cx, 2, "explain"));
 
     spawn_promise(&mut cx, move |channel, deferred| {
+        let resolved = resolve_or_throw_deferred!(&channel, deferred, impl_config);
+        let impl_config = resolved.impl_config;
         match create_provider(&config, impl_config.clone()) {
-            Some(prov) => deferred.settle_with(&channel, |mut cx| box_if_ok(&mut cx, Ok(prov))),
+            Some(prov) => match new_boxed_provider(prov, &impl_config, resolved.storage_key) {
+                Ok(prov) => deferred.settle_with(&channel, |mut cx| {
+                    instance_from_boxed::<Provider>(&mut cx, prov)
+                }),
+                Err(err) => {
+                    let message = err.to_string();
+                    deferred.settle_with(&channel, move |mut cx| {
+                        cx.throw_error::<_, Handle<JsValue>>(message)
+                    })
+                }
+            },
+            None if explain => {
+                let evaluations = rank_providers(
+                    &config,
+                    get_all_providers(),
+                    get_provider_capabilities(impl_config),
+                );
+                deferred.settle_with(&channel, move |mut cx| {
+                    throw_no_matching_provider_error::<_, JsValue>(&mut cx, evaluations)
+                })
+            }
             None => deferred.settle_with(&channel, |mut cx| Ok(cx.undefined())),
         };
     })
@@ -76,6 +125,10 @@ fn export_create_provider(mut cx: FunctionContext) -> JsResult<JsPromise> {
 
 /// Wraps `create_provider_from_name` function.
 ///
+/// Providers with a `FileStoreConfig` are cached by their `db_dir`, so that the same database is not
+/// opened twice (see [acquire_provider]). Identical `StoragePassword`s derive the same key handle and
+/// thus share a provider as well.
+///
 /// # Arguments
 /// * **name**: `string`
 /// * **impl_config**: `ProviderImplConfig`
@@ -86,21 +139,36 @@ fn export_create_provider(mut cx: FunctionContext) -> JsResult<JsPromise> {
 ///
 /// # Throws
 /// * When one of the inputs is incorrect.
+/// * When the storage key of a `StoragePassword` cannot be derived.
+/// * When the `db_dir` does not exist and cannot be created.
+/// * When a provider of the same `db_dir` is still alive, but was created with another `name`
+///   or other storage keys.
+/// * When the key metadata file of the storage fails verification with the storage key.
 #[tracing::instrument(level = "trace", skip(cx))]
 fn export_create_provider_from_name(mut cx: FunctionContext) -> JsResult<JsPromise> {
     let name_js = cx.argument::<JsString>(0)?;
     let impl_config_js = cx.argument::<JsObject>(1)?;
 
     let name = name_js.value(&mut cx);
-    let impl_config = unwrap_or_throw!(
+    let impl_config = unwrap_or_throw_conversion!(
         cx,
+        "impl_config",
         from_wrapped_provider_impl_config(&mut cx, impl_config_js)
     );
 
     spawn_promise(&mut cx, move |channel, deferred| {
-        match create_provider_from_name(&name, impl_config.clone()) {
-            Some(prov) => deferred.settle_with(&channel, |mut cx| box_if_ok(&mut cx, Ok(prov))),
-            None => deferred.settle_with(&channel, |mut cx| Ok(cx.undefined())),
+        let resolved = resolve_or_throw_deferred!(&channel, deferred, impl_config);
+        match acquire_provider(&name, resolved) {
+            Ok(Some(prov)) => deferred.settle_with(&channel, |mut cx| {
+                instance_from_boxed::<Provider>(&mut cx, prov)
+            }),
+            Ok(None) => deferred.settle_with(&channel, |mut cx| Ok(cx.undefined())),
+            Err(err) => {
+                let message = err.to_string();
+                deferred.settle_with(&channel, move |mut cx| {
+                    cx.throw_error::<_, Handle<JsValue>>(message)
+                })
+            }
         };
     })
 }
@@ -116,13 +184,15 @@ fn export_create_provider_from_name(mut cx: FunctionContext) -> JsResult<JsPromi
 /// # Throws
 fn export_get_provider_capabilities(mut cx: FunctionContext) -> JsResult<JsPromise> {
     let impl_config_js = cx.argument::<JsObject>(0)?;
-    let impl_config = unwrap_or_throw!(
+    let impl_config = unwrap_or_throw_conversion!(
         cx,
+        "impl_config",
         from_wrapped_provider_impl_config(&mut cx, impl_config_js)
     );
 
     spawn_promise(&mut cx, move |channel, deferred| {
-        let provider_caps_list = get_provider_capabilities(impl_config.clone());
+        let impl_config = resolve_or_throw_deferred!(&channel, deferred, impl_config).impl_config;
+        let provider_caps_list = get_provider_capabilities(impl_config);
         deferred.settle_with(&channel, |mut cx| {
             js_array_from_vec(&mut cx, provider_caps_list, |cx, value| {
                 let name = JsString::new(cx, value.0);
@@ -138,6 +208,47 @@ fn export_get_provider_capabilities(mut cx: FunctionContext) -> JsResult<JsPromi
     })
 }
 
+/// Evaluates every provider against the requirements.
+///
+/// # Arguments
+/// * **requirements**: `ProviderConfig`
+/// * **impl_config**: `ProviderImplConfig`
+///
+/// # Returns
+/// * `ProviderEvaluation[]` - all providers, matching ones first, then ranked by the amount of unmet requirements.
+///
+/// # Throws
+/// * When one of the inputs is incorrect.
+fn export_select_providers(mut cx: FunctionContext) -> JsResult<JsPromise> {
+    let requirements_js = cx.argument::<JsObject>(0)?;
+    let impl_config_js = cx.argument::<JsObject>(1)?;
+
+    let requirements = unwrap_or_throw_conversion!(
+        cx,
+        "requirements",
+        from_wrapped_provider_config(&mut cx, requirements_js)
+    );
+    let impl_config = unwrap_or_throw_conversion!(
+        cx,
+        "impl_config",
+        from_wrapped_provider_impl_config(&mut cx, impl_config_js)
+    );
+
+    spawn_promise(&mut cx, move |channel, deferred| {
+        let impl_config = resolve_or_throw_deferred!(&channel, deferred, impl_config).impl_config;
+        let evaluations = rank_providers(
+            &requirements,
+            get_all_providers(),
+            get_provider_capabilities(impl_config),
+        );
+        deferred.settle_with(&channel, |mut cx| {
+            js_array_from_vec(&mut cx, evaluations, |cx, evaluation| {
+                Ok(wrap_provider_evaluation(cx, evaluation)?.upcast())
+            })
+        });
+    })
+}
+
 #[neon::main]
 fn main(mut cx: ModuleContext) -> NeonResult<()> {
     fmt()
@@ -160,116 +271,149 @@ fn main(mut cx: ModuleContext) -> NeonResult<()> {
         export_create_provider_from_name,
     )?;
     cx.export_function("getProviderCapabilities", export_get_provider_capabilities)?;
+    cx.export_function("selectProviders", export_select_providers)?;
+
+    // abort
+    cx.export_function("createAbortToken", crate::abort::export_create_abort_token)?;
+    cx.export_function("abortToken", crate::abort::export_abort)?;
+
+    // runtime
+    cx.export_function("configureRuntime", crate::runtime::export_configure_runtime)?;
+    cx.export_function("getRuntimeMetrics", crate::runtime::export_runtime_metrics)?;
 
     // provider
-    cx.export_function("providerName", crate::provider::export_provider_name)?;
-    cx.export_function("createBareKey", crate::provider::export_create_key)?;
-    cx.export_function("createBareKeyPair", crate::provider::export_create_key_pair)?;
-    cx.export_function("loadBareKey", crate::provider::export_load_key)?;
-    cx.export_function("loadBareKeyPair", crate::provider::export_load_key_pair)?;
-    cx.export_function("importBareKey", crate::provider::export_import_key)?;
-    cx.export_function("importBareKeyPair", crate::provider::export_import_key_pair)?;
-    cx.export_function(
-        "importBarePublicKey",
-        crate::provider::export_import_public_key,
-    )?;
-    cx.export_function("getCapabilities", crate::provider::export_get_capabilities)?;
-    cx.export_function(
-        "startEphemeralDhExchange",
-        crate::provider::export_start_ephemeral_dh_exchange,
-    )?;
-    cx.export_function(
-        "dhExchangeFromKeys",
-        crate::provider::export_dh_exchange_from_keys,
-    )?;
-    cx.export_function(
-        "deriveKeyFromPassword",
-        crate::provider::export_derive_key_from_password,
-    )?;
-    cx.export_function(
-        "deriveKeyFromBase",
-        crate::provider::export_derive_key_from_base,
+    let provider_class = define_class::<Provider>(
+        &mut cx,
+        &[
+            ("providerName", crate::provider::export_provider_name),
+            ("createKey", crate::provider::export_create_key),
+            ("createKeyPair", crate::provider::export_create_key_pair),
+            ("loadKey", crate::provider::export_load_key),
+            ("loadKeyPair", crate::provider::export_load_key_pair),
+            ("importKey", crate::provider::export_import_key),
+            ("importKeyPair", crate::provider::export_import_key_pair),
+            ("importPublicKey", crate::provider::export_import_public_key),
+            ("getCapabilities", crate::provider::export_get_capabilities),
+            ("completeSpec", crate::provider::export_complete_spec),
+            (
+                "startEphemeralDhExchange",
+                crate::provider::export_start_ephemeral_dh_exchange,
+            ),
+            ("dhExchangeFromKeys", crate::provider::export_dh_exchange_from_keys),
+            (
+                "deriveKeyFromPassword",
+                crate::provider::export_derive_key_from_password,
+            ),
+            ("deriveKeyFromBase", crate::provider::export_derive_key_from_base),
+            ("getRandom", crate::provider::export_get_random),
+            ("hash", crate::provider::export_hash),
+            ("hashMany", crate::provider::export_hash_many),
+            ("getAllKeys", crate::provider::export_get_all_keys),
+            ("listKeys", crate::provider::export_list_keys),
+            ("countKeys", crate::provider::export_count_keys),
+            ("rotateKey", crate::provider::export_rotate_key),
+            ("encryptForFamily", crate::provider::export_encrypt_for_family),
+            ("decryptForFamily", crate::provider::export_decrypt_for_family),
+            ("rewrap", crate::provider::export_rewrap),
+            ("exportBackup", crate::provider::export_export_backup),
+            ("importBackup", crate::provider::export_import_backup),
+            ("migrateKeysTo", crate::provider::export_migrate_keys_to),
+            ("verifyStore", crate::provider::export_verify_store),
+            ("repairStore", crate::provider::export_repair_store),
+        ],
+        &[
+            ("nameSync", crate::provider::export_name_sync),
+            ("release", crate::provider::export_release),
+        ],
     )?;
-    cx.export_function("getRandom", crate::provider::export_get_random)?;
-    cx.export_function("hash", crate::provider::export_hash)?;
-    cx.export_function("getAllKeys", crate::provider::export_get_all_keys)?;
 
     // key pair handle
-    cx.export_function("signData", crate::keypairhandle::export_sign_data)?;
-    cx.export_function("verifySignature", crate::keypairhandle::export_verify_data)?;
-    cx.export_function("idForKeyPair", crate::keypairhandle::export_id)?;
-    cx.export_function("deleteForKeyPair", crate::keypairhandle::export_delete)?;
-    cx.export_function("getPublicKey", crate::keypairhandle::export_get_public_key)?;
-    cx.export_function(
-        "extractKeyForKeyPairHandle",
-        crate::keypairhandle::export_extract_key,
-    )?;
-    cx.export_function(
-        "encryptDataForKeyPairHandle",
-        crate::keypairhandle::export_encrypt_data,
-    )?;
-    cx.export_function(
-        "decryptDataForKeyPairHandle",
-        crate::keypairhandle::export_decrypt_data,
-    )?;
-    cx.export_function("specForKeyPairHandle", crate::keypairhandle::export_spec)?;
-    cx.export_function(
-        "startDhExchangeForKeyPairHandle",
-        crate::keypairhandle::export_start_dh_exchange,
+    let key_pair_handle_class = define_class::<KeyPairHandle>(
+        &mut cx,
+        &[
+            ("id", crate::keypairhandle::export_id),
+            ("delete", crate::keypairhandle::export_delete),
+            ("metadata", crate::keypairhandle::export_metadata),
+            ("signData", crate::keypairhandle::export_sign_data),
+            ("verifySignature", crate::keypairhandle::export_verify_data),
+            ("signMany", crate::keypairhandle::export_sign_many),
+            ("verifyMany", crate::keypairhandle::export_verify_many),
+            ("encryptData", crate::keypairhandle::export_encrypt_data),
+            ("decryptData", crate::keypairhandle::export_decrypt_data),
+            ("getPublicKey", crate::keypairhandle::export_get_public_key),
+            ("extractKey", crate::keypairhandle::export_extract_key),
+            ("spec", crate::keypairhandle::export_spec),
+            ("startDhExchange", crate::keypairhandle::export_start_dh_exchange),
+        ],
+        &[
+            ("idSync", crate::keypairhandle::export_id_sync),
+            ("specSync", crate::keypairhandle::export_spec_sync),
+        ],
     )?;
 
     // key handle
-    cx.export_function("idForKeyHandle", crate::keyhandle::export_id)?;
-    cx.export_function("deleteForKeyHandle", crate::keyhandle::export_delete)?;
-    cx.export_function(
-        "extractKeyForKeyHandle",
-        crate::keyhandle::export_extract_key,
-    )?;
-    cx.export_function(
-        "encryptDataForKeyHandle",
-        crate::keyhandle::export_encrypt_data,
-    )?;
-    cx.export_function("encryptForKeyHandle", crate::keyhandle::export_encrypt)?;
-    cx.export_function(
-        "encryptWithIvForKeyHandle",
-        crate::keyhandle::export_encrypt_with_iv,
-    )?;
-    cx.export_function(
-        "decryptDataForKeyHandle",
-        crate::keyhandle::export_decrypt_data,
+    let key_handle_class = define_class::<KeyHandle>(
+        &mut cx,
+        &[
+            ("id", crate::keyhandle::export_id),
+            ("delete", crate::keyhandle::export_delete),
+            ("metadata", crate::keyhandle::export_metadata),
+            ("extractKey", crate::keyhandle::export_extract_key),
+            ("encryptData", crate::keyhandle::export_encrypt_data),
+            ("encrypt", crate::keyhandle::export_encrypt),
+            ("encryptWithIv", crate::keyhandle::export_encrypt_with_iv),
+            ("encryptInto", crate::keyhandle::export_encrypt_into),
+            ("decryptData", crate::keyhandle::export_decrypt_data),
+            ("decryptInto", crate::keyhandle::export_decrypt_into),
+            ("encryptMany", crate::keyhandle::export_encrypt_many),
+            ("decryptMany", crate::keyhandle::export_decrypt_many),
+            ("spec", crate::keyhandle::export_spec),
+            ("deriveKey", crate::keyhandle::export_derive_key),
+        ],
+        &[
+            ("idSync", crate::keyhandle::export_id_sync),
+            ("specSync", crate::keyhandle::export_spec_sync),
+        ],
     )?;
-    cx.export_function("specForKeyHandle", crate::keyhandle::export_spec)?;
-    cx.export_function("deriveKeyForKeyHandle", crate::keyhandle::export_derive_key)?;
 
     // dh exchange
-    cx.export_function(
-        "getPublicKeyForDHExchange",
-        crate::dhexchange::export_get_public_key,
-    )?;
-    /* cx.export_function(
-        "addExternalForDHExchange",
-        crate::dhexchange::export_add_external,
-    )?;
-    cx.export_function(
-        "addExternalFinalForDHExchange",
-        crate::dhexchange::export_add_external_final,
-    )?; */
-    cx.export_function(
-        "deriveClientSessionKeys",
-        crate::dhexchange::export_derive_client_session_keys,
-    )?;
-    cx.export_function(
-        "deriveServerSessionKeys",
-        crate::dhexchange::export_derive_server_session_keys,
+    let dh_exchange_class = define_class::<DHExchange>(
+        &mut cx,
+        &[
+            ("getPublicKey", crate::dhexchange::export_get_public_key),
+            /* ("addExternal", crate::dhexchange::export_add_external),
+            ("addExternalFinal", crate::dhexchange::export_add_external_final), */
+            (
+                "deriveClientSessionKeys",
+                crate::dhexchange::export_derive_client_session_keys,
+            ),
+            (
+                "deriveServerSessionKeys",
+                crate::dhexchange::export_derive_server_session_keys,
+            ),
+            (
+                "deriveClientKeyHandles",
+                crate::dhexchange::export_derive_client_key_handles,
+            ),
+            (
+                "deriveServerKeyHandles",
+                crate::dhexchange::export_derive_server_key_handles,
+            ),
+        ],
+        &[],
     )?;
-    cx.export_function(
-        "deriveClientKeyHandles",
-        crate::dhexchange::export_derive_client_key_handles,
-    )?;
-    cx.export_function(
-        "deriveServerKeyHandles",
-        crate::dhexchange::export_derive_server_key_handles,
+
+    init_classes(
+        &mut cx,
+        provider_class,
+        key_handle_class,
+        key_pair_handle_class,
+        dh_exchange_class,
     )?;
+    cx.export_value("Provider", provider_class)?;
+    cx.export_value("KeyHandle", key_handle_class)?;
+    cx.export_value("KeyPairHandle", key_pair_handle_class)?;
+    cx.export_value("DHExchange", dh_exchange_class)?;
 
     load_function_span.exit();
     tracing::trace!("crypto-layer loaded.");
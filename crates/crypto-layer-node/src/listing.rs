@@ -0,0 +1,139 @@
+use std::collections::HashSet;
+
+use crypto_layer::common::config::Spec;
+use crypto_layer::prelude::*;
+
+use crate::metadata::MetadataStore;
+
+/// Amount of keys returned by `listKeys` without `limit`.
+pub(crate) const DEFAULT_PAGE_LIMIT: usize = 100;
+
+/// Whether a key is a symmetric key or a key pair.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum KeyKind {
+    Key,
+    KeyPair,
+}
+
+impl KeyKind {
+    pub(crate) const VARIANTS: &'static [&'static str] = &["Key", "KeyPair"];
+
+    pub(crate) fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "Key" => Some(KeyKind::Key),
+            "KeyPair" => Some(KeyKind::KeyPair),
+            _ => None,
+        }
+    }
+}
+
+/// Criteria a key must meet to be listed or counted. Missing criteria match every key.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct KeyFilter {
+    pub kind: Option<KeyKind>,
+    pub cipher: Option<Cipher>,
+    pub asym_spec: Option<AsymmetricKeySpec>,
+    /// Tag of the key metadata. Keys without metadata never match.
+    pub tag: Option<String>,
+    pub ephemeral: Option<bool>,
+}
+
+impl KeyFilter {
+    /// Prepares matching keys against the metadata of `metadata`.
+    ///
+    /// The metadata is read once here instead of once per key.
+    pub(crate) fn matcher(&self, metadata: &MetadataStore) -> KeyMatcher<'_> {
+        KeyMatcher {
+            filter: self,
+            tagged: self.tag.as_ref().map(|tag| metadata.tagged_ids(tag)),
+        }
+    }
+}
+
+/// [KeyFilter] bound to the metadata of one storage.
+pub(crate) struct KeyMatcher<'a> {
+    filter: &'a KeyFilter,
+    /// Ids of the keys with the tag of the filter.
+    tagged: Option<HashSet<String>>,
+}
+
+impl KeyMatcher<'_> {
+    pub(crate) fn matches(&self, id: &str, spec: &Spec) -> bool {
+        let (kind, cipher, asym_spec, ephemeral) = match spec {
+            Spec::KeySpec(spec) => (KeyKind::Key, Some(&spec.cipher), None, spec.ephemeral),
+            Spec::KeyPairSpec(spec) => (
+                KeyKind::KeyPair,
+                spec.cipher.as_ref(),
+                Some(&spec.asym_spec),
+                spec.ephemeral,
+            ),
+        };
+
+        let filter = self.filter;
+        filter.kind.is_none_or(|expected| expected == kind)
+            && filter.cipher.as_ref().is_none_or(|expected| Some(expected) == cipher)
+            && filter.asym_spec.as_ref().is_none_or(|expected| Some(expected) == asym_spec)
+            && filter.ephemeral.is_none_or(|expected| expected == ephemeral)
+            && self.tagged.as_ref().is_none_or(|tagged| tagged.contains(id))
+    }
+}
+
+/// Page of keys returned by [list_keys].
+#[derive(Debug)]
+pub(crate) struct KeyPage {
+    pub keys: Vec<(String, Spec)>,
+    /// Passed as `cursor` to continue the listing. `None` on the last page.
+    pub cursor: Option<String>,
+}
+
+/// Returns up to `limit` keys of `keys` matching `filter`, which follow the key `cursor`.
+///
+/// Keys are ordered by id and the cursor is the id of the last key of the previous page,
+/// thus keys created or deleted between two calls neither shift nor repeat other keys.
+///
+/// `crypto-layer` can only enumerate every key of a storage, thus each page still costs
+/// one enumeration and one pass over all keys. Only the matching keys following the cursor are ordered,
+/// and only up to the end of the page, so that paging saves the sorting and the conversion to JS.
+pub(crate) fn list_keys(
+    keys: Vec<(String, Spec)>,
+    filter: &KeyFilter,
+    metadata: &MetadataStore,
+    cursor: Option<&str>,
+    limit: usize,
+) -> KeyPage {
+    let matcher = filter.matcher(metadata);
+    let mut matching: Vec<(String, Spec)> = keys
+        .into_iter()
+        .filter(|(id, _)| cursor.is_none_or(|cursor| id.as_str() > cursor))
+        .filter(|(id, spec)| matcher.matches(id, spec))
+        .collect();
+
+    // One key more than the page, to know whether another page follows.
+    if limit < matching.len() {
+        matching.select_nth_unstable_by(limit, |(a, _), (b, _)| a.cmp(b));
+        matching.truncate(limit + 1);
+    }
+    matching.sort_unstable_by(|(a, _), (b, _)| a.cmp(b));
+
+    let has_next_page = matching.len() > limit;
+    matching.truncate(limit);
+    let cursor = match matching.last() {
+        Some((last_id, _)) if has_next_page => Some(last_id.clone()),
+        _ => None,
+    };
+
+    KeyPage {
+        keys: matching,
+        cursor,
+    }
+}
+
+/// Counts the keys matching `filter`.
+pub(crate) fn count_keys(
+    keys: &[(String, Spec)],
+    filter: &KeyFilter,
+    metadata: &MetadataStore,
+) -> usize {
+    let matcher = filter.matcher(metadata);
+    keys.iter().filter(|(id, spec)| matcher.matches(id, spec)).count()
+}
@@ -0,0 +1,929 @@
+use std::collections::{HashMap, HashSet};
+use std::fs::{self, OpenOptions};
+use std::io::{ErrorKind, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard, OnceLock, PoisonError, Weak};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crypto_layer::common::error::CalError;
+use crypto_layer::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::nonce::EncryptionLimits;
+use crate::policy::KeyOperation;
+use crate::tombstone::Deletable;
+
+/// Name of the file holding the metadata of all keys of a file store.
+const METADATA_FILE_NAME: &str = "crypto-layer-node-key-metadata.json";
+
+/// Name of the file holding metadata entries moved aside by [MetadataStore::quarantine].
+const QUARANTINE_FILE_NAME: &str = "crypto-layer-node-key-metadata.quarantine.json";
+
+/// Name of the append-only log of the caller supplied ivs of keys with `strict_iv_check`.
+///
+/// One JSON object per line, see [UsedIv].
+const IV_LOG_FILE_NAME: &str = "crypto-layer-node-key-metadata.ivs.jsonl";
+
+/// Name of the file locked while the metadata file or the iv log is read and changed.
+const LOCK_FILE_NAME: &str = "crypto-layer-node-key-metadata.lock";
+
+/// Prepended to the content of protected files before it is authenticated,
+/// so that tags and signatures of the metadata cannot be confused with those of other data.
+const PROTECTION_DOMAIN: &[u8] = b"crypto-layer-node key metadata v1\n";
+
+/// Information stored alongside a key, which helps telling keys apart.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct KeyMetadata {
+    pub label: Option<String>,
+    #[serde(default)]
+    pub tags: Vec<String>,
+    /// Milliseconds since the unix epoch.
+    pub created_at: u64,
+    /// Milliseconds since the unix epoch.
+    pub expires_at: Option<u64>,
+    #[serde(default)]
+    pub validity: Validity,
+    /// Operations the key may be used for, enforced by [crate::policy::check_key_policy].
+    /// Unrestricted if `None`.
+    #[serde(default)]
+    pub usages: Option<Vec<KeyOperation>>,
+    /// Set for keys, which were rotated or created by rotation.
+    #[serde(default)]
+    pub family: Option<FamilyMember>,
+    /// Limits of [crate::nonce::record_encryptions]. Defaults depend on the cipher if `None`.
+    #[serde(default)]
+    pub encryption_limits: Option<EncryptionLimits>,
+    /// Encryptions reserved by [crate::nonce::record_encryptions],
+    /// an upper bound of the encryptions the key performed.
+    #[serde(default)]
+    pub encryptions: u64,
+    /// Id of the key in the source provider, set for keys copied by [crate::migration::migrate_keys].
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub migrated_from: Option<String>,
+}
+
+/// Position of a key among the versions of a key family created by [crate::rotation::rotate_key].
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct FamilyMember {
+    /// Id of the first version of the family.
+    pub family_id: String,
+    /// Starts with 1 for the first version.
+    pub version: u32,
+}
+
+/// Period in which a key may be used, enforced by [crate::policy::check_key_policy].
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct Validity {
+    /// Milliseconds since the unix epoch.
+    pub not_before: Option<u64>,
+    /// Milliseconds since the unix epoch.
+    pub not_after: Option<u64>,
+    /// Milliseconds after `not_after`, in which decryption and verification are still allowed.
+    /// No grace period if `None`.
+    pub grace_period: Option<u64>,
+}
+
+/// Metadata given on key creation or import.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct NewKeyMetadata {
+    pub label: Option<String>,
+    pub tags: Vec<String>,
+    pub expires_at: Option<u64>,
+    pub validity: Validity,
+    pub usages: Option<Vec<KeyOperation>>,
+    pub encryption_limits: Option<EncryptionLimits>,
+}
+
+#[derive(thiserror::Error, Debug)]
+pub(crate) enum MetadataError {
+    #[error("Failed persisting key metadata: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("Failed serializing key metadata: {0}")]
+    Serialization(#[from] serde_json::Error),
+    #[error("Failed protecting key metadata with the storage key: {0}")]
+    Cal(#[from] CalError),
+    #[error(
+        "The key metadata file {} failed its integrity check with the storage key. \
+        It was modified or belongs to another storage key.",
+        .0.display()
+    )]
+    Integrity(PathBuf),
+    #[error("The key metadata of {} is already open with another storage key.", .0.display())]
+    KeyMismatch(PathBuf),
+}
+
+/// Storage key of the provider protecting the metadata file.
+///
+/// The strongest configured key is used: encryption over a MAC over a signature.
+#[derive(Clone)]
+enum FileProtection {
+    /// Neither the provider storage nor the metadata file is protected.
+    None,
+    /// Encrypted with the key of `StorageConfigSymmetricEncryption`.
+    Encryption(KeyHandle),
+    /// Authenticated with the key of `StorageConfigHMAC`.
+    Hmac(KeyHandle),
+    /// Signed with the key pair of `StorageConfigDSA`.
+    Signature(KeyPairHandle),
+}
+
+/// Content of a protected file.
+#[derive(Serialize, Deserialize)]
+#[serde(tag = "protection", rename_all = "camelCase")]
+enum ProtectedFile {
+    Encryption { ciphertext: Vec<u8>, iv: Vec<u8> },
+    Hmac { payload: String, tag: Vec<u8> },
+    Signature { payload: String, signature: Vec<u8> },
+}
+
+fn authenticated(payload: &[u8]) -> Vec<u8> {
+    [PROTECTION_DOMAIN, payload].concat()
+}
+
+impl FileProtection {
+    fn from_impl_config(impl_config: &ProviderImplConfig) -> Self {
+        let mut protection = FileProtection::None;
+        for additional_config in &impl_config.additional_config {
+            protection = match (additional_config, protection) {
+                (AdditionalConfig::StorageConfigSymmetricEncryption(key), _) => {
+                    FileProtection::Encryption(key.clone())
+                }
+                (AdditionalConfig::StorageConfigHMAC(key), FileProtection::None)
+                | (AdditionalConfig::StorageConfigHMAC(key), FileProtection::Signature(_)) => {
+                    FileProtection::Hmac(key.clone())
+                }
+                (AdditionalConfig::StorageConfigDSA(key_pair), FileProtection::None) => {
+                    FileProtection::Signature(key_pair.clone())
+                }
+                (_, protection) => protection,
+            };
+        }
+        protection
+    }
+
+    /// Id of the storage key, `None` without protection.
+    fn key_id(&self) -> Result<Option<String>, CalError> {
+        match self {
+            FileProtection::None => Ok(None),
+            FileProtection::Encryption(key) | FileProtection::Hmac(key) => key.id().map(Some),
+            FileProtection::Signature(key_pair) => key_pair.id().map(Some),
+        }
+    }
+
+    /// Protects `payload` for writing it to a file.
+    fn seal(&self, payload: Vec<u8>) -> Result<Vec<u8>, MetadataError> {
+        let protected = match self {
+            FileProtection::None => return Ok(payload),
+            FileProtection::Encryption(key) => {
+                let (ciphertext, iv) = key.encrypt(&payload)?;
+                ProtectedFile::Encryption { ciphertext, iv }
+            }
+            FileProtection::Hmac(key) => ProtectedFile::Hmac {
+                tag: key.hmac(&authenticated(&payload))?,
+                payload: String::from_utf8_lossy(&payload).into_owned(),
+            },
+            FileProtection::Signature(key_pair) => ProtectedFile::Signature {
+                signature: key_pair.sign_data(&authenticated(&payload))?,
+                payload: String::from_utf8_lossy(&payload).into_owned(),
+            },
+        };
+        Ok(serde_json::to_vec(&protected)?)
+    }
+
+    /// Verifies the content of the file at `path` and returns its payload.
+    ///
+    /// # Errors
+    /// * [MetadataError::Integrity], if the file is not protected as configured or fails verification.
+    fn open(&self, bytes: Vec<u8>, path: &Path) -> Result<Vec<u8>, MetadataError> {
+        if matches!(self, FileProtection::None) {
+            return Ok(bytes);
+        }
+        let integrity_error = || MetadataError::Integrity(path.to_path_buf());
+        let protected: ProtectedFile =
+            serde_json::from_slice(&bytes).map_err(|_| integrity_error())?;
+
+        let verified = match (self, protected) {
+            (FileProtection::Encryption(key), ProtectedFile::Encryption { ciphertext, iv }) => {
+                key.decrypt_data(&ciphertext, &iv).ok()
+            }
+            (FileProtection::Hmac(key), ProtectedFile::Hmac { payload, tag }) => key
+                .verify_hmac(&authenticated(payload.as_bytes()), &tag)?
+                .then(|| payload.into_bytes()),
+            (
+                FileProtection::Signature(key_pair),
+                ProtectedFile::Signature { payload, signature },
+            ) => key_pair
+                .verify_signature(&authenticated(payload.as_bytes()), &signature)?
+                .then(|| payload.into_bytes()),
+            _ => None,
+        };
+        verified.ok_or_else(integrity_error)
+    }
+}
+
+/// Metadata of the keys of one provider storage.
+///
+/// Stores of file backed providers are persisted as JSON next to the database.
+/// The file is encrypted, authenticated or signed with the storage key of the provider, if one is configured,
+/// and only readable with that key. Without storage key it is plain JSON, like the unprotected database.
+/// Stores of other providers only live as long as the provider.
+pub(crate) struct MetadataStore {
+    /// Tells stores apart, e.g. for keying the encryption counters of their keys.
+    instance: u64,
+    path: Option<PathBuf>,
+    protection: FileProtection,
+    entries: Mutex<HashMap<String, KeyMetadata>>,
+    /// Entries of the metadata file, which are no valid [KeyMetadata], kept as read until quarantined.
+    corrupt_entries: Mutex<HashMap<String, CorruptEntry>>,
+    /// Why the metadata file could not be read as a whole.
+    file_error: Option<String>,
+    /// Ids of keys of this storage deleted through any handle.
+    ///
+    /// Lets handles, which were obtained independently of the deleting handle, detect that their key is gone.
+    deleted: Mutex<HashSet<String>>,
+    /// Held by [crate::rotation::rotate_key] from checking the newest version until the new version is recorded.
+    ///
+    /// Providers created with `createProvider` share the store, but not the lock of the provider,
+    /// thus rotations of the same family would otherwise create the same version twice.
+    rotation: Mutex<()>,
+    /// Number of live handles of each ephemeral key of this storage, see [MetadataStore::hold_ephemeral].
+    ephemeral_holds: Mutex<HashMap<String, usize>>,
+}
+
+/// Keeps an ephemeral key recorded as held by a live handle, until it is dropped.
+pub(crate) struct EphemeralHold {
+    store: Arc<MetadataStore>,
+    id: String,
+}
+
+impl Drop for EphemeralHold {
+    fn drop(&mut self) {
+        let mut holds = self.store.ephemeral_holds();
+        if let Some(count) = holds.get_mut(&self.id) {
+            *count -= 1;
+            if *count == 0 {
+                holds.remove(&self.id);
+            }
+        }
+    }
+}
+
+/// Entry of the metadata file, which failed to deserialize.
+#[derive(Debug, Clone)]
+pub(crate) struct CorruptEntry {
+    pub raw: serde_json::Value,
+    pub error: String,
+}
+
+/// Metadata of a key as seen by [crate::policy::check_key_policy].
+pub(crate) enum PolicyMetadata {
+    Found(KeyMetadata),
+    /// Keys without entry are not restricted.
+    Unrestricted,
+    /// The entry is unreadable, thus the restrictions of the key are unknown.
+    Unavailable(String),
+}
+
+/// Line of the iv log, see [IV_LOG_FILE_NAME].
+#[derive(Serialize, Deserialize)]
+struct UsedIv {
+    id: String,
+    iv: Vec<u8>,
+}
+
+/// Splits the entries of a metadata file into valid and corrupt entries.
+fn parse_entries(
+    bytes: &[u8],
+) -> Result<(HashMap<String, KeyMetadata>, HashMap<String, CorruptEntry>), serde_json::Error> {
+    let raw_entries: HashMap<String, serde_json::Value> = serde_json::from_slice(bytes)?;
+    let mut entries = HashMap::new();
+    let mut corrupt_entries = HashMap::new();
+    for (id, raw) in raw_entries {
+        match KeyMetadata::deserialize(&raw) {
+            Ok(metadata) => {
+                entries.insert(id, metadata);
+            }
+            Err(err) => {
+                let error = err.to_string();
+                corrupt_entries.insert(id, CorruptEntry { raw, error });
+            }
+        }
+    }
+    Ok((entries, corrupt_entries))
+}
+
+/// Returns a new value for [MetadataStore::instance].
+fn next_instance() -> u64 {
+    static NEXT_INSTANCE: AtomicU64 = AtomicU64::new(0);
+    NEXT_INSTANCE.fetch_add(1, Ordering::Relaxed)
+}
+
+/// Entry of the quarantine file.
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct QuarantinedEntry {
+    /// Milliseconds since the unix epoch.
+    quarantined_at: u64,
+    reason: String,
+    entry: serde_json::Value,
+}
+
+impl MetadataStore {
+    fn in_memory() -> Self {
+        Self {
+            instance: next_instance(),
+            path: None,
+            protection: FileProtection::None,
+            entries: Mutex::default(),
+            corrupt_entries: Mutex::default(),
+            file_error: None,
+            deleted: Mutex::default(),
+            rotation: Mutex::default(),
+            ephemeral_holds: Mutex::default(),
+        }
+    }
+
+    /// Reads the metadata file at `path` and verifies it with the storage key of `protection`.
+    ///
+    /// Entries, which fail to deserialize, are kept aside as [CorruptEntry].
+    /// A verified file, which is no JSON object at all, is renamed with the extension `corrupt`,
+    /// so that it is not overwritten by the next change.
+    ///
+    /// # Errors
+    /// * [MetadataError::Integrity], if the file fails verification. It is left untouched in that case.
+    /// * [MetadataError::Io], if the file exists, but cannot be read.
+    fn load(path: PathBuf, protection: FileProtection) -> Result<Self, MetadataError> {
+        let mut entries = HashMap::new();
+        let mut corrupt_entries = HashMap::new();
+        let mut file_error = None;
+
+        let bytes = match fs::read(&path) {
+            Ok(bytes) => Some(protection.open(bytes, &path)?),
+            Err(err) if err.kind() == ErrorKind::NotFound => None,
+            Err(err) => return Err(err.into()),
+        };
+        if let Some(bytes) = bytes {
+            match parse_entries(&bytes) {
+                Ok((valid_entries, invalid_entries)) => {
+                    for (id, corrupt_entry) in &invalid_entries {
+                        tracing::error!(
+                            error = %corrupt_entry.error,
+                            id = %id,
+                            "Found corrupt key metadata."
+                        );
+                    }
+                    entries = valid_entries;
+                    corrupt_entries = invalid_entries;
+                }
+                Err(err) => {
+                    let corrupt_path = path.with_extension("corrupt");
+                    tracing::error!(
+                        error = %err,
+                        path = %path.display(),
+                        corrupt_path = %corrupt_path.display(),
+                        "Moving aside corrupt key metadata."
+                    );
+                    file_error = Some(match fs::rename(&path, &corrupt_path) {
+                        Ok(()) => format!("{err}, moved to {}", corrupt_path.display()),
+                        Err(rename_err) => format!("{err}, failed moving it: {rename_err}"),
+                    });
+                }
+            }
+        }
+
+        Ok(Self {
+            instance: next_instance(),
+            path: Some(path),
+            protection,
+            entries: Mutex::new(entries),
+            corrupt_entries: Mutex::new(corrupt_entries),
+            file_error,
+            deleted: Mutex::default(),
+            rotation: Mutex::default(),
+            ephemeral_holds: Mutex::default(),
+        })
+    }
+
+    fn entries(&self) -> MutexGuard<'_, HashMap<String, KeyMetadata>> {
+        self.entries.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Serializes rotations of the keys of this store, see [MetadataStore::rotation].
+    pub(crate) fn lock_rotations(&self) -> MutexGuard<'_, ()> {
+        self.rotation.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    fn ephemeral_holds(&self) -> MutexGuard<'_, HashMap<String, usize>> {
+        self.ephemeral_holds
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+    }
+
+    fn deleted(&self) -> MutexGuard<'_, HashSet<String>> {
+        self.deleted.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    fn corrupt_entries(&self) -> MutexGuard<'_, HashMap<String, CorruptEntry>> {
+        self.corrupt_entries
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Locks the files of this store against other processes until the returned file is dropped.
+    ///
+    /// Threads of this process are excluded as well, as every call opens the lock file anew.
+    /// Returns `None` for stores without file.
+    fn lock_files(&self) -> Result<Option<fs::File>, MetadataError> {
+        let Some(path) = &self.path else {
+            return Ok(None);
+        };
+        let file = OpenOptions::new()
+            .create(true)
+            .truncate(false)
+            .write(true)
+            .open(path.with_file_name(LOCK_FILE_NAME))?;
+        file.lock()?;
+        Ok(Some(file))
+    }
+
+    /// Reads the valid and corrupt entries of the metadata file at `path`, none if it does not exist.
+    fn read_file(
+        &self,
+        path: &Path,
+    ) -> Result<(HashMap<String, KeyMetadata>, HashMap<String, CorruptEntry>), MetadataError> {
+        match fs::read(path) {
+            Ok(bytes) => Ok(parse_entries(&self.protection.open(bytes, path)?)?),
+            Err(err) if err.kind() == ErrorKind::NotFound => Ok(Default::default()),
+            Err(err) => Err(err.into()),
+        }
+    }
+
+    /// Writes all entries to a temporary file, which then replaces the metadata file.
+    ///
+    /// Corrupt entries are written back as read. Must be called with [MetadataStore::lock_files] held.
+    fn persist(
+        &self,
+        entries: &HashMap<String, KeyMetadata>,
+        corrupt_entries: &HashMap<String, CorruptEntry>,
+    ) -> Result<(), MetadataError> {
+        let Some(path) = &self.path else {
+            return Ok(());
+        };
+        let mut raw_entries = serde_json::Map::new();
+        for (id, corrupt_entry) in corrupt_entries {
+            raw_entries.insert(id.clone(), corrupt_entry.raw.clone());
+        }
+        for (id, metadata) in entries {
+            raw_entries.insert(id.clone(), serde_json::to_value(metadata)?);
+        }
+
+        let temporary_path = path.with_extension("json.tmp");
+        fs::write(&temporary_path, self.protection.seal(serde_json::to_vec(&raw_entries)?)?)?;
+        fs::rename(&temporary_path, path)?;
+        Ok(())
+    }
+
+    /// Applies `change` to the entries of the metadata file as currently stored and persists the result.
+    ///
+    /// The files are locked from reading until the metadata file was replaced,
+    /// so that processes sharing the storage never overwrite or miss each other's changes.
+    /// Afterwards this store holds the entries of the file, including those changed by other processes.
+    /// Nothing is written, if `change` returns `None`, and the store stays unchanged on errors.
+    fn modify<T>(
+        &self,
+        change: impl FnOnce(
+            &mut HashMap<String, KeyMetadata>,
+            &mut HashMap<String, CorruptEntry>,
+        ) -> Result<Option<T>, MetadataError>,
+    ) -> Result<Option<T>, MetadataError> {
+        let mut entries = self.entries();
+        let mut corrupt_entries = self.corrupt_entries();
+        let _lock = self.lock_files()?;
+        let (mut current_entries, mut current_corrupt_entries) = match &self.path {
+            Some(path) => self.read_file(path)?,
+            None => (entries.clone(), corrupt_entries.clone()),
+        };
+
+        let result = change(&mut current_entries, &mut current_corrupt_entries)?;
+        if result.is_some() {
+            self.persist(&current_entries, &current_corrupt_entries)?;
+        }
+        *entries = current_entries;
+        *corrupt_entries = current_corrupt_entries;
+        Ok(result)
+    }
+
+    pub(crate) fn get(&self, id: &str) -> Option<KeyMetadata> {
+        self.entries().get(id).cloned()
+    }
+
+    pub(crate) fn instance(&self) -> u64 {
+        self.instance
+    }
+
+    /// Reserves `count` encryptions of the key `id` and returns the number of the first reserved encryption.
+    ///
+    /// The counter is read from the metadata file under its lock, so that processes sharing the storage
+    /// reserve disjoint blocks. Returns `None` for keys without metadata.
+    pub(crate) fn reserve_encryptions(
+        &self,
+        id: &str,
+        count: u64,
+    ) -> Result<Option<u64>, MetadataError> {
+        self.modify(|entries, _| {
+            Ok(entries.get_mut(id).map(|metadata| {
+                let first = metadata.encryptions;
+                metadata.encryptions = first.saturating_add(count);
+                first
+            }))
+        })
+    }
+
+    /// Reads the ivs of the key `id` appended to the iv log since `offset`.
+    ///
+    /// Returns the ivs and the offset to continue from. Stores without file have no iv log.
+    fn read_used_ivs(
+        &self,
+        id: &str,
+        offset: u64,
+    ) -> Result<(Vec<Vec<u8>>, u64), MetadataError> {
+        let Some(path) = &self.path else {
+            return Ok((Vec::new(), offset));
+        };
+        let mut file = match fs::File::open(path.with_file_name(IV_LOG_FILE_NAME)) {
+            Ok(file) => file,
+            Err(err) if err.kind() == ErrorKind::NotFound => return Ok((Vec::new(), offset)),
+            Err(err) => return Err(err.into()),
+        };
+        file.seek(SeekFrom::Start(offset))?;
+        let mut appended = Vec::new();
+        file.read_to_end(&mut appended)?;
+
+        // Lines are only appended under the lock, but a process may have crashed while writing one.
+        let complete = appended
+            .iter()
+            .rposition(|&byte| byte == b'\n')
+            .map_or(0, |position| position + 1);
+        let ivs = appended[..complete]
+            .split(|&byte| byte == b'\n')
+            .filter_map(|line| serde_json::from_slice::<UsedIv>(line).ok())
+            .filter(|used_iv| used_iv.id == id)
+            .map(|used_iv| used_iv.iv)
+            .collect();
+        Ok((ivs, offset + complete as u64))
+    }
+
+    /// Appends the caller supplied `iv` of the key `id` to the iv log.
+    fn append_used_iv(&self, path: &Path, id: &str, iv: &[u8]) -> Result<(), MetadataError> {
+        let mut line = serde_json::to_vec(&UsedIv {
+            id: id.to_owned(),
+            iv: iv.to_vec(),
+        })?;
+        line.push(b'\n');
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path.with_file_name(IV_LOG_FILE_NAME))?;
+        file.write_all(&line)?;
+        Ok(())
+    }
+
+    /// Appends the caller supplied `iv` of the key `id` to the iv log, unless `accept` refuses it.
+    ///
+    /// `accept` gets the ivs of the key appended since `offset` and the offset to continue from.
+    /// The files are locked meanwhile, so that processes sharing the storage cannot both accept the same iv.
+    /// Stores without file have no iv log and only call `accept`.
+    pub(crate) fn log_used_iv<E: From<MetadataError>>(
+        &self,
+        id: &str,
+        iv: &[u8],
+        offset: u64,
+        accept: impl FnOnce(Vec<Vec<u8>>, u64) -> Result<(), E>,
+    ) -> Result<(), E> {
+        let Some(path) = &self.path else {
+            return accept(Vec::new(), offset);
+        };
+        let _lock = self.lock_files()?;
+        let (logged_ivs, offset) = self.read_used_ivs(id, offset)?;
+        accept(logged_ivs, offset)?;
+        self.append_used_iv(path, id, iv)?;
+        Ok(())
+    }
+
+    /// Returns the metadata of the key `id` for enforcing its policy.
+    ///
+    /// Keys without entry, e.g. keys created before the addon kept metadata or by another user of the storage,
+    /// are unrestricted. A key, whose entry cannot be read, is reported as unavailable,
+    /// so that damaging its entry cannot lift its restrictions.
+    pub(crate) fn policy_metadata(&self, id: &str) -> PolicyMetadata {
+        if let Some(metadata) = self.get(id) {
+            return PolicyMetadata::Found(metadata);
+        }
+        if let Some(corrupt_entry) = self.corrupt_entries().get(id) {
+            return PolicyMetadata::Unavailable(format!("unreadable: {}", corrupt_entry.error));
+        }
+        PolicyMetadata::Unrestricted
+    }
+
+    /// Returns whether the metadata file has an entry for the key `id`, readable or not.
+    pub(crate) fn has_entry(&self, id: &str) -> bool {
+        if self.entries().contains_key(id) {
+            return true;
+        }
+        self.corrupt_entries().contains_key(id)
+    }
+
+    /// Ids of the keys, whose metadata has the tag `tag`.
+    pub(crate) fn tagged_ids(&self, tag: &str) -> HashSet<String> {
+        self.entries()
+            .iter()
+            .filter(|(_, metadata)| metadata.tags.iter().any(|own| own == tag))
+            .map(|(id, _)| id.clone())
+            .collect()
+    }
+
+    /// Stores `metadata` for the key `id` with the current time as creation timestamp.
+    pub(crate) fn insert(
+        &self,
+        id: String,
+        metadata: NewKeyMetadata,
+    ) -> Result<KeyMetadata, MetadataError> {
+        let metadata = KeyMetadata {
+            label: metadata.label,
+            tags: metadata.tags,
+            created_at: now_millis(),
+            expires_at: metadata.expires_at,
+            validity: metadata.validity,
+            usages: metadata.usages,
+            encryption_limits: metadata.encryption_limits,
+            ..Default::default()
+        };
+
+        self.deleted().remove(&id);
+        let inserted = metadata.clone();
+        self.modify(|entries, _| {
+            entries.insert(id, inserted);
+            Ok(Some(()))
+        })?;
+        Ok(metadata)
+    }
+
+    /// Replaces the metadata of several keys at once.
+    ///
+    /// Nothing is changed, if the change cannot be persisted.
+    pub(crate) fn replace_all(
+        &self,
+        updates: Vec<(String, KeyMetadata)>,
+    ) -> Result<(), MetadataError> {
+        {
+            let mut deleted = self.deleted();
+            for (id, _) in &updates {
+                deleted.remove(id);
+            }
+        }
+        self.modify(|entries, _| {
+            entries.extend(updates);
+            Ok(Some(()))
+        })?;
+        Ok(())
+    }
+
+    /// Returns the id and metadata of all versions of the key family `family_id` ordered by version.
+    pub(crate) fn family_members(&self, family_id: &str) -> Vec<(String, KeyMetadata)> {
+        let mut members: Vec<(String, KeyMetadata)> = self
+            .entries()
+            .iter()
+            .filter(|(_, metadata)| {
+                metadata
+                    .family
+                    .as_ref()
+                    .is_some_and(|family| family.family_id == family_id)
+            })
+            .map(|(id, metadata)| (id.clone(), metadata.clone()))
+            .collect();
+        members.sort_by_key(|(_, metadata)| metadata.family.as_ref().map(|family| family.version));
+        members
+    }
+
+    /// Returns the ids of keys copied by [crate::migration::migrate_keys] by the id of their source key.
+    pub(crate) fn migrated_ids(&self) -> HashMap<String, String> {
+        self.entries()
+            .iter()
+            .filter_map(|(id, metadata)| {
+                let migrated_from = metadata.migrated_from.clone()?;
+                Some((migrated_from, id.clone()))
+            })
+            .collect()
+    }
+
+    /// Returns the ids of all keys with metadata.
+    pub(crate) fn ids(&self) -> Vec<String> {
+        self.entries().keys().cloned().collect()
+    }
+
+    /// Returns the ids and deserialization errors of the corrupt entries of the metadata file.
+    pub(crate) fn corrupt_ids(&self) -> Vec<(String, String)> {
+        self.corrupt_entries()
+            .iter()
+            .map(|(id, corrupt_entry)| (id.clone(), corrupt_entry.error.clone()))
+            .collect()
+    }
+
+    /// Returns why the metadata file could not be read, if it was moved aside on load.
+    pub(crate) fn file_error(&self) -> Option<&str> {
+        self.file_error.as_deref()
+    }
+
+    /// Removes the valid or corrupt entries `ids` from the store.
+    ///
+    /// With `keep` the entries are first added to the quarantine file next to the metadata file
+    /// together with their `reason`. Stores without file discard the entries.
+    pub(crate) fn quarantine(
+        &self,
+        ids: &[(String, String)],
+        keep: bool,
+    ) -> Result<(), MetadataError> {
+        let quarantine_path = self
+            .path
+            .as_ref()
+            .filter(|_| keep)
+            .map(|path| path.with_file_name(QUARANTINE_FILE_NAME));
+
+        self.modify(|entries, corrupt_entries| {
+            let mut quarantined = HashMap::new();
+            for (id, reason) in ids {
+                let raw = match entries.remove(id) {
+                    Some(metadata) => serde_json::to_value(metadata)?,
+                    None => match corrupt_entries.remove(id) {
+                        Some(corrupt_entry) => corrupt_entry.raw,
+                        None => continue,
+                    },
+                };
+                quarantined.insert(
+                    id.clone(),
+                    QuarantinedEntry {
+                        quarantined_at: now_millis(),
+                        reason: reason.clone(),
+                        entry: raw,
+                    },
+                );
+            }
+            if quarantined.is_empty() {
+                return Ok(None);
+            }
+
+            if let Some(quarantine_path) = &quarantine_path {
+                let mut quarantine_file: HashMap<String, QuarantinedEntry> =
+                    match fs::read(quarantine_path) {
+                        Ok(bytes) => serde_json::from_slice(
+                            &self.protection.open(bytes, quarantine_path)?,
+                        )?,
+                        Err(_) => HashMap::new(),
+                    };
+                quarantine_file.extend(quarantined);
+                let temporary_path = quarantine_path.with_extension("json.tmp");
+                let sealed = self.protection.seal(serde_json::to_vec(&quarantine_file)?)?;
+                fs::write(&temporary_path, sealed)?;
+                fs::rename(&temporary_path, quarantine_path)?;
+            }
+            Ok(Some(()))
+        })?;
+        Ok(())
+    }
+
+    pub(crate) fn remove(&self, id: &str) -> Result<(), MetadataError> {
+        self.modify(|entries, _| Ok(entries.remove(id).map(drop)))?;
+        Ok(())
+    }
+
+    /// Records that the key `id` of this storage was deleted and removes its metadata.
+    pub(crate) fn forget(&self, id: &str) {
+        self.deleted().insert(id.to_owned());
+        if let Err(err) = self.remove(id) {
+            tracing::error!(error = %err, id, "Failed removing metadata of deleted key.");
+        }
+    }
+
+    /// Records a live handle of the ephemeral key `id` until the returned hold is dropped.
+    ///
+    /// Ephemeral keys are not listed by the provider storage,
+    /// thus their metadata would otherwise look orphaned to [crate::verification::verify_store].
+    pub(crate) fn hold_ephemeral(self: &Arc<Self>, id: String) -> EphemeralHold {
+        *self.ephemeral_holds().entry(id.clone()).or_default() += 1;
+        EphemeralHold {
+            store: Arc::clone(self),
+            id,
+        }
+    }
+
+    /// Returns whether a live handle holds the ephemeral key `id`.
+    pub(crate) fn is_held_ephemeral(&self, id: &str) -> bool {
+        self.ephemeral_holds().contains_key(id)
+    }
+
+    /// Returns whether the key `id` of this storage was deleted through any handle.
+    pub(crate) fn is_deleted(&self, id: &str) -> bool {
+        self.deleted().contains(id)
+    }
+}
+
+/// Stores of file backed providers, which are still referenced by a provider.
+fn stores() -> &'static Mutex<Vec<Weak<MetadataStore>>> {
+    static STORES: OnceLock<Mutex<Vec<Weak<MetadataStore>>>> = OnceLock::new();
+    STORES.get_or_init(Default::default)
+}
+
+/// Canonical path of the storage directory `db_dir`, which is created first if missing.
+///
+/// So a storage maps to the same path before and after crypto-layer created its directory.
+pub(crate) fn canonical_db_dir(db_dir: &str) -> std::io::Result<PathBuf> {
+    fs::create_dir_all(db_dir)?;
+    Path::new(db_dir).canonicalize()
+}
+
+/// Returns the metadata store for a provider created with `impl_config`.
+///
+/// Providers sharing a `FileStoreConfig` share the store.
+///
+/// # Errors
+/// * [MetadataError::Integrity], if the metadata file fails verification with the storage key.
+/// * [MetadataError::KeyMismatch], if the store is already open with another storage key.
+pub(crate) fn metadata_store(
+    impl_config: &ProviderImplConfig,
+) -> Result<Arc<MetadataStore>, MetadataError> {
+    let db_dir = impl_config
+        .additional_config
+        .iter()
+        .find_map(|additional_config| match additional_config {
+            AdditionalConfig::FileStoreConfig { db_dir } => Some(db_dir),
+            _ => None,
+        });
+    let Some(db_dir) = db_dir else {
+        return Ok(new_in_memory_store());
+    };
+
+    let path = canonical_db_dir(db_dir)?.join(METADATA_FILE_NAME);
+    let protection = FileProtection::from_impl_config(impl_config);
+
+    let mut stores = stores().lock().unwrap_or_else(PoisonError::into_inner);
+    stores.retain(|store| store.strong_count() > 0);
+    let existing = stores
+        .iter()
+        .filter_map(Weak::upgrade)
+        .find(|store| store.path.as_ref() == Some(&path));
+    if let Some(store) = existing {
+        if store.protection.key_id()? != protection.key_id()? {
+            return Err(MetadataError::KeyMismatch(path));
+        }
+        return Ok(store);
+    }
+
+    let store = Arc::new(MetadataStore::load(path, protection)?);
+    stores.push(Arc::downgrade(&store));
+    Ok(store)
+}
+
+/// Returns a store for providers without persistent storage.
+pub(crate) fn new_in_memory_store() -> Arc<MetadataStore> {
+    Arc::new(MetadataStore::in_memory())
+}
+
+/// Stores `metadata` for the newly created `handle`.
+///
+/// The key is deleted, if the metadata cannot be stored,
+/// so that no key remains without the metadata it was created with.
+pub(crate) fn attach_metadata<H: Deletable>(
+    store: &MetadataStore,
+    handle: H,
+    metadata: Option<NewKeyMetadata>,
+) -> Result<H, String> {
+    let Some(metadata) = metadata else {
+        return Ok(handle);
+    };
+
+    let result = handle
+        .key_id()
+        .map_err(|err| err.to_string())
+        .and_then(|id| store.insert(id, metadata).map_err(|err| err.to_string()));
+    match result {
+        Ok(_) => Ok(handle),
+        Err(err) => {
+            if let Err(delete_err) = handle.delete_key() {
+                tracing::error!(error = %delete_err, "Failed deleting key without metadata.");
+            }
+            Err(err)
+        }
+    }
+}
+
+/// Milliseconds since the unix epoch.
+pub(crate) fn now_millis() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_millis() as u64)
+        .unwrap_or(0)
+}
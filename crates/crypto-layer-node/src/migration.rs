@@ -0,0 +1,253 @@
+use std::collections::BTreeMap;
+
+use crypto_layer::common::config::Spec;
+use crypto_layer::common::error::CalError;
+use crypto_layer::prelude::*;
+use zeroize::Zeroizing;
+
+use crate::listing::KeyFilter;
+use crate::metadata::{now_millis, KeyMetadata, MetadataError, MetadataStore};
+use crate::tombstone::{delete_and_forget, Deletable};
+
+#[derive(thiserror::Error, Debug)]
+pub(crate) enum MigrationError {
+    #[error(transparent)]
+    Cal(#[from] CalError),
+    #[error(transparent)]
+    Metadata(#[from] MetadataError),
+    #[error("The key is not exportable and crypto-layer offers no way to wrap it for transport.")]
+    NotExportable,
+    #[error("Ephemeral keys are not stored and cannot be migrated.")]
+    Ephemeral,
+    #[error("The source and the target provider use the same storage.")]
+    SameStorage,
+}
+
+/// Options of [migrate_keys].
+#[derive(Debug, Default)]
+pub(crate) struct MigrationOptions {
+    /// Selects the keys of the source provider, which are migrated.
+    pub filter: KeyFilter,
+    /// Deletes keys from the source provider, once they exist in the target provider.
+    pub delete_source: bool,
+}
+
+/// Outcome of the migration of one key.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum MigrationStatus {
+    /// Copied to the target provider.
+    Migrated,
+    /// Copied to the target provider by an earlier migration.
+    AlreadyMigrated,
+    /// Cannot be copied, e.g. because it is not exportable.
+    Unsupported,
+    /// Copying failed.
+    Failed,
+}
+
+impl MigrationStatus {
+    pub(crate) fn name(&self) -> &'static str {
+        match self {
+            MigrationStatus::Migrated => "migrated",
+            MigrationStatus::AlreadyMigrated => "alreadyMigrated",
+            MigrationStatus::Unsupported => "unsupported",
+            MigrationStatus::Failed => "failed",
+        }
+    }
+}
+
+/// Result of [migrate_keys] for one key of the source provider.
+#[derive(Debug)]
+pub(crate) struct KeyMigration {
+    pub id: String,
+    pub status: MigrationStatus,
+    /// Id of the key in the target provider.
+    pub new_id: Option<String>,
+    pub source_deleted: bool,
+    /// Why the key was not migrated or, for migrated keys, why the source key was not deleted.
+    pub error: Option<String>,
+}
+
+/// Result of [migrate_keys].
+#[derive(Debug)]
+pub(crate) struct MigrationReport {
+    /// One result per key matching the filter.
+    pub keys: Vec<KeyMigration>,
+    /// Ids of the source keys with the ids of their copies in the target provider,
+    /// for every key copied by this or an earlier migration.
+    pub ids: BTreeMap<String, String>,
+}
+
+/// Stores `metadata` for `handle`, the copy of a source key.
+///
+/// The copy is deleted, if the metadata cannot be stored,
+/// so that a repeated migration does not leave a second copy behind.
+fn record_migration<H: Deletable>(
+    store: &MetadataStore,
+    handle: H,
+    metadata: KeyMetadata,
+) -> Result<String, MigrationError> {
+    let new_id = handle.key_id()?;
+    if let Err(err) = store.replace_all(vec![(new_id.clone(), metadata)]) {
+        if let Err(delete_err) = handle.delete_key() {
+            tracing::error!(error = %delete_err, "Failed deleting key of failed migration.");
+        }
+        return Err(err.into());
+    }
+    Ok(new_id)
+}
+
+/// Copies the key `id` with its secret into `target` and records `metadata` for the copy.
+fn migrate_key(
+    source: &mut Provider,
+    target: &mut Provider,
+    target_store: &MetadataStore,
+    id: &str,
+    spec: Spec,
+    metadata: KeyMetadata,
+) -> Result<String, MigrationError> {
+    match spec {
+        Spec::KeySpec(spec) => {
+            if spec.ephemeral {
+                return Err(MigrationError::Ephemeral);
+            }
+            if spec.non_exportable {
+                return Err(MigrationError::NotExportable);
+            }
+            let secret = Zeroizing::new(source.load_key(id.to_owned())?.extract_key()?);
+            let handle = target.import_key(spec, &secret)?;
+            record_migration(target_store, handle, metadata)
+        }
+        Spec::KeyPairSpec(spec) => {
+            if spec.ephemeral {
+                return Err(MigrationError::Ephemeral);
+            }
+            if spec.non_exportable {
+                return Err(MigrationError::NotExportable);
+            }
+            let source_handle = source.load_key_pair(id.to_owned())?;
+            let public_key = source_handle.get_public_key()?;
+            let secret = Zeroizing::new(source_handle.extract_key()?);
+            let handle = target.import_key_pair(spec, &public_key, &secret)?;
+            record_migration(target_store, handle, metadata)
+        }
+    }
+}
+
+/// Deletes the key `id` from `source` and its metadata from `source_store`.
+fn delete_source_key(
+    source: &mut Provider,
+    source_store: &MetadataStore,
+    id: &str,
+    spec: &Spec,
+) -> Result<(), CalError> {
+    match spec {
+        Spec::KeySpec(_) => delete_and_forget(source.load_key(id.to_owned())?, Some(source_store)),
+        Spec::KeyPairSpec(_) => {
+            delete_and_forget(source.load_key_pair(id.to_owned())?, Some(source_store))
+        }
+    }
+}
+
+/// Copies the keys of `source` matching `options.filter` with their metadata into `target`.
+///
+/// Ids are not preserved: crypto-layer assigns the ids of imported keys, thus copies get new ids,
+/// which the report maps from the ids of their source keys.
+/// Each copy records the id of its source key, so that a repeated migration continues
+/// where a previous one stopped and only reports keys copied before.
+/// Key families keep their versions and are named after the new id of their first version,
+/// if it was migrated.
+///
+/// Only exportable keys can be copied: crypto-layer offers no way to wrap a key under a transport key,
+/// so non exportable keys have no path into another provider and are reported as unsupported.
+/// Source keys are deleted with `options.delete_source` once a copy with its metadata exists.
+pub(crate) fn migrate_keys(
+    source: &mut Provider,
+    source_store: &MetadataStore,
+    target: &mut Provider,
+    target_store: &MetadataStore,
+    options: &MigrationOptions,
+) -> Result<MigrationReport, MigrationError> {
+    if std::ptr::eq(source_store, target_store) {
+        return Err(MigrationError::SameStorage);
+    }
+
+    let matcher = options.filter.matcher(source_store);
+    let mut keys: Vec<(String, Spec, Option<KeyMetadata>)> = source
+        .get_all_keys()?
+        .into_iter()
+        .filter(|(id, spec)| matcher.matches(id, spec))
+        .map(|(id, spec)| {
+            let metadata = source_store.get(&id);
+            (id, spec, metadata)
+        })
+        .collect();
+    // First versions of key families are migrated first, so that later versions know their new id.
+    keys.sort_by_key(|(_, _, metadata)| {
+        metadata
+            .as_ref()
+            .and_then(|metadata| metadata.family.as_ref())
+            .map_or(0, |family| family.version)
+    });
+
+    let mut migrated_ids = target_store.migrated_ids();
+    let mut results = Vec::with_capacity(keys.len());
+    for (id, spec, metadata) in keys {
+        let (status, result) = match migrated_ids.get(&id) {
+            Some(new_id) => (MigrationStatus::AlreadyMigrated, Ok(new_id.clone())),
+            None => {
+                let mut metadata = metadata.unwrap_or_else(|| KeyMetadata {
+                    created_at: now_millis(),
+                    ..Default::default()
+                });
+                if let Some(family) = &mut metadata.family {
+                    if let Some(new_id) = migrated_ids.get(&family.family_id) {
+                        family.family_id = new_id.clone();
+                    }
+                }
+                metadata.migrated_from = Some(id.clone());
+
+                let result = migrate_key(source, target, target_store, &id, spec.clone(), metadata);
+                match result {
+                    Ok(new_id) => {
+                        migrated_ids.insert(id.clone(), new_id.clone());
+                        (MigrationStatus::Migrated, Ok(new_id))
+                    }
+                    Err(err @ (MigrationError::NotExportable | MigrationError::Ephemeral)) => {
+                        (MigrationStatus::Unsupported, Err(err))
+                    }
+                    Err(err) => (MigrationStatus::Failed, Err(err)),
+                }
+            }
+        };
+
+        let migration = match result {
+            Ok(new_id) => {
+                let deleted = options
+                    .delete_source
+                    .then(|| delete_source_key(source, source_store, &id, &spec));
+                KeyMigration {
+                    id,
+                    status,
+                    new_id: Some(new_id),
+                    source_deleted: matches!(deleted, Some(Ok(()))),
+                    error: deleted.and_then(Result::err).map(|err| err.to_string()),
+                }
+            }
+            Err(err) => KeyMigration {
+                id,
+                status,
+                new_id: None,
+                source_deleted: false,
+                error: Some(err.to_string()),
+            },
+        };
+        results.push(migration);
+    }
+
+    let ids = results
+        .iter()
+        .filter_map(|migration| Some((migration.id.clone(), migration.new_id.clone()?)))
+        .collect();
+    Ok(MigrationReport { keys: results, ids })
+}
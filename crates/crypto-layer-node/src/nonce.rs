@@ -0,0 +1,349 @@
+use std::collections::{HashMap, HashSet};
+use std::sync::{Mutex, OnceLock, PoisonError};
+
+use crypto_layer::prelude::*;
+use neon::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::metadata::{MetadataError, MetadataStore};
+
+/// `code` of the error thrown when a key reached its maximum amount of encryptions.
+pub(crate) const ENCRYPTION_LIMIT_ERROR_CODE: &str = "ERR_ENCRYPTION_LIMIT";
+
+/// `code` of the error thrown when a caller supplied iv was used before with the key.
+pub(crate) const IV_REUSED_ERROR_CODE: &str = "ERR_IV_REUSED";
+
+/// `code` of the process warning emitted when a key approaches its maximum amount of encryptions.
+pub(crate) const ENCRYPTION_LIMIT_WARNING_CODE: &str = "WARN_ENCRYPTION_LIMIT";
+
+/// Encryptions with random 96 bit nonces under one AES-GCM key,
+/// after which the probability of a repeated nonce exceeds 2^-32 (NIST SP 800-38D).
+const AES_GCM_MAX_ENCRYPTIONS: u64 = 1 << 32;
+
+/// Encryptions reserved in the persisted counter at once, so that not every encryption writes the metadata file.
+///
+/// After a crash up to this many encryptions are counted, which never happened.
+const RESERVATION_BLOCK: u64 = 1024;
+
+/// Caller supplied ivs remembered per key with `strict_iv_check`.
+///
+/// Bounds the memory of the check. Keys reaching it must be rotated.
+const MAX_CHECKED_IVS: usize = 1 << 16;
+
+/// Limits on the encryptions of a key, stored with its metadata.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct EncryptionLimits {
+    /// Encryptions after which further encryptions are refused.
+    pub max_encryptions: Option<u64>,
+    /// Encryptions after which a warning is emitted. Defaults to 90% of `max_encryptions`.
+    pub warn_at: Option<u64>,
+    /// Refuses caller supplied ivs, which were used with the key before.
+    #[serde(default)]
+    pub strict_iv_check: bool,
+}
+
+impl EncryptionLimits {
+    /// Fills missing limits of `configured` with the defaults of `cipher`.
+    fn effective(configured: Option<&EncryptionLimits>, cipher: &Cipher) -> Self {
+        let default_max_encryptions = match cipher {
+            Cipher::AesGcm128 | Cipher::AesGcm256 => Some(AES_GCM_MAX_ENCRYPTIONS),
+            _ => None,
+        };
+        let configured = configured.cloned().unwrap_or_default();
+        Self {
+            max_encryptions: configured.max_encryptions.or(default_max_encryptions),
+            ..configured
+        }
+    }
+
+    fn warn_at(&self) -> Option<u64> {
+        self.warn_at
+            .or(self.max_encryptions.map(|max_encryptions| max_encryptions - max_encryptions / 10))
+    }
+}
+
+#[derive(thiserror::Error, Debug)]
+pub(crate) enum NonceError {
+    #[error("The key {id} reached its limit of {max_encryptions} encryptions and must be rotated.")]
+    LimitReached { id: String, max_encryptions: u64 },
+    #[error("The iv was already used for an encryption with the key {id}.")]
+    IvReused { id: String },
+    #[error(
+        "The key {id} reached its limit of {max_ivs} encryptions with caller supplied ivs \
+        and must be rotated."
+    )]
+    IvLimitReached { id: String, max_ivs: usize },
+    #[error("Failed recording encryptions: {0}")]
+    Metadata(#[from] MetadataError),
+}
+
+/// Returned by [record_encryptions], when a key first passes the `warn_at` limit.
+#[derive(Debug, Clone)]
+pub(crate) struct EncryptionWarning {
+    pub id: String,
+    pub encryptions: u64,
+    pub max_encryptions: Option<u64>,
+}
+
+impl EncryptionWarning {
+    fn message(&self) -> String {
+        match self.max_encryptions {
+            Some(max_encryptions) => format!(
+                "The key {} performed {} of at most {} encryptions and should be rotated.",
+                self.id, self.encryptions, max_encryptions
+            ),
+            None => format!(
+                "The key {} performed {} encryptions and should be rotated.",
+                self.id, self.encryptions
+            ),
+        }
+    }
+}
+
+/// Key of the counter of the key `id` obtained from the store with the instance `store`.
+///
+/// Keys with the same id in different storages have separate counters.
+type CounterKey = (Option<u64>, String);
+
+/// Encryptions of one key in this process.
+struct KeyCounter {
+    limits: EncryptionLimits,
+    /// Number of the last encryption counted by this process.
+    encryptions: u64,
+    /// End of the block of encryptions this process reserved in the store.
+    reserved: u64,
+    warned: bool,
+    /// Caller supplied ivs, only recorded with `strict_iv_check`.
+    used_ivs: HashSet<Vec<u8>>,
+    /// Offset in the iv log up to which `used_ivs` contains the ivs of the key.
+    iv_log_offset: u64,
+}
+
+impl KeyCounter {
+    /// Continues the counter persisted with the metadata of the key `id` in `store`.
+    fn load(id: &str, handle: &KeyHandle, store: Option<&MetadataStore>) -> Self {
+        let metadata = store.and_then(|store| store.get(id));
+        let limits = EncryptionLimits::effective(
+            metadata
+                .as_ref()
+                .and_then(|metadata| metadata.encryption_limits.as_ref()),
+            &handle.spec().cipher,
+        );
+        let encryptions = metadata.map(|metadata| metadata.encryptions).unwrap_or_default();
+
+        Self {
+            limits,
+            encryptions,
+            reserved: encryptions,
+            warned: false,
+            used_ivs: HashSet::new(),
+            iv_log_offset: 0,
+        }
+    }
+
+    /// Accepts the caller supplied `iv`, unless it was used before, including by other processes.
+    ///
+    /// `logged_ivs` are the ivs of the key appended to the iv log up to `offset`.
+    fn accept_iv(
+        &mut self,
+        id: &str,
+        iv: &[u8],
+        logged_ivs: Vec<Vec<u8>>,
+        offset: u64,
+    ) -> Result<(), NonceError> {
+        self.used_ivs.extend(logged_ivs);
+        self.iv_log_offset = offset;
+        if self.used_ivs.contains(iv) {
+            return Err(NonceError::IvReused { id: id.to_owned() });
+        }
+        if self.used_ivs.len() >= MAX_CHECKED_IVS {
+            return Err(NonceError::IvLimitReached {
+                id: id.to_owned(),
+                max_ivs: MAX_CHECKED_IVS,
+            });
+        }
+        self.used_ivs.insert(iv.to_vec());
+        Ok(())
+    }
+}
+
+/// Counters of all keys, which encrypted data in this process.
+fn counters() -> &'static Mutex<HashMap<CounterKey, KeyCounter>> {
+    static COUNTERS: OnceLock<Mutex<HashMap<CounterKey, KeyCounter>>> = OnceLock::new();
+    COUNTERS.get_or_init(Default::default)
+}
+
+/// Counts `count` encryptions with the key of `handle` obtained from `store` before they happen.
+///
+/// Refuses encryptions beyond `max_encryptions` and, in strict mode, a caller supplied `iv` used before.
+/// Counters of keys with metadata are persisted in blocks of [RESERVATION_BLOCK] encryptions.
+/// Each block is reserved under the lock of the metadata file,
+/// so that processes sharing a storage count each others encryptions.
+/// Counters of other keys, e.g. derived keys, only live in memory.
+/// Ivs of strict mode are checked and appended to the iv log of the store under the same lock,
+/// and at most [MAX_CHECKED_IVS] are accepted.
+/// Returns a warning, the first time the key passes `warn_at` in this process.
+pub(crate) fn record_encryptions(
+    handle: &KeyHandle,
+    store: Option<&MetadataStore>,
+    count: u64,
+    iv: Option<&[u8]>,
+) -> Result<Option<EncryptionWarning>, NonceError> {
+    let Ok(id) = handle.id() else {
+        return Ok(None);
+    };
+
+    let mut counters = counters().lock().unwrap_or_else(PoisonError::into_inner);
+    let counter = counters
+        .entry((store.map(MetadataStore::instance), id.clone()))
+        .or_insert_with(|| KeyCounter::load(&id, handle, store));
+    let iv = iv.filter(|iv| counter.limits.strict_iv_check && !iv.is_empty());
+
+    let mut encryptions = counter.encryptions.saturating_add(count);
+    if let Some(store) = store {
+        if encryptions > counter.reserved {
+            let block = count.max(RESERVATION_BLOCK);
+            if let Some(first) = store.reserve_encryptions(&id, block)? {
+                encryptions = first.saturating_add(count);
+                counter.reserved = first.saturating_add(block);
+            }
+        }
+    }
+    if let Some(max_encryptions) = counter.limits.max_encryptions {
+        if encryptions > max_encryptions {
+            return Err(NonceError::LimitReached {
+                id,
+                max_encryptions,
+            });
+        }
+    }
+
+    if let Some(iv) = iv {
+        let offset = counter.iv_log_offset;
+        match store {
+            Some(store) => store.log_used_iv(&id, iv, offset, |logged_ivs, offset| {
+                counter.accept_iv(&id, iv, logged_ivs, offset)
+            })?,
+            None => counter.accept_iv(&id, iv, Vec::new(), offset)?,
+        }
+    }
+    counter.encryptions = encryptions;
+
+    let passed_warn_at = counter
+        .limits
+        .warn_at()
+        .is_some_and(|warn_at| encryptions >= warn_at);
+    if !passed_warn_at || counter.warned {
+        return Ok(None);
+    }
+    counter.warned = true;
+    let warning = EncryptionWarning {
+        id,
+        encryptions,
+        max_encryptions: counter.limits.max_encryptions,
+    };
+    tracing::warn!("{}", warning.message());
+    Ok(Some(warning))
+}
+
+/// Drops the counter of the deleted key `id` of `store`.
+///
+/// Counters of keys with the same id in other storages are kept.
+pub(crate) fn forget_encryption_counter(store: Option<&MetadataStore>, id: &str) {
+    counters()
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner)
+        .remove(&(store.map(MetadataStore::instance), id.to_owned()));
+}
+
+/// Emits `warning` with `process.emitWarning` as `EncryptionLimitWarning` with the code [ENCRYPTION_LIMIT_WARNING_CODE].
+pub(crate) fn emit_encryption_warning<'a, C: Context<'a>>(
+    cx: &mut C,
+    warning: &EncryptionWarning,
+) -> NeonResult<()> {
+    let process = cx.global::<JsObject>("process")?;
+    let emit_warning = process.get::<JsFunction, _, _>(cx, "emitWarning")?;
+    let message = cx.string(warning.message());
+    let name = cx.string("EncryptionLimitWarning");
+    let code = cx.string(ENCRYPTION_LIMIT_WARNING_CODE);
+    emit_warning
+        .call_with(cx)
+        .this(process)
+        .arg(message)
+        .arg(name)
+        .arg(code)
+        .exec(cx)
+}
+
+/// Throws an `Error` with the `keyId`.
+///
+/// Exceeded limits have the name `EncryptionLimitError`, `code` [ENCRYPTION_LIMIT_ERROR_CODE]
+/// and `maxEncryptions`, the limit on encryptions with caller supplied ivs in strict mode included.
+/// Reused ivs have the name `IvReusedError` and `code` [IV_REUSED_ERROR_CODE].
+pub(crate) fn throw_nonce_error<'a, C: Context<'a>, V: Value>(
+    cx: &mut C,
+    err: &NonceError,
+) -> JsResult<'a, V> {
+    let js_err = cx.error(err.to_string())?;
+    match err {
+        NonceError::LimitReached {
+            id,
+            max_encryptions,
+        } => {
+            let name = cx.string("EncryptionLimitError");
+            js_err.set(cx, "name", name)?;
+            let code = cx.string(ENCRYPTION_LIMIT_ERROR_CODE);
+            js_err.set(cx, "code", code)?;
+            let key_id = cx.string(id);
+            js_err.set(cx, "keyId", key_id)?;
+            let max_encryptions = cx.number(*max_encryptions as f64);
+            js_err.set(cx, "maxEncryptions", max_encryptions)?;
+        }
+        NonceError::IvLimitReached { id, max_ivs } => {
+            let name = cx.string("EncryptionLimitError");
+            js_err.set(cx, "name", name)?;
+            let code = cx.string(ENCRYPTION_LIMIT_ERROR_CODE);
+            js_err.set(cx, "code", code)?;
+            let key_id = cx.string(id);
+            js_err.set(cx, "keyId", key_id)?;
+            let max_ivs = cx.number(*max_ivs as f64);
+            js_err.set(cx, "maxEncryptions", max_ivs)?;
+        }
+        NonceError::IvReused { id } => {
+            let name = cx.string("IvReusedError");
+            js_err.set(cx, "name", name)?;
+            let code = cx.string(IV_REUSED_ERROR_CODE);
+            js_err.set(cx, "code", code)?;
+            let key_id = cx.string(id);
+            js_err.set(cx, "keyId", key_id)?;
+        }
+        NonceError::Metadata(_) => {}
+    }
+    cx.throw(js_err)
+}
+
+/// Counts the encryptions with [record_encryptions] and emits its warning.
+///
+/// Rejects the deferred with [throw_nonce_error] and returns from the current function,
+/// if the encryptions are refused.
+macro_rules! counted_or_error_deferred {
+    ($channel:expr, $deferred:expr, $record_expr:expr) => {{
+        match $record_expr {
+            Ok(Some(warning)) => {
+                $channel.send(move |mut cx| {
+                    crate::nonce::emit_encryption_warning(&mut cx, &warning)
+                });
+            }
+            Ok(None) => {}
+            Err(err) => {
+                $deferred.settle_with($channel, move |mut cx| {
+                    crate::nonce::throw_nonce_error::<_, JsValue>(&mut cx, &err)
+                });
+                return ();
+            }
+        }
+    }};
+}
+
+pub(crate) use counted_or_error_deferred;
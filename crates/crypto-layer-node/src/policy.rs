@@ -0,0 +1,291 @@
+use neon::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::metadata::{now_millis, KeyMetadata, MetadataStore, PolicyMetadata, Validity};
+use crate::tojs::wrap_string_array;
+use crate::tombstone::Deletable;
+
+/// `code` of the error thrown when using a key outside of its validity period.
+pub(crate) const KEY_NOT_VALID_ERROR_CODE: &str = "ERR_KEY_NOT_VALID";
+
+/// `code` of the error thrown when using a key for an operation not among its usages.
+pub(crate) const KEY_USAGE_ERROR_CODE: &str = "ERR_KEY_USAGE";
+
+/// `code` of the error thrown when using a key, whose metadata is missing or unreadable.
+pub(crate) const KEY_METADATA_UNAVAILABLE_ERROR_CODE: &str = "ERR_KEY_METADATA_UNAVAILABLE";
+
+/// Operation of a key or key pair handle, which is restricted by the policy stored with the key.
+///
+/// Also the usages a key may be restricted to on creation or import.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) enum KeyOperation {
+    Encrypt,
+    Decrypt,
+    Sign,
+    Verify,
+    DeriveKey,
+    DhExchange,
+}
+
+impl KeyOperation {
+    pub(crate) const VARIANTS: &'static [&'static str] = &[
+        "encrypt",
+        "decrypt",
+        "sign",
+        "verify",
+        "deriveKey",
+        "dhExchange",
+    ];
+
+    pub(crate) fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "encrypt" => Some(KeyOperation::Encrypt),
+            "decrypt" => Some(KeyOperation::Decrypt),
+            "sign" => Some(KeyOperation::Sign),
+            "verify" => Some(KeyOperation::Verify),
+            "deriveKey" => Some(KeyOperation::DeriveKey),
+            "dhExchange" => Some(KeyOperation::DhExchange),
+            _ => None,
+        }
+    }
+
+    pub(crate) fn name(self) -> &'static str {
+        match self {
+            KeyOperation::Encrypt => "encrypt",
+            KeyOperation::Decrypt => "decrypt",
+            KeyOperation::Sign => "sign",
+            KeyOperation::Verify => "verify",
+            KeyOperation::DeriveKey => "deriveKey",
+            KeyOperation::DhExchange => "dhExchange",
+        }
+    }
+
+    /// Operations, which only process data protected while the key was valid,
+    /// and thus stay allowed after the validity period within the grace period.
+    fn processes_protected_data(self) -> bool {
+        matches!(self, KeyOperation::Decrypt | KeyOperation::Verify)
+    }
+}
+
+/// Use of a key, which its policy forbids.
+#[derive(thiserror::Error, Debug, Clone)]
+pub(crate) enum KeyPolicyError {
+    #[error("The key {id} is not valid yet and must not be used to {}.", .operation.name())]
+    NotYetValid {
+        id: String,
+        operation: KeyOperation,
+        validity: Validity,
+    },
+    #[error("The key {id} expired and must not be used to {}.", .operation.name())]
+    Expired {
+        id: String,
+        operation: KeyOperation,
+        validity: Validity,
+    },
+    #[error("The key {id} must not be used to {}.", .operation.name())]
+    UsageNotAllowed {
+        id: String,
+        operation: KeyOperation,
+        usages: Vec<KeyOperation>,
+    },
+    #[error(
+        "The metadata of the key {id} is {reason}, thus it must not be used to {}.",
+        .operation.name()
+    )]
+    MetadataUnavailable {
+        id: String,
+        operation: KeyOperation,
+        reason: String,
+    },
+}
+
+/// Checks `operation` against the validity period of the key.
+///
+/// Before `not_before` every operation is refused.
+/// After `not_after` decryption and verification stay allowed within an explicitly set grace period,
+/// everything else is refused.
+fn check_validity(
+    id: &str,
+    validity: &Validity,
+    operation: KeyOperation,
+    now: u64,
+) -> Result<(), KeyPolicyError> {
+    if validity.not_before.is_some_and(|not_before| now < not_before) {
+        return Err(KeyPolicyError::NotYetValid {
+            id: id.to_owned(),
+            operation,
+            validity: validity.clone(),
+        });
+    }
+
+    let Some(not_after) = validity.not_after else {
+        return Ok(());
+    };
+    if now <= not_after {
+        return Ok(());
+    }
+    let in_grace_period = operation.processes_protected_data()
+        && validity
+            .grace_period
+            .is_some_and(|grace_period| now <= not_after.saturating_add(grace_period));
+    if in_grace_period {
+        return Ok(());
+    }
+    Err(KeyPolicyError::Expired {
+        id: id.to_owned(),
+        operation,
+        validity: validity.clone(),
+    })
+}
+
+/// Checks `operation` against the usages of the key. Keys without usages are not restricted.
+fn check_usages(
+    id: &str,
+    usages: Option<&[KeyOperation]>,
+    operation: KeyOperation,
+) -> Result<(), KeyPolicyError> {
+    match usages {
+        Some(usages) if !usages.contains(&operation) => Err(KeyPolicyError::UsageNotAllowed {
+            id: id.to_owned(),
+            operation,
+            usages: usages.to_vec(),
+        }),
+        _ => Ok(()),
+    }
+}
+
+fn check_metadata(
+    id: &str,
+    metadata: &KeyMetadata,
+    operation: KeyOperation,
+) -> Result<(), KeyPolicyError> {
+    check_usages(id, metadata.usages.as_deref(), operation)?;
+    check_validity(id, &metadata.validity, operation, now_millis())
+}
+
+/// Checks whether the policy stored with the key of `handle` in `store`, its usages and validity period,
+/// allows `operation`.
+///
+/// Fails closed for keys, whose metadata entry is unreadable: they are refused every operation.
+/// Keys without entry and keys outside of any storage, e.g. derived keys, are not restricted.
+pub(crate) fn check_key_policy<H: Deletable>(
+    handle: &H,
+    store: Option<&MetadataStore>,
+    operation: KeyOperation,
+) -> Result<(), KeyPolicyError> {
+    let Some(store) = store else {
+        return Ok(());
+    };
+    let id = handle
+        .key_id()
+        .map_err(|err| KeyPolicyError::MetadataUnavailable {
+            id: String::new(),
+            operation,
+            reason: format!("unknown, as the key id is unavailable ({err})"),
+        })?;
+
+    match store.policy_metadata(&id) {
+        PolicyMetadata::Found(metadata) => check_metadata(&id, &metadata, operation),
+        PolicyMetadata::Unrestricted => Ok(()),
+        PolicyMetadata::Unavailable(reason) => Err(KeyPolicyError::MetadataUnavailable {
+            id,
+            operation,
+            reason,
+        }),
+    }
+}
+
+fn set_optional_date<'a, C: Context<'a>, O: Object>(
+    cx: &mut C,
+    obj: Handle<'a, O>,
+    key: &str,
+    millis: Option<u64>,
+) -> NeonResult<()> {
+    let value: Handle<JsValue> = match millis {
+        Some(millis) => cx.date(millis as f64).or_throw(cx)?.upcast(),
+        None => cx.null().upcast(),
+    };
+    obj.set(cx, key, value)?;
+    Ok(())
+}
+
+/// Throws an `Error` with the `keyId` and the refused `operation`.
+///
+/// Validity violations have the name `KeyNotValidError`, `code` [KEY_NOT_VALID_ERROR_CODE]
+/// and the validity period as `notBefore` and `notAfter`.
+/// Usage violations have the name `KeyUsageError`, `code` [KEY_USAGE_ERROR_CODE] and the allowed `usages`.
+/// Keys with unreadable metadata have the name `KeyMetadataUnavailableError`
+/// and `code` [KEY_METADATA_UNAVAILABLE_ERROR_CODE].
+pub(crate) fn throw_key_policy_error<'a, C: Context<'a>, V: Value>(
+    cx: &mut C,
+    err: &KeyPolicyError,
+) -> JsResult<'a, V> {
+    let js_err = cx.error(err.to_string())?;
+    match err {
+        KeyPolicyError::NotYetValid {
+            id,
+            operation,
+            validity,
+        }
+        | KeyPolicyError::Expired {
+            id,
+            operation,
+            validity,
+        } => {
+            let name = cx.string("KeyNotValidError");
+            js_err.set(cx, "name", name)?;
+            let code = cx.string(KEY_NOT_VALID_ERROR_CODE);
+            js_err.set(cx, "code", code)?;
+            let key_id = cx.string(id);
+            js_err.set(cx, "keyId", key_id)?;
+            let operation = cx.string(operation.name());
+            js_err.set(cx, "operation", operation)?;
+            set_optional_date(cx, js_err, "notBefore", validity.not_before)?;
+            set_optional_date(cx, js_err, "notAfter", validity.not_after)?;
+        }
+        KeyPolicyError::UsageNotAllowed {
+            id,
+            operation,
+            usages,
+        } => {
+            let name = cx.string("KeyUsageError");
+            js_err.set(cx, "name", name)?;
+            let code = cx.string(KEY_USAGE_ERROR_CODE);
+            js_err.set(cx, "code", code)?;
+            let key_id = cx.string(id);
+            js_err.set(cx, "keyId", key_id)?;
+            let operation = cx.string(operation.name());
+            js_err.set(cx, "operation", operation)?;
+            let usages = usages.iter().map(|usage| usage.name().to_owned()).collect();
+            let usages = wrap_string_array(cx, usages)?;
+            js_err.set(cx, "usages", usages)?;
+        }
+        KeyPolicyError::MetadataUnavailable { id, operation, .. } => {
+            let name = cx.string("KeyMetadataUnavailableError");
+            js_err.set(cx, "name", name)?;
+            let code = cx.string(KEY_METADATA_UNAVAILABLE_ERROR_CODE);
+            js_err.set(cx, "code", code)?;
+            let key_id = cx.string(id);
+            js_err.set(cx, "keyId", key_id)?;
+            let operation = cx.string(operation.name());
+            js_err.set(cx, "operation", operation)?;
+        }
+    }
+    cx.throw(js_err)
+}
+
+/// Rejects the deferred with [throw_key_policy_error] and returns from the current function,
+/// if the policy of the key forbids the operation.
+macro_rules! permitted_or_error_deferred {
+    ($channel:expr, $deferred:expr, $check_expr:expr) => {{
+        if let Err(err) = $check_expr {
+            $deferred.settle_with($channel, move |mut cx| {
+                crate::policy::throw_key_policy_error::<_, JsValue>(&mut cx, &err)
+            });
+            return ();
+        }
+    }};
+}
+
+pub(crate) use permitted_or_error_deferred;
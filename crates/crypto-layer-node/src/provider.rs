@@ -1,67 +1,246 @@
-use crypto_layer::prelude::CryptoHash;
-use neon::prelude::*;
+use std::ops::Deref;
+use std::sync::Arc;
 
-use crate::common::{arc_or_poisoned_error_deferred, box_if_ok, spawn_promise};
-use crate::fromjs::error::unwrap_or_throw;
-use crate::fromjs::{from_wrapped_simple_enum, int_from_js_number, vec_from_uint_8_array};
+use crypto_layer::prelude::{CryptoHash, Provider, ProviderImplConfig};
+use neon::prelude::*;
+use zeroize::Zeroizing;
+
+use crate::backup::{export_backup, import_backup, throw_backup_error};
+use crate::classes::{boxed_from_content, boxed_this, new_stored_instance, Boxed};
+use crate::common::{
+    arc_or_poisoned_error_deferred, box_if_ok, box_stored_if_ok, spawn_promise, Finalized,
+};
+use crate::fromjs::backup::{
+    backup_protection_from_argument, export_backup_options_from_argument,
+    import_backup_options_from_argument,
+};
+use crate::fromjs::config::{
+    from_wrapped_partial_key_pair_spec, from_wrapped_partial_key_spec, from_wrapped_partial_spec,
+};
+use crate::fromjs::error::{
+    conversion_or_error_deferred, rw_lock_poisoned, unwrap_or_throw, unwrap_or_throw_conversion,
+};
+use crate::fromjs::{
+    flag_from_options_argument, from_wrapped_simple_enum, int_from_js_number,
+    secret_from_uint_8_array, vec_from_uint_8_array, vec_from_uint_8_array_array,
+    zeroize_uint_8_array,
+};
+use crate::fromjs::listing::{key_filter_from_argument, list_keys_options_from_argument};
+use crate::fromjs::metadata::metadata_from_options_argument;
+use crate::fromjs::migration::{boxed_provider_from_argument, migration_options_from_argument};
 use crate::kdf::kdf_from_object;
-use crate::tojs::config::{wrap_provider_config, wrap_spec};
-use crate::tojs::{js_array_from_vec, uint_8_array_from_vec_u8};
-use crate::JsProvider;
+use crate::listing::{count_keys, list_keys, DEFAULT_PAGE_LIMIT};
+use crate::metadata::{
+    attach_metadata, metadata_store, new_in_memory_store, MetadataStore, NewKeyMetadata,
+};
+use crate::migration::{migrate_keys, MigrationError};
+use crate::registry::release_provider;
+use crate::rotation::{
+    decrypt_for_family, encrypt_for_family, rewrap, rotate_key, throw_rotation_error,
+};
+use crate::runtime::parallel_map;
+use crate::spec::{complete_key_pair_spec, complete_key_spec, complete_spec};
+use crate::storage_password::DerivedStorageKey;
+use crate::tojs::backup::{wrap_exported_backup, wrap_imported_backup};
+use crate::tojs::config::{wrap_key_page, wrap_provider_config, wrap_spec};
+use crate::tojs::metadata::wrap_optional_key_metadata;
+use crate::tojs::migration::wrap_migration_report;
+use crate::tojs::verification::wrap_store_report;
+use crate::tojs::{js_array_from_vec, uint_8_array_from_vec_u8, wrap_batch_results};
+use crate::verification::{repair_store, verify_store};
 use crate::{from_wrapped_key_pair_spec, from_wrapped_key_spec};
 
+/// Option key of import functions, which requests wiping the source `Uint8Array` of secret material.
+const ZEROIZE_SOURCE_OPTION: &str = "zeroizeSource";
+
+/// Option key of `getAllKeys`, which requests the metadata of every key.
+const METADATA_OPTION: &str = "metadata";
+
+/// Option key of `repairStore`, which requests moving bad metadata entries aside instead of discarding them.
+const QUARANTINE_OPTION: &str = "quarantine";
+
+/// Content of the box of providers.
+pub(crate) struct ProviderState {
+    provider: Provider,
+    /// Metadata of the keys in the storage of the provider.
+    metadata: Arc<MetadataStore>,
+    /// Storage key derived from a `StoragePassword`, cached for other providers as long as it is held.
+    _storage_key: Option<Arc<DerivedStorageKey>>,
+}
+
+impl Deref for ProviderState {
+    type Target = Provider;
+
+    fn deref(&self) -> &Self::Target {
+        &self.provider
+    }
+}
+
+impl From<Provider> for ProviderState {
+    /// Providers created without impl config only keep metadata in memory.
+    fn from(provider: Provider) -> Self {
+        Self {
+            provider,
+            metadata: new_in_memory_store(),
+            _storage_key: None,
+        }
+    }
+}
+
+/// Boxes `provider` together with the metadata store of the storage configured by `impl_config`
+/// and the `storage_key` derived for it.
+///
+/// # Errors
+/// * [MetadataError], if the metadata file cannot be read or fails verification with the storage key.
+pub(crate) fn new_boxed_provider(
+    provider: Provider,
+    impl_config: &ProviderImplConfig,
+    storage_key: Option<Arc<DerivedStorageKey>>,
+) -> Result<Boxed<Provider>, MetadataError> {
+    Ok(boxed_from_content::<Provider>(ProviderState {
+        provider,
+        metadata: metadata_store(impl_config)?,
+        _storage_key: storage_key,
+    }))
+}
+
+/// Clones the read locked provider, so that `crypto-layer` functions taking `&mut self` can run under a shared lock.
+///
+/// Providers are handles to internally synchronized key storage, thus all clones operate on the same keys.
+/// The read lock must be held until the operation finished, so that operations checking the storage
+/// and then changing it, which hold the write lock with [exclusive_provider_with_metadata],
+/// never run concurrently with it.
+/// Creating and importing keys share the read lock as well, as they only add keys with new ids.
+/// Their metadata entries are serialized by the lock of the metadata store, see [MetadataStore::insert].
+fn detach_provider(state: &Finalized<ProviderState>) -> Provider {
+    state.provider.clone()
+}
+
+/// Like [detach_provider], but also returns the metadata store, which is shared by all clones.
+fn detach_provider_with_metadata(
+    state: &Finalized<ProviderState>,
+) -> (Provider, Arc<MetadataStore>) {
+    (detach_provider(state), state.metadata.clone())
+}
+
+/// Provider and metadata store of the write locked provider.
+///
+/// Rotating, restoring, migrating and repairing keys hold the write lock for the whole operation,
+/// as they check the storage and then change it based on that.
+/// Verifying the store holds it as well, as keys are created under the read lock before their metadata is stored.
+/// Loads and other read only operations share the read lock with [detach_provider].
+fn exclusive_provider_with_metadata(
+    state: &mut Finalized<ProviderState>,
+) -> (&mut Provider, &Arc<MetadataStore>) {
+    let state = &mut **state;
+    (&mut state.provider, &state.metadata)
+}
+
 /// Wraps `create_key` function.
 ///
+/// Missing fields of the spec are filled and given fields are validated with [complete_key_spec].
+///
 /// # Arguments
-/// * **spec**: `KeySpec`
+/// * **spec**: `Partial<KeySpec>`
+/// * **options**: `{ metadata?: {...}, validity?: {...}, usages?: KeyUsage[], encryptionLimits?: {...} }` -
+///   optional, metadata, validity period, usages and encryption limits stored with the key,
+///   see [metadata_from_options_argument]
 ///
 /// # Returns
 /// * `{}` - bare key handle on success
 ///
 /// # Throws
 /// * When one of the inputs is incorrect.
+/// * When the spec is not supported by the provider.
 /// * When failing to generate the key.
+/// * When failing to store the metadata. The key is deleted in that case.
 pub fn export_create_key(mut cx: FunctionContext) -> JsResult<JsPromise> {
-    let provider_arc = (**cx.this::<JsProvider>()?).clone();
+    let provider_arc = boxed_this::<Provider>(&mut cx)?;
     let spec_js = cx.argument::<JsObject>(0)?;
 
-    let spec = unwrap_or_throw!(cx, from_wrapped_key_spec(&mut cx, spec_js));
+    let partial_spec = unwrap_or_throw_conversion!(
+        cx,
+        "spec",
+        from_wrapped_partial_key_spec(&mut cx, spec_js)
+    );
+    let metadata =
+        unwrap_or_throw_conversion!(cx, "options", metadata_from_options_argument(&mut cx, 1));
 
     spawn_promise(&mut cx, move |channel, deferred| {
-        let mut provider =
-            arc_or_poisoned_error_deferred!(&channel, deferred, provider_arc.write());
-
-        let key_handle_result = provider.create_key(spec);
-
-        deferred.settle_with(&channel, |mut cx| box_if_ok(&mut cx, key_handle_result));
+        let state = arc_or_poisoned_error_deferred!(&channel, deferred, provider_arc.read());
+        let (mut provider, metadata_store) = detach_provider_with_metadata(&state);
+
+        let spec = conversion_or_error_deferred!(
+            &channel,
+            deferred,
+            "spec",
+            complete_key_spec(partial_spec, provider.get_capabilities().as_ref())
+        );
+
+        // Persistent keys always get metadata, which persists their encryption counter
+        // and tells an unrestricted key apart from one, whose metadata was lost.
+        let metadata = metadata.or_else(|| (!spec.ephemeral).then(NewKeyMetadata::default));
+        let key_handle_result = provider
+            .create_key(spec)
+            .map_err(|err| err.to_string())
+            .and_then(|handle| attach_metadata(&metadata_store, handle, metadata));
+
+        deferred.settle_with(&channel, move |mut cx| {
+            box_stored_if_ok(&mut cx, key_handle_result, metadata_store)
+        });
     })
 }
 
 /// Wraps `create_key_pair` function.
 ///
+/// Missing fields of the spec are filled and given fields are validated with [complete_key_pair_spec].
+///
 /// # Arguments
-/// * **spec**: `KeyPairSpec`
+/// * **spec**: `Partial<KeyPairSpec>`
+/// * **options**: `{ metadata?: {...}, validity?: {...}, usages?: KeyUsage[], encryptionLimits?: {...} }` -
+///   optional, metadata, validity period, usages and encryption limits stored with the key pair,
+///   see [metadata_from_options_argument]
 ///
 /// # Returns
 /// * `{}` - bare key pair handle on success
 ///
 /// # Throws
 /// * When one of the inputs is incorrect.
+/// * When the spec is not supported by the provider.
 /// * When failing to generate the key pair.
+/// * When failing to store the metadata. The key pair is deleted in that case.
 pub fn export_create_key_pair(mut cx: FunctionContext) -> JsResult<JsPromise> {
-    let provider_arc = (**cx.this::<JsProvider>()?).clone();
+    let provider_arc = boxed_this::<Provider>(&mut cx)?;
     let spec_js = cx.argument::<JsObject>(0)?;
 
-    let spec = unwrap_or_throw!(cx, from_wrapped_key_pair_spec(&mut cx, spec_js));
+    let partial_spec = unwrap_or_throw_conversion!(
+        cx,
+        "spec",
+        from_wrapped_partial_key_pair_spec(&mut cx, spec_js)
+    );
+    let metadata =
+        unwrap_or_throw_conversion!(cx, "options", metadata_from_options_argument(&mut cx, 1));
 
     spawn_promise(&mut cx, move |channel, deferred| {
-        let mut provider =
-            arc_or_poisoned_error_deferred!(&channel, deferred, provider_arc.write());
-
-        let key_pair_handle_result = provider.create_key_pair(spec);
-
-        deferred.settle_with(&channel, |mut cx| {
-            box_if_ok(&mut cx, key_pair_handle_result)
+        let state = arc_or_poisoned_error_deferred!(&channel, deferred, provider_arc.read());
+        let (mut provider, metadata_store) = detach_provider_with_metadata(&state);
+
+        let spec = conversion_or_error_deferred!(
+            &channel,
+            deferred,
+            "spec",
+            complete_key_pair_spec(partial_spec, provider.get_capabilities().as_ref())
+        );
+
+        // Persistent key pairs always get metadata, see [crate::policy::check_key_policy].
+        let metadata = metadata.or_else(|| (!spec.ephemeral).then(NewKeyMetadata::default));
+        let key_pair_handle_result = provider
+            .create_key_pair(spec)
+            .map_err(|err| err.to_string())
+            .and_then(|handle| attach_metadata(&metadata_store, handle, metadata));
+
+        deferred.settle_with(&channel, move |mut cx| {
+            box_stored_if_ok(&mut cx, key_pair_handle_result, metadata_store)
         });
     })
 }
@@ -75,14 +254,44 @@ pub fn export_create_key_pair(mut cx: FunctionContext) -> JsResult<JsPromise> {
 ///
 /// # Throws
 pub fn export_provider_name(mut cx: FunctionContext) -> JsResult<JsPromise> {
-    let provider_js = cx.this::<JsProvider>()?;
-    let provider = unwrap_or_throw!(cx, provider_js.read());
+    let provider_arc = boxed_this::<Provider>(&mut cx)?;
+    let provider = unwrap_or_throw!(cx, provider_arc.read());
     let (deferred, promise) = cx.promise();
     let name = cx.string(provider.provider_name());
     deferred.resolve(&mut cx, name);
     Ok(promise)
 }
 
+/// Synchronous variant of [export_provider_name].
+///
+/// # Arguments
+///
+/// # Returns
+/// * `string` - provider name
+///
+/// # Throws
+/// * When the lock of the provider is poisoned.
+pub fn export_name_sync(mut cx: FunctionContext) -> JsResult<JsValue> {
+    let provider_arc = boxed_this::<Provider>(&mut cx)?;
+    let provider = unwrap_or_throw!(cx, rw_lock_poisoned(provider_arc.read()));
+
+    Ok(cx.string(provider.provider_name()).upcast())
+}
+
+/// Releases one acquisition of a cached provider returned by `createBareProviderFromName`.
+///
+/// Only counts the acquisitions and never ends the lifetime of the provider:
+/// it stays cached and usable as long as any instance of it is alive, see [release_provider].
+///
+/// # Returns
+/// * `number` - acquisitions, which are not released yet. `0` for providers, which are not cached.
+pub fn export_release(mut cx: FunctionContext) -> JsResult<JsValue> {
+    let provider_arc = boxed_this::<Provider>(&mut cx)?;
+    let references = release_provider(&provider_arc);
+
+    Ok(cx.number(references as f64).upcast())
+}
+
 /// Wraps `load_key` function.
 ///
 /// # Arguments
@@ -94,17 +303,19 @@ pub fn export_provider_name(mut cx: FunctionContext) -> JsResult<JsPromise> {
 /// # Throws
 /// * When failing to load the key.
 pub fn export_load_key(mut cx: FunctionContext) -> JsResult<JsPromise> {
-    let provider_arc = (**cx.this::<JsProvider>()?).clone();
+    let provider_arc = boxed_this::<Provider>(&mut cx)?;
     let id_js = cx.argument::<JsString>(0)?;
     let id = id_js.value(&mut cx);
 
     spawn_promise(&mut cx, move |channel, deferred| {
-        let mut provider =
-            arc_or_poisoned_error_deferred!(&channel, deferred, provider_arc.write());
+        let state = arc_or_poisoned_error_deferred!(&channel, deferred, provider_arc.read());
+        let (mut provider, metadata_store) = detach_provider_with_metadata(&state);
 
         let key_handle_result = provider.load_key(id.clone());
 
-        deferred.settle_with(&channel, |mut cx| box_if_ok(&mut cx, key_handle_result));
+        deferred.settle_with(&channel, move |mut cx| {
+            box_stored_if_ok(&mut cx, key_handle_result, metadata_store)
+        });
     })
 }
 
@@ -119,17 +330,19 @@ pub fn export_load_key(mut cx: FunctionContext) -> JsResult<JsPromise> {
 /// # Throws
 /// * When failing to load the key pair.
 pub fn export_load_key_pair(mut cx: FunctionContext) -> JsResult<JsPromise> {
-    let provider_arc = (**cx.this::<JsProvider>()?).clone();
+    let provider_arc = boxed_this::<Provider>(&mut cx)?;
     let id_js = cx.argument::<JsString>(0)?;
     let id = id_js.value(&mut cx);
 
     spawn_promise(&mut cx, move |channel, deferred| {
-        let mut provider =
-            arc_or_poisoned_error_deferred!(&channel, deferred, provider_arc.write());
+        let state = arc_or_poisoned_error_deferred!(&channel, deferred, provider_arc.read());
+        let (mut provider, metadata_store) = detach_provider_with_metadata(&state);
 
         let key_pair_handle = provider.load_key_pair(id.clone());
 
-        deferred.settle_with(&channel, |mut cx| box_if_ok(&mut cx, key_pair_handle));
+        deferred.settle_with(&channel, move |mut cx| {
+            box_stored_if_ok(&mut cx, key_pair_handle, metadata_store)
+        });
     })
 }
 
@@ -138,6 +351,8 @@ pub fn export_load_key_pair(mut cx: FunctionContext) -> JsResult<JsPromise> {
 /// # Arguments
 /// * **spec**: `KeySpec`
 /// * **key**: `Uint8Array`
+/// * **options**: `{ zeroizeSource?: boolean, metadata?: {...} }` - optional, wipes `key` after it was copied
+///   and stores metadata like [export_create_key]
 ///
 /// # Returns
 /// * `{}` - bare key handle on success
@@ -145,20 +360,34 @@ pub fn export_load_key_pair(mut cx: FunctionContext) -> JsResult<JsPromise> {
 /// # Throws
 /// * When one of the inputs is incorrect.
 /// * When failing to import the key.
+/// * When failing to store the metadata. The key is deleted in that case.
 pub fn export_import_key(mut cx: FunctionContext) -> JsResult<JsPromise> {
-    let provider_arc = (**cx.this::<JsProvider>()?).clone();
+    let provider_arc = boxed_this::<Provider>(&mut cx)?;
     let spec_js = cx.argument::<JsObject>(0)?;
-    let spec = unwrap_or_throw!(cx, from_wrapped_key_spec(&mut cx, spec_js));
+    let spec = unwrap_or_throw_conversion!(cx, "spec", from_wrapped_key_spec(&mut cx, spec_js));
     let raw_key_js = cx.argument::<JsUint8Array>(1)?;
-    let raw_key = vec_from_uint_8_array(&mut cx, raw_key_js);
+    let raw_key = secret_from_uint_8_array(&mut cx, raw_key_js);
+    let metadata =
+        unwrap_or_throw_conversion!(cx, "options", metadata_from_options_argument(&mut cx, 2));
+    if unwrap_or_throw!(cx, flag_from_options_argument(&mut cx, 2, ZEROIZE_SOURCE_OPTION)) {
+        zeroize_uint_8_array(&mut cx, raw_key_js);
+    }
 
     spawn_promise(&mut cx, move |channel, deferred| {
-        let mut provider =
-            arc_or_poisoned_error_deferred!(&channel, deferred, provider_arc.write());
-
-        let key_handle = provider.import_key(spec, &raw_key);
-
-        deferred.settle_with(&channel, |mut cx| box_if_ok(&mut cx, key_handle));
+        let state = arc_or_poisoned_error_deferred!(&channel, deferred, provider_arc.read());
+        let (mut provider, metadata_store) = detach_provider_with_metadata(&state);
+
+        // Persistent keys always get metadata, which persists their encryption counter
+        // and tells an unrestricted key apart from one, whose metadata was lost.
+        let metadata = metadata.or_else(|| (!spec.ephemeral).then(NewKeyMetadata::default));
+        let key_handle = provider
+            .import_key(spec, &raw_key)
+            .map_err(|err| err.to_string())
+            .and_then(|handle| attach_metadata(&metadata_store, handle, metadata));
+
+        deferred.settle_with(&channel, move |mut cx| {
+            box_stored_if_ok(&mut cx, key_handle, metadata_store)
+        });
     })
 }
 
@@ -168,6 +397,8 @@ pub fn export_import_key(mut cx: FunctionContext) -> JsResult<JsPromise> {
 /// * **spec**: `KeyPairSpec`
 /// * **publicKey**: `Uint8Array`
 /// * **privateKey**: `Uint8Array`
+/// * **options**: `{ zeroizeSource?: boolean, metadata?: {...} }` - optional, wipes `privateKey` after it was
+///   copied and stores metadata like [export_create_key_pair]
 ///
 /// # Returns
 /// * `{}` - bare key pair handle on success
@@ -175,22 +406,39 @@ pub fn export_import_key(mut cx: FunctionContext) -> JsResult<JsPromise> {
 /// # Throws
 /// * When one of the inputs is incorrect.
 /// * When failing to import the key pair.
+/// * When failing to store the metadata. The key pair is deleted in that case.
 pub fn export_import_key_pair(mut cx: FunctionContext) -> JsResult<JsPromise> {
-    let provider_arc = (**cx.this::<JsProvider>()?).clone();
+    let provider_arc = boxed_this::<Provider>(&mut cx)?;
     let spec_js = cx.argument::<JsObject>(0)?;
-    let spec = unwrap_or_throw!(cx, from_wrapped_key_pair_spec(&mut cx, spec_js));
+    let spec = unwrap_or_throw_conversion!(
+        cx,
+        "spec",
+        from_wrapped_key_pair_spec(&mut cx, spec_js)
+    );
     let raw_public_key_js = cx.argument::<JsUint8Array>(1)?;
     let raw_public_key = vec_from_uint_8_array(&mut cx, raw_public_key_js);
     let raw_private_key_js = cx.argument::<JsUint8Array>(2)?;
-    let raw_private_key = vec_from_uint_8_array(&mut cx, raw_private_key_js);
+    let raw_private_key = secret_from_uint_8_array(&mut cx, raw_private_key_js);
+    let metadata =
+        unwrap_or_throw_conversion!(cx, "options", metadata_from_options_argument(&mut cx, 3));
+    if unwrap_or_throw!(cx, flag_from_options_argument(&mut cx, 3, ZEROIZE_SOURCE_OPTION)) {
+        zeroize_uint_8_array(&mut cx, raw_private_key_js);
+    }
 
     spawn_promise(&mut cx, move |channel, deferred| {
-        let mut provider =
-            arc_or_poisoned_error_deferred!(&channel, deferred, provider_arc.write());
-
-        let key_pair_handle = provider.import_key_pair(spec, &raw_public_key, &raw_private_key);
-
-        deferred.settle_with(&channel, |mut cx| box_if_ok(&mut cx, key_pair_handle));
+        let state = arc_or_poisoned_error_deferred!(&channel, deferred, provider_arc.read());
+        let (mut provider, metadata_store) = detach_provider_with_metadata(&state);
+
+        // Persistent key pairs always get metadata, see [crate::policy::check_key_policy].
+        let metadata = metadata.or_else(|| (!spec.ephemeral).then(NewKeyMetadata::default));
+        let key_pair_handle = provider
+            .import_key_pair(spec, &raw_public_key, &raw_private_key)
+            .map_err(|err| err.to_string())
+            .and_then(|handle| attach_metadata(&metadata_store, handle, metadata));
+
+        deferred.settle_with(&channel, move |mut cx| {
+            box_stored_if_ok(&mut cx, key_pair_handle, metadata_store)
+        });
     })
 }
 
@@ -206,20 +454,32 @@ pub fn export_import_key_pair(mut cx: FunctionContext) -> JsResult<JsPromise> {
 /// # Throws
 /// * When one of the inputs is incorrect.
 /// * When failing to import the public key.
+/// * When failing to store the metadata. The key pair is deleted in that case.
 pub fn export_import_public_key(mut cx: FunctionContext) -> JsResult<JsPromise> {
-    let provider_arc = (**cx.this::<JsProvider>()?).clone();
+    let provider_arc = boxed_this::<Provider>(&mut cx)?;
     let spec_js = cx.argument::<JsObject>(0)?;
-    let spec = unwrap_or_throw!(cx, from_wrapped_key_pair_spec(&mut cx, spec_js));
+    let spec = unwrap_or_throw_conversion!(
+        cx,
+        "spec",
+        from_wrapped_key_pair_spec(&mut cx, spec_js)
+    );
     let raw_public_key_js = cx.argument::<JsUint8Array>(1)?;
     let raw_public_key = vec_from_uint_8_array(&mut cx, raw_public_key_js);
 
     spawn_promise(&mut cx, move |channel, deferred| {
-        let mut provider =
-            arc_or_poisoned_error_deferred!(&channel, deferred, provider_arc.write());
-
-        let key_pair_handle = provider.import_public_key(spec, &raw_public_key);
-
-        deferred.settle_with(&channel, |mut cx| box_if_ok(&mut cx, key_pair_handle));
+        let state = arc_or_poisoned_error_deferred!(&channel, deferred, provider_arc.read());
+        let (mut provider, metadata_store) = detach_provider_with_metadata(&state);
+
+        // Persistent public keys always get metadata, see [crate::policy::check_key_policy].
+        let metadata = (!spec.ephemeral).then(NewKeyMetadata::default);
+        let key_pair_handle = provider
+            .import_public_key(spec, &raw_public_key)
+            .map_err(|err| err.to_string())
+            .and_then(|handle| attach_metadata(&metadata_store, handle, metadata));
+
+        deferred.settle_with(&channel, move |mut cx| {
+            box_stored_if_ok(&mut cx, key_pair_handle, metadata_store)
+        });
     })
 }
 
@@ -234,7 +494,7 @@ pub fn export_import_public_key(mut cx: FunctionContext) -> JsResult<JsPromise>
 /// # Throws
 /// * When failing to wrap provider config.
 pub fn export_get_capabilities(mut cx: FunctionContext) -> JsResult<JsPromise> {
-    let provider_arc = (**cx.this::<JsProvider>()?).clone();
+    let provider_arc = boxed_this::<Provider>(&mut cx)?;
 
     spawn_promise(&mut cx, move |channel, deferred| {
         let provider = arc_or_poisoned_error_deferred!(&channel, deferred, provider_arc.read());
@@ -249,6 +509,40 @@ pub fn export_get_capabilities(mut cx: FunctionContext) -> JsResult<JsPromise> {
     })
 }
 
+/// Fills missing fields of a spec with values supported by the provider and validates given fields.
+///
+/// See [complete_key_spec] and [complete_key_pair_spec] for the chosen defaults.
+///
+/// # Arguments
+/// * **spec**: `{ KeySpec: Partial<KeySpec> } | { KeyPairSpec: Partial<KeyPairSpec> }`
+///
+/// # Returns
+/// * `Spec` - complete spec of the same variant
+///
+/// # Throws
+/// * When one of the inputs is incorrect.
+/// * When the spec is not supported by the provider.
+pub fn export_complete_spec(mut cx: FunctionContext) -> JsResult<JsPromise> {
+    let provider_arc = boxed_this::<Provider>(&mut cx)?;
+    let spec_js = cx.argument::<JsObject>(0)?;
+
+    let partial_spec =
+        unwrap_or_throw_conversion!(cx, "spec", from_wrapped_partial_spec(&mut cx, spec_js));
+
+    spawn_promise(&mut cx, move |channel, deferred| {
+        let provider = arc_or_poisoned_error_deferred!(&channel, deferred, provider_arc.read());
+
+        let spec = conversion_or_error_deferred!(
+            &channel,
+            deferred,
+            "spec",
+            complete_spec(partial_spec, provider.get_capabilities().as_ref())
+        );
+
+        deferred.settle_with(&channel, |mut cx| wrap_spec(&mut cx, spec));
+    })
+}
+
 /// Wraps `ephemeral_dh_exchange` function.
 ///
 /// # Arguments
@@ -261,13 +555,17 @@ pub fn export_get_capabilities(mut cx: FunctionContext) -> JsResult<JsPromise> {
 /// * When one of the inputs is incorrect.
 /// * When failing to start the dh exchange.
 pub fn export_start_ephemeral_dh_exchange(mut cx: FunctionContext) -> JsResult<JsPromise> {
-    let provider_arc = (**cx.this::<JsProvider>()?).clone();
+    let provider_arc = boxed_this::<Provider>(&mut cx)?;
     let spec_js = cx.argument::<JsObject>(0)?;
-    let spec = unwrap_or_throw!(cx, from_wrapped_key_pair_spec(&mut cx, spec_js));
+    let spec = unwrap_or_throw_conversion!(
+        cx,
+        "spec",
+        from_wrapped_key_pair_spec(&mut cx, spec_js)
+    );
 
     spawn_promise(&mut cx, move |channel, deferred| {
-        let mut provider =
-            arc_or_poisoned_error_deferred!(&channel, deferred, provider_arc.write());
+        let state = arc_or_poisoned_error_deferred!(&channel, deferred, provider_arc.read());
+        let mut provider = detach_provider(&state);
 
         let dh_exchange = provider.start_ephemeral_dh_exchange(spec);
 
@@ -281,6 +579,7 @@ pub fn export_start_ephemeral_dh_exchange(mut cx: FunctionContext) -> JsResult<J
 /// * **public_key**: `Uint8Array` - The public key bytes
 /// * **private_key**: `Uint8Array` - The private key bytes
 /// * **spec**: `KeyPairSpec` - The key pair specification
+/// * **options**: `{ zeroizeSource?: boolean }` - optional, wipes `private_key` after it was copied
 ///
 /// # Returns
 /// * `{}` - bare dh exchange
@@ -289,17 +588,24 @@ pub fn export_start_ephemeral_dh_exchange(mut cx: FunctionContext) -> JsResult<J
 /// * When one of the inputs is incorrect.
 /// * When failing to create the dh exchange from the provided keys.
 pub fn export_dh_exchange_from_keys(mut cx: FunctionContext) -> JsResult<JsPromise> {
-    let provider_arc = (**cx.this::<JsProvider>()?).clone();
+    let provider_arc = boxed_this::<Provider>(&mut cx)?;
     let public_key_js = cx.argument::<JsUint8Array>(0)?;
     let public_key = vec_from_uint_8_array(&mut cx, public_key_js);
     let private_key_js = cx.argument::<JsUint8Array>(1)?;
-    let private_key = vec_from_uint_8_array(&mut cx, private_key_js);
+    let private_key = secret_from_uint_8_array(&mut cx, private_key_js);
     let spec_js = cx.argument::<JsObject>(2)?;
-    let spec = unwrap_or_throw!(cx, from_wrapped_key_pair_spec(&mut cx, spec_js));
+    let spec = unwrap_or_throw_conversion!(
+        cx,
+        "spec",
+        from_wrapped_key_pair_spec(&mut cx, spec_js)
+    );
+    if unwrap_or_throw!(cx, flag_from_options_argument(&mut cx, 3, ZEROIZE_SOURCE_OPTION)) {
+        zeroize_uint_8_array(&mut cx, private_key_js);
+    }
 
     spawn_promise(&mut cx, move |channel, deferred| {
-        let mut provider =
-            arc_or_poisoned_error_deferred!(&channel, deferred, provider_arc.write());
+        let state = arc_or_poisoned_error_deferred!(&channel, deferred, provider_arc.read());
+        let mut provider = detach_provider(&state);
 
         let dh_exchange = provider.dh_exchange_from_keys(&public_key, &private_key, spec);
 
@@ -321,13 +627,13 @@ pub fn export_dh_exchange_from_keys(mut cx: FunctionContext) -> JsResult<JsPromi
 /// # Throws
 /// * When one of the inputs is incorrect.
 pub fn export_derive_key_from_password(mut cx: FunctionContext) -> JsResult<JsPromise> {
-    let provider_arc = (**cx.this::<JsProvider>()?).clone();
+    let provider_arc = boxed_this::<Provider>(&mut cx)?;
     let password_js = cx.argument::<JsString>(0)?;
-    let password = password_js.value(&mut cx);
+    let password = Zeroizing::new(password_js.value(&mut cx));
     let salt_js = cx.argument::<JsUint8Array>(1)?;
     let salt = vec_from_uint_8_array(&mut cx, salt_js);
     let spec_js = cx.argument::<JsObject>(2)?;
-    let spec = unwrap_or_throw!(cx, from_wrapped_key_spec(&mut cx, spec_js));
+    let spec = unwrap_or_throw_conversion!(cx, "spec", from_wrapped_key_spec(&mut cx, spec_js));
     let kdf_js = cx.argument::<JsObject>(3)?;
     let kdf = unwrap_or_throw!(cx, kdf_from_object(&mut cx, kdf_js));
 
@@ -354,15 +660,15 @@ pub fn export_derive_key_from_password(mut cx: FunctionContext) -> JsResult<JsPr
 /// # Throws
 /// * When one of the inputs is incorrect.
 pub fn export_derive_key_from_base(mut cx: FunctionContext) -> JsResult<JsPromise> {
-    let provider_arc = (**cx.this::<JsProvider>()?).clone();
+    let provider_arc = boxed_this::<Provider>(&mut cx)?;
     let base_key_js = cx.argument::<JsUint8Array>(0)?;
-    let base_key = vec_from_uint_8_array(&mut cx, base_key_js);
+    let base_key = secret_from_uint_8_array(&mut cx, base_key_js);
     let key_id_js = cx.argument::<JsNumber>(1)?;
     let key_id: u64 = unwrap_or_throw!(cx, int_from_js_number(&mut cx, key_id_js));
     let context_js = cx.argument::<JsString>(2)?;
     let context = context_js.value(&mut cx);
     let spec_js = cx.argument::<JsObject>(3)?;
-    let spec = unwrap_or_throw!(cx, from_wrapped_key_spec(&mut cx, spec_js));
+    let spec = unwrap_or_throw_conversion!(cx, "spec", from_wrapped_key_spec(&mut cx, spec_js));
 
     spawn_promise(&mut cx, move |channel, deferred| {
         let provider = arc_or_poisoned_error_deferred!(&channel, deferred, provider_arc.read());
@@ -384,7 +690,7 @@ pub fn export_derive_key_from_base(mut cx: FunctionContext) -> JsResult<JsPromis
 /// # Throws
 /// * When `len` is negative.
 pub fn export_get_random(mut cx: FunctionContext) -> JsResult<JsPromise> {
-    let provider_arc = (**cx.this::<JsProvider>()?).clone();
+    let provider_arc = boxed_this::<Provider>(&mut cx)?;
     let len_js = cx.argument::<JsNumber>(0)?;
     let len = len_js.value(&mut cx);
     let len_trunc = len.trunc();
@@ -418,12 +724,12 @@ pub fn export_get_random(mut cx: FunctionContext) -> JsResult<JsPromise> {
 /// # Throws
 /// * When one of the inputs is incorrect.
 pub fn export_hash(mut cx: FunctionContext) -> JsResult<JsPromise> {
-    let provider_arc = (**cx.this::<JsProvider>()?).clone();
+    let provider_arc = boxed_this::<Provider>(&mut cx)?;
     let data_js = cx.argument::<JsUint8Array>(0)?;
     let data = vec_from_uint_8_array(&mut cx, data_js);
     let hash_algo_js = cx.argument::<JsValue>(1)?;
     let hash_algo: CryptoHash =
-        unwrap_or_throw!(cx, from_wrapped_simple_enum(&mut cx, hash_algo_js));
+        unwrap_or_throw_conversion!(cx, "hash", from_wrapped_simple_enum(&mut cx, hash_algo_js));
 
     spawn_promise(&mut cx, move |channel, deferred| {
         let provider = arc_or_poisoned_error_deferred!(&channel, deferred, provider_arc.read());
@@ -437,34 +743,483 @@ pub fn export_hash(mut cx: FunctionContext) -> JsResult<JsPromise> {
     })
 }
 
+/// Wraps `hash` function for many inputs at once.
+///
+/// The provider is locked once and the inputs are hashed in parallel.
+///
+/// # Arguments
+/// * **data**: `Uint8Array[]`
+/// * **hash**: `string` hash algorithm to use.
+///
+/// # Returns
+/// * `BatchResult<Uint8Array>[]` - per input result
+///
+/// # Throws
+/// * When one of the inputs is incorrect.
+pub fn export_hash_many(mut cx: FunctionContext) -> JsResult<JsPromise> {
+    let provider_arc = boxed_this::<Provider>(&mut cx)?;
+    let items_js = cx.argument::<JsArray>(0)?;
+    let items = unwrap_or_throw!(cx, vec_from_uint_8_array_array(&mut cx, items_js));
+    let hash_algo_js = cx.argument::<JsValue>(1)?;
+    let hash_algo: CryptoHash =
+        unwrap_or_throw_conversion!(cx, "hash", from_wrapped_simple_enum(&mut cx, hash_algo_js));
+
+    spawn_promise(&mut cx, move |channel, deferred| {
+        let state = arc_or_poisoned_error_deferred!(&channel, deferred, provider_arc.read());
+        let provider = detach_provider(&state);
+
+        let results = parallel_map(items, move |data| provider.hash(&data, hash_algo));
+
+        deferred.settle_with(&channel, |mut cx| {
+            wrap_batch_results(&mut cx, results, |cx, hash| {
+                Ok(uint_8_array_from_vec_u8(cx, hash)?.upcast())
+            })
+        });
+    })
+}
+
 /// Wraps `get_all_keys` function.
 ///
 /// # Arguments
+/// * **options**: `{ metadata?: boolean }` - optional, appends the metadata of every key
 ///
 /// # Returns
 /// * `[string, Spec][]` - list of key id and key spec
+/// * `[string, Spec, KeyMetadata | null][]` - list of key id, key spec and metadata, if `metadata` is set
 ///
 /// # Throws
+/// * When one of the inputs is incorrect.
 pub fn export_get_all_keys(mut cx: FunctionContext) -> JsResult<JsPromise> {
-    let provider_arc = (**cx.this::<JsProvider>()?).clone();
+    let provider_arc = boxed_this::<Provider>(&mut cx)?;
+    let with_metadata =
+        unwrap_or_throw!(cx, flag_from_options_argument(&mut cx, 0, METADATA_OPTION));
 
     spawn_promise(&mut cx, move |channel, deferred| {
         let provider = arc_or_poisoned_error_deferred!(&channel, deferred, provider_arc.read());
 
-        let keys_result = provider.get_all_keys();
+        let keys_result = provider.get_all_keys().map(|keys| {
+            keys.into_iter()
+                .map(|(id, spec)| {
+                    let metadata = with_metadata.then(|| provider.metadata.get(&id));
+                    (id, spec, metadata)
+                })
+                .collect::<Vec<_>>()
+        });
 
         deferred.settle_with(&channel, |mut cx| {
             let keys = unwrap_or_throw!(cx, keys_result);
-            js_array_from_vec(&mut cx, keys, |cx, (id, spec)| {
+            js_array_from_vec(&mut cx, keys, |cx, (id, spec, metadata)| {
                 let spec_js = wrap_spec(cx, spec)?;
                 let id_js = JsString::new(cx, id);
 
                 let result = JsArray::new(cx, 2);
                 result.prop(cx, 0).set(id_js)?;
                 result.prop(cx, 1).set(spec_js)?;
+                if let Some(metadata) = metadata {
+                    let metadata_js = wrap_optional_key_metadata(cx, metadata)?;
+                    result.prop(cx, 2).set(metadata_js)?;
+                }
 
                 Ok(result.upcast())
             })
         });
     })
 }
+
+/// Lists keys matching a filter page by page.
+///
+/// See [list_keys] for the order of the keys and the meaning of the cursor.
+///
+/// # Arguments
+/// * **options**: `{ filter?: KeyFilter, cursor?: string, limit?: number }` - optional,
+///   `limit` defaults to [DEFAULT_PAGE_LIMIT]
+///
+/// # Returns
+/// * `{ keys: [string, Spec][], cursor: string | null }` - page of keys and the cursor of the next page
+///
+/// # Throws
+/// * When one of the inputs is incorrect.
+pub fn export_list_keys(mut cx: FunctionContext) -> JsResult<JsPromise> {
+    let provider_arc = boxed_this::<Provider>(&mut cx)?;
+    let options =
+        unwrap_or_throw_conversion!(cx, "options", list_keys_options_from_argument(&mut cx, 0));
+
+    spawn_promise(&mut cx, move |channel, deferred| {
+        let provider = arc_or_poisoned_error_deferred!(&channel, deferred, provider_arc.read());
+
+        let page_result = provider.get_all_keys().map(|keys| {
+            list_keys(
+                keys,
+                &options.filter,
+                &provider.metadata,
+                options.cursor.as_deref(),
+                options.limit,
+            )
+        });
+
+        deferred.settle_with(&channel, |mut cx| {
+            let page = unwrap_or_throw!(cx, page_result);
+            wrap_key_page(&mut cx, page)
+        });
+    })
+}
+
+/// Counts the keys matching a filter.
+///
+/// # Arguments
+/// * **filter**: `KeyFilter` - optional, counts every key if missing
+///
+/// # Returns
+/// * `number` - amount of matching keys
+///
+/// # Throws
+/// * When one of the inputs is incorrect.
+pub fn export_count_keys(mut cx: FunctionContext) -> JsResult<JsPromise> {
+    let provider_arc = boxed_this::<Provider>(&mut cx)?;
+    let filter = unwrap_or_throw_conversion!(cx, "filter", key_filter_from_argument(&mut cx, 0));
+
+    spawn_promise(&mut cx, move |channel, deferred| {
+        let provider = arc_or_poisoned_error_deferred!(&channel, deferred, provider_arc.read());
+
+        let count_result = provider
+            .get_all_keys()
+            .map(|keys| count_keys(&keys, &filter, &provider.metadata));
+
+        deferred.settle_with(&channel, move |mut cx| {
+            let count = unwrap_or_throw!(cx, count_result);
+            Ok(cx.number(count as f64))
+        });
+    })
+}
+
+/// Creates the next version of a key with the same spec and makes the previous version decrypt-only.
+///
+/// See [rotate_key].
+///
+/// # Arguments
+/// * **id**: `string` - id of the newest version of the key family or of a key, which was never rotated
+///
+/// # Returns
+/// * `{}` - bare key handle of the new version on success
+///
+/// # Throws
+/// * When the key is not the newest version of its family.
+/// * When failing to load or create the key.
+/// * When failing to store the metadata. The new key is deleted in that case.
+pub fn export_rotate_key(mut cx: FunctionContext) -> JsResult<JsPromise> {
+    let provider_arc = boxed_this::<Provider>(&mut cx)?;
+    let id_js = cx.argument::<JsString>(0)?;
+    let id = id_js.value(&mut cx);
+
+    spawn_promise(&mut cx, move |channel, deferred| {
+        let mut state = arc_or_poisoned_error_deferred!(&channel, deferred, provider_arc.write());
+        let (provider, metadata_store) = exclusive_provider_with_metadata(&mut state);
+
+        let result = rotate_key(provider, metadata_store, &id);
+
+        let store = metadata_store.clone();
+        deferred.settle_with(&channel, move |mut cx| match result {
+            Ok(handle) => new_stored_instance(&mut cx, handle, store),
+            Err(err) => throw_rotation_error(&mut cx, &err),
+        });
+    })
+}
+
+/// Encrypts data with the newest version of a key family.
+///
+/// # Arguments
+/// * **familyId**: `string` - id of the first version of the family
+/// * **data**: `Uint8Array`
+///
+/// # Returns
+/// * `Uint8Array` - ciphertext prefixed with a header naming the key version
+///
+/// # Throws
+/// * When the family does not exist.
+/// * `KeyUsageError` or `KeyNotValidError`, when the policy of the newest version forbids encryption.
+/// * When failing to encrypt.
+pub fn export_encrypt_for_family(mut cx: FunctionContext) -> JsResult<JsPromise> {
+    let provider_arc = boxed_this::<Provider>(&mut cx)?;
+    let family_id_js = cx.argument::<JsString>(0)?;
+    let family_id = family_id_js.value(&mut cx);
+    let data_js = cx.argument::<JsUint8Array>(1)?;
+    let data = Zeroizing::new(vec_from_uint_8_array(&mut cx, data_js));
+
+    spawn_promise(&mut cx, move |channel, deferred| {
+        let state = arc_or_poisoned_error_deferred!(&channel, deferred, provider_arc.read());
+        let (mut provider, metadata_store) = detach_provider_with_metadata(&state);
+
+        let result = encrypt_for_family(&mut provider, &metadata_store, &family_id, &data);
+
+        deferred.settle_with(&channel, move |mut cx| match result {
+            Ok(sealed) => Ok(uint_8_array_from_vec_u8(&mut cx, sealed)?),
+            Err(err) => throw_rotation_error(&mut cx, &err),
+        });
+    })
+}
+
+/// Decrypts data encrypted by [export_encrypt_for_family] with the key version named in its header.
+///
+/// # Arguments
+/// * **familyId**: `string` - id of the first version of the family
+/// * **ciphertext**: `Uint8Array`
+///
+/// # Returns
+/// * `Uint8Array` - decrypted data
+///
+/// # Throws
+/// * When the header is malformed or names a key outside of the family.
+/// * `KeyUsageError` or `KeyNotValidError`, when the policy of the key version forbids decryption.
+/// * When failing to decrypt.
+pub fn export_decrypt_for_family(mut cx: FunctionContext) -> JsResult<JsPromise> {
+    let provider_arc = boxed_this::<Provider>(&mut cx)?;
+    let family_id_js = cx.argument::<JsString>(0)?;
+    let family_id = family_id_js.value(&mut cx);
+    let ciphertext_js = cx.argument::<JsUint8Array>(1)?;
+    let ciphertext = vec_from_uint_8_array(&mut cx, ciphertext_js);
+
+    spawn_promise(&mut cx, move |channel, deferred| {
+        let state = arc_or_poisoned_error_deferred!(&channel, deferred, provider_arc.read());
+        let (mut provider, metadata_store) = detach_provider_with_metadata(&state);
+
+        let result = decrypt_for_family(&mut provider, &metadata_store, &family_id, &ciphertext);
+
+        deferred.settle_with(&channel, move |mut cx| match result {
+            Ok(data) => Ok(uint_8_array_from_vec_u8(&mut cx, data)?),
+            Err(err) => throw_rotation_error(&mut cx, &err),
+        });
+    })
+}
+
+/// Re-encrypts data encrypted by [export_encrypt_for_family] with the newest version of the same key family.
+///
+/// # Arguments
+/// * **ciphertext**: `Uint8Array`
+///
+/// # Returns
+/// * `Uint8Array` - ciphertext of the newest version
+///
+/// # Throws
+/// * When the header is malformed or names a key without family.
+/// * `KeyUsageError` or `KeyNotValidError`, when a policy forbids decryption or encryption.
+/// * When failing to decrypt or encrypt.
+pub fn export_rewrap(mut cx: FunctionContext) -> JsResult<JsPromise> {
+    let provider_arc = boxed_this::<Provider>(&mut cx)?;
+    let ciphertext_js = cx.argument::<JsUint8Array>(0)?;
+    let ciphertext = vec_from_uint_8_array(&mut cx, ciphertext_js);
+
+    spawn_promise(&mut cx, move |channel, deferred| {
+        let state = arc_or_poisoned_error_deferred!(&channel, deferred, provider_arc.read());
+        let (mut provider, metadata_store) = detach_provider_with_metadata(&state);
+
+        let result = rewrap(&mut provider, &metadata_store, &ciphertext);
+
+        deferred.settle_with(&channel, move |mut cx| match result {
+            Ok(sealed) => Ok(uint_8_array_from_vec_u8(&mut cx, sealed)?),
+            Err(err) => throw_rotation_error(&mut cx, &err),
+        });
+    })
+}
+
+/// Serializes every exportable key with its spec and metadata into an encrypted, authenticated archive.
+///
+/// See [export_backup].
+///
+/// # Arguments
+/// * **protection**: `string | {}` - password or bare key handle encrypting the archive
+/// * **options**: `{ kdf?: KDF }` - optional, kdf deriving the key from the password,
+///   Argon2id with 19 MiB, 2 iterations and 1 lane by default
+///
+/// # Returns
+/// * `{ archive: Uint8Array, skipped: string[] }` - archive and ids of keys, which cannot be exported
+///
+/// # Throws
+/// * When one of the inputs is incorrect.
+/// * When failing to list, load or extract the keys.
+/// * When failing to derive the key or to encrypt the archive.
+pub fn export_export_backup(mut cx: FunctionContext) -> JsResult<JsPromise> {
+    let provider_arc = boxed_this::<Provider>(&mut cx)?;
+    let protection =
+        unwrap_or_throw_conversion!(cx, "protection", backup_protection_from_argument(&mut cx, 0));
+    let kdf =
+        unwrap_or_throw_conversion!(cx, "options", export_backup_options_from_argument(&mut cx, 1));
+
+    spawn_promise(&mut cx, move |channel, deferred| {
+        let state = arc_or_poisoned_error_deferred!(&channel, deferred, provider_arc.read());
+        let (mut provider, metadata_store) = detach_provider_with_metadata(&state);
+
+        let result = export_backup(&mut provider, &metadata_store, &protection, kdf);
+
+        deferred.settle_with(&channel, move |mut cx| match result {
+            Ok(backup) => wrap_exported_backup(&mut cx, backup),
+            Err(err) => throw_backup_error(&mut cx, &err),
+        });
+    })
+}
+
+/// Restores the keys of an archive of [export_export_backup] with their metadata.
+///
+/// Ids are not preserved, as crypto-layer assigns the id of every imported key:
+/// restored keys get new ids, which are returned with the ids in the archive.
+/// Family ids of rotated keys are changed accordingly. See [import_backup].
+///
+/// # Arguments
+/// * **archive**: `Uint8Array`
+/// * **protection**: `string | {}` - password or bare key handle, which encrypted the archive
+/// * **options**: `{ onConflict?: "fail" | "skip" | "duplicate" }` - optional,
+///   handling of keys, which exist in the provider, `"fail"` by default
+///
+/// # Returns
+/// * `{ restored: { originalId: string, id: string }[], skipped: { originalId: string, id: string }[] }`
+///
+/// # Throws
+/// * When one of the inputs is incorrect.
+/// * When the archive is malformed or its kdf parameters exceed the limits.
+/// * `BackupError` with `code` `ERR_BACKUP_AUTHENTICATION`,
+///   when the archive was modified or the password or key is wrong.
+/// * `BackupError` with `code` `ERR_BACKUP_CONFLICT` and `keyIds`,
+///   when keys exist and `onConflict` is `"fail"`.
+/// * When failing to import a key or to store the metadata. No key is restored in that case.
+pub fn export_import_backup(mut cx: FunctionContext) -> JsResult<JsPromise> {
+    let provider_arc = boxed_this::<Provider>(&mut cx)?;
+    let archive_js = cx.argument::<JsUint8Array>(0)?;
+    let archive = vec_from_uint_8_array(&mut cx, archive_js);
+    let protection =
+        unwrap_or_throw_conversion!(cx, "protection", backup_protection_from_argument(&mut cx, 1));
+    let on_conflict =
+        unwrap_or_throw_conversion!(cx, "options", import_backup_options_from_argument(&mut cx, 2));
+
+    spawn_promise(&mut cx, move |channel, deferred| {
+        let mut state = arc_or_poisoned_error_deferred!(&channel, deferred, provider_arc.write());
+        let (provider, metadata_store) = exclusive_provider_with_metadata(&mut state);
+
+        let result = import_backup(provider, metadata_store, &archive, &protection, on_conflict);
+
+        deferred.settle_with(&channel, move |mut cx| match result {
+            Ok(backup) => wrap_imported_backup(&mut cx, backup),
+            Err(err) => throw_backup_error(&mut cx, &err),
+        });
+    })
+}
+
+/// Copies the keys of this provider with their metadata into another provider.
+///
+/// See [migrate_keys].
+///
+/// # Arguments
+/// * **target**: `{}` - bare provider receiving the keys
+/// * **options**: `{ filter?: KeyFilter, deleteSource?: boolean }` - optional,
+///   keys to migrate and whether to delete them from this provider once copied
+///
+/// # Returns
+/// * `{ keys: { id: string, status: "migrated" | "alreadyMigrated" | "unsupported" | "failed",
+///   newId: string | null, sourceDeleted: boolean, error: string | null }[], ids: Record<string, string> }` -
+///   one result per key matching the filter and the ids of the copies by the ids of their source keys
+///
+/// # Throws
+/// * When one of the inputs is incorrect.
+/// * When both providers use the same storage.
+/// * When failing to list the keys of this provider.
+pub fn export_migrate_keys_to(mut cx: FunctionContext) -> JsResult<JsPromise> {
+    let provider_arc = boxed_this::<Provider>(&mut cx)?;
+    let target_arc =
+        unwrap_or_throw_conversion!(cx, "target", boxed_provider_from_argument(&mut cx, 0));
+    let options =
+        unwrap_or_throw_conversion!(cx, "options", migration_options_from_argument(&mut cx, 1));
+
+    spawn_promise(&mut cx, move |channel, deferred| {
+        // Both providers change, thus both are write locked, in the order of their addresses,
+        // so that concurrent migrations in opposite directions do not deadlock.
+        if Arc::ptr_eq(&provider_arc, &target_arc) {
+            let message = MigrationError::SameStorage.to_string();
+            deferred.settle_with(&channel, move |mut cx| {
+                cx.throw_error::<_, Handle<JsValue>>(message)
+            });
+            return;
+        }
+        let source_first = Arc::as_ptr(&provider_arc) < Arc::as_ptr(&target_arc);
+        let (first_arc, second_arc) = if source_first {
+            (&provider_arc, &target_arc)
+        } else {
+            (&target_arc, &provider_arc)
+        };
+        let mut first = arc_or_poisoned_error_deferred!(&channel, deferred, first_arc.write());
+        let mut second = arc_or_poisoned_error_deferred!(&channel, deferred, second_arc.write());
+        let (source_state, target_state) = if source_first {
+            (&mut first, &mut second)
+        } else {
+            (&mut second, &mut first)
+        };
+        let (provider, metadata_store) = exclusive_provider_with_metadata(source_state);
+        let (target, target_metadata_store) = exclusive_provider_with_metadata(target_state);
+
+        let report =
+            migrate_keys(provider, metadata_store, target, target_metadata_store, &options);
+
+        deferred.settle_with(&channel, move |mut cx| {
+            let report = unwrap_or_throw!(cx, report);
+            wrap_migration_report(&mut cx, report)
+        });
+    })
+}
+
+/// Checks every key of the provider storage and the metadata stored by the addon.
+///
+/// See [verify_store].
+///
+/// # Returns
+/// * `StoreReport` - unreadable keys, keys without metadata, orphaned and corrupted metadata entries
+///
+/// # Throws
+/// * When failing to list the keys.
+pub fn export_verify_store(mut cx: FunctionContext) -> JsResult<JsPromise> {
+    let provider_arc = boxed_this::<Provider>(&mut cx)?;
+
+    spawn_promise(&mut cx, move |channel, deferred| {
+        // Exclusive, so that keys being created are not reported before their metadata was stored.
+        let mut state = arc_or_poisoned_error_deferred!(&channel, deferred, provider_arc.write());
+        let (provider, metadata_store) = exclusive_provider_with_metadata(&mut state);
+
+        let report = verify_store(provider, metadata_store);
+
+        deferred.settle_with(&channel, move |mut cx| {
+            let report = unwrap_or_throw!(cx, report);
+            wrap_store_report(&mut cx, report)
+        });
+    })
+}
+
+/// Verifies the store like [export_verify_store] and removes orphaned and corrupted metadata entries.
+///
+/// Only the metadata file of the addon is repaired, keys of the provider storage are never changed.
+///
+/// See [repair_store].
+///
+/// # Arguments
+/// * **options**: `{ quarantine?: boolean }` - optional, moves the entries to
+///   `crypto-layer-node-key-metadata.quarantine.json` instead of discarding them
+///
+/// # Returns
+/// * `StoreReport` - with the ids of the removed entries as `repaired`
+///
+/// # Throws
+/// * When one of the inputs is incorrect.
+/// * When failing to list the keys.
+/// * When failing to write the quarantine or metadata file. No entry is removed in that case.
+pub fn export_repair_store(mut cx: FunctionContext) -> JsResult<JsPromise> {
+    let provider_arc = boxed_this::<Provider>(&mut cx)?;
+    let quarantine =
+        unwrap_or_throw!(cx, flag_from_options_argument(&mut cx, 0, QUARANTINE_OPTION));
+
+    spawn_promise(&mut cx, move |channel, deferred| {
+        let mut state = arc_or_poisoned_error_deferred!(&channel, deferred, provider_arc.write());
+        let (provider, metadata_store) = exclusive_provider_with_metadata(&mut state);
+
+        let report = repair_store(provider, metadata_store, quarantine);
+
+        deferred.settle_with(&channel, move |mut cx| {
+            let report = unwrap_or_throw!(cx, report);
+            wrap_store_report(&mut cx, report)
+        });
+    })
+}
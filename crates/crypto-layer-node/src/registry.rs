@@ -0,0 +1,166 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex, OnceLock, PoisonError, RwLock, Weak};
+
+use crypto_layer::prelude::*;
+
+use crate::classes::Boxed;
+use crate::common::Finalized;
+use crate::metadata::{canonical_db_dir, MetadataError};
+use crate::provider::{new_boxed_provider, ProviderState};
+use crate::storage_password::ResolvedImplConfig;
+
+struct Entry {
+    /// Weak, so that the provider is dropped once every instance was garbage collected.
+    provider: Weak<RwLock<Finalized<ProviderState>>>,
+    /// Name and storage keys the provider was created with, see [config_fingerprint].
+    config: Option<String>,
+    /// Acquisitions, which were not released yet.
+    references: usize,
+}
+
+#[derive(thiserror::Error, Debug)]
+pub(crate) enum RegistryError {
+    #[error(
+        "The storage {db_dir} is already open with another provider or other storage keys. \
+        Release every instance of that provider before opening the storage differently."
+    )]
+    ConfigMismatch { db_dir: String },
+    #[error("Failed opening the storage directory {db_dir}: {source}")]
+    StorageDirectory {
+        db_dir: String,
+        source: std::io::Error,
+    },
+    #[error(transparent)]
+    Metadata(#[from] MetadataError),
+}
+
+/// Providers with file backed storage, keyed by the canonical `db_dir` of their `FileStoreConfig`.
+///
+/// Opening the same database twice in one process leads to lock errors, thus such providers are shared.
+fn registry() -> &'static Mutex<HashMap<String, Entry>> {
+    static REGISTRY: OnceLock<Mutex<HashMap<String, Entry>>> = OnceLock::new();
+    REGISTRY.get_or_init(Default::default)
+}
+
+/// Canonical `db_dir` of the `FileStoreConfig` of `impl_config`, see [canonical_db_dir].
+///
+/// Returns `None` for configs without a `FileStoreConfig`, as those providers do not share state.
+fn cache_key(impl_config: &ProviderImplConfig) -> Result<Option<String>, RegistryError> {
+    let db_dir = impl_config
+        .additional_config
+        .iter()
+        .find_map(|additional_config| match additional_config {
+            AdditionalConfig::FileStoreConfig { db_dir } => Some(db_dir),
+            _ => None,
+        });
+    let Some(db_dir) = db_dir else {
+        return Ok(None);
+    };
+    let path = canonical_db_dir(db_dir).map_err(|source| RegistryError::StorageDirectory {
+        db_dir: db_dir.clone(),
+        source,
+    })?;
+    Ok(Some(path.to_string_lossy().into_owned()))
+}
+
+/// Identifies the provider `name` and the storage keys of `impl_config`.
+///
+/// Returns `None` for configs, which cannot be identified. Those never match a cached provider.
+fn config_fingerprint(name: &str, impl_config: &ProviderImplConfig) -> Option<String> {
+    let mut parts = vec![name.to_owned()];
+    for additional_config in &impl_config.additional_config {
+        let part = match additional_config {
+            AdditionalConfig::FileStoreConfig { .. } => continue,
+            AdditionalConfig::StorageConfigHMAC(key_handle) => {
+                format!("StorageConfigHMAC:{}", key_handle.id().ok()?)
+            }
+            AdditionalConfig::StorageConfigDSA(key_pair_handle) => {
+                format!("StorageConfigDSA:{}", key_pair_handle.id().ok()?)
+            }
+            AdditionalConfig::StorageConfigSymmetricEncryption(key_handle) => {
+                format!("StorageConfigSymmetricEncryption:{}", key_handle.id().ok()?)
+            }
+            AdditionalConfig::StorageConfigAsymmetricEncryption(key_pair_handle) => {
+                format!("StorageConfigAsymmetricEncryption:{}", key_pair_handle.id().ok()?)
+            }
+            _ => return None,
+        };
+        parts.push(part);
+    }
+    Some(parts.join("\n"))
+}
+
+/// Creates the provider `name` or returns the cached provider of the same storage.
+///
+/// Every call, which returns a cached provider, must be matched by a call to [release_provider].
+///
+/// # Errors
+/// * [RegistryError::ConfigMismatch], if a provider of the same storage is still alive,
+///   but was created with another `name` or other storage keys.
+/// * [RegistryError::Metadata], if the key metadata of the storage cannot be read
+///   or fails verification with the storage key.
+pub(crate) fn acquire_provider(
+    name: &str,
+    resolved: ResolvedImplConfig,
+) -> Result<Option<Boxed<Provider>>, RegistryError> {
+    let ResolvedImplConfig {
+        impl_config,
+        storage_key,
+    } = resolved;
+    let Some(key) = cache_key(&impl_config)? else {
+        let provider = create_provider_from_name(name, impl_config.clone());
+        return Ok(provider
+            .map(|provider| new_boxed_provider(provider, &impl_config, storage_key))
+            .transpose()?);
+    };
+    let config = config_fingerprint(name, &impl_config);
+
+    // The lock is held while creating the provider, so that concurrent calls do not open the storage twice.
+    let mut registry = registry().lock().unwrap_or_else(PoisonError::into_inner);
+    if let Some(entry) = registry.get_mut(&key) {
+        if let Some(provider) = entry.provider.upgrade() {
+            if config.is_none() || entry.config != config {
+                return Err(RegistryError::ConfigMismatch { db_dir: key });
+            }
+            entry.references += 1;
+            return Ok(Some(provider));
+        }
+    }
+
+    let Some(provider) = create_provider_from_name(name, impl_config.clone()) else {
+        return Ok(None);
+    };
+    let provider = new_boxed_provider(provider, &impl_config, storage_key)?;
+    registry.insert(
+        key,
+        Entry {
+            provider: Arc::downgrade(&provider),
+            config,
+            references: 1,
+        },
+    );
+    Ok(Some(provider))
+}
+
+/// Releases one acquisition of `provider`.
+///
+/// The provider stays cached as long as any instance of it is alive, even if every acquisition was released,
+/// as opening its storage again would fail. Once it was garbage collected, the next [acquire_provider] creates
+/// a new provider.
+///
+/// # Returns
+/// * Acquisitions, which are not released yet. `0` for providers, which are not cached.
+pub(crate) fn release_provider(provider: &Boxed<Provider>) -> usize {
+    let mut registry = registry().lock().unwrap_or_else(PoisonError::into_inner);
+    registry.retain(|_, entry| entry.provider.strong_count() > 0);
+
+    let Some(entry) = registry
+        .values_mut()
+        .find(|entry| std::ptr::eq(entry.provider.as_ptr(), Arc::as_ptr(provider)))
+    else {
+        return 0;
+    };
+
+    entry.references = entry.references.saturating_sub(1);
+    entry.references
+}
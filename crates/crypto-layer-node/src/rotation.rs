@@ -0,0 +1,282 @@
+use crypto_layer::common::error::CalError;
+use crypto_layer::prelude::*;
+use neon::prelude::*;
+use zeroize::Zeroizing;
+
+use crate::metadata::{
+    now_millis, FamilyMember, KeyMetadata, MetadataError, MetadataStore, PolicyMetadata,
+};
+use crate::nonce::{record_encryptions, throw_nonce_error, NonceError};
+use crate::policy::{check_key_policy, throw_key_policy_error, KeyOperation, KeyPolicyError};
+
+/// First bytes of every envelope created by [encrypt_for_family].
+const ENVELOPE_MAGIC: &[u8; 4] = b"CLKF";
+
+/// Version of the envelope layout following [ENVELOPE_MAGIC].
+const ENVELOPE_FORMAT_VERSION: u8 = 1;
+
+#[derive(thiserror::Error, Debug)]
+pub(crate) enum RotationError {
+    #[error(transparent)]
+    Policy(#[from] KeyPolicyError),
+    #[error(transparent)]
+    Cal(#[from] CalError),
+    #[error(transparent)]
+    Metadata(#[from] MetadataError),
+    #[error(transparent)]
+    Nonce(#[from] NonceError),
+    #[error("The key {id} was superseded by version {latest_version} of its family and cannot be rotated.")]
+    NotLatest { id: String, latest_version: u32 },
+    #[error("The key family {0} does not exist.")]
+    UnknownFamily(String),
+    #[error("The key {id} does not belong to the key family {family_id}.")]
+    NotInFamily { id: String, family_id: String },
+    #[error("The metadata of the key {id} is {reason}, thus it cannot be rotated.")]
+    MetadataUnavailable { id: String, reason: String },
+    #[error("The ciphertext is not an envelope of a key family: {0}")]
+    MalformedEnvelope(&'static str),
+}
+
+/// Ciphertext prefixed with the id of the key version, which encrypted it.
+///
+/// Layout: magic (4 bytes), format version (1 byte), key id length (2 bytes, big endian), key id,
+/// iv length (1 byte), iv, ciphertext.
+struct Envelope<'a> {
+    key_id: &'a str,
+    iv: &'a [u8],
+    ciphertext: &'a [u8],
+}
+
+impl<'a> Envelope<'a> {
+    fn seal(key_id: &str, iv: &[u8], ciphertext: &[u8]) -> Result<Vec<u8>, RotationError> {
+        let key_id_len = u16::try_from(key_id.len())
+            .map_err(|_| RotationError::MalformedEnvelope("key id too long"))?;
+        let iv_len =
+            u8::try_from(iv.len()).map_err(|_| RotationError::MalformedEnvelope("iv too long"))?;
+
+        let mut sealed = Vec::with_capacity(8 + key_id.len() + iv.len() + ciphertext.len());
+        sealed.extend_from_slice(ENVELOPE_MAGIC);
+        sealed.push(ENVELOPE_FORMAT_VERSION);
+        sealed.extend_from_slice(&key_id_len.to_be_bytes());
+        sealed.extend_from_slice(key_id.as_bytes());
+        sealed.push(iv_len);
+        sealed.extend_from_slice(iv);
+        sealed.extend_from_slice(ciphertext);
+        Ok(sealed)
+    }
+
+    fn open(sealed: &'a [u8]) -> Result<Self, RotationError> {
+        let rest = sealed
+            .strip_prefix(ENVELOPE_MAGIC.as_slice())
+            .ok_or(RotationError::MalformedEnvelope("missing header"))?;
+        let (&format_version, rest) = rest
+            .split_first()
+            .ok_or(RotationError::MalformedEnvelope("truncated header"))?;
+        if format_version != ENVELOPE_FORMAT_VERSION {
+            return Err(RotationError::MalformedEnvelope("unsupported format version"));
+        }
+
+        let (key_id_len, rest) = rest
+            .split_first_chunk::<2>()
+            .ok_or(RotationError::MalformedEnvelope("truncated header"))?;
+        let key_id_len = u16::from_be_bytes(*key_id_len) as usize;
+        if rest.len() < key_id_len {
+            return Err(RotationError::MalformedEnvelope("truncated key id"));
+        }
+        let (key_id, rest) = rest.split_at(key_id_len);
+        let key_id = std::str::from_utf8(key_id)
+            .map_err(|_| RotationError::MalformedEnvelope("key id is not utf-8"))?;
+
+        let (&iv_len, rest) = rest
+            .split_first()
+            .ok_or(RotationError::MalformedEnvelope("truncated header"))?;
+        if rest.len() < iv_len as usize {
+            return Err(RotationError::MalformedEnvelope("truncated iv"));
+        }
+        let (iv, ciphertext) = rest.split_at(iv_len as usize);
+
+        Ok(Self {
+            key_id,
+            iv,
+            ciphertext,
+        })
+    }
+}
+
+/// Membership of the key `id` or, if it was never rotated, the first version of a new family.
+fn family_member(id: &str, metadata: Option<&KeyMetadata>) -> FamilyMember {
+    metadata
+        .and_then(|metadata| metadata.family.clone())
+        .unwrap_or_else(|| FamilyMember {
+            family_id: id.to_owned(),
+            version: 1,
+        })
+}
+
+/// Returns the id of the newest version of the key family `family_id`.
+fn latest_version(store: &MetadataStore, family_id: &str) -> Result<String, RotationError> {
+    store
+        .family_members(family_id)
+        .pop()
+        .map(|(id, _)| id)
+        .ok_or_else(|| RotationError::UnknownFamily(family_id.to_owned()))
+}
+
+/// Loads the key `id` and checks its policy for `operation`.
+fn load_permitted_key(
+    provider: &mut Provider,
+    store: &MetadataStore,
+    id: &str,
+    operation: KeyOperation,
+) -> Result<KeyHandle, RotationError> {
+    let handle = provider.load_key(id.to_owned())?;
+    check_key_policy(&handle, Some(store), operation)?;
+    Ok(handle)
+}
+
+/// Creates the next version of the key `id` with the same spec, label, tags and usages.
+///
+/// The key `id` becomes decrypt-only. Both keys are members of the family named after the first version,
+/// which is the key `id` itself, if it was never rotated.
+/// Only the newest version of a family can be rotated.
+/// Rotations of one store are serialized, so that concurrent rotations of a family cannot both pass
+/// the check for the newest version. Processes sharing a storage are not synchronized.
+/// Keys, whose metadata is unreadable, are refused like by [check_key_policy],
+/// as their restrictions and family are unknown.
+pub(crate) fn rotate_key(
+    provider: &mut Provider,
+    store: &MetadataStore,
+    id: &str,
+) -> Result<KeyHandle, RotationError> {
+    let _rotation = store.lock_rotations();
+    let previous = provider.load_key(id.to_owned())?;
+    let previous_metadata = match store.policy_metadata(id) {
+        PolicyMetadata::Found(metadata) => Some(metadata),
+        PolicyMetadata::Unrestricted => None,
+        PolicyMetadata::Unavailable(reason) => {
+            return Err(RotationError::MetadataUnavailable {
+                id: id.to_owned(),
+                reason,
+            })
+        }
+    };
+    let member = family_member(id, previous_metadata.as_ref());
+
+    if let Some((_, latest)) = store.family_members(&member.family_id).pop() {
+        let latest_version = latest.family.map_or(member.version, |family| family.version);
+        if latest_version > member.version {
+            return Err(RotationError::NotLatest {
+                id: id.to_owned(),
+                latest_version,
+            });
+        }
+    }
+
+    let handle = provider.create_key(previous.spec())?;
+    let result = handle.id().map_err(RotationError::from).and_then(|new_id| {
+        // Keys without metadata are recorded when they are rotated.
+        let previous_metadata = previous_metadata.unwrap_or_else(|| KeyMetadata {
+            created_at: now_millis(),
+            ..Default::default()
+        });
+        let metadata = KeyMetadata {
+            label: previous_metadata.label.clone(),
+            tags: previous_metadata.tags.clone(),
+            created_at: now_millis(),
+            usages: previous_metadata.usages.clone(),
+            encryption_limits: previous_metadata.encryption_limits.clone(),
+            family: Some(FamilyMember {
+                family_id: member.family_id.clone(),
+                version: member.version + 1,
+            }),
+            ..Default::default()
+        };
+        let previous_metadata = KeyMetadata {
+            usages: Some(vec![KeyOperation::Decrypt]),
+            family: Some(member),
+            ..previous_metadata
+        };
+
+        store
+            .replace_all(vec![(id.to_owned(), previous_metadata), (new_id, metadata)])
+            .map_err(RotationError::from)
+    });
+
+    match result {
+        Ok(()) => Ok(handle),
+        Err(err) => {
+            if let Err(delete_err) = handle.delete() {
+                tracing::error!(error = %delete_err, "Failed deleting key of failed rotation.");
+            }
+            Err(err)
+        }
+    }
+}
+
+/// Encrypts `data` with the newest version of the key family `family_id` into an envelope,
+/// which names the version for [decrypt_for_family].
+pub(crate) fn encrypt_for_family(
+    provider: &mut Provider,
+    store: &MetadataStore,
+    family_id: &str,
+    data: &[u8],
+) -> Result<Vec<u8>, RotationError> {
+    let id = latest_version(store, family_id)?;
+    let handle = load_permitted_key(provider, store, &id, KeyOperation::Encrypt)?;
+    // The warning was already logged and there is no channel to emit it on.
+    let _warning = record_encryptions(&handle, Some(store), 1, None)?;
+    let (ciphertext, iv) = handle.encrypt(data)?;
+    Envelope::seal(&id, &iv, &ciphertext)
+}
+
+/// Decrypts an envelope created by [encrypt_for_family] with the version of the key family,
+/// which encrypted it.
+pub(crate) fn decrypt_for_family(
+    provider: &mut Provider,
+    store: &MetadataStore,
+    family_id: &str,
+    sealed: &[u8],
+) -> Result<Vec<u8>, RotationError> {
+    let envelope = Envelope::open(sealed)?;
+    let member = store.get(envelope.key_id).and_then(|metadata| metadata.family);
+    if member.is_none_or(|member| member.family_id != family_id) {
+        return Err(RotationError::NotInFamily {
+            id: envelope.key_id.to_owned(),
+            family_id: family_id.to_owned(),
+        });
+    }
+
+    let handle = load_permitted_key(provider, store, envelope.key_id, KeyOperation::Decrypt)?;
+    Ok(handle.decrypt_data(envelope.ciphertext, envelope.iv)?)
+}
+
+/// Decrypts an envelope created by [encrypt_for_family] and encrypts the data again
+/// with the newest version of the same key family.
+pub(crate) fn rewrap(
+    provider: &mut Provider,
+    store: &MetadataStore,
+    sealed: &[u8],
+) -> Result<Vec<u8>, RotationError> {
+    let envelope = Envelope::open(sealed)?;
+    let family_id = store
+        .get(envelope.key_id)
+        .and_then(|metadata| metadata.family)
+        .map(|member| member.family_id)
+        .ok_or_else(|| RotationError::UnknownFamily(envelope.key_id.to_owned()))?;
+
+    let data = Zeroizing::new(decrypt_for_family(provider, store, &family_id, sealed)?);
+    encrypt_for_family(provider, store, &family_id, &data)
+}
+
+/// Throws policy violations with [throw_key_policy_error], refused encryptions with [throw_nonce_error]
+/// and other errors as plain `Error`.
+pub(crate) fn throw_rotation_error<'a, C: Context<'a>, V: Value>(
+    cx: &mut C,
+    err: &RotationError,
+) -> JsResult<'a, V> {
+    match err {
+        RotationError::Policy(err) => throw_key_policy_error(cx, err),
+        RotationError::Nonce(err) => throw_nonce_error(cx, err),
+        err => cx.throw_error(err.to_string()),
+    }
+}
@@ -0,0 +1,542 @@
+use std::any::Any;
+use std::collections::VecDeque;
+use std::num::NonZeroUsize;
+use std::panic::{catch_unwind, resume_unwind, AssertUnwindSafe};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Condvar, Mutex, OnceLock, PoisonError, RwLock, Weak};
+use std::thread;
+
+use neon::prelude::*;
+
+use crate::abort::AbortToken;
+use crate::fromjs::error::{bad_parameter, rw_lock_poisoned, unwrap_or_throw, ConversionError};
+use crate::fromjs::int_from_js_number;
+
+type Task = Box<dyn FnOnce() + Send + 'static>;
+
+#[derive(thiserror::Error, Debug)]
+pub(crate) enum RuntimeError {
+    #[error(transparent)]
+    Conversion(#[from] ConversionError),
+    #[error("Failed spawning worker thread: {0}")]
+    Spawn(#[from] std::io::Error),
+}
+
+/// Sizing of the thread pool executing the blocking crypto operations.
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct RuntimeConfig {
+    pub worker_threads: usize,
+    /// `None` means the queue is unbounded.
+    pub max_queued: Option<usize>,
+}
+
+impl Default for RuntimeConfig {
+    fn default() -> Self {
+        Self {
+            worker_threads: thread::available_parallelism()
+                .map(NonZeroUsize::get)
+                .unwrap_or(1),
+            max_queued: None,
+        }
+    }
+}
+
+/// Task in the queue and the token of the operation it belongs to.
+struct QueuedTask {
+    run: Task,
+    token: Option<AbortToken>,
+}
+
+impl QueuedTask {
+    fn is_aborted(&self) -> bool {
+        self.token.as_ref().is_some_and(AbortToken::is_aborted)
+    }
+}
+
+#[derive(Default)]
+struct Queue {
+    tasks: VecDeque<QueuedTask>,
+    shutdown: bool,
+}
+
+#[derive(Default)]
+struct Shared {
+    queue: Mutex<Queue>,
+    available: Condvar,
+    /// Tasks reserved or queued, but not yet started.
+    pending: AtomicUsize,
+    running: AtomicUsize,
+    completed: AtomicUsize,
+    rejected: AtomicUsize,
+}
+
+/// Fixed size thread pool with an optionally bounded queue.
+pub(crate) struct Runtime {
+    config: RuntimeConfig,
+    shared: Arc<Shared>,
+    /// Why the workers of the default runtime could not be spawned.
+    spawn_error: Option<String>,
+}
+
+/// Snapshot of the state of the runtime.
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct RuntimeMetrics {
+    pub worker_threads: usize,
+    pub max_queued: Option<usize>,
+    pub pending: usize,
+    pub running: usize,
+    pub completed: usize,
+    pub rejected: usize,
+}
+
+impl Runtime {
+    /// Spawns the workers of a new runtime.
+    ///
+    /// # Errors
+    /// * If a worker cannot be spawned. Workers spawned before exit again.
+    fn new(config: RuntimeConfig) -> Result<Self, std::io::Error> {
+        let runtime = Self {
+            config,
+            shared: Arc::new(Shared::default()),
+            spawn_error: None,
+        };
+
+        for i in 0..config.worker_threads {
+            let shared = runtime.shared.clone();
+            let spawn_result = thread::Builder::new()
+                .name(format!("crypto-layer-node-worker-{}", i))
+                .spawn(move || worker_loop(shared));
+            if let Err(e) = spawn_result {
+                runtime.shutdown();
+                return Err(e);
+            }
+        }
+
+        Ok(runtime)
+    }
+
+    /// Runtime without workers, which rejects every task with `err`.
+    fn failed(config: RuntimeConfig, err: std::io::Error) -> Self {
+        tracing::error!(error = %err, "Failed spawning worker thread.");
+        Self {
+            config,
+            shared: Arc::new(Shared::default()),
+            spawn_error: Some(RuntimeError::Spawn(err).to_string()),
+        }
+    }
+
+    /// Reserves a slot in the queue without counting a rejection.
+    ///
+    /// Tasks, which idle workers start right away, do not wait and thus do not count towards `max_queued`.
+    fn try_reserve_silently(&self) -> bool {
+        if self.spawn_error.is_some() {
+            return false;
+        }
+        self.shared
+            .pending
+            .fetch_update(Ordering::AcqRel, Ordering::Acquire, |pending| {
+                let running = self.shared.running.load(Ordering::Acquire);
+                let idle = self.config.worker_threads.saturating_sub(running);
+                match self.config.max_queued {
+                    Some(max_queued) if pending >= max_queued + idle => None,
+                    _ => Some(pending + 1),
+                }
+            })
+            .is_ok()
+    }
+
+    /// Reserves a slot in the queue.
+    ///
+    /// Returns `false` if the queue is full.
+    fn try_reserve(&self) -> bool {
+        let reserved = self.try_reserve_silently();
+        if !reserved {
+            self.shared.rejected.fetch_add(1, Ordering::Relaxed);
+        }
+        reserved
+    }
+
+    /// Queues a task, for which a slot was reserved with [Runtime::try_reserve].
+    fn push_reserved(&self, task: QueuedTask) {
+        match self.shared.queue.lock() {
+            Ok(mut queue) => {
+                queue.tasks.push_back(task);
+                self.shared.available.notify_one();
+            }
+            Err(_) => {
+                self.shared.pending.fetch_sub(1, Ordering::AcqRel);
+                tracing::error!("{}", ConversionError::RwLockPoisoned);
+            }
+        }
+    }
+
+    pub(crate) fn metrics(&self) -> RuntimeMetrics {
+        RuntimeMetrics {
+            worker_threads: self.config.worker_threads,
+            max_queued: self.config.max_queued,
+            pending: self.shared.pending.load(Ordering::Acquire),
+            running: self.shared.running.load(Ordering::Acquire),
+            completed: self.shared.completed.load(Ordering::Relaxed),
+            rejected: self.shared.rejected.load(Ordering::Relaxed),
+        }
+    }
+
+    /// Lets the workers exit after the queue has been drained.
+    fn shutdown(&self) {
+        if let Ok(mut queue) = self.shared.queue.lock() {
+            queue.shutdown = true;
+        }
+        self.shared.available.notify_all();
+    }
+}
+
+fn worker_loop(shared: Arc<Shared>) {
+    loop {
+        let task = {
+            let Ok(mut queue) = shared.queue.lock() else {
+                return;
+            };
+            loop {
+                if let Some(task) = queue.tasks.pop_front() {
+                    break task.run;
+                }
+                if queue.shutdown {
+                    return;
+                }
+                queue = match shared.available.wait(queue) {
+                    Ok(queue) => queue,
+                    Err(_) => return,
+                };
+            }
+        };
+
+        shared.pending.fetch_sub(1, Ordering::AcqRel);
+        shared.running.fetch_add(1, Ordering::AcqRel);
+        if catch_unwind(AssertUnwindSafe(task)).is_err() {
+            tracing::error!("Task panicked.");
+        }
+        shared.running.fetch_sub(1, Ordering::AcqRel);
+        shared.completed.fetch_add(1, Ordering::Relaxed);
+    }
+}
+
+fn runtime_lock() -> &'static RwLock<Arc<Runtime>> {
+    static RUNTIME: OnceLock<RwLock<Arc<Runtime>>> = OnceLock::new();
+    RUNTIME.get_or_init(|| {
+        let config = RuntimeConfig::default();
+        let runtime = Runtime::new(config).unwrap_or_else(|err| Runtime::failed(config, err));
+        RwLock::new(Arc::new(runtime))
+    })
+}
+
+/// Queues of runtimes replaced by [configure_runtime], which are alive as long as their workers drain them.
+fn retired_queues() -> &'static Mutex<Vec<Weak<Shared>>> {
+    static RETIRED_QUEUES: OnceLock<Mutex<Vec<Weak<Shared>>>> = OnceLock::new();
+    RETIRED_QUEUES.get_or_init(Default::default)
+}
+
+/// Returns the currently configured runtime.
+pub(crate) fn current_runtime() -> Result<Arc<Runtime>, ConversionError> {
+    Ok(rw_lock_poisoned(runtime_lock().read())?.clone())
+}
+
+/// Replaces the current runtime.
+///
+/// Tasks already queued on the previous runtime are still executed, unless [cancel_aborted] removes them.
+/// The current runtime is kept, if the workers of the new runtime cannot be spawned.
+pub(crate) fn configure_runtime(config: RuntimeConfig) -> Result<(), RuntimeError> {
+    let new_runtime = Arc::new(Runtime::new(config)?);
+    let old_runtime = {
+        let mut runtime = rw_lock_poisoned(runtime_lock().write())?;
+        std::mem::replace(&mut *runtime, new_runtime)
+    };
+    {
+        let mut retired_queues = retired_queues()
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner);
+        retired_queues.retain(|shared| shared.strong_count() > 0);
+        retired_queues.push(Arc::downgrade(&old_runtime.shared));
+    }
+    old_runtime.shutdown();
+    Ok(())
+}
+
+/// Slot in the queue of a runtime, which is released if no task is spawned with it.
+pub(crate) struct Reservation {
+    runtime: Arc<Runtime>,
+    used: bool,
+}
+
+impl Reservation {
+    /// Queues `task` in the reserved slot.
+    pub(crate) fn spawn<F>(self, task: F)
+    where
+        F: FnOnce() + Send + 'static,
+    {
+        self.spawn_abortable(None, task);
+    }
+
+    /// Queues `task` in the reserved slot, which is released by [cancel_aborted] once `token` is aborted.
+    ///
+    /// `task` must only settle its operation as aborted, when it runs after `token` was aborted,
+    /// as [cancel_aborted] runs it on the main thread.
+    pub(crate) fn spawn_abortable<F>(mut self, token: Option<AbortToken>, task: F)
+    where
+        F: FnOnce() + Send + 'static,
+    {
+        self.used = true;
+        self.runtime.push_reserved(QueuedTask {
+            run: Box::new(task),
+            token,
+        });
+    }
+}
+
+impl Drop for Reservation {
+    fn drop(&mut self) {
+        if !self.used {
+            self.runtime.shared.pending.fetch_sub(1, Ordering::AcqRel);
+        }
+    }
+}
+
+/// Removes the tasks of aborted operations from the queue of `shared` and releases their slots.
+fn take_aborted(shared: &Shared) -> VecDeque<QueuedTask> {
+    let aborted: VecDeque<QueuedTask> = {
+        let Ok(mut queue) = shared.queue.lock() else {
+            return VecDeque::new();
+        };
+        let (aborted, kept) = queue.tasks.drain(..).partition(QueuedTask::is_aborted);
+        queue.tasks = kept;
+        aborted
+    };
+    shared.pending.fetch_sub(aborted.len(), Ordering::AcqRel);
+    aborted
+}
+
+/// Removes the tasks of aborted operations from the queues of the current runtime
+/// and of the runtimes it replaced, and releases their slots.
+///
+/// The removed tasks run on the calling thread, where they only reject their promises with an `AbortError`.
+pub(crate) fn cancel_aborted() {
+    let mut aborted = VecDeque::new();
+    if let Ok(runtime) = current_runtime() {
+        aborted.extend(take_aborted(&runtime.shared));
+    }
+    let retired_queues: Vec<Arc<Shared>> = retired_queues()
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner)
+        .iter()
+        .filter_map(Weak::upgrade)
+        .collect();
+    for shared in retired_queues {
+        aborted.extend(take_aborted(&shared));
+    }
+
+    for task in aborted {
+        if catch_unwind(AssertUnwindSafe(task.run)).is_err() {
+            tracing::error!("Task panicked.");
+        }
+    }
+}
+
+/// Reserves a slot in the queue of the current runtime.
+///
+/// # Errors
+/// * Returns the error message, if the queue is full or the runtime has no workers.
+pub(crate) fn reserve() -> Result<Reservation, String> {
+    let runtime = current_runtime().map_err(|e| e.to_string())?;
+    if let Some(spawn_error) = &runtime.spawn_error {
+        return Err(spawn_error.clone());
+    }
+    if !runtime.try_reserve() {
+        return Err(format!(
+            "Runtime queue is full. At most {} operations may wait for a worker.",
+            runtime.config.max_queued.unwrap_or(usize::MAX)
+        ));
+    }
+    Ok(Reservation {
+        runtime,
+        used: false,
+    })
+}
+
+fn optional_usize_from_object(
+    cx: &mut FunctionContext,
+    obj: Handle<JsObject>,
+    key: &str,
+) -> Result<Option<usize>, ConversionError> {
+    match bad_parameter(obj.get_opt::<JsNumber, _, _>(cx, key))? {
+        Some(number) => Ok(Some(int_from_js_number(cx, number)?)),
+        None => Ok(None),
+    }
+}
+
+/// Configures the thread pool executing the crypto operations.
+///
+/// # Arguments
+/// * **options**: `{ workerThreads?: number, maxQueued?: number }`
+///
+/// # Returns
+/// * `undefined`
+///
+/// # Throws
+/// * When one of the inputs is incorrect.
+/// * When the worker threads cannot be spawned. The previous configuration stays in effect.
+pub fn export_configure_runtime(mut cx: FunctionContext) -> JsResult<JsUndefined> {
+    let options_js = cx.argument::<JsObject>(0)?;
+
+    let defaults = RuntimeConfig::default();
+    let worker_threads = unwrap_or_throw!(
+        cx,
+        optional_usize_from_object(&mut cx, options_js, "workerThreads")
+    )
+    .unwrap_or(defaults.worker_threads);
+    let max_queued = unwrap_or_throw!(
+        cx,
+        optional_usize_from_object(&mut cx, options_js, "maxQueued")
+    );
+
+    if worker_threads == 0 {
+        return cx.throw_range_error("workerThreads must be at least 1.");
+    }
+
+    unwrap_or_throw!(
+        cx,
+        configure_runtime(RuntimeConfig {
+            worker_threads,
+            max_queued,
+        })
+    );
+    Ok(cx.undefined())
+}
+
+/// Returns metrics of the thread pool executing the crypto operations.
+///
+/// # Returns
+/// * `{ workerThreads: number, maxQueued: number | null, pending: number, running: number, completed: number, rejected: number }`
+///
+/// # Throws
+pub fn export_runtime_metrics(mut cx: FunctionContext) -> JsResult<JsObject> {
+    let metrics = unwrap_or_throw!(cx, current_runtime()).metrics();
+
+    let obj = cx.empty_object();
+    let worker_threads_js = cx.number(metrics.worker_threads as f64);
+    obj.set(&mut cx, "workerThreads", worker_threads_js)?;
+    let max_queued_js: Handle<JsValue> = match metrics.max_queued {
+        Some(max_queued) => cx.number(max_queued as f64).upcast(),
+        None => cx.null().upcast(),
+    };
+    obj.set(&mut cx, "maxQueued", max_queued_js)?;
+    let pending_js = cx.number(metrics.pending as f64);
+    obj.set(&mut cx, "pending", pending_js)?;
+    let running_js = cx.number(metrics.running as f64);
+    obj.set(&mut cx, "running", running_js)?;
+    let completed_js = cx.number(metrics.completed as f64);
+    obj.set(&mut cx, "completed", completed_js)?;
+    let rejected_js = cx.number(metrics.rejected as f64);
+    obj.set(&mut cx, "rejected", rejected_js)?;
+
+    Ok(obj)
+}
+
+/// Items of a [parallel_map] call shared with the helper tasks.
+struct Batch<I, O, F> {
+    items: Mutex<std::iter::Enumerate<std::vec::IntoIter<I>>>,
+    results: Mutex<Vec<Option<O>>>,
+    /// Items, which are not finished yet.
+    remaining: Mutex<usize>,
+    finished: Condvar,
+    panic: Mutex<Option<Box<dyn Any + Send>>>,
+    func: F,
+}
+
+impl<I, O, F: Fn(I) -> O> Batch<I, O, F> {
+    /// Processes items until none are left.
+    fn work(&self) {
+        loop {
+            let next = self
+                .items
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner)
+                .next();
+            let Some((i, item)) = next else {
+                return;
+            };
+
+            match catch_unwind(AssertUnwindSafe(|| (self.func)(item))) {
+                Ok(result) => {
+                    self.results.lock().unwrap_or_else(PoisonError::into_inner)[i] = Some(result);
+                }
+                Err(panic) => {
+                    let mut first_panic = self.panic.lock().unwrap_or_else(PoisonError::into_inner);
+                    first_panic.get_or_insert(panic);
+                }
+            }
+
+            let mut remaining = self.remaining.lock().unwrap_or_else(PoisonError::into_inner);
+            *remaining -= 1;
+            if *remaining == 0 {
+                self.finished.notify_all();
+            }
+        }
+    }
+}
+
+/// Applies `func` to every item on the workers of the current runtime.
+///
+/// The calling thread, usually a worker itself, processes items as well,
+/// so that the batch completes even if all other workers are busy.
+/// Helper tasks are only queued while the queue has room for them and count towards its limits.
+///
+/// The order of the results matches the order of the items.
+pub(crate) fn parallel_map<I, O, F>(items: Vec<I>, func: F) -> Vec<O>
+where
+    I: Send + 'static,
+    O: Send + 'static,
+    F: Fn(I) -> O + Send + Sync + 'static,
+{
+    let len = items.len();
+    let batch = Arc::new(Batch {
+        items: Mutex::new(items.into_iter().enumerate()),
+        results: Mutex::new((0..len).map(|_| None).collect()),
+        remaining: Mutex::new(len),
+        finished: Condvar::new(),
+        panic: Mutex::new(None),
+        func,
+    });
+
+    if let Ok(runtime) = current_runtime() {
+        let helpers = runtime.config.worker_threads.min(len).saturating_sub(1);
+        for _ in 0..helpers {
+            if !runtime.try_reserve_silently() {
+                break;
+            }
+            let batch = batch.clone();
+            let reservation = Reservation {
+                runtime: runtime.clone(),
+                used: false,
+            };
+            reservation.spawn(move || batch.work());
+        }
+    }
+
+    batch.work();
+    let mut remaining = batch.remaining.lock().unwrap_or_else(PoisonError::into_inner);
+    while *remaining > 0 {
+        remaining = batch
+            .finished
+            .wait(remaining)
+            .unwrap_or_else(PoisonError::into_inner);
+    }
+    drop(remaining);
+
+    if let Some(panic) = batch.panic.lock().unwrap_or_else(PoisonError::into_inner).take() {
+        resume_unwind(panic);
+    }
+    let mut results = batch.results.lock().unwrap_or_else(PoisonError::into_inner);
+    std::mem::take(&mut *results)
+        .into_iter()
+        .map(|result| result.expect("every item of the batch was processed"))
+        .collect()
+}
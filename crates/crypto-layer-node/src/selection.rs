@@ -0,0 +1,224 @@
+use std::cmp::Reverse;
+use std::collections::HashSet;
+use std::hash::Hash;
+
+use crypto_layer::prelude::*;
+use neon::prelude::*;
+
+use crate::spec::variant_name;
+use crate::tojs::config::wrap_provider_evaluation;
+use crate::tojs::js_array_from_vec;
+
+/// `code` of the error thrown by `createProvider` with `{ explain: true }`, if no provider matches.
+pub(crate) const NO_MATCHING_PROVIDER_ERROR_CODE: &str = "ERR_NO_MATCHING_PROVIDER";
+
+/// Security levels from lowest to highest.
+const SECURITY_LEVEL_ORDER: &[&str] = &["Unsafe", "Network", "Software", "Hardware"];
+
+/// Requirement a provider does not meet.
+#[derive(Debug)]
+pub(crate) struct UnmetRequirement {
+    /// Field of the requirements, e.g. `supported_ciphers`.
+    pub requirement: &'static str,
+    /// Required variants the provider lacks.
+    pub missing: Vec<&'static str>,
+    pub message: String,
+}
+
+/// Result of comparing the capabilities of a provider with the requirements.
+#[derive(Debug)]
+pub(crate) struct ProviderEvaluation {
+    pub name: String,
+    /// `None` if the provider is not initializable with the given impl config.
+    pub capabilities: Option<ProviderConfig>,
+    pub unmet: Vec<UnmetRequirement>,
+}
+
+impl ProviderEvaluation {
+    pub fn matches(&self) -> bool {
+        self.unmet.is_empty()
+    }
+
+    /// Amount of missing variants and security level mismatches.
+    fn shortfall(&self) -> usize {
+        self.unmet
+            .iter()
+            .map(|unmet| unmet.missing.len().max(1))
+            .sum()
+    }
+
+    fn security_rank(&self) -> usize {
+        self.capabilities
+            .as_ref()
+            .map(|capabilities| security_rank(&capabilities.max_security_level))
+            .unwrap_or(0)
+    }
+}
+
+fn security_rank(level: &SecurityLevel) -> usize {
+    let name = variant_name(level);
+    SECURITY_LEVEL_ORDER
+        .iter()
+        .position(|ordered| *ordered == name)
+        .unwrap_or(0)
+}
+
+fn missing_variants<T: Eq + Hash>(
+    required: &HashSet<T>,
+    supported: &HashSet<T>,
+) -> Vec<&'static str>
+where
+    for<'b> &'b T: Into<&'static str>,
+{
+    let mut missing: Vec<&'static str> = required.difference(supported).map(variant_name).collect();
+    missing.sort_unstable();
+    missing
+}
+
+fn check_variants<T: Eq + Hash>(
+    unmet: &mut Vec<UnmetRequirement>,
+    requirement: &'static str,
+    required: &HashSet<T>,
+    supported: &HashSet<T>,
+) where
+    for<'b> &'b T: Into<&'static str>,
+{
+    let missing = missing_variants(required, supported);
+    if !missing.is_empty() {
+        unmet.push(UnmetRequirement {
+            requirement,
+            message: format!("Does not support {} of {}.", missing.join(", "), requirement),
+            missing,
+        });
+    }
+}
+
+/// Compares the `capabilities` of the provider `name` with the `requirements`.
+///
+/// The security level range of the provider must overlap the required range
+/// and every required cipher, hash and asymmetric spec must be supported.
+pub(crate) fn evaluate_provider(
+    requirements: &ProviderConfig,
+    name: String,
+    capabilities: Option<ProviderConfig>,
+) -> ProviderEvaluation {
+    let mut unmet = vec![];
+
+    let Some(capabilities) = capabilities else {
+        unmet.push(UnmetRequirement {
+            requirement: "impl_config",
+            missing: vec![],
+            message: "Is not initializable with the given impl config.".to_owned(),
+        });
+        return ProviderEvaluation {
+            name,
+            capabilities: None,
+            unmet,
+        };
+    };
+
+    if security_rank(&capabilities.max_security_level)
+        < security_rank(&requirements.min_security_level)
+        || security_rank(&capabilities.min_security_level)
+            > security_rank(&requirements.max_security_level)
+    {
+        unmet.push(UnmetRequirement {
+            requirement: "security_level",
+            missing: vec![],
+            message: format!(
+                "Security levels {}..{} are outside of the required range {}..{}.",
+                variant_name(&capabilities.min_security_level),
+                variant_name(&capabilities.max_security_level),
+                variant_name(&requirements.min_security_level),
+                variant_name(&requirements.max_security_level),
+            ),
+        });
+    }
+    check_variants(
+        &mut unmet,
+        "supported_ciphers",
+        &requirements.supported_ciphers,
+        &capabilities.supported_ciphers,
+    );
+    check_variants(
+        &mut unmet,
+        "supported_hashes",
+        &requirements.supported_hashes,
+        &capabilities.supported_hashes,
+    );
+    check_variants(
+        &mut unmet,
+        "supported_asym_spec",
+        &requirements.supported_asym_spec,
+        &capabilities.supported_asym_spec,
+    );
+
+    ProviderEvaluation {
+        name,
+        capabilities: Some(capabilities),
+        unmet,
+    }
+}
+
+/// Evaluates every provider of `all_providers` and ranks them.
+///
+/// Matching providers come first, followed by the providers missing the fewest requirements.
+/// Providers not initializable with the impl config come last.
+/// Ties are broken by the higher security level and then by name.
+///
+/// * `capabilities` - result of `get_provider_capabilities`, which lacks providers not initializable with the impl config.
+pub(crate) fn rank_providers(
+    requirements: &ProviderConfig,
+    all_providers: Vec<String>,
+    mut capabilities: Vec<(String, ProviderConfig)>,
+) -> Vec<ProviderEvaluation> {
+    let mut evaluations: Vec<ProviderEvaluation> = all_providers
+        .into_iter()
+        .map(|name| {
+            let provider_capabilities = capabilities
+                .iter()
+                .position(|(capable_name, _)| *capable_name == name)
+                .map(|i| capabilities.swap_remove(i).1);
+            evaluate_provider(requirements, name, provider_capabilities)
+        })
+        .collect();
+
+    evaluations.sort_by_key(|evaluation| {
+        (
+            !evaluation.matches(),
+            evaluation.capabilities.is_none(),
+            evaluation.shortfall(),
+            Reverse(evaluation.security_rank()),
+            evaluation.name.clone(),
+        )
+    });
+    evaluations
+}
+
+/// Throws an `Error` with the name `ProviderSelectionError`, `code` [NO_MATCHING_PROVIDER_ERROR_CODE]
+/// and the ranked `evaluations`.
+pub(crate) fn throw_no_matching_provider_error<'a, C: Context<'a>, V: Value>(
+    cx: &mut C,
+    evaluations: Vec<ProviderEvaluation>,
+) -> JsResult<'a, V> {
+    let mut message = "No provider matches the requirements.".to_owned();
+    for evaluation in &evaluations {
+        let reasons: Vec<&str> = evaluation
+            .unmet
+            .iter()
+            .map(|unmet| unmet.message.as_str())
+            .collect();
+        message.push_str(&format!("\n  {}: {}", evaluation.name, reasons.join(" ")));
+    }
+
+    let js_err = cx.error(message)?;
+    let name = cx.string("ProviderSelectionError");
+    js_err.set(cx, "name", name)?;
+    let code = cx.string(NO_MATCHING_PROVIDER_ERROR_CODE);
+    js_err.set(cx, "code", code)?;
+    let evaluations_js = js_array_from_vec(cx, evaluations, |cx, evaluation| {
+        Ok(wrap_provider_evaluation(cx, evaluation)?.upcast())
+    })?;
+    js_err.set(cx, "evaluations", evaluations_js)?;
+    cx.throw(js_err)
+}
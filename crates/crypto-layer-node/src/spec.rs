@@ -0,0 +1,262 @@
+use std::collections::HashSet;
+use std::hash::Hash;
+
+use crypto_layer::common::config::Spec;
+use crypto_layer::prelude::*;
+
+use crate::fromjs::error::{ConversionError, InvalidValue};
+
+/// Variants chosen for missing fields of specs, most preferred first.
+///
+/// Variants supported by the provider, but missing here, are only chosen if none of these is supported.
+struct Preference {
+    ciphers: &'static [Cipher],
+    hashes: &'static [CryptoHash],
+    asym_specs: &'static [AsymmetricKeySpec],
+}
+
+/// Preference of software providers.
+const SOFTWARE_PREFERENCE: Preference = Preference {
+    ciphers: &[
+        Cipher::AesGcm256,
+        Cipher::XChaCha20Poly1305,
+        Cipher::ChaCha20Poly1305,
+        Cipher::AesGcm128,
+    ],
+    hashes: &[
+        CryptoHash::Sha2_256,
+        CryptoHash::Sha2_384,
+        CryptoHash::Sha2_512,
+        CryptoHash::Sha3_256,
+        CryptoHash::Sha3_384,
+        CryptoHash::Sha3_512,
+    ],
+    asym_specs: &[
+        AsymmetricKeySpec::P256,
+        AsymmetricKeySpec::Curve25519,
+        AsymmetricKeySpec::P384,
+        AsymmetricKeySpec::P521,
+    ],
+};
+
+/// Preference of hardware backed providers.
+///
+/// Secure elements implement AES and the NIST curves, thus only those are preferred,
+/// with the larger sizes first, as hardware keys usually live longer than software keys.
+const HARDWARE_PREFERENCE: Preference = Preference {
+    ciphers: &[Cipher::AesGcm256, Cipher::AesGcm128],
+    hashes: &[CryptoHash::Sha2_384, CryptoHash::Sha2_256, CryptoHash::Sha2_512],
+    asym_specs: &[
+        AsymmetricKeySpec::P384,
+        AsymmetricKeySpec::P256,
+        AsymmetricKeySpec::P521,
+    ],
+};
+
+/// Security level of providers, whose keys are not exportable by default.
+const HARDWARE_SECURITY_LEVEL: &str = "Hardware";
+
+/// `KeySpec` with optional fields.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct PartialKeySpec {
+    pub cipher: Option<Cipher>,
+    pub signing_hash: Option<CryptoHash>,
+    pub ephemeral: Option<bool>,
+    pub non_exportable: Option<bool>,
+}
+
+/// `KeyPairSpec` with optional fields.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct PartialKeyPairSpec {
+    pub asym_spec: Option<AsymmetricKeySpec>,
+    /// `Some(None)` if the cipher was explicitly set to `null`.
+    pub cipher: Option<Option<Cipher>>,
+    pub signing_hash: Option<CryptoHash>,
+    pub ephemeral: Option<bool>,
+    pub non_exportable: Option<bool>,
+}
+
+/// `Spec` with optional fields.
+#[derive(Debug, Clone)]
+pub(crate) enum PartialSpec {
+    KeySpec(PartialKeySpec),
+    KeyPairSpec(PartialKeyPairSpec),
+}
+
+/// Name of the variant of a `crypto-layer` enum as used in JS.
+pub(crate) fn variant_name<T>(value: &T) -> &'static str
+where
+    for<'b> &'b T: Into<&'static str>,
+{
+    value.into()
+}
+
+/// Returns the supported variant appearing first in `preference` or, if none does, the supported variant with the
+/// lowest name.
+fn preferred<T: Clone + Eq + Hash>(supported: &HashSet<T>, preference: &[T]) -> Option<T>
+where
+    for<'b> &'b T: Into<&'static str>,
+{
+    preference
+        .iter()
+        .find(|v| supported.contains(*v))
+        .or_else(|| supported.iter().min_by_key(|v| variant_name(*v)))
+        .cloned()
+}
+
+fn check_supported<T: Eq + Hash>(value: &T, supported: &HashSet<T>) -> Result<(), ConversionError>
+where
+    for<'b> &'b T: Into<&'static str>,
+{
+    if supported.contains(value) {
+        return Ok(());
+    }
+    let mut names: Vec<&'static str> = supported.iter().map(variant_name).collect();
+    names.sort_unstable();
+    Err(ConversionError::unsupported(variant_name(value), names))
+}
+
+fn missing(field: &str, expected: &str) -> ConversionError {
+    ConversionError::InvalidValue(Box::new(InvalidValue {
+        path: vec![field.to_owned()],
+        received: "undefined".to_owned(),
+        expected: format!("{expected}, as the provider has no capabilities to choose from"),
+        accepted: vec![],
+    }))
+}
+
+/// Checks `value` against `supported` or chooses a value from `supported`, if `value` is missing.
+///
+/// Without capabilities `value` is returned unchecked.
+fn complete_field<T: Clone + Eq + Hash>(
+    value: Option<T>,
+    supported: Option<&HashSet<T>>,
+    preference: &[T],
+    field: &str,
+) -> Result<T, ConversionError>
+where
+    for<'b> &'b T: Into<&'static str>,
+{
+    match (value, supported) {
+        (Some(value), Some(supported)) => {
+            check_supported(&value, supported).map_err(|err| err.at(field))?;
+            Ok(value)
+        }
+        (Some(value), None) => Ok(value),
+        (None, Some(supported)) => {
+            preferred(supported, preference).ok_or_else(|| missing(field, "string"))
+        }
+        (None, None) => Err(missing(field, "string")),
+    }
+}
+
+/// Whether the provider guarantees hardware backed keys.
+fn is_hardware_backed(capabilities: Option<&ProviderConfig>) -> bool {
+    capabilities.is_some_and(|capabilities| {
+        variant_name(&capabilities.min_security_level) == HARDWARE_SECURITY_LEVEL
+    })
+}
+
+/// Default of `non_exportable`: Keys of hardware backed providers cannot be exported.
+fn default_non_exportable(capabilities: Option<&ProviderConfig>) -> bool {
+    is_hardware_backed(capabilities)
+}
+
+/// Preference for the missing fields of specs of the provider, which depends on its security level.
+fn preference(capabilities: Option<&ProviderConfig>) -> &'static Preference {
+    if is_hardware_backed(capabilities) {
+        &HARDWARE_PREFERENCE
+    } else {
+        &SOFTWARE_PREFERENCE
+    }
+}
+
+/// Fills missing fields of `partial` with values supported by the provider and validates given fields against
+/// `capabilities`.
+///
+/// * `cipher` and `signing_hash` default to the most preferred variant the provider supports.
+///   Hardware backed providers prefer other variants than software providers, see [HARDWARE_PREFERENCE].
+/// * `ephemeral` defaults to `false`.
+/// * `non_exportable` defaults to `true` for hardware backed providers, `false` otherwise.
+///
+/// Paths of errors are relative to the spec.
+pub(crate) fn complete_key_spec(
+    partial: PartialKeySpec,
+    capabilities: Option<&ProviderConfig>,
+) -> Result<KeySpec, ConversionError> {
+    let preference = preference(capabilities);
+    Ok(KeySpec {
+        cipher: complete_field(
+            partial.cipher,
+            capabilities.map(|c| &c.supported_ciphers),
+            preference.ciphers,
+            "cipher",
+        )?,
+        signing_hash: complete_field(
+            partial.signing_hash,
+            capabilities.map(|c| &c.supported_hashes),
+            preference.hashes,
+            "signing_hash",
+        )?,
+        ephemeral: partial.ephemeral.unwrap_or(false),
+        non_exportable: partial
+            .non_exportable
+            .unwrap_or_else(|| default_non_exportable(capabilities)),
+    })
+}
+
+/// Key pair variant of [complete_key_spec].
+///
+/// A missing `cipher` stays `null`, as key pairs do not require one.
+pub(crate) fn complete_key_pair_spec(
+    partial: PartialKeyPairSpec,
+    capabilities: Option<&ProviderConfig>,
+) -> Result<KeyPairSpec, ConversionError> {
+    let preference = preference(capabilities);
+    let cipher = match partial.cipher.flatten() {
+        Some(cipher) => Some(complete_field(
+            Some(cipher),
+            capabilities.map(|c| &c.supported_ciphers),
+            preference.ciphers,
+            "cipher",
+        )?),
+        None => None,
+    };
+
+    Ok(KeyPairSpec {
+        asym_spec: complete_field(
+            partial.asym_spec,
+            capabilities.map(|c| &c.supported_asym_spec),
+            preference.asym_specs,
+            "asym_spec",
+        )?,
+        cipher,
+        signing_hash: complete_field(
+            partial.signing_hash,
+            capabilities.map(|c| &c.supported_hashes),
+            preference.hashes,
+            "signing_hash",
+        )?,
+        ephemeral: partial.ephemeral.unwrap_or(false),
+        non_exportable: partial
+            .non_exportable
+            .unwrap_or_else(|| default_non_exportable(capabilities)),
+    })
+}
+
+/// Completes either variant of `partial`.
+///
+/// Paths of errors are relative to the spec, e.g. `KeySpec.cipher`.
+pub(crate) fn complete_spec(
+    partial: PartialSpec,
+    capabilities: Option<&ProviderConfig>,
+) -> Result<Spec, ConversionError> {
+    match partial {
+        PartialSpec::KeySpec(partial) => complete_key_spec(partial, capabilities)
+            .map(Spec::KeySpec)
+            .map_err(|err| err.at("KeySpec")),
+        PartialSpec::KeyPairSpec(partial) => complete_key_pair_spec(partial, capabilities)
+            .map(Spec::KeyPairSpec)
+            .map_err(|err| err.at("KeyPairSpec")),
+    }
+}
@@ -0,0 +1,244 @@
+use std::collections::HashMap;
+use std::fs::{self, OpenOptions};
+use std::io::{ErrorKind, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, OnceLock, PoisonError, Weak};
+
+use crypto_layer::common::error::CalError;
+use crypto_layer::prelude::*;
+use sha2::{Digest, Sha256};
+use zeroize::Zeroizing;
+
+/// Provider deriving storage keys, as the provider using the key does not exist yet.
+///
+/// Derived keys are ephemeral, thus it is created without storage.
+const KDF_PROVIDER_NAME: &str = "SoftwareProvider";
+
+/// Length of salts created by [StoragePassword::salt].
+const SALT_LEN: usize = 16;
+
+#[derive(thiserror::Error, Debug)]
+pub(crate) enum StoragePasswordError {
+    #[error(transparent)]
+    Cal(#[from] CalError),
+    #[error("Failed accessing the salt file {path}: {source}")]
+    Salt {
+        path: PathBuf,
+        source: std::io::Error,
+    },
+    #[error("The salt file {path} holds {len} bytes instead of {SALT_LEN}.")]
+    InvalidSalt { path: PathBuf, len: usize },
+    #[error("The provider {KDF_PROVIDER_NAME} deriving the storage key is not available.")]
+    NoKdfProvider,
+}
+
+/// Password, from which the key of `StorageConfigSymmetricEncryption` is derived.
+pub(crate) struct StoragePassword {
+    pub password: Zeroizing<String>,
+    pub kdf: KDF,
+    /// File holding the salt, which is created with a random salt, if it does not exist.
+    pub salt_path: PathBuf,
+}
+
+/// Storage key derived by [StoragePassword::derive_key].
+///
+/// Every provider created with the key holds it, see [crate::provider::new_boxed_provider],
+/// thus the key and the digest of its password stay cached only as long as such a provider is alive.
+pub(crate) struct DerivedStorageKey {
+    /// Digest of password, salt and kdf, under which the key is cached in [derived_keys].
+    digest: Vec<u8>,
+    key: KeyHandle,
+}
+
+impl Drop for DerivedStorageKey {
+    fn drop(&mut self) {
+        let mut derived_keys = derived_keys().lock().unwrap_or_else(PoisonError::into_inner);
+        // The key may have been derived again in the meantime.
+        if derived_keys
+            .get(&self.digest)
+            .is_some_and(|key| key.strong_count() == 0)
+        {
+            derived_keys.remove(&self.digest);
+        }
+    }
+}
+
+/// Keys derived by [StoragePassword::derive_key], which are still held by a provider.
+///
+/// Deriving the same key again while the first provider is alive returns the same handle,
+/// so that the provider registry recognizes providers created with the same password.
+fn derived_keys() -> &'static Mutex<HashMap<Vec<u8>, Weak<DerivedStorageKey>>> {
+    static DERIVED_KEYS: OnceLock<Mutex<HashMap<Vec<u8>, Weak<DerivedStorageKey>>>> =
+        OnceLock::new();
+    DERIVED_KEYS.get_or_init(Default::default)
+}
+
+/// Creates the file `path` with `content` and syncs it to disk.
+fn write_new_file(path: &Path, content: &[u8]) -> std::io::Result<()> {
+    let mut file = OpenOptions::new().write(true).create_new(true).open(path)?;
+    file.write_all(content)?;
+    file.sync_all()
+}
+
+impl StoragePassword {
+    fn salt_error(&self, source: std::io::Error) -> StoragePasswordError {
+        StoragePasswordError::Salt {
+            path: self.salt_path.clone(),
+            source,
+        }
+    }
+
+    /// Reads the salt from `salt_path` or creates the file with a random salt.
+    ///
+    /// # Errors
+    /// * [StoragePasswordError::InvalidSalt], if the file does not hold exactly [SALT_LEN] bytes.
+    fn salt(&self, provider: &mut Provider) -> Result<Vec<u8>, StoragePasswordError> {
+        let salt = match fs::read(&self.salt_path) {
+            Ok(salt) => salt,
+            Err(err) if err.kind() == ErrorKind::NotFound => self.create_salt(provider)?,
+            Err(err) => return Err(self.salt_error(err)),
+        };
+
+        if salt.len() != SALT_LEN {
+            return Err(StoragePasswordError::InvalidSalt {
+                path: self.salt_path.clone(),
+                len: salt.len(),
+            });
+        }
+        Ok(salt)
+    }
+
+    /// Writes a random salt to a temporary file and links it to `salt_path`.
+    ///
+    /// Linking fails, if `salt_path` exists, thus concurrent creators never replace each other's salt
+    /// and readers never see a partially written file.
+    /// Returns the salt of the file, which was linked first.
+    fn create_salt(&self, provider: &mut Provider) -> Result<Vec<u8>, StoragePasswordError> {
+        let salt = provider.get_random(SALT_LEN);
+        let suffix: String = provider
+            .get_random(8)
+            .iter()
+            .map(|byte| format!("{byte:02x}"))
+            .collect();
+        let mut temp_path = self.salt_path.clone().into_os_string();
+        temp_path.push(format!(".{suffix}.tmp"));
+        let temp_path = PathBuf::from(temp_path);
+
+        let result = write_new_file(&temp_path, &salt)
+            .and_then(|()| fs::hard_link(&temp_path, &self.salt_path));
+        if let Err(err) = fs::remove_file(&temp_path) {
+            if err.kind() != ErrorKind::NotFound {
+                tracing::warn!(
+                    error = %err,
+                    path = %temp_path.display(),
+                    "Failed removing temporary salt file."
+                );
+            }
+        }
+
+        match result {
+            Ok(()) => Ok(salt),
+            Err(err) if err.kind() == ErrorKind::AlreadyExists => {
+                fs::read(&self.salt_path).map_err(|err| self.salt_error(err))
+            }
+            Err(err) => Err(self.salt_error(err)),
+        }
+    }
+
+    /// Derives the AES-256-GCM key encrypting the storage.
+    ///
+    /// The key is ephemeral and non exportable. It only depends on password, salt and kdf,
+    /// thus a wrong password is not detected here, but when keys of the storage are loaded.
+    pub(crate) fn derive_key(&self) -> Result<Arc<DerivedStorageKey>, StoragePasswordError> {
+        let mut provider = create_provider_from_name(
+            KDF_PROVIDER_NAME,
+            ProviderImplConfig {
+                additional_config: vec![],
+            },
+        )
+        .ok_or(StoragePasswordError::NoKdfProvider)?;
+        let salt = self.salt(&mut provider)?;
+
+        let mut hasher = Sha256::new();
+        let kdf = format!("{:?}", self.kdf);
+        for part in [self.password.as_bytes(), &salt[..], kdf.as_bytes()] {
+            hasher.update((part.len() as u64).to_be_bytes());
+            hasher.update(part);
+        }
+        let digest = hasher.finalize().to_vec();
+
+        // The lock is held while deriving, so that concurrent calls derive each key once.
+        let mut derived_keys = derived_keys().lock().unwrap_or_else(PoisonError::into_inner);
+        if let Some(key) = derived_keys.get(&digest).and_then(Weak::upgrade) {
+            return Ok(key);
+        }
+
+        let spec = KeySpec {
+            cipher: Cipher::AesGcm256,
+            signing_hash: CryptoHash::Sha2_256,
+            ephemeral: true,
+            non_exportable: true,
+        };
+        let key = provider.derive_key_from_password(&self.password, &salt, spec, self.kdf.clone())?;
+        let key = Arc::new(DerivedStorageKey {
+            digest: digest.clone(),
+            key,
+        });
+        derived_keys.insert(digest, Arc::downgrade(&key));
+        Ok(key)
+    }
+}
+
+/// `ProviderImplConfig`, whose storage key may still have to be derived from a password.
+///
+/// Deriving is slow by design, thus it is left to the thread creating the provider.
+pub(crate) struct PendingImplConfig {
+    pub impl_config: ProviderImplConfig,
+    pub storage_password: Option<StoragePassword>,
+}
+
+/// `ProviderImplConfig` with the storage key derived by [PendingImplConfig::resolve].
+pub(crate) struct ResolvedImplConfig {
+    pub impl_config: ProviderImplConfig,
+    /// Derived storage key, which a provider created with `impl_config` must hold.
+    pub storage_key: Option<Arc<DerivedStorageKey>>,
+}
+
+impl PendingImplConfig {
+    /// Derives the storage key and adds it as `StorageConfigSymmetricEncryption`.
+    pub(crate) fn resolve(self) -> Result<ResolvedImplConfig, StoragePasswordError> {
+        let mut impl_config = self.impl_config;
+        let storage_key = self
+            .storage_password
+            .map(|storage_password| storage_password.derive_key())
+            .transpose()?;
+        if let Some(storage_key) = &storage_key {
+            impl_config
+                .additional_config
+                .push(AdditionalConfig::StorageConfigSymmetricEncryption(storage_key.key.clone()));
+        }
+        Ok(ResolvedImplConfig {
+            impl_config,
+            storage_key,
+        })
+    }
+}
+
+/// Resolves a [PendingImplConfig] inside a worker or rejects `$deferred` and returns.
+macro_rules! resolve_or_throw_deferred {
+    ($channel:expr, $deferred:expr, $pending_impl_config:expr) => {{
+        match $pending_impl_config.resolve() {
+            Ok(impl_config) => impl_config,
+            Err(err) => {
+                tracing::error!(error = %err, "Failed deriving the storage key.");
+                let message = err.to_string();
+                $deferred.settle_with($channel, move |mut cx| {
+                    cx.throw_error::<_, Handle<JsValue>>(message)
+                });
+                return;
+            }
+        }
+    }};
+}
+
+pub(crate) use resolve_or_throw_deferred;
@@ -0,0 +1,50 @@
+use neon::prelude::*;
+
+use super::{js_array_from_vec, uint_8_array_from_vec_u8, wrap_string_array};
+use crate::backup::{ExportedBackup, ImportedBackup, RestoredKey};
+
+/// Converts [ExportedBackup] to `{ archive: Uint8Array, skipped: string[] }`.
+pub(crate) fn wrap_exported_backup<'a>(
+    cx: &mut impl Context<'a>,
+    backup: ExportedBackup,
+) -> JsResult<'a, JsObject> {
+    let obj = cx.empty_object();
+
+    let archive_js = uint_8_array_from_vec_u8(cx, backup.archive)?;
+    obj.set(cx, "archive", archive_js)?;
+    let skipped_js = wrap_string_array(cx, backup.skipped)?;
+    obj.set(cx, "skipped", skipped_js)?;
+
+    Ok(obj)
+}
+
+/// Converts [RestoredKey]s to `{ originalId: string, id: string }[]`.
+fn wrap_restored_keys<'a>(
+    cx: &mut impl Context<'a>,
+    keys: Vec<RestoredKey>,
+) -> JsResult<'a, JsArray> {
+    js_array_from_vec(cx, keys, |cx, key| {
+        let key_js = cx.empty_object();
+        let original_id_js = cx.string(key.original_id);
+        key_js.set(cx, "originalId", original_id_js)?;
+        let id_js = cx.string(key.id);
+        key_js.set(cx, "id", id_js)?;
+        Ok(key_js.upcast())
+    })
+}
+
+/// Converts [ImportedBackup] to
+/// `{ restored: { originalId: string, id: string }[], skipped: { originalId: string, id: string }[] }`.
+pub(crate) fn wrap_imported_backup<'a>(
+    cx: &mut impl Context<'a>,
+    backup: ImportedBackup,
+) -> JsResult<'a, JsObject> {
+    let obj = cx.empty_object();
+
+    let restored_js = wrap_restored_keys(cx, backup.restored)?;
+    obj.set(cx, "restored", restored_js)?;
+    let skipped_js = wrap_restored_keys(cx, backup.skipped)?;
+    obj.set(cx, "skipped", skipped_js)?;
+
+    Ok(obj)
+}
@@ -1,7 +1,9 @@
 use crypto_layer::{common::config::Spec, prelude::*};
 use neon::prelude::*;
 
-use super::wrap_string_array;
+use super::{js_array_from_vec, wrap_string_array};
+use crate::listing::KeyPage;
+use crate::selection::ProviderEvaluation;
 
 /// Inserts into a JsObject an enum as a JsString.
 ///
@@ -70,6 +72,48 @@ pub fn wrap_provider_config<'a>(
     Ok(obj)
 }
 
+/// Converts [ProviderEvaluation] to a JS object.
+///
+/// # Example Output Type
+/// ```ts
+/// type ProviderEvaluation = {
+///   name: string;
+///   matches: boolean;
+///   capabilities: ProviderConfig | null;
+///   unmet: { requirement: string; missing: string[]; message: string }[];
+/// };
+/// ```
+pub(crate) fn wrap_provider_evaluation<'a>(
+    cx: &mut impl Context<'a>,
+    evaluation: ProviderEvaluation,
+) -> JsResult<'a, JsObject> {
+    let obj = cx.empty_object();
+
+    let name_js = cx.string(&evaluation.name);
+    obj.set(cx, "name", name_js)?;
+    let matches_js = cx.boolean(evaluation.matches());
+    obj.set(cx, "matches", matches_js)?;
+    let capabilities_js = match evaluation.capabilities {
+        Some(capabilities) => wrap_provider_config(cx, capabilities)?.upcast::<JsValue>(),
+        None => cx.null().upcast(),
+    };
+    obj.set(cx, "capabilities", capabilities_js)?;
+    let unmet_js = js_array_from_vec(cx, evaluation.unmet, |cx, unmet| {
+        let unmet_obj = cx.empty_object();
+        let requirement_js = cx.string(unmet.requirement);
+        unmet_obj.set(cx, "requirement", requirement_js)?;
+        let missing = unmet.missing.iter().map(|v| v.to_string()).collect();
+        let missing_js = wrap_string_array(cx, missing)?;
+        unmet_obj.set(cx, "missing", missing_js)?;
+        let message_js = cx.string(unmet.message);
+        unmet_obj.set(cx, "message", message_js)?;
+        Ok(unmet_obj.upcast())
+    })?;
+    obj.set(cx, "unmet", unmet_js)?;
+
+    Ok(obj)
+}
+
 pub fn wrap_key_spec<'a>(cx: &mut impl Context<'a>, spec: KeySpec) -> JsResult<'a, JsObject> {
     let obj = cx.empty_object();
 
@@ -121,3 +165,31 @@ pub fn wrap_spec<'a>(cx: &mut impl Context<'a>, spec: Spec) -> JsResult<'a, JsOb
 
     return Ok(obj);
 }
+
+/// Converts [KeyPage] to `{ keys: [string, Spec][], cursor: string | null }`.
+pub(crate) fn wrap_key_page<'a>(
+    cx: &mut impl Context<'a>,
+    page: KeyPage,
+) -> JsResult<'a, JsObject> {
+    let obj = cx.empty_object();
+
+    let keys_js = js_array_from_vec(cx, page.keys, |cx, (id, spec)| {
+        let id_js = cx.string(id);
+        let spec_js = wrap_spec(cx, spec)?;
+
+        let tuple = JsArray::new(cx, 2);
+        tuple.set(cx, 0, id_js)?;
+        tuple.set(cx, 1, spec_js)?;
+
+        Ok(tuple.upcast())
+    })?;
+    obj.set(cx, "keys", keys_js)?;
+
+    let cursor_js: Handle<JsValue> = match page.cursor {
+        Some(cursor) => cx.string(cursor).upcast(),
+        None => cx.null().upcast(),
+    };
+    obj.set(cx, "cursor", cursor_js)?;
+
+    Ok(obj)
+}
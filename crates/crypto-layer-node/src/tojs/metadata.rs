@@ -0,0 +1,131 @@
+use neon::prelude::*;
+
+use super::wrap_string_array;
+use crate::metadata::{KeyMetadata, Validity};
+use crate::nonce::EncryptionLimits;
+
+/// Converts milliseconds since the unix epoch to a `Date` or `null`.
+fn optional_date<'a>(cx: &mut impl Context<'a>, millis: Option<u64>) -> JsResult<'a, JsValue> {
+    match millis {
+        Some(millis) => Ok(cx.date(millis as f64).or_throw(cx)?.upcast()),
+        None => Ok(cx.null().upcast()),
+    }
+}
+
+/// Converts [Validity] to `{ notBefore: Date | null, notAfter: Date | null, gracePeriod: number | null }`.
+pub(crate) fn wrap_validity<'a>(
+    cx: &mut impl Context<'a>,
+    validity: Validity,
+) -> JsResult<'a, JsObject> {
+    let obj = cx.empty_object();
+
+    let not_before_js = optional_date(cx, validity.not_before)?;
+    obj.set(cx, "notBefore", not_before_js)?;
+    let not_after_js = optional_date(cx, validity.not_after)?;
+    obj.set(cx, "notAfter", not_after_js)?;
+    let grace_period_js: Handle<JsValue> = match validity.grace_period {
+        Some(grace_period) => cx.number(grace_period as f64).upcast(),
+        None => cx.null().upcast(),
+    };
+    obj.set(cx, "gracePeriod", grace_period_js)?;
+
+    Ok(obj)
+}
+
+/// Converts [EncryptionLimits] to `{ maxEncryptions: number | null, warnAt: number | null, strictIvCheck: boolean }`.
+pub(crate) fn wrap_encryption_limits<'a>(
+    cx: &mut impl Context<'a>,
+    encryption_limits: EncryptionLimits,
+) -> JsResult<'a, JsObject> {
+    let obj = cx.empty_object();
+
+    let max_encryptions_js: Handle<JsValue> = match encryption_limits.max_encryptions {
+        Some(max_encryptions) => cx.number(max_encryptions as f64).upcast(),
+        None => cx.null().upcast(),
+    };
+    obj.set(cx, "maxEncryptions", max_encryptions_js)?;
+    let warn_at_js: Handle<JsValue> = match encryption_limits.warn_at {
+        Some(warn_at) => cx.number(warn_at as f64).upcast(),
+        None => cx.null().upcast(),
+    };
+    obj.set(cx, "warnAt", warn_at_js)?;
+    let strict_iv_check_js = cx.boolean(encryption_limits.strict_iv_check);
+    obj.set(cx, "strictIvCheck", strict_iv_check_js)?;
+
+    Ok(obj)
+}
+
+/// Converts [KeyMetadata] to `{ label: string | null, tags: string[], createdAt: Date, expiresAt: Date | null,
+/// validity: Validity, usages: string[] | null, family: { id: string, version: number } | null,
+/// encryptionLimits: EncryptionLimits | null, migratedFrom: string | null }`.
+pub(crate) fn wrap_key_metadata<'a>(
+    cx: &mut impl Context<'a>,
+    metadata: KeyMetadata,
+) -> JsResult<'a, JsObject> {
+    let obj = cx.empty_object();
+
+    let label_js: Handle<JsValue> = match metadata.label {
+        Some(label) => cx.string(label).upcast(),
+        None => cx.null().upcast(),
+    };
+    obj.set(cx, "label", label_js)?;
+
+    let tags_js = wrap_string_array(cx, metadata.tags)?;
+    obj.set(cx, "tags", tags_js)?;
+
+    let created_at_js = cx.date(metadata.created_at as f64).or_throw(cx)?;
+    obj.set(cx, "createdAt", created_at_js)?;
+
+    let expires_at_js = optional_date(cx, metadata.expires_at)?;
+    obj.set(cx, "expiresAt", expires_at_js)?;
+
+    let validity_js = wrap_validity(cx, metadata.validity)?;
+    obj.set(cx, "validity", validity_js)?;
+
+    let usages_js: Handle<JsValue> = match metadata.usages {
+        Some(usages) => {
+            let usages = usages.iter().map(|usage| usage.name().to_owned()).collect();
+            wrap_string_array(cx, usages)?.upcast()
+        }
+        None => cx.null().upcast(),
+    };
+    obj.set(cx, "usages", usages_js)?;
+
+    let family_js: Handle<JsValue> = match metadata.family {
+        Some(family) => {
+            let family_obj = cx.empty_object();
+            let family_id_js = cx.string(family.family_id);
+            family_obj.set(cx, "id", family_id_js)?;
+            let version_js = cx.number(family.version);
+            family_obj.set(cx, "version", version_js)?;
+            family_obj.upcast()
+        }
+        None => cx.null().upcast(),
+    };
+    obj.set(cx, "family", family_js)?;
+
+    let encryption_limits_js: Handle<JsValue> = match metadata.encryption_limits {
+        Some(encryption_limits) => wrap_encryption_limits(cx, encryption_limits)?.upcast(),
+        None => cx.null().upcast(),
+    };
+    obj.set(cx, "encryptionLimits", encryption_limits_js)?;
+
+    let migrated_from_js: Handle<JsValue> = match metadata.migrated_from {
+        Some(migrated_from) => cx.string(migrated_from).upcast(),
+        None => cx.null().upcast(),
+    };
+    obj.set(cx, "migratedFrom", migrated_from_js)?;
+
+    Ok(obj)
+}
+
+/// Converts optional [KeyMetadata] with [wrap_key_metadata] or to `null`, if the key has no metadata.
+pub(crate) fn wrap_optional_key_metadata<'a>(
+    cx: &mut impl Context<'a>,
+    metadata: Option<KeyMetadata>,
+) -> JsResult<'a, JsValue> {
+    match metadata {
+        Some(metadata) => Ok(wrap_key_metadata(cx, metadata)?.upcast()),
+        None => Ok(cx.null().upcast()),
+    }
+}
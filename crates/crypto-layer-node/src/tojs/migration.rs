@@ -0,0 +1,65 @@
+use neon::prelude::*;
+
+use super::js_array_from_vec;
+use crate::migration::{KeyMigration, MigrationReport};
+
+/// Converts an optional string to a `string` or `null`.
+fn optional_string<'a>(cx: &mut impl Context<'a>, value: Option<String>) -> Handle<'a, JsValue> {
+    match value {
+        Some(value) => cx.string(value).upcast(),
+        None => cx.null().upcast(),
+    }
+}
+
+/// Converts the results of [crate::migration::migrate_keys] to a js array of `KeyMigration`.
+///
+/// # Example Output Type
+/// ```ts
+/// type KeyMigration = {
+///     id: string;
+///     status: "migrated" | "alreadyMigrated" | "unsupported" | "failed";
+///     newId: string | null;
+///     sourceDeleted: boolean;
+///     error: string | null;
+/// };
+/// ```
+fn wrap_key_migrations<'a>(
+    cx: &mut impl Context<'a>,
+    migrations: Vec<KeyMigration>,
+) -> JsResult<'a, JsArray> {
+    js_array_from_vec(cx, migrations, |cx, migration| {
+        let obj = cx.empty_object();
+
+        let id_js = cx.string(migration.id);
+        obj.set(cx, "id", id_js)?;
+        let status_js = cx.string(migration.status.name());
+        obj.set(cx, "status", status_js)?;
+        let new_id_js = optional_string(cx, migration.new_id);
+        obj.set(cx, "newId", new_id_js)?;
+        let source_deleted_js = cx.boolean(migration.source_deleted);
+        obj.set(cx, "sourceDeleted", source_deleted_js)?;
+        let error_js = optional_string(cx, migration.error);
+        obj.set(cx, "error", error_js)?;
+
+        Ok(obj.upcast())
+    })
+}
+
+/// Converts [MigrationReport] to `{ keys: KeyMigration[], ids: Record<string, string> }`.
+pub(crate) fn wrap_migration_report<'a>(
+    cx: &mut impl Context<'a>,
+    report: MigrationReport,
+) -> JsResult<'a, JsObject> {
+    let obj = cx.empty_object();
+
+    let keys_js = wrap_key_migrations(cx, report.keys)?;
+    obj.set(cx, "keys", keys_js)?;
+    let ids_js = cx.empty_object();
+    for (id, new_id) in report.ids {
+        let new_id_js = cx.string(new_id);
+        ids_js.set(cx, id.as_str(), new_id_js)?;
+    }
+    obj.set(cx, "ids", ids_js)?;
+
+    Ok(obj)
+}
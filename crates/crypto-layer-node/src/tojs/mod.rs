@@ -1,7 +1,18 @@
+pub(crate) mod backup;
 pub(crate) mod config;
+pub(crate) mod metadata;
+pub(crate) mod migration;
+pub(crate) mod verification;
 pub(crate) mod wrap_error;
 
+use std::sync::OnceLock;
+
+use crypto_layer::common::error::CalError;
 use neon::prelude::*;
+use neon::types::buffer::TypedArray;
+use zeroize::Zeroizing;
+
+use wrap_error::js_error_from_cal_error;
 
 pub fn js_array_from_vec<'a, C, F, T>(
     cx: &mut C,
@@ -21,22 +32,145 @@ where
     Ok(result)
 }
 
+/// Converts the per item results of a batch operation to a js array of `BatchResult`.
+///
+/// # Example Output Type
+/// ```ts
+/// type BatchResult<T> = { ok: true; value: T } | { ok: false; error: Error & { kind: string } };
+/// ```
+pub(crate) fn wrap_batch_results<'a, C, F, T>(
+    cx: &mut C,
+    results: Vec<Result<T, CalError>>,
+    convert: F,
+) -> JsResult<'a, JsArray>
+where
+    C: Context<'a>,
+    F: Fn(&mut C, T) -> JsResult<'a, JsValue>,
+{
+    js_array_from_vec(cx, results, |cx, result| {
+        let obj = cx.empty_object();
+        match result {
+            Ok(value) => {
+                let ok_js = cx.boolean(true);
+                obj.set(cx, "ok", ok_js)?;
+                let value_js = convert(cx, value)?;
+                obj.set(cx, "value", value_js)?;
+            }
+            Err(err) => {
+                let ok_js = cx.boolean(false);
+                obj.set(cx, "ok", ok_js)?;
+                let error_js = js_error_from_cal_error(cx, &err)?;
+                obj.set(cx, "error", error_js)?;
+            }
+        }
+        Ok(obj.upcast())
+    })
+}
+
 /// Converts a `Vec<String>` to an js array (`string[]`).
 pub fn wrap_string_array<'a>(cx: &mut impl Context<'a>, arr: Vec<String>) -> JsResult<'a, JsArray> {
     js_array_from_vec(cx, arr, |cx, s| Ok(JsString::new(cx, s).upcast()))
 }
 
-/// Converts a `Vec<u8>` into a `Uint8Array`.
+/// Whether V8 accepts external `ArrayBuffer`s.
+///
+/// Electron enables the V8 memory cage, which refuses memory allocated outside of it.
+fn external_buffers_allowed<'a>(cx: &mut impl Context<'a>) -> NeonResult<bool> {
+    static ALLOWED: OnceLock<bool> = OnceLock::new();
+    if let Some(allowed) = ALLOWED.get() {
+        return Ok(*allowed);
+    }
+    let process = cx.global::<JsObject>("process")?;
+    let versions = process.get::<JsObject, _, _>(cx, "versions")?;
+    let electron = versions.get_opt::<JsString, _, _>(cx, "electron")?;
+    Ok(*ALLOWED.get_or_init(|| electron.is_none()))
+}
+
+/// Converts bytes into a `Uint8Array`.
+///
+/// The allocation of `value` is handed over to V8 as an external `ArrayBuffer`, thus no copy is made.
+/// Where V8 refuses external `ArrayBuffer`s, see [external_buffers_allowed], `value` is copied instead.
+fn uint_8_array_from_bytes<'a, T>(
+    cx: &mut impl Context<'a>,
+    mut value: T,
+) -> NeonResult<Handle<'a, JsUint8Array>>
+where
+    T: AsMut<[u8]> + Send + 'static,
+{
+    if value.as_mut().is_empty() {
+        JsUint8Array::new(cx, 0)
+    } else if external_buffers_allowed(cx)? {
+        let buffer = JsArrayBuffer::external(cx, value);
+        JsUint8Array::from_buffer(cx, buffer)
+    } else {
+        JsUint8Array::from_slice(cx, value.as_mut())
+    }
+}
+
+/// Converts a `Vec<u8>` into a `Uint8Array` like [uint_8_array_from_bytes].
 pub(crate) fn uint_8_array_from_vec_u8<'a>(
     cx: &mut impl Context<'a>,
     value: Vec<u8>,
 ) -> NeonResult<Handle<'a, JsUint8Array>> {
-    if value.is_empty() {
-        JsUint8Array::new(cx, 0)
-    } else {
-        // Panics on empty slice.
-        JsUint8Array::from_slice(cx, &value)
+    uint_8_array_from_bytes(cx, value)
+}
+
+/// Converts secret material into a `Uint8Array` like [uint_8_array_from_bytes].
+///
+/// External memory is wiped once the `ArrayBuffer` is garbage collected.
+/// Copies are left to the garbage collector of V8, only `value` itself is wiped right away.
+pub(crate) fn uint_8_array_from_secret<'a>(
+    cx: &mut impl Context<'a>,
+    value: Zeroizing<Vec<u8>>,
+) -> NeonResult<Handle<'a, JsUint8Array>> {
+    uint_8_array_from_bytes(cx, value)
+}
+
+pub(crate) fn uint_8_array_tuple_from_secret_tuple<'a>(
+    cx: &mut impl Context<'a>,
+    value: (Zeroizing<Vec<u8>>, Zeroizing<Vec<u8>>),
+) -> NeonResult<Handle<'a, JsArray>> {
+    let arr = cx.empty_array();
+    let val1 = uint_8_array_from_secret(cx, value.0)?;
+    arr.set(cx, 0, val1)?;
+    let val2 = uint_8_array_from_secret(cx, value.1)?;
+    arr.set(cx, 1, val2)?;
+    Ok(arr)
+}
+
+/// Throws a `RangeError`, if the caller supplied `Uint8Array` `out` is shorter than `required`.
+pub(crate) fn check_output_len<'a>(
+    cx: &mut impl Context<'a>,
+    out: Handle<JsUint8Array>,
+    required: usize,
+) -> NeonResult<()> {
+    let out_len = out.len(cx);
+    if out_len < required {
+        return cx.throw_range_error(format!(
+            "Output buffer too small. Expected at least {required} bytes got {out_len}."
+        ));
+    }
+    Ok(())
+}
+
+/// Copies `value` into the beginning of the caller supplied `Uint8Array` `out`.
+///
+/// # Returns
+/// * Amount of bytes written.
+///
+/// # Throws
+/// * When `out` is too small to hold `value`.
+pub(crate) fn copy_into_uint_8_array<'a>(
+    cx: &mut impl Context<'a>,
+    out: Handle<JsUint8Array>,
+    value: &[u8],
+) -> NeonResult<usize> {
+    check_output_len(cx, out, value.len())?;
+    if !value.is_empty() {
+        // `as_mut_slice` method panics on empty array.
+        out.as_mut_slice(cx)[..value.len()].copy_from_slice(value);
     }
+    Ok(value.len())
 }
 
 pub fn uint_8_array_tuple_from_vec_u8_tuple<'a>(
@@ -0,0 +1,60 @@
+use neon::prelude::*;
+
+use super::{js_array_from_vec, wrap_string_array};
+use crate::verification::{FailedEntry, StoreReport};
+
+/// Converts [FailedEntry] values to `{ id: string, error: string }[]`.
+fn wrap_failed_entries<'a>(
+    cx: &mut impl Context<'a>,
+    entries: Vec<FailedEntry>,
+) -> JsResult<'a, JsArray> {
+    js_array_from_vec(cx, entries, |cx, entry| {
+        let obj = cx.empty_object();
+        let id_js = cx.string(entry.id);
+        obj.set(cx, "id", id_js)?;
+        let error_js = cx.string(entry.error);
+        obj.set(cx, "error", error_js)?;
+        Ok(obj.upcast())
+    })
+}
+
+/// Converts [StoreReport] to a `StoreReport`.
+///
+/// # Example Output Type
+/// ```ts
+/// type StoreReport = {
+///     checked: number;
+///     unreadable: { id: string; error: string }[];
+///     orphaned: string[];
+///     corrupted: { id: string; error: string }[];
+///     withoutMetadata: string[];
+///     metadataFileError: string | null;
+///     repaired: string[];
+/// };
+/// ```
+pub(crate) fn wrap_store_report<'a>(
+    cx: &mut impl Context<'a>,
+    report: StoreReport,
+) -> JsResult<'a, JsObject> {
+    let obj = cx.empty_object();
+
+    let checked_js = cx.number(report.checked as f64);
+    obj.set(cx, "checked", checked_js)?;
+    let unreadable_js = wrap_failed_entries(cx, report.unreadable)?;
+    obj.set(cx, "unreadable", unreadable_js)?;
+    let orphaned_js = wrap_string_array(cx, report.orphaned)?;
+    obj.set(cx, "orphaned", orphaned_js)?;
+    let corrupted_js = wrap_failed_entries(cx, report.corrupted)?;
+    obj.set(cx, "corrupted", corrupted_js)?;
+    let without_metadata_js = wrap_string_array(cx, report.without_metadata)?;
+    obj.set(cx, "withoutMetadata", without_metadata_js)?;
+    let metadata_file_error_js: Handle<JsValue> = match report.metadata_file_error {
+        Some(metadata_file_error) => cx.string(metadata_file_error).upcast(),
+        None => cx.null().upcast(),
+    };
+    obj.set(cx, "metadataFileError", metadata_file_error_js)?;
+    let repaired_js = wrap_string_array(cx, report.repaired)?;
+    obj.set(cx, "repaired", repaired_js)?;
+
+    Ok(obj)
+}
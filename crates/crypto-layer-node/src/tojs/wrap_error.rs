@@ -1,12 +1,22 @@
-//TODO: Write function to wrap cal error
-/* use crypto_layer::common::error::{CalError, CalErrorKind};
-use crypto_layer::prelude::*;
+use crypto_layer::common::error::CalError;
 use neon::prelude::*;
 
+/// Name of the `CalErrorKind` variant of `err`, e.g. `BadParameter`.
+fn cal_error_kind_name(err: &CalError) -> String {
+    let kind = format!("{:?}", err.error_kind());
+    match kind.find(|c: char| !c.is_alphanumeric() && c != '_') {
+        Some(end) => kind[..end].to_owned(),
+        None => kind,
+    }
+}
+
+/// Converts `err` to an `Error`, whose `kind` is the name of the `CalErrorKind` variant.
 pub(crate) fn js_error_from_cal_error<'a>(
     cx: &mut impl Context<'a>,
-    err: CalError,
-) -> JsResult<'a, JsUndefined> {
-    Ok(cx.undefined())
+    err: &CalError,
+) -> JsResult<'a, JsError> {
+    let js_err = cx.error(err.to_string())?;
+    let kind = cx.string(cal_error_kind_name(err));
+    js_err.set(cx, "kind", kind)?;
+    Ok(js_err)
 }
- */
@@ -0,0 +1,171 @@
+use std::sync::Arc;
+
+use crypto_layer::common::error::CalError;
+use crypto_layer::prelude::{KeyHandle, KeyPairHandle};
+use neon::prelude::*;
+
+use crate::metadata::{EphemeralHold, MetadataStore};
+use crate::nonce::forget_encryption_counter;
+
+/// `code` of the error thrown when using a handle of a deleted key.
+pub(crate) const KEY_DELETED_ERROR_CODE: &str = "ERR_KEY_DELETED";
+
+#[derive(thiserror::Error, Debug, Clone)]
+#[error("The key {id} was deleted.")]
+pub(crate) struct KeyDeletedError {
+    pub id: String,
+}
+
+/// Handle of a key, which can be deleted.
+pub(crate) trait Deletable: Clone {
+    fn key_id(&self) -> Result<String, CalError>;
+
+    fn is_ephemeral(&self) -> bool;
+
+    fn delete_key(self) -> Result<(), CalError>;
+}
+
+impl Deletable for KeyHandle {
+    fn key_id(&self) -> Result<String, CalError> {
+        self.id()
+    }
+
+    fn is_ephemeral(&self) -> bool {
+        self.spec().ephemeral
+    }
+
+    fn delete_key(self) -> Result<(), CalError> {
+        self.delete()
+    }
+}
+
+impl Deletable for KeyPairHandle {
+    fn key_id(&self) -> Result<String, CalError> {
+        self.id()
+    }
+
+    fn is_ephemeral(&self) -> bool {
+        self.spec().ephemeral
+    }
+
+    fn delete_key(self) -> Result<(), CalError> {
+        self.delete()
+    }
+}
+
+/// Content of the box of key and key pair handles.
+pub(crate) struct Tombstoned<T> {
+    pub state: KeyState<T>,
+    /// Metadata store of the provider storage the handle was obtained from.
+    /// `None` for keys outside of any storage, e.g. derived keys.
+    pub store: Option<Arc<MetadataStore>>,
+    /// Keeps the metadata of an ephemeral key from being repaired as orphaned, while the handle is live.
+    _hold: Option<EphemeralHold>,
+}
+
+pub(crate) enum KeyState<T> {
+    Live(T),
+    /// Replaces the handle after its key was deleted.
+    Deleted { id: String },
+}
+
+impl<T> From<T> for Tombstoned<T> {
+    fn from(value: T) -> Self {
+        Self {
+            state: KeyState::Live(value),
+            store: None,
+            _hold: None,
+        }
+    }
+}
+
+impl<T: Deletable> Tombstoned<T> {
+    /// Content of a handle obtained from the provider storage with the metadata `store`.
+    pub(crate) fn stored(handle: T, store: Arc<MetadataStore>) -> Self {
+        let hold = match handle.key_id() {
+            Ok(id) if handle.is_ephemeral() => Some(store.hold_ephemeral(id)),
+            _ => None,
+        };
+        Self {
+            state: KeyState::Live(handle),
+            store: Some(store),
+            _hold: hold,
+        }
+    }
+
+    /// Returns the handle, if neither it nor another handle to the same key of its storage was deleted.
+    pub(crate) fn live(&self) -> Result<&T, KeyDeletedError> {
+        match &self.state {
+            KeyState::Live(handle) => match (handle.key_id(), &self.store) {
+                (Ok(id), Some(store)) if store.is_deleted(&id) => Err(KeyDeletedError { id }),
+                _ => Ok(handle),
+            },
+            KeyState::Deleted { id } => Err(KeyDeletedError { id: id.clone() }),
+        }
+    }
+
+    /// Deletes the key and replaces the handle with a tombstone.
+    ///
+    /// The handle stays usable, if the key could not be deleted.
+    pub(crate) fn delete(&mut self) -> Result<Result<(), CalError>, KeyDeletedError> {
+        let handle = self.live()?.clone();
+        let id = handle.key_id().unwrap_or_default();
+
+        let result = delete_and_forget(handle, self.store.as_deref());
+        if result.is_ok() {
+            self.state = KeyState::Deleted { id };
+            self._hold = None;
+        }
+        Ok(result)
+    }
+}
+
+/// Deletes the key of `handle` together with its metadata in `store` and its encryption counter.
+///
+/// Other handles of the key obtained from the same storage detect the deletion with [Tombstoned::live].
+/// Keys with the same id in other storages are not affected.
+pub(crate) fn delete_and_forget<T: Deletable>(
+    handle: T,
+    store: Option<&MetadataStore>,
+) -> Result<(), CalError> {
+    let id = handle.key_id().unwrap_or_default();
+    handle.delete_key()?;
+
+    if let Some(store) = store {
+        store.forget(&id);
+    }
+    forget_encryption_counter(store, &id);
+    Ok(())
+}
+
+/// Throws an `Error` with the name `KeyDeletedError`, `code` [KEY_DELETED_ERROR_CODE] and the `keyId`.
+pub(crate) fn throw_key_deleted_error<'a, C: Context<'a>, V: Value>(
+    cx: &mut C,
+    err: &KeyDeletedError,
+) -> JsResult<'a, V> {
+    let js_err = cx.error(err.to_string())?;
+    let name = cx.string("KeyDeletedError");
+    js_err.set(cx, "name", name)?;
+    let code = cx.string(KEY_DELETED_ERROR_CODE);
+    js_err.set(cx, "code", code)?;
+    let key_id = cx.string(&err.id);
+    js_err.set(cx, "keyId", key_id)?;
+    cx.throw(js_err)
+}
+
+/// Returns the live handle or rejects the deferred with a `KeyDeletedError` and returns from the current function.
+macro_rules! live_or_deleted_error_deferred {
+    ($channel:expr, $deferred:expr, $live_expr:expr) => {{
+        match $live_expr {
+            Ok(live) => live,
+            Err(err) => {
+                $deferred.settle_with($channel, move |mut cx| {
+                    crate::tombstone::throw_key_deleted_error::<_, JsValue>(&mut cx, &err)
+                });
+                return ();
+            }
+        }
+    }};
+}
+
+pub(crate) use live_or_deleted_error_deferred;
@@ -0,0 +1,130 @@
+use std::collections::HashSet;
+
+use crypto_layer::common::config::Spec;
+use crypto_layer::common::error::CalError;
+use crypto_layer::prelude::*;
+
+use crate::metadata::{MetadataError, MetadataStore};
+
+#[derive(thiserror::Error, Debug)]
+pub(crate) enum VerificationError {
+    #[error(transparent)]
+    Cal(#[from] CalError),
+    #[error(transparent)]
+    Metadata(#[from] MetadataError),
+}
+
+/// Entry, which failed verification, and why.
+#[derive(Debug, Clone)]
+pub(crate) struct FailedEntry {
+    pub id: String,
+    pub error: String,
+}
+
+/// Result of [verify_store] and [repair_store].
+#[derive(Debug, Default)]
+pub(crate) struct StoreReport {
+    /// Keys listed by the provider storage.
+    pub checked: usize,
+    /// Listed keys, which cannot be loaded, e.g. because their MAC or signature does not match.
+    pub unreadable: Vec<FailedEntry>,
+    /// Keys with metadata, which are missing in the provider storage and not held by a live ephemeral handle.
+    pub orphaned: Vec<String>,
+    /// Metadata entries, which cannot be deserialized.
+    pub corrupted: Vec<FailedEntry>,
+    /// Listed keys without metadata entry, e.g. created before the addon kept metadata.
+    /// They are not restricted by usages or validity periods.
+    pub without_metadata: Vec<String>,
+    /// Why the metadata file was moved aside when the provider was created.
+    pub metadata_file_error: Option<String>,
+    /// Metadata entries removed by [repair_store].
+    pub repaired: Vec<String>,
+}
+
+/// Loads the key `id` without using it.
+fn load(provider: &mut Provider, id: &str, spec: &Spec) -> Result<(), CalError> {
+    match spec {
+        Spec::KeySpec(_) => provider.load_key(id.to_owned()).map(drop),
+        Spec::KeyPairSpec(_) => provider.load_key_pair(id.to_owned()).map(drop),
+    }
+}
+
+/// Checks every key of the provider storage and the metadata of the addon.
+///
+/// crypto-layer verifies the MAC or signature of an entry of a storage configured with
+/// `StorageConfigHMAC` or `StorageConfigDSA` when the key is loaded,
+/// thus each listed key is loaded once and reported as unreadable if that fails.
+/// Listed keys without metadata entry are reported, as no policy applies to them.
+/// Metadata entries of keys, which are not listed, are orphaned,
+/// unless they belong to an ephemeral key, which a live handle of this storage still holds.
+pub(crate) fn verify_store(
+    provider: &mut Provider,
+    store: &MetadataStore,
+) -> Result<StoreReport, VerificationError> {
+    let keys = provider.get_all_keys()?;
+    let mut report = StoreReport {
+        checked: keys.len(),
+        metadata_file_error: store.file_error().map(str::to_owned),
+        ..Default::default()
+    };
+
+    let mut listed = HashSet::with_capacity(keys.len());
+    for (id, spec) in keys {
+        if let Err(err) = load(provider, &id, &spec) {
+            report.unreadable.push(FailedEntry {
+                id: id.clone(),
+                error: err.to_string(),
+            });
+        }
+        if !store.has_entry(&id) {
+            report.without_metadata.push(id.clone());
+        }
+        listed.insert(id);
+    }
+
+    report.orphaned = store
+        .ids()
+        .into_iter()
+        .filter(|id| !listed.contains(id) && !store.is_held_ephemeral(id))
+        .collect();
+    report.orphaned.sort();
+    report.without_metadata.sort();
+    report.corrupted = store
+        .corrupt_ids()
+        .into_iter()
+        .map(|(id, error)| FailedEntry { id, error })
+        .collect();
+    report.corrupted.sort_by(|a, b| a.id.cmp(&b.id));
+
+    Ok(report)
+}
+
+/// Runs [verify_store] and removes orphaned and corrupted metadata entries.
+///
+/// Only the metadata file of the addon is repaired, the provider storage of crypto-layer is never changed.
+/// With `quarantine` the entries are moved to a quarantine file next to the metadata file instead of
+/// being discarded. Unreadable keys are only reported, as their storage entries belong to crypto-layer,
+/// which offers no way to move or delete an entry without loading it.
+pub(crate) fn repair_store(
+    provider: &mut Provider,
+    store: &MetadataStore,
+    quarantine: bool,
+) -> Result<StoreReport, VerificationError> {
+    let mut report = verify_store(provider, store)?;
+
+    let bad_entries: Vec<(String, String)> = report
+        .orphaned
+        .iter()
+        .map(|id| (id.clone(), "orphaned".to_owned()))
+        .chain(
+            report
+                .corrupted
+                .iter()
+                .map(|entry| (entry.id.clone(), format!("corrupted: {}", entry.error))),
+        )
+        .collect();
+    store.quarantine(&bad_entries, quarantine)?;
+
+    report.repaired = bad_entries.into_iter().map(|(id, _)| id).collect();
+    Ok(report)
+}
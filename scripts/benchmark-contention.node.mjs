@@ -0,0 +1,83 @@
+// Measures throughput of parallel `createKey` and `loadKey` calls on a single provider
+// for different amounts of worker threads.
+//
+// USAGE: npm run build && node scripts/benchmark-contention.node.mjs [operations per run]
+
+import { mkdtempSync, rmSync } from "node:fs";
+import { tmpdir, availableParallelism } from "node:os";
+import { join, dirname } from "node:path";
+import { fileURLToPath } from "node:url";
+import { argv, exit } from "node:process";
+import { createRequire } from "node:module";
+
+const sourceFileDir = dirname(fileURLToPath(import.meta.url));
+const packageRootDir = join(sourceFileDir, "..");
+
+const require = createRequire(import.meta.url);
+const { createProviderFromName, configureRuntime } = require(
+    join(packageRootDir, "lib", "index.cjs"),
+);
+
+const operations = Number(argv[2] ?? 2000);
+if (!Number.isInteger(operations) || operations <= 0) {
+    console.log("USAGE: node benchmark-contention.node.mjs [operations]");
+    exit(1);
+}
+
+const spec = {
+    cipher: "AesGcm256",
+    signing_hash: "Sha2_256",
+    ephemeral: false,
+    non_exportable: false,
+};
+
+const threadCounts = [1, 2, 4, 8, 16].filter(
+    (count) => count <= Math.max(availableParallelism(), 1),
+);
+
+async function measure(label, run) {
+    const start = process.hrtime.bigint();
+    await run();
+    const seconds = Number(process.hrtime.bigint() - start) / 1e9;
+    return { label, opsPerSecond: Math.round(operations / seconds) };
+}
+
+const dbDir = mkdtempSync(join(tmpdir(), "crypto-layer-node-bench-"));
+try {
+    const provider = await createProviderFromName("SoftwareProvider", {
+        additional_config: [{ FileStoreConfig: { db_dir: dbDir } }],
+    });
+    if (!provider) {
+        console.log("Failed initializing software provider.");
+        exit(1);
+    }
+
+    const results = [];
+    for (const workerThreads of threadCounts) {
+        configureRuntime({ workerThreads });
+
+        let keys = [];
+        const create = await measure("createKey", async () => {
+            keys = await Promise.all(
+                Array.from({ length: operations }, () =>
+                    provider.createKey(spec),
+                ),
+            );
+        });
+        const ids = await Promise.all(keys.map((key) => key.id()));
+        const load = await measure("loadKey", async () => {
+            await Promise.all(ids.map((id) => provider.loadKey(id)));
+        });
+
+        results.push({
+            workerThreads,
+            "createKey ops/s": create.opsPerSecond,
+            "loadKey ops/s": load.opsPerSecond,
+        });
+    }
+
+    console.table(results);
+} finally {
+    configureRuntime({});
+    rmSync(dbDir, { recursive: true, force: true });
+}
@@ -1,7 +1,16 @@
 // This module is the CJS entry point for the library.
 
 // The Rust addon.
-export { getAllProviders, getProviderCapabilities } from "./load.cjs";
+export {
+    configureRuntime,
+    getRuntimeMetrics,
+    Provider as BareProvider,
+    KeyHandle as BareKeyHandle,
+    KeyPairHandle as BareKeyPairHandle,
+    DHExchange as BareDHExchange,
+} from "./load.cjs";
+
+import { inspect } from "node:util";
 
 import type {
     Provider,
@@ -15,265 +24,964 @@ import type {
     KDF,
     CryptoHash,
     Spec,
+    Cipher,
+    AsymmetricKeySpec,
 } from "@nmshd/rs-crypto-types";
 
 import {
+    getAllProviders as getAllBareProviders,
+    getProviderCapabilities as getBareProviderCapabilities,
+    selectProviders as selectBareProviders,
+    createAbortToken,
+    abortToken,
     createBareProvider,
-    providerName,
-    signData,
-    verifySignature,
-    idForKeyHandle,
-    idForKeyPair,
-    deleteForKeyHandle,
-    deleteForKeyPair,
-    decryptDataForKeyHandle,
-    decryptDataForKeyPairHandle,
-    encryptDataForKeyHandle,
-    encryptDataForKeyPairHandle,
-    extractKeyForKeyHandle,
-    getPublicKey,
-    createBareKey,
-    createBareKeyPair,
-    loadBareKey,
-    loadBareKeyPair,
-    importBareKey,
-    importBareKeyPair,
-    importBarePublicKey,
     createBareProviderFromName,
-    getCapabilities,
-    startEphemeralDhExchange,
-    dhExchangeFromKeys,
-    getPublicKeyForDHExchange,
-    // addExternalFinalForDHExchange,
-    // addExternalForDHExchange,
-    deriveClientSessionKeys,
-    deriveServerSessionKeys,
-    deriveClientKeyHandles,
-    deriveServerKeyHandles,
-    specForKeyHandle,
-    specForKeyPairHandle,
-    extractKeyForKeyPairHandle,
-    deriveKeyFromPassword,
-    getRandom,
-    deriveKeyFromBase,
-    hash,
-    startDhExchangeForKeyPairHandle,
-    deriveKeyForKeyHandle,
-    encryptForKeyHandle,
-    encryptWithIvForKeyHandle,
-    getAllKeys,
+    Provider as BareProvider,
+    KeyHandle as BareKeyHandle,
+    KeyPairHandle as BareKeyPairHandle,
+    DHExchange as BareDHExchange,
 } from "./load.cjs";
 
-type BareProvider = object;
-type BareKeyHandle = object;
-type BareKeyPairHandle = object;
-type BareDHExchange = object;
+type BareAbortToken = object;
+
+/** Sizing of the thread pool executing the crypto operations. */
+export type RuntimeOptions = {
+    /** Amount of threads executing operations. Defaults to the amount of available cores. */
+    workerThreads?: number;
+    /**
+     * Amount of operations which may wait for a busy worker. Further operations are rejected. Unbounded by default.
+     * Operations an idle worker starts right away do not wait, thus `0` only rejects when all workers are busy.
+     */
+    maxQueued?: number;
+};
+
+/** Snapshot of the state of the thread pool executing the crypto operations. */
+export type RuntimeMetrics = {
+    workerThreads: number;
+    maxQueued: number | null;
+    /** Operations waiting for execution. */
+    pending: number;
+    /** Operations currently executing. */
+    running: number;
+    completed: number;
+    /** Operations rejected, because the queue was full. */
+    rejected: number;
+};
+
+/** Error of one item of a batch operation. */
+export type BatchError = Error & {
+    /** Variant of the `CalErrorKind` of crypto-layer, e.g. `BadParameter`. */
+    kind: string;
+};
+
+/** Per item result of a batch operation. */
+export type BatchResult<T> =
+    | { ok: true; value: T }
+    | { ok: false; error: BatchError };
+
+/** Options accepted by every asynchronous operation. */
+export type OperationOptions = {
+    /**
+     * Rejects the operation with `signal.reason` once aborted.
+     *
+     * Operations which did not start yet are skipped and
+     * handles created by an aborted operation are released.
+     */
+    signal?: AbortSignal;
+};
+
+/** Operation a key may be restricted to with {@link CreateKeyOptions.usages}. */
+export type KeyUsage =
+    | "encrypt"
+    | "decrypt"
+    | "sign"
+    | "verify"
+    | "deriveKey"
+    | "dhExchange";
+
+/** Metadata stored next to a key in the storage of its provider. */
+export type KeyMetadata = {
+    label: string | null;
+    tags: string[];
+    createdAt: Date;
+    expiresAt: Date | null;
+    validity: {
+        notBefore: Date | null;
+        notAfter: Date | null;
+        /** Milliseconds, `null` if there is no grace period. */
+        gracePeriod: number | null;
+    };
+    /** `null` if the key is not restricted to any usages. */
+    usages: KeyUsage[] | null;
+    /**
+     * Set for keys, which were rotated with {@link NodeProvider.rotateKey} or created by it.
+     * `id` is the id of the first version of the family.
+     */
+    family: { id: string; version: number } | null;
+    encryptionLimits: {
+        maxEncryptions: number | null;
+        warnAt: number | null;
+        strictIvCheck: boolean;
+    } | null;
+    /** Id of the source key, set for keys copied by {@link migrateKeys}. */
+    migratedFrom: string | null;
+};
+
+/** Options for functions creating keys. */
+export type CreateKeyOptions = OperationOptions & {
+    /** Stored with the key and returned by `metadata()` of handles and {@link NodeProvider.getAllKeys}. */
+    metadata?: {
+        label?: string;
+        tags?: string[];
+        /** `Date` or milliseconds since the unix epoch. */
+        expiresAt?: Date | number;
+    };
+    /**
+     * Period in which the key may be used, enforced by every operation of its handles.
+     *
+     * Outside of it operations reject with a {@link KeyNotValidError}.
+     * After `notAfter` decryption and verification stay allowed for `gracePeriod` milliseconds.
+     * Without `gracePeriod` every operation is refused after `notAfter`.
+     */
+    validity?: {
+        notBefore?: Date | number;
+        notAfter?: Date | number;
+        gracePeriod?: number;
+    };
+    /**
+     * Operations the key may be used for, enforced by every operation of its handles.
+     *
+     * Other operations reject with a {@link KeyUsageError}.
+     * The key is not restricted, if `usages` is missing.
+     */
+    usages?: KeyUsage[];
+    /**
+     * Limits on the encryptions of a key, counted across restarts.
+     *
+     * Beyond `maxEncryptions` encryptions reject with an {@link EncryptionLimitError}.
+     * AES-GCM keys default to 2^32 encryptions. After `warnAt` encryptions, by default 90% of `maxEncryptions`,
+     * an `EncryptionLimitWarning` is emitted with `process.emitWarning`.
+     * With `strictIvCheck` caller supplied ivs used before with the key reject with an {@link IvReusedError}.
+     * The ivs are logged next to the metadata file. At most 65536 caller supplied ivs are accepted per key,
+     * further ones reject with an {@link EncryptionLimitError}.
+     *
+     * Counters and logged ivs are shared by processes using the same `FileStoreConfig`.
+     * They are read and changed under a lock on `crypto-layer-node-key-metadata.lock` next to the metadata file.
+     */
+    encryptionLimits?: {
+        maxEncryptions?: number;
+        warnAt?: number;
+        strictIvCheck?: boolean;
+    };
+};
+
+/** Options for functions importing secret key material. */
+export type ImportOptions = CreateKeyOptions & {
+    /** Overwrites the given secret key material with zeros, after it was copied into the addon. */
+    zeroizeSource?: boolean;
+};
+
+/** Options of {@link NodeKeyHandle.encrypt} and {@link NodeKeyHandle.decryptData}. */
+export type CommittingOptions = OperationOptions & {
+    /**
+     * Prepends a commitment to the key on encryption and verifies it on decryption.
+     *
+     * AES-GCM and ChaCha20-Poly1305 ciphertexts can be crafted to decrypt under several keys,
+     * which matters e.g. for password derived keys. Committing ciphertexts only decrypt under their key.
+     * The commitment is an HMAC with the key, which uses the `signing_hash` of its spec.
+     */
+    committing?: boolean;
+};
+
+/** Options of {@link NodeProvider.getAllKeys}. */
+export type GetAllKeysOptions = OperationOptions & {
+    /** Appends the metadata of every key, `null` for keys without metadata. */
+    metadata?: boolean;
+};
+
+/** Criteria of {@link NodeProvider.listKeys} and {@link NodeProvider.countKeys}. Missing criteria match every key. */
+export type KeyFilter = {
+    kind?: "Key" | "KeyPair";
+    cipher?: Cipher;
+    asymSpec?: AsymmetricKeySpec;
+    /** Tag of the {@link KeyMetadata}. Keys without metadata never match. */
+    tag?: string;
+    ephemeral?: boolean;
+};
+
+/** Options of {@link NodeProvider.listKeys}. */
+export type ListKeysOptions = OperationOptions & {
+    filter?: KeyFilter;
+    /** `cursor` of the previous page. */
+    cursor?: string;
+    /** Maximum amount of keys per page. Defaults to 100. */
+    limit?: number;
+};
+
+/** Page of keys returned by {@link NodeProvider.listKeys}. */
+export type KeyPage = {
+    keys: [string, Spec][];
+    /** Continues the listing, if passed as `cursor`. `null` on the last page. */
+    cursor: string | null;
+};
+
+/** Options of {@link migrateKeys}. */
+export type MigrateKeysOptions = OperationOptions & {
+    /** Selects the keys to migrate. Defaults to all keys. */
+    filter?: KeyFilter;
+    /** Deletes keys from the source provider once they were copied with their metadata. */
+    deleteSource?: boolean;
+};
+
+/** Result of {@link migrateKeys} for one key of the source provider. */
+export type KeyMigration = {
+    id: string;
+    /**
+     * `"alreadyMigrated"` for keys copied by an earlier migration,
+     * `"unsupported"` for ephemeral and non exportable keys,
+     * as crypto-layer cannot wrap a key under a transport key of the target provider.
+     */
+    status: "migrated" | "alreadyMigrated" | "unsupported" | "failed";
+    /** Id of the copy in the target provider. */
+    newId: string | null;
+    sourceDeleted: boolean;
+    /** Why the key was not migrated or, for migrated keys, why it was not deleted from the source. */
+    error: string | null;
+};
+
+/** Result of {@link migrateKeys}. */
+export type MigrationReport = {
+    /** One result per key matching the filter. */
+    keys: KeyMigration[];
+    /**
+     * Ids of the copies in the target provider by the ids of their source keys,
+     * for every key copied by this or an earlier migration.
+     */
+    ids: Record<string, string>;
+};
+
+/** Report of {@link NodeProvider.verifyStore} and {@link NodeProvider.repairStore}. */
+export type StoreReport = {
+    /** Amount of keys listed by the provider storage. */
+    checked: number;
+    /** Keys, which cannot be loaded, e.g. because their MAC or signature does not match. */
+    unreadable: { id: string; error: string }[];
+    /**
+     * Keys with metadata, which are missing in the provider storage.
+     * Ephemeral keys with a live handle are never orphaned.
+     */
+    orphaned: string[];
+    /** Metadata entries, which cannot be read. */
+    corrupted: { id: string; error: string }[];
+    /**
+     * Keys without metadata entry, e.g. created before this package kept metadata or by another user of the storage.
+     * No usages or validity period restrict them.
+     */
+    withoutMetadata: string[];
+    /** Why the metadata file was moved aside, when the provider was created. */
+    metadataFileError: string | null;
+    /** Metadata entries removed by {@link NodeProvider.repairStore}. */
+    repaired: string[];
+};
+
+/** Options of {@link NodeProvider.repairStore}. */
+export type RepairStoreOptions = OperationOptions & {
+    /** Moves bad metadata entries to `crypto-layer-node-key-metadata.quarantine.json` instead of discarding them. */
+    quarantine?: boolean;
+};
+
+/** Options of {@link NodeProvider.exportBackup}. */
+export type ExportBackupOptions = OperationOptions & {
+    /** Derives the key from the password. Defaults to Argon2id with 19 MiB, 2 iterations and 1 lane. */
+    kdf?: KDF;
+};
+
+/** Archive created by {@link NodeProvider.exportBackup}. */
+export type ExportedBackup = {
+    archive: Uint8Array;
+    /** Ids of the ephemeral and non exportable keys, which are missing in the archive. */
+    skipped: string[];
+};
+
+/** Options of {@link NodeProvider.importBackup}. */
+export type ImportBackupOptions = OperationOptions & {
+    /**
+     * Handling of keys of the archive, whose key material exists in the provider.
+     *
+     * Keys are matched by their id and spec or, as ids are not preserved,
+     * by their secret for symmetric keys and by their public key for key pairs.
+     * Only the key material of keys with the spec of a key in the archive is read.
+     * Non exportable symmetric keys of the provider are only matched by id.
+     * `"fail"` rejects with a {@link BackupError} and restores nothing, `"skip"` keeps the existing keys
+     * and `"duplicate"` restores them as additional keys. Defaults to `"fail"`.
+     */
+    onConflict?: "fail" | "skip" | "duplicate";
+};
+
+/** Result of {@link NodeProvider.importBackup}. */
+export type ImportedBackup = {
+    /**
+     * Restored keys get new ids, which are listed with their id in the archive.
+     * crypto-layer assigns the ids of imported keys, so the ids of the archive cannot be kept.
+     */
+    restored: { originalId: string; id: string }[];
+    /** Keys of the archive, which were kept with `onConflict: "skip"`, with the id of the existing key. */
+    skipped: { originalId: string; id: string }[];
+};
+
+/** `code` of errors thrown when using a handle of a deleted key. */
+export const KEY_DELETED_ERROR_CODE = "ERR_KEY_DELETED";
+
+/** Error thrown when using a handle, after it or another handle of the same key was deleted. */
+export type KeyDeletedError = Error & {
+    name: "KeyDeletedError";
+    code: typeof KEY_DELETED_ERROR_CODE;
+    keyId: string;
+};
+
+export function isKeyDeletedError(error: unknown): error is KeyDeletedError {
+    return (
+        error instanceof Error &&
+        (error as Partial<KeyDeletedError>).code === KEY_DELETED_ERROR_CODE
+    );
+}
+
+/** `code` of errors thrown when using a key outside of its validity period. */
+export const KEY_NOT_VALID_ERROR_CODE = "ERR_KEY_NOT_VALID";
+
+/** Error thrown when using a key before its `notBefore` or after its `notAfter`. */
+export type KeyNotValidError = Error & {
+    name: "KeyNotValidError";
+    code: typeof KEY_NOT_VALID_ERROR_CODE;
+    keyId: string;
+    /** Refused operation, e.g. `sign`. */
+    operation: string;
+    notBefore: Date | null;
+    notAfter: Date | null;
+};
+
+export function isKeyNotValidError(error: unknown): error is KeyNotValidError {
+    return (
+        error instanceof Error &&
+        (error as Partial<KeyNotValidError>).code === KEY_NOT_VALID_ERROR_CODE
+    );
+}
+
+/** `code` of errors thrown when using a key for an operation not among its usages. */
+export const KEY_USAGE_ERROR_CODE = "ERR_KEY_USAGE";
+
+/** Error thrown when using a key for an operation not among its {@link CreateKeyOptions.usages}. */
+export type KeyUsageError = Error & {
+    name: "KeyUsageError";
+    code: typeof KEY_USAGE_ERROR_CODE;
+    keyId: string;
+    /** Refused operation. */
+    operation: KeyUsage;
+    /** Usages the key is restricted to. */
+    usages: KeyUsage[];
+};
+
+export function isKeyUsageError(error: unknown): error is KeyUsageError {
+    return (
+        error instanceof Error &&
+        (error as Partial<KeyUsageError>).code === KEY_USAGE_ERROR_CODE
+    );
+}
+
+/** `code` of errors thrown when using a key, whose metadata is unreadable. */
+export const KEY_METADATA_UNAVAILABLE_ERROR_CODE =
+    "ERR_KEY_METADATA_UNAVAILABLE";
+
+/**
+ * Error thrown when using a key, whose metadata entry cannot be read.
+ *
+ * The restrictions of such a key are unknown, thus every operation is refused.
+ * Keys without any entry, e.g. created before this package kept metadata, are not restricted
+ * and reported by {@link NodeProvider.verifyStore} as `withoutMetadata`.
+ */
+export type KeyMetadataUnavailableError = Error & {
+    name: "KeyMetadataUnavailableError";
+    code: typeof KEY_METADATA_UNAVAILABLE_ERROR_CODE;
+    keyId: string;
+    /** Refused operation. */
+    operation: KeyUsage;
+};
+
+export function isKeyMetadataUnavailableError(
+    error: unknown,
+): error is KeyMetadataUnavailableError {
+    return (
+        error instanceof Error &&
+        (error as Partial<KeyMetadataUnavailableError>).code ===
+            KEY_METADATA_UNAVAILABLE_ERROR_CODE
+    );
+}
+
+/** `code` of errors thrown when a key reached its maximum amount of encryptions. */
+export const ENCRYPTION_LIMIT_ERROR_CODE = "ERR_ENCRYPTION_LIMIT";
+
+/** `code` of warnings emitted when a key approaches its maximum amount of encryptions. */
+export const ENCRYPTION_LIMIT_WARNING_CODE = "WARN_ENCRYPTION_LIMIT";
+
+/** Error thrown when encrypting with a key beyond its {@link CreateKeyOptions.encryptionLimits}. */
+export type EncryptionLimitError = Error & {
+    name: "EncryptionLimitError";
+    code: typeof ENCRYPTION_LIMIT_ERROR_CODE;
+    keyId: string;
+    maxEncryptions: number;
+};
+
+export function isEncryptionLimitError(
+    error: unknown,
+): error is EncryptionLimitError {
+    return (
+        error instanceof Error &&
+        (error as Partial<EncryptionLimitError>).code ===
+            ENCRYPTION_LIMIT_ERROR_CODE
+    );
+}
+
+/** `code` of errors thrown when a caller supplied iv was used before with the key. */
+export const IV_REUSED_ERROR_CODE = "ERR_IV_REUSED";
+
+/** Error thrown when reusing an iv with a key with `strictIvCheck`. */
+export type IvReusedError = Error & {
+    name: "IvReusedError";
+    code: typeof IV_REUSED_ERROR_CODE;
+    keyId: string;
+};
+
+export function isIvReusedError(error: unknown): error is IvReusedError {
+    return (
+        error instanceof Error &&
+        (error as Partial<IvReusedError>).code === IV_REUSED_ERROR_CODE
+    );
+}
+
+/** `code` of errors thrown when keys of a backup already exist in the provider. */
+export const BACKUP_CONFLICT_ERROR_CODE = "ERR_BACKUP_CONFLICT";
+
+/** `code` of errors thrown when a backup was modified or the password or key is wrong. */
+export const BACKUP_AUTHENTICATION_ERROR_CODE = "ERR_BACKUP_AUTHENTICATION";
+
+/** Error thrown by {@link NodeProvider.importBackup}. */
+export type BackupError = Error & {
+    name: "BackupError";
+    code:
+        | typeof BACKUP_CONFLICT_ERROR_CODE
+        | typeof BACKUP_AUTHENTICATION_ERROR_CODE;
+    /** Ids of the existing keys, set for {@link BACKUP_CONFLICT_ERROR_CODE}. */
+    keyIds?: string[];
+};
+
+export function isBackupError(error: unknown): error is BackupError {
+    const code = (error as Partial<BackupError>).code;
+    return (
+        error instanceof Error &&
+        (code === BACKUP_CONFLICT_ERROR_CODE ||
+            code === BACKUP_AUTHENTICATION_ERROR_CODE)
+    );
+}
+
+/** `code` of errors thrown when an argument does not match its expected type. */
+export const INVALID_ARGUMENT_ERROR_CODE = "ERR_INVALID_ARG_VALUE";
+
+/** `code` of errors thrown for specs using algorithms the provider does not support. */
+export const UNSUPPORTED_ERROR_CODE = "ERR_UNSUPPORTED_BY_PROVIDER";
+
+/** `TypeError` thrown when an argument, like a spec or config, does not match its expected type. */
+export type InvalidArgumentError = TypeError & {
+    code: typeof INVALID_ARGUMENT_ERROR_CODE | typeof UNSUPPORTED_ERROR_CODE;
+    /** Path of the offending value, e.g. `spec.asym_spec`. */
+    path: string;
+    /** JS type and, for primitives, the value received. */
+    received: string;
+    expected: string;
+    /** Accepted variant names, if an enum is expected. */
+    accepted: string[];
+};
+
+export function isInvalidArgumentError(
+    error: unknown,
+): error is InvalidArgumentError {
+    const code = (error as Partial<InvalidArgumentError>).code;
+    return (
+        error instanceof TypeError &&
+        (code === INVALID_ARGUMENT_ERROR_CODE ||
+            code === UNSUPPORTED_ERROR_CODE)
+    );
+}
+
+/** Spec, whose missing fields are filled by {@link NodeProvider.completeSpec}. */
+export type PartialSpec =
+    | { KeySpec: Partial<KeySpec> }
+    | { KeyPairSpec: Partial<KeyPairSpec> };
+
+/** Comparison of the capabilities of a provider with the requirements given to {@link selectProviders}. */
+export type ProviderEvaluation = {
+    name: string;
+    /** `true` if `unmet` is empty. */
+    matches: boolean;
+    /** `null` if the provider is not initializable with the impl config. */
+    capabilities: ProviderConfig | null;
+    unmet: {
+        /** Field of the requirements, e.g. `supported_ciphers`. */
+        requirement: string;
+        /** Required variants the provider lacks. */
+        missing: string[];
+        message: string;
+    }[];
+};
+
+/**
+ * Entry of `additional_config`, which derives the key of `StorageConfigSymmetricEncryption` from a password,
+ * when the provider is created.
+ *
+ * The salt is read from `salt_path`, which must hold exactly 16 bytes.
+ * If it does not exist, a random salt is written to a temporary file, which is then linked to `salt_path`,
+ * so that concurrent creators agree on one salt and never read a partially written file.
+ * The derived key is only cached while a provider created with it is alive.
+ * `kdf` defaults to Argon2id with the parameters recommended by OWASP.
+ * A wrong password is not detected on creation, but when keys of the storage are loaded.
+ */
+export type StoragePasswordConfig = {
+    StoragePassword: {
+        password: string;
+        kdf?: KDF;
+        salt_path: string;
+    };
+};
+
+/** `ProviderImplConfig`, whose `additional_config` may also hold a {@link StoragePasswordConfig}. */
+export type NodeProviderImplConfig = {
+    additional_config: (
+        | ProviderImplConfig["additional_config"][number]
+        | StoragePasswordConfig
+    )[];
+};
+
+/** Options of {@link createProvider}. */
+export type CreateProviderOptions = OperationOptions & {
+    /** Rejects with a {@link ProviderSelectionError} instead of resolving `undefined`, if no provider matches. */
+    explain?: boolean;
+};
+
+/** `code` of errors thrown by {@link createProvider}, if no provider matches and `explain` is set. */
+export const NO_MATCHING_PROVIDER_ERROR_CODE = "ERR_NO_MATCHING_PROVIDER";
+
+export type ProviderSelectionError = Error & {
+    name: "ProviderSelectionError";
+    code: typeof NO_MATCHING_PROVIDER_ERROR_CODE;
+    /** All providers, ranked like in {@link selectProviders}. */
+    evaluations: ProviderEvaluation[];
+};
 
 // Use this declaration to assign types to the addon's exports,
 // which otherwise by default are `any`.
 declare module "./load.cjs" {
     // root
-    function getAllProviders(): Promise<string[]>;
+    function getAllProviders(token?: BareAbortToken): Promise<string[]>;
     function createBareProvider(
         config: ProviderConfig,
-        impl_config: ProviderImplConfig,
+        impl_config: NodeProviderImplConfig,
+        options?: { explain?: boolean },
+        token?: BareAbortToken,
     ): Promise<BareProvider | undefined>;
     function createBareProviderFromName(
         name: string,
-        impl_config: ProviderImplConfig,
+        impl_config: NodeProviderImplConfig,
+        token?: BareAbortToken,
     ): Promise<BareProvider | undefined>;
     function getProviderCapabilities(
-        providerImplConfig: ProviderImplConfig,
+        providerImplConfig: NodeProviderImplConfig,
+        token?: BareAbortToken,
     ): Promise<[string, ProviderConfig][]>;
+    function selectProviders(
+        requirements: ProviderConfig,
+        providerImplConfig: NodeProviderImplConfig,
+        token?: BareAbortToken,
+    ): Promise<ProviderEvaluation[]>;
+    function configureRuntime(options: RuntimeOptions): void;
+    function getRuntimeMetrics(): RuntimeMetrics;
+    function createAbortToken(): BareAbortToken;
+    function abortToken(token: BareAbortToken): void;
 
-    // Provider
-    function providerName(this: BareProvider): Promise<string>;
-    function createBareKey(
-        this: BareProvider,
-        spec: KeySpec,
-    ): Promise<BareKeyHandle>;
-    function createBareKeyPair(
-        this: BareProvider,
-        spec: KeyPairSpec,
-    ): Promise<BareKeyPairHandle>;
-    function loadBareKey(
-        this: BareProvider,
-        id: string,
-    ): Promise<BareKeyHandle>;
-    function loadBareKeyPair(
-        this: BareProvider,
-        id: string,
-    ): Promise<BareKeyPairHandle>;
-    function importBareKey(
-        this: BareProvider,
-        spec: KeySpec,
-        key: Uint8Array,
-    ): Promise<BareKeyHandle>;
-    function importBareKeyPair(
-        this: BareProvider,
-        spec: KeyPairSpec,
-        publicKey: Uint8Array,
-        privateKey: Uint8Array,
-    ): Promise<BareKeyPairHandle>;
-    function importBarePublicKey(
-        this: BareProvider,
-        spec: KeyPairSpec,
-        publicKey: Uint8Array,
-    ): Promise<BareKeyPairHandle>;
-    function getCapabilities(
-        this: BareProvider,
-    ): Promise<ProviderConfig | undefined>;
-    function startEphemeralDhExchange(
-        this: BareProvider,
-        spec: KeyPairSpec,
-    ): Promise<BareDHExchange>;
-    function dhExchangeFromKeys(
-        this: BareProvider,
-        publicKey: Uint8Array,
-        privateKey: Uint8Array,
-        spec: KeyPairSpec,
-    ): Promise<BareDHExchange>;
-    function deriveKeyFromPassword(
-        password: string,
-        salt: Uint8Array,
-        algorithm: KeySpec,
-        kdf: KDF,
-    ): Promise<KeyPairHandle>;
-    function deriveKeyFromBase(
-        this: BareProvider,
-        baseKey: Uint8Array,
-        keyId: number,
-        context: string,
-        spec: KeySpec,
-    ): Promise<KeyHandle>;
-    function getRandom(this: BareProvider, len: number): Promise<Uint8Array>;
-    function hash(
-        this: BareProvider,
-        input: Uint8Array,
-        hash: CryptoHash,
-    ): Promise<Uint8Array>;
-    function getAllKeys(): Promise<[string, Spec][]>;
+    /** Instances are only created by the addon. */
+    class Provider {
+        private constructor();
+        providerName(): Promise<string>;
+        nameSync(): string;
+        release(): number;
+        createKey(
+            spec: Partial<KeySpec>,
+            options?: CreateKeyOptions,
+            token?: BareAbortToken,
+        ): Promise<KeyHandle>;
+        createKeyPair(
+            spec: Partial<KeyPairSpec>,
+            options?: CreateKeyOptions,
+            token?: BareAbortToken,
+        ): Promise<KeyPairHandle>;
+        loadKey(id: string, token?: BareAbortToken): Promise<KeyHandle>;
+        loadKeyPair(id: string, token?: BareAbortToken): Promise<KeyPairHandle>;
+        importKey(
+            spec: KeySpec,
+            key: Uint8Array,
+            options?: ImportOptions,
+            token?: BareAbortToken,
+        ): Promise<KeyHandle>;
+        importKeyPair(
+            spec: KeyPairSpec,
+            publicKey: Uint8Array,
+            privateKey: Uint8Array,
+            options?: ImportOptions,
+            token?: BareAbortToken,
+        ): Promise<KeyPairHandle>;
+        importPublicKey(
+            spec: KeyPairSpec,
+            publicKey: Uint8Array,
+            token?: BareAbortToken,
+        ): Promise<KeyPairHandle>;
+        getCapabilities(
+            token?: BareAbortToken,
+        ): Promise<ProviderConfig | undefined>;
+        completeSpec(spec: PartialSpec, token?: BareAbortToken): Promise<Spec>;
+        startEphemeralDhExchange(
+            spec: KeyPairSpec,
+            token?: BareAbortToken,
+        ): Promise<DHExchange>;
+        dhExchangeFromKeys(
+            publicKey: Uint8Array,
+            privateKey: Uint8Array,
+            spec: KeyPairSpec,
+            options?: ImportOptions,
+            token?: BareAbortToken,
+        ): Promise<DHExchange>;
+        deriveKeyFromPassword(
+            password: string,
+            salt: Uint8Array,
+            algorithm: KeySpec,
+            kdf: KDF,
+            token?: BareAbortToken,
+        ): Promise<KeyHandle>;
+        deriveKeyFromBase(
+            baseKey: Uint8Array,
+            keyId: number,
+            context: string,
+            spec: KeySpec,
+            token?: BareAbortToken,
+        ): Promise<KeyHandle>;
+        getRandom(len: number, token?: BareAbortToken): Promise<Uint8Array>;
+        hash(
+            input: Uint8Array,
+            hash: CryptoHash,
+            token?: BareAbortToken,
+        ): Promise<Uint8Array>;
+        hashMany(
+            input: Uint8Array[],
+            hash: CryptoHash,
+            token?: BareAbortToken,
+        ): Promise<BatchResult<Uint8Array>[]>;
+        getAllKeys(
+            options?: GetAllKeysOptions,
+            token?: BareAbortToken,
+        ): Promise<[string, Spec][] | [string, Spec, KeyMetadata | null][]>;
+        listKeys(
+            options?: ListKeysOptions,
+            token?: BareAbortToken,
+        ): Promise<KeyPage>;
+        countKeys(filter?: KeyFilter, token?: BareAbortToken): Promise<number>;
+        rotateKey(id: string, token?: BareAbortToken): Promise<KeyHandle>;
+        encryptForFamily(
+            familyId: string,
+            data: Uint8Array,
+            token?: BareAbortToken,
+        ): Promise<Uint8Array>;
+        decryptForFamily(
+            familyId: string,
+            ciphertext: Uint8Array,
+            token?: BareAbortToken,
+        ): Promise<Uint8Array>;
+        rewrap(
+            ciphertext: Uint8Array,
+            token?: BareAbortToken,
+        ): Promise<Uint8Array>;
+        exportBackup(
+            protection: string | KeyHandle,
+            options?: { kdf?: KDF },
+            token?: BareAbortToken,
+        ): Promise<ExportedBackup>;
+        importBackup(
+            archive: Uint8Array,
+            protection: string | KeyHandle,
+            options?: { onConflict?: "fail" | "skip" | "duplicate" },
+            token?: BareAbortToken,
+        ): Promise<ImportedBackup>;
+        migrateKeysTo(
+            target: Provider,
+            options?: { filter?: KeyFilter; deleteSource?: boolean },
+            token?: BareAbortToken,
+        ): Promise<MigrationReport>;
+        verifyStore(token?: BareAbortToken): Promise<StoreReport>;
+        repairStore(
+            options?: { quarantine?: boolean },
+            token?: BareAbortToken,
+        ): Promise<StoreReport>;
+    }
 
-    // KeyPairHandle
-    function signData(
-        this: BareKeyPairHandle,
-        data: Uint8Array,
-    ): Promise<Uint8Array>;
-    function verifySignature(
-        this: BareKeyPairHandle,
-        data: Uint8Array,
-        signature: Uint8Array,
-    ): Promise<boolean>;
-    function idForKeyPair(this: BareKeyPairHandle): Promise<string>;
-    function deleteForKeyPair(this: BareKeyPairHandle): Promise<undefined>;
-    function getPublicKey(this: BareKeyPairHandle): Promise<Uint8Array>;
-    function extractKeyForKeyPairHandle(
-        this: BareKeyPairHandle,
-    ): Promise<Uint8Array>;
-    function encryptDataForKeyPairHandle(
-        this: BareKeyPairHandle,
-        data: Uint8Array,
-    ): Promise<Uint8Array>;
-    function decryptDataForKeyPairHandle(
-        this: BareKeyPairHandle,
-        data: Uint8Array,
-    ): Promise<Uint8Array>;
-    function specForKeyPairHandle(
-        this: BareKeyPairHandle,
-    ): Promise<KeyPairSpec>;
-    function startDhExchangeForKeyPairHandle(
-        this: BareKeyPairHandle,
-    ): Promise<DHExchange>;
-
-    // KeyHandle
-    function idForKeyHandle(this: BareKeyHandle): Promise<string>;
-    function deleteForKeyHandle(this: BareKeyHandle): Promise<undefined>;
-    function extractKeyForKeyHandle(this: BareKeyHandle): Promise<Uint8Array>;
-    function encryptDataForKeyHandle(
-        this: BareKeyHandle,
-        data: Uint8Array,
-        iv: Uint8Array,
-    ): Promise<[Uint8Array, Uint8Array]>;
-    function encryptForKeyHandle(
-        this: BareKeyHandle,
-        data: Uint8Array,
-    ): Promise<[Uint8Array, Uint8Array]>;
-    function encryptWithIvForKeyHandle(
-        this: BareKeyHandle,
-        data: Uint8Array,
-        iv: Uint8Array,
-    ): Promise<Uint8Array>;
-    function decryptDataForKeyHandle(
-        this: BareKeyHandle,
-        data: Uint8Array,
-        iv: Uint8Array,
-    ): Promise<Uint8Array>;
-    function specForKeyHandle(this: BareKeyHandle): Promise<KeySpec>;
-    function deriveKeyForKeyHandle(
-        this: BareKeyHandle,
-        nonce: Uint8Array,
-    ): Promise<KeyHandle>;
-
-    // DHExchange
-    function getPublicKeyForDHExchange(
-        this: BareDHExchange,
-    ): Promise<Uint8Array>;
-    /* function addExternalForDHExchange(
-        this: BareDHExchange,
-        key: Uint8Array
-    ): Promise<Uint8Array>;
-    function addExternalFinalForDHExchange(
-        this: BareDHExchange,
-        key: Uint8Array
-    ): Promise<BareKeyHandle>; */
-    function deriveClientSessionKeys(
-        serverPk: Uint8Array,
-    ): Promise<[Uint8Array, Uint8Array]>;
-    function deriveServerSessionKeys(
-        clientPk: Uint8Array,
-    ): Promise<[Uint8Array, Uint8Array]>;
-    function deriveClientKeyHandles(
-        serverPk: Uint8Array,
-    ): Promise<[KeyHandle, KeyHandle]>;
-    function deriveServerKeyHandles(
-        clientPk: Uint8Array,
-    ): Promise<[KeyHandle, KeyHandle]>;
+    /** Instances are only created by the addon. */
+    class KeyPairHandle {
+        private constructor();
+        id(token?: BareAbortToken): Promise<string>;
+        idSync(): string;
+        delete(token?: BareAbortToken): Promise<undefined>;
+        metadata(token?: BareAbortToken): Promise<KeyMetadata | null>;
+        signData(data: Uint8Array, token?: BareAbortToken): Promise<Uint8Array>;
+        verifySignature(
+            data: Uint8Array,
+            signature: Uint8Array,
+            token?: BareAbortToken,
+        ): Promise<boolean>;
+        signMany(
+            data: Uint8Array[],
+            token?: BareAbortToken,
+        ): Promise<BatchResult<Uint8Array>[]>;
+        verifyMany(
+            items: [Uint8Array, Uint8Array][],
+            token?: BareAbortToken,
+        ): Promise<BatchResult<boolean>[]>;
+        encryptData(
+            data: Uint8Array,
+            token?: BareAbortToken,
+        ): Promise<Uint8Array>;
+        decryptData(
+            data: Uint8Array,
+            token?: BareAbortToken,
+        ): Promise<Uint8Array>;
+        getPublicKey(token?: BareAbortToken): Promise<Uint8Array>;
+        extractKey(token?: BareAbortToken): Promise<Uint8Array>;
+        spec(token?: BareAbortToken): Promise<KeyPairSpec>;
+        specSync(): KeyPairSpec;
+        startDhExchange(token?: BareAbortToken): Promise<DHExchange>;
+    }
+
+    /** Instances are only created by the addon. */
+    class KeyHandle {
+        private constructor();
+        id(token?: BareAbortToken): Promise<string>;
+        idSync(): string;
+        delete(token?: BareAbortToken): Promise<undefined>;
+        metadata(token?: BareAbortToken): Promise<KeyMetadata | null>;
+        extractKey(token?: BareAbortToken): Promise<Uint8Array>;
+        encryptData(
+            data: Uint8Array,
+            iv: Uint8Array,
+            token?: BareAbortToken,
+        ): Promise<[Uint8Array, Uint8Array]>;
+        encrypt(
+            data: Uint8Array,
+            options: CommittingOptions | undefined,
+            token?: BareAbortToken,
+        ): Promise<[Uint8Array, Uint8Array]>;
+        encryptWithIv(
+            data: Uint8Array,
+            iv: Uint8Array,
+            token?: BareAbortToken,
+        ): Promise<Uint8Array>;
+        encryptInto(
+            data: Uint8Array,
+            out: Uint8Array,
+            token?: BareAbortToken,
+        ): Promise<[number, Uint8Array]>;
+        decryptData(
+            data: Uint8Array,
+            iv: Uint8Array,
+            options: CommittingOptions | undefined,
+            token?: BareAbortToken,
+        ): Promise<Uint8Array>;
+        decryptInto(
+            data: Uint8Array,
+            iv: Uint8Array,
+            out: Uint8Array,
+            token?: BareAbortToken,
+        ): Promise<number>;
+        encryptMany(
+            data: Uint8Array[],
+            token?: BareAbortToken,
+        ): Promise<BatchResult<[Uint8Array, Uint8Array]>[]>;
+        decryptMany(
+            items: [Uint8Array, Uint8Array][],
+            token?: BareAbortToken,
+        ): Promise<BatchResult<Uint8Array>[]>;
+        spec(token?: BareAbortToken): Promise<KeySpec>;
+        specSync(): KeySpec;
+        deriveKey(
+            nonce: Uint8Array,
+            token?: BareAbortToken,
+        ): Promise<KeyHandle>;
+    }
+
+    /** Instances are only created by the addon. */
+    class DHExchange {
+        private constructor();
+        getPublicKey(token?: BareAbortToken): Promise<Uint8Array>;
+        /* addExternal(key: Uint8Array): Promise<Uint8Array>;
+        addExternalFinal(key: Uint8Array): Promise<KeyHandle>; */
+        deriveClientSessionKeys(
+            serverPk: Uint8Array,
+            token?: BareAbortToken,
+        ): Promise<[Uint8Array, Uint8Array]>;
+        deriveServerSessionKeys(
+            clientPk: Uint8Array,
+            token?: BareAbortToken,
+        ): Promise<[Uint8Array, Uint8Array]>;
+        deriveClientKeyHandles(
+            serverPk: Uint8Array,
+            token?: BareAbortToken,
+        ): Promise<[KeyHandle, KeyHandle]>;
+        deriveServerKeyHandles(
+            clientPk: Uint8Array,
+            token?: BareAbortToken,
+        ): Promise<[KeyHandle, KeyHandle]>;
+    }
+}
+
+/**
+ * Runs `operation` with a bare abort token, which is aborted together with `options.signal`.
+ *
+ * The returned promise rejects with `signal.reason` as soon as the signal is aborted.
+ */
+async function abortable<T>(
+    options: OperationOptions | undefined,
+    operation: (token?: BareAbortToken) => Promise<T>,
+): Promise<T> {
+    const signal = options?.signal;
+    if (!signal) {
+        return await operation();
+    }
+    signal.throwIfAborted();
+
+    const token = createAbortToken();
+    const pending = operation(token);
+    // The addon rejects aborted operations with an `AbortError`, which is superseded by `signal.reason`.
+    pending.catch(() => {});
+
+    let onAbort = () => {};
+    const aborted = new Promise<never>((_, reject) => {
+        onAbort = () => {
+            abortToken(token);
+            reject(signal.reason);
+        };
+    });
+    signal.addEventListener("abort", onAbort, { once: true });
+    try {
+        return await Promise.race([pending, aborted]);
+    } finally {
+        signal.removeEventListener("abort", onAbort);
+    }
 }
 
-class NodeProvider implements Provider {
+export class NodeProvider implements Provider {
     private provider: BareProvider;
 
     constructor(bareProvider: BareProvider) {
         this.provider = bareProvider;
     }
 
+    toString(): string {
+        return this.provider.toString();
+    }
+
+    [inspect.custom](): string {
+        return this.provider.toString();
+    }
+
     async providerName(): Promise<string> {
-        return await providerName.call(this.provider);
+        return await this.provider.providerName();
+    }
+
+    /** Returns the provider name without a round trip through the event loop. */
+    nameSync(): string {
+        return this.provider.nameSync();
+    }
+
+    /**
+     * Releases one acquisition of a provider cached by {@link createProviderFromName}.
+     *
+     * Only counts the acquisitions and never ends the lifetime of the provider:
+     * it stays usable and cached as long as it is referenced, even after
+     * every acquisition was released. Only once it was garbage collected,
+     * the next {@link createProviderFromName} creates a new provider.
+     *
+     * @returns Acquisitions not released yet. `0` for providers, which are not cached.
+     */
+    release(): number {
+        return this.provider.release();
     }
 
-    async createKey(spec: KeySpec): Promise<KeyHandle> {
-        return new NodeKeyHandle(await createBareKey.call(this.provider, spec));
+    /**
+     * Creates a key.
+     *
+     * Missing fields of `spec` are filled like in {@link completeSpec}.
+     */
+    async createKey(
+        spec: Partial<KeySpec>,
+        options?: CreateKeyOptions,
+    ): Promise<NodeKeyHandle> {
+        return new NodeKeyHandle(
+            await abortable(options, (token) =>
+                this.provider.createKey(spec, options, token),
+            ),
+        );
     }
 
-    async createKeyPair(spec: KeyPairSpec): Promise<KeyPairHandle> {
+    /**
+     * Creates a key pair.
+     *
+     * Missing fields of `spec` are filled like in {@link completeSpec}.
+     */
+    async createKeyPair(
+        spec: Partial<KeyPairSpec>,
+        options?: CreateKeyOptions,
+    ): Promise<NodeKeyPairHandle> {
         return new NodeKeyPairHandle(
-            await createBareKeyPair.call(this.provider, spec),
+            await abortable(options, (token) =>
+                this.provider.createKeyPair(spec, options, token),
+            ),
         );
     }
 
-    async loadKey(id: string): Promise<KeyHandle> {
-        return new NodeKeyHandle(await loadBareKey.call(this.provider, id));
+    async loadKey(
+        id: string,
+        options?: OperationOptions,
+    ): Promise<NodeKeyHandle> {
+        return new NodeKeyHandle(
+            await abortable(options, (token) =>
+                this.provider.loadKey(id, token),
+            ),
+        );
     }
 
-    async loadKeyPair(id: string): Promise<KeyPairHandle> {
+    async loadKeyPair(
+        id: string,
+        options?: OperationOptions,
+    ): Promise<NodeKeyPairHandle> {
         return new NodeKeyPairHandle(
-            await loadBareKeyPair.call(this.provider, id),
+            await abortable(options, (token) =>
+                this.provider.loadKeyPair(id, token),
+            ),
         );
     }
 
-    async importKey(spec: KeySpec, key: Uint8Array): Promise<KeyHandle> {
+    async importKey(
+        spec: KeySpec,
+        key: Uint8Array,
+        options?: ImportOptions,
+    ): Promise<NodeKeyHandle> {
         return new NodeKeyHandle(
-            await importBareKey.call(this.provider, spec, key),
+            await abortable(options, (token) =>
+                this.provider.importKey(spec, key, options, token),
+            ),
         );
     }
 
@@ -281,13 +989,17 @@ class NodeProvider implements Provider {
         spec: KeyPairSpec,
         publicKey: Uint8Array,
         privateKey: Uint8Array,
-    ): Promise<KeyPairHandle> {
+        options?: ImportOptions,
+    ): Promise<NodeKeyPairHandle> {
         return new NodeKeyPairHandle(
-            await importBareKeyPair.call(
-                this.provider,
-                spec,
-                publicKey,
-                privateKey,
+            await abortable(options, (token) =>
+                this.provider.importKeyPair(
+                    spec,
+                    publicKey,
+                    privateKey,
+                    options,
+                    token,
+                ),
             ),
         );
     }
@@ -295,15 +1007,23 @@ class NodeProvider implements Provider {
     async importPublicKey(
         spec: KeyPairSpec,
         publicKey: Uint8Array,
-    ): Promise<KeyPairHandle> {
+        options?: OperationOptions,
+    ): Promise<NodeKeyPairHandle> {
         return new NodeKeyPairHandle(
-            await importBarePublicKey.call(this.provider, spec, publicKey),
+            await abortable(options, (token) =>
+                this.provider.importPublicKey(spec, publicKey, token),
+            ),
         );
     }
 
-    async startEphemeralDhExchange(spec: KeyPairSpec): Promise<DHExchange> {
+    async startEphemeralDhExchange(
+        spec: KeyPairSpec,
+        options?: OperationOptions,
+    ): Promise<NodeDHExchange> {
         return new NodeDHExchange(
-            await startEphemeralDhExchange.call(this.provider, spec),
+            await abortable(options, (token) =>
+                this.provider.startEphemeralDhExchange(spec, token),
+            ),
         );
     }
 
@@ -311,19 +1031,48 @@ class NodeProvider implements Provider {
         publicKey: Uint8Array,
         privateKey: Uint8Array,
         spec: KeyPairSpec,
-    ): Promise<DHExchange> {
+        options?: ImportOptions,
+    ): Promise<NodeDHExchange> {
         return new NodeDHExchange(
-            await dhExchangeFromKeys.call(
-                this.provider,
-                publicKey,
-                privateKey,
-                spec,
+            await abortable(options, (token) =>
+                this.provider.dhExchangeFromKeys(
+                    publicKey,
+                    privateKey,
+                    spec,
+                    options,
+                    token,
+                ),
             ),
         );
     }
 
-    async getCapabilities(): Promise<ProviderConfig | undefined> {
-        return await getCapabilities.call(this.provider);
+    async getCapabilities(
+        options?: OperationOptions,
+    ): Promise<ProviderConfig | undefined> {
+        return await abortable(options, (token) =>
+            this.provider.getCapabilities(token),
+        );
+    }
+
+    /**
+     * Fills missing fields of `spec` with algorithms supported by the provider
+     * and rejects algorithms the provider does not support.
+     *
+     * Software providers prefer `AesGcm256`, `Sha2_256` and `P256`.
+     * Hardware backed providers prefer `AesGcm256`, `Sha2_384` and `P384`,
+     * then other AES ciphers and NIST curves.
+     *
+     * `ephemeral` defaults to `false`,
+     * `non_exportable` to `true` for hardware backed providers.
+     * A missing `cipher` of a key pair spec stays `null`.
+     */
+    async completeSpec(
+        spec: PartialSpec,
+        options?: OperationOptions,
+    ): Promise<Spec> {
+        return await abortable(options, (token) =>
+            this.provider.completeSpec(spec, token),
+        );
     }
 
     async deriveKeyFromPassword(
@@ -331,14 +1080,17 @@ class NodeProvider implements Provider {
         salt: Uint8Array,
         algorithm: KeySpec,
         kdf: KDF,
-    ): Promise<KeyHandle> {
+        options?: OperationOptions,
+    ): Promise<NodeKeyHandle> {
         return new NodeKeyHandle(
-            await deriveKeyFromPassword.call(
-                this.provider,
-                password,
-                salt,
-                algorithm,
-                kdf,
+            await abortable(options, (token) =>
+                this.provider.deriveKeyFromPassword(
+                    password,
+                    salt,
+                    algorithm,
+                    kdf,
+                    token,
+                ),
             ),
         );
     }
@@ -348,32 +1100,220 @@ class NodeProvider implements Provider {
         keyId: number,
         context: string,
         spec: KeySpec,
-    ): Promise<KeyHandle> {
+        options?: OperationOptions,
+    ): Promise<NodeKeyHandle> {
+        return new NodeKeyHandle(
+            await abortable(options, (token) =>
+                this.provider.deriveKeyFromBase(
+                    baseKey,
+                    keyId,
+                    context,
+                    spec,
+                    token,
+                ),
+            ),
+        );
+    }
+
+    async getRandom(
+        len: number,
+        options?: OperationOptions,
+    ): Promise<Uint8Array> {
+        return await abortable(options, (token) =>
+            this.provider.getRandom(len, token),
+        );
+    }
+
+    async hash(
+        input: Uint8Array,
+        hashAlgo: CryptoHash,
+        options?: OperationOptions,
+    ): Promise<Uint8Array> {
+        return await abortable(options, (token) =>
+            this.provider.hash(input, hashAlgo, token),
+        );
+    }
+
+    /** Hashes every input with a single call into the addon. */
+    async hashMany(
+        inputs: Uint8Array[],
+        hashAlgo: CryptoHash,
+        options?: OperationOptions,
+    ): Promise<BatchResult<Uint8Array>[]> {
+        return await abortable(options, (token) =>
+            this.provider.hashMany(inputs, hashAlgo, token),
+        );
+    }
+
+    /**
+     * Lists the id and spec of every key of the provider.
+     *
+     * With `metadata` set, the {@link KeyMetadata} of every key is appended.
+     */
+    async getAllKeys(
+        options: GetAllKeysOptions & { metadata: true },
+    ): Promise<[string, Spec, KeyMetadata | null][]>;
+    async getAllKeys(options?: GetAllKeysOptions): Promise<[string, Spec][]>;
+    async getAllKeys(
+        options?: GetAllKeysOptions,
+    ): Promise<[string, Spec][] | [string, Spec, KeyMetadata | null][]> {
+        return await abortable(options, (token) =>
+            this.provider.getAllKeys(options, token),
+        );
+    }
+
+    /**
+     * Lists the keys matching `options.filter` ordered by id, at most `options.limit` at once.
+     *
+     * Pass the returned `cursor` to get the next page.
+     * Keys created or deleted in between neither shift nor repeat other keys.
+     *
+     * Every page enumerates all keys of the storage, as `crypto-layer` cannot list keys partially.
+     * Paging limits the keys returned to JS, not the work of enumerating the storage.
+     */
+    async listKeys(options?: ListKeysOptions): Promise<KeyPage> {
+        return await abortable(options, (token) =>
+            this.provider.listKeys(options, token),
+        );
+    }
+
+    /** Counts the keys matching `filter`. */
+    async countKeys(
+        filter?: KeyFilter,
+        options?: OperationOptions,
+    ): Promise<number> {
+        return await abortable(options, (token) =>
+            this.provider.countKeys(filter, token),
+        );
+    }
+
+    /**
+     * Creates the next version of the key `id` with the same spec, label, tags and usages.
+     *
+     * The key `id` becomes decrypt-only and both keys join the key family named after the first version.
+     * Only the newest version of a family can be rotated.
+     */
+    async rotateKey(
+        id: string,
+        options?: OperationOptions,
+    ): Promise<NodeKeyHandle> {
         return new NodeKeyHandle(
-            await deriveKeyFromBase.call(
-                this.provider,
-                baseKey,
-                keyId,
-                context,
-                spec,
+            await abortable(options, (token) =>
+                this.provider.rotateKey(id, token),
+            ),
+        );
+    }
+
+    /** Encrypts `data` with the newest version of the key family into a ciphertext naming that version. */
+    async encryptForFamily(
+        familyId: string,
+        data: Uint8Array,
+        options?: OperationOptions,
+    ): Promise<Uint8Array> {
+        return await abortable(options, (token) =>
+            this.provider.encryptForFamily(familyId, data, token),
+        );
+    }
+
+    /** Decrypts a ciphertext of {@link encryptForFamily} with the key version named in it. */
+    async decryptForFamily(
+        familyId: string,
+        ciphertext: Uint8Array,
+        options?: OperationOptions,
+    ): Promise<Uint8Array> {
+        return await abortable(options, (token) =>
+            this.provider.decryptForFamily(familyId, ciphertext, token),
+        );
+    }
+
+    /** Re-encrypts a ciphertext of {@link encryptForFamily} with the newest version of its key family. */
+    async rewrap(
+        ciphertext: Uint8Array,
+        options?: OperationOptions,
+    ): Promise<Uint8Array> {
+        return await abortable(options, (token) =>
+            this.provider.rewrap(ciphertext, token),
+        );
+    }
+
+    /**
+     * Serializes every exportable key with its spec and metadata into one encrypted, authenticated archive.
+     *
+     * The archive is encrypted with `protection` or, if it is a password, a key derived with `options.kdf`.
+     */
+    async exportBackup(
+        protection: string | NodeKeyHandle,
+        options?: ExportBackupOptions,
+    ): Promise<ExportedBackup> {
+        const bareProtection =
+            typeof protection === "string" ? protection : protection.keyHandle;
+        return await abortable(options, (token) =>
+            this.provider.exportBackup(bareProtection, options, token),
+        );
+    }
+
+    /**
+     * Restores the keys of an archive of {@link exportBackup} with their metadata.
+     *
+     * Ids are not preserved, as crypto-layer assigns the id of every imported key: restored keys get new ids,
+     * which are returned with their id in the archive.
+     * Thus key families of rotated keys are renamed after the new id of their first version.
+     * Keys, whose key material already exists, are handled according to `options.onConflict`.
+     * The archive's kdf parameters are limited to 1 GiB memory, 64 iterations and a parallelism of 16.
+     */
+    async importBackup(
+        archive: Uint8Array,
+        protection: string | NodeKeyHandle,
+        options?: ImportBackupOptions,
+    ): Promise<ImportedBackup> {
+        const bareProtection =
+            typeof protection === "string" ? protection : protection.keyHandle;
+        return await abortable(options, (token) =>
+            this.provider.importBackup(
+                archive,
+                bareProtection,
+                options,
+                token,
             ),
         );
     }
 
-    async getRandom(len: number): Promise<Uint8Array> {
-        return await getRandom.call(this.provider, len);
+    /** Copies the keys of this provider into `target`. See {@link migrateKeys}. */
+    async migrateKeysTo(
+        target: NodeProvider,
+        options?: MigrateKeysOptions,
+    ): Promise<MigrationReport> {
+        return await abortable(options, (token) =>
+            this.provider.migrateKeysTo(target.provider, options, token),
+        );
     }
 
-    async hash(input: Uint8Array, hashAlgo: CryptoHash): Promise<Uint8Array> {
-        return await hash.call(this.provider, input, hashAlgo);
+    /**
+     * Loads every key of the storage once and checks the metadata stored by the addon.
+     *
+     * Storages with `StorageConfigHMAC` or `StorageConfigDSA` verify the MAC or signature of a key when it is loaded,
+     * thus tampered keys are reported as unreadable.
+     */
+    async verifyStore(options?: OperationOptions): Promise<StoreReport> {
+        return await abortable(options, (token) =>
+            this.provider.verifyStore(token),
+        );
     }
 
-    async getAllKeys(): Promise<[string, Spec][]> {
-        return await getAllKeys.call(this.provider);
+    /**
+     * Verifies the storage like {@link verifyStore} and removes orphaned and corrupted metadata entries.
+     *
+     * Only the addon's metadata file is repaired: the provider storage of crypto-layer is never changed.
+     * Unreadable keys are only reported, as their entries can only be deleted through a loaded key.
+     */
+    async repairStore(options?: RepairStoreOptions): Promise<StoreReport> {
+        return await abortable(options, (token) =>
+            this.provider.repairStore(options, token),
+        );
     }
 }
 
-class NodeKeyHandle implements KeyHandle {
+export class NodeKeyHandle implements KeyHandle {
     // Do not change this variable. The rust code needs to unwrap this `NodeKeyHandle` to a `BareKeyHandle` on provider creation.
     public keyHandle: BareKeyHandle;
 
@@ -381,56 +1321,185 @@ class NodeKeyHandle implements KeyHandle {
         this.keyHandle = bareKeyHandle;
     }
 
-    async id(): Promise<string> {
-        return await idForKeyHandle.call(this.keyHandle);
+    toString(): string {
+        return this.keyHandle.toString();
+    }
+
+    [inspect.custom](): string {
+        return this.keyHandle.toString();
+    }
+
+    async id(options?: OperationOptions): Promise<string> {
+        return await abortable(options, (token) => this.keyHandle.id(token));
+    }
+
+    /** Returns the id without a round trip through the event loop. */
+    idSync(): string {
+        return this.keyHandle.idSync();
     }
 
-    async delete(): Promise<undefined> {
-        return await deleteForKeyHandle.call(this.keyHandle);
+    /**
+     * Deletes the key.
+     *
+     * Afterwards every operation on this handle and on other handles of the same key
+     * rejects with a {@link KeyDeletedError}.
+     */
+    async delete(options?: OperationOptions): Promise<undefined> {
+        return await abortable(options, (token) =>
+            this.keyHandle.delete(token),
+        );
+    }
+
+    /** Returns the metadata given on creation or import, `null` if none was given. */
+    async metadata(options?: OperationOptions): Promise<KeyMetadata | null> {
+        return await abortable(options, (token) =>
+            this.keyHandle.metadata(token),
+        );
     }
 
-    async extractKey(): Promise<Uint8Array> {
-        return await extractKeyForKeyHandle.call(this.keyHandle);
+    async extractKey(options?: OperationOptions): Promise<Uint8Array> {
+        return await abortable(options, (token) =>
+            this.keyHandle.extractKey(token),
+        );
     }
 
     async encryptData(
         data: Uint8Array,
         iv: Uint8Array,
+        options?: OperationOptions,
     ): Promise<[Uint8Array, Uint8Array]> {
-        return await encryptDataForKeyHandle.call(this.keyHandle, data, iv);
+        return await abortable(options, (token) =>
+            this.keyHandle.encryptData(data, iv, token),
+        );
     }
 
-    async encrypt(data: Uint8Array): Promise<[Uint8Array, Uint8Array]> {
-        return await encryptForKeyHandle.call(this.keyHandle, data);
+    /**
+     * Encrypts `data` with a random iv.
+     *
+     * With `options.committing` a commitment to the key is prepended to the ciphertext,
+     * which must then be decrypted with `committing` as well.
+     */
+    async encrypt(
+        data: Uint8Array,
+        options?: CommittingOptions,
+    ): Promise<[Uint8Array, Uint8Array]> {
+        return await abortable(options, (token) =>
+            this.keyHandle.encrypt(data, options, token),
+        );
+    }
+
+    async encryptWithIv(
+        data: Uint8Array,
+        iv: Uint8Array,
+        options?: OperationOptions,
+    ): Promise<Uint8Array> {
+        return await abortable(options, (token) =>
+            this.keyHandle.encryptWithIv(data, iv, token),
+        );
     }
 
-    async encryptWithIv(data: Uint8Array, iv: Uint8Array): Promise<Uint8Array> {
-        return await encryptWithIvForKeyHandle.call(this.keyHandle, data, iv);
+    /**
+     * Encrypts `data` and writes the cipher text into `out`.
+     *
+     * `out` must be at least 16 bytes longer than `data`, which is checked before encrypting.
+     * `data` is copied when called, thus it may be modified right away.
+     * Only the allocation of the cipher text is saved.
+     *
+     * @returns bytes written to `out` and the generated iv
+     */
+    async encryptInto(
+        data: Uint8Array,
+        out: Uint8Array,
+        options?: OperationOptions,
+    ): Promise<[number, Uint8Array]> {
+        return await abortable(options, (token) =>
+            this.keyHandle.encryptInto(data, out, token),
+        );
     }
 
+    /**
+     * Decrypts `encryptedData`.
+     *
+     * With `options.committing` the key commitment of {@link encrypt} is verified first,
+     * so that ciphertexts crafted to decrypt under several keys are rejected.
+     */
     async decryptData(
         encryptedData: Uint8Array,
         iv: Uint8Array,
+        options?: CommittingOptions,
     ): Promise<Uint8Array> {
-        return await decryptDataForKeyHandle.call(
-            this.keyHandle,
-            encryptedData,
-            iv,
+        return await abortable(options, (token) =>
+            this.keyHandle.decryptData(encryptedData, iv, options, token),
+        );
+    }
+
+    /**
+     * Decrypts `encryptedData` and writes the plain text into `out`.
+     *
+     * `out` must be at least as long as `encryptedData` without its 16 byte tag, which is checked before decrypting.
+     * `encryptedData` is copied when called, thus it may be modified right away.
+     * Only the allocation of the plain text is saved.
+     *
+     * @returns bytes written to `out`
+     */
+    async decryptInto(
+        encryptedData: Uint8Array,
+        iv: Uint8Array,
+        out: Uint8Array,
+        options?: OperationOptions,
+    ): Promise<number> {
+        return await abortable(options, (token) =>
+            this.keyHandle.decryptInto(
+                encryptedData,
+                iv,
+                out,
+                token,
+            ),
         );
     }
 
-    async spec(): Promise<KeySpec> {
-        return await specForKeyHandle.call(this.keyHandle);
+    /** Encrypts every message with a single call into the addon. */
+    async encryptMany(
+        data: Uint8Array[],
+        options?: OperationOptions,
+    ): Promise<BatchResult<[Uint8Array, Uint8Array]>[]> {
+        return await abortable(options, (token) =>
+            this.keyHandle.encryptMany(data, token),
+        );
+    }
+
+    /** Decrypts every `[encryptedData, iv]` tuple with a single call into the addon. */
+    async decryptMany(
+        items: [Uint8Array, Uint8Array][],
+        options?: OperationOptions,
+    ): Promise<BatchResult<Uint8Array>[]> {
+        return await abortable(options, (token) =>
+            this.keyHandle.decryptMany(items, token),
+        );
+    }
+
+    async spec(options?: OperationOptions): Promise<KeySpec> {
+        return await abortable(options, (token) => this.keyHandle.spec(token));
+    }
+
+    /** Returns the spec without a round trip through the event loop. */
+    specSync(): KeySpec {
+        return this.keyHandle.specSync();
     }
 
-    async deriveKey(nonce: Uint8Array): Promise<KeyHandle> {
+    async deriveKey(
+        nonce: Uint8Array,
+        options?: OperationOptions,
+    ): Promise<NodeKeyHandle> {
         return new NodeKeyHandle(
-            await deriveKeyForKeyHandle.call(this.keyHandle, nonce),
+            await abortable(options, (token) =>
+                this.keyHandle.deriveKey(nonce, token),
+            ),
         );
     }
 }
 
-class NodeKeyPairHandle implements KeyPairHandle {
+export class NodeKeyPairHandle implements KeyPairHandle {
     // Do not change this variable. The rust code needs to unwrap this `NodeKeyPairHandle` to a `BareKeyPairHandle` on provider creation.
     public keyPairHandle: BareKeyPairHandle;
 
@@ -438,123 +1507,287 @@ class NodeKeyPairHandle implements KeyPairHandle {
         this.keyPairHandle = bareKeyPairHandle;
     }
 
-    async id(): Promise<string> {
-        return await idForKeyPair.call(this.keyPairHandle);
+    toString(): string {
+        return this.keyPairHandle.toString();
+    }
+
+    [inspect.custom](): string {
+        return this.keyPairHandle.toString();
+    }
+
+    async id(options?: OperationOptions): Promise<string> {
+        return await abortable(options, (token) =>
+            this.keyPairHandle.id(token),
+        );
+    }
+
+    /** Returns the id without a round trip through the event loop. */
+    idSync(): string {
+        return this.keyPairHandle.idSync();
     }
 
-    async delete(): Promise<undefined> {
-        return await deleteForKeyPair.call(this.keyPairHandle);
+    /**
+     * Deletes the key.
+     *
+     * Afterwards every operation on this handle and on other handles of the same key
+     * rejects with a {@link KeyDeletedError}.
+     */
+    async delete(options?: OperationOptions): Promise<undefined> {
+        return await abortable(options, (token) =>
+            this.keyPairHandle.delete(token),
+        );
     }
 
-    async signData(data: Uint8Array): Promise<Uint8Array> {
-        return await signData.call(this.keyPairHandle, data);
+    /** Returns the metadata given on creation or import, `null` if none was given. */
+    async metadata(options?: OperationOptions): Promise<KeyMetadata | null> {
+        return await abortable(options, (token) =>
+            this.keyPairHandle.metadata(token),
+        );
+    }
+
+    async signData(
+        data: Uint8Array,
+        options?: OperationOptions,
+    ): Promise<Uint8Array> {
+        return await abortable(options, (token) =>
+            this.keyPairHandle.signData(data, token),
+        );
     }
 
     async verifySignature(
         data: Uint8Array,
         signature: Uint8Array,
+        options?: OperationOptions,
     ): Promise<boolean> {
-        return await verifySignature.call(this.keyPairHandle, data, signature);
+        return await abortable(options, (token) =>
+            this.keyPairHandle.verifySignature(data, signature, token),
+        );
+    }
+
+    /** Signs every message with a single call into the addon. */
+    async signMany(
+        data: Uint8Array[],
+        options?: OperationOptions,
+    ): Promise<BatchResult<Uint8Array>[]> {
+        return await abortable(options, (token) =>
+            this.keyPairHandle.signMany(data, token),
+        );
+    }
+
+    /** Verifies every `[data, signature]` tuple with a single call into the addon. */
+    async verifyMany(
+        items: [Uint8Array, Uint8Array][],
+        options?: OperationOptions,
+    ): Promise<BatchResult<boolean>[]> {
+        return await abortable(options, (token) =>
+            this.keyPairHandle.verifyMany(items, token),
+        );
+    }
+
+    async encryptData(
+        data: Uint8Array,
+        options?: OperationOptions,
+    ): Promise<Uint8Array> {
+        return await abortable(options, (token) =>
+            this.keyPairHandle.encryptData(data, token),
+        );
     }
 
-    async encryptData(data: Uint8Array): Promise<Uint8Array> {
-        return await encryptDataForKeyPairHandle.call(this.keyPairHandle, data);
+    async decryptData(
+        encryptedData: Uint8Array,
+        options?: OperationOptions,
+    ): Promise<Uint8Array> {
+        return await abortable(options, (token) =>
+            this.keyPairHandle.decryptData(
+                encryptedData,
+                token,
+            ),
+        );
     }
 
-    async decryptData(encryptedData: Uint8Array): Promise<Uint8Array> {
-        return await decryptDataForKeyPairHandle.call(
-            this.keyPairHandle,
-            encryptedData,
+    async getPublicKey(options?: OperationOptions): Promise<Uint8Array> {
+        return await abortable(options, (token) =>
+            this.keyPairHandle.getPublicKey(token),
         );
     }
 
-    async getPublicKey(): Promise<Uint8Array> {
-        return await getPublicKey.call(this.keyPairHandle);
+    async extractKey(options?: OperationOptions): Promise<Uint8Array> {
+        return await abortable(options, (token) =>
+            this.keyPairHandle.extractKey(token),
+        );
     }
 
-    async extractKey(): Promise<Uint8Array> {
-        return await extractKeyForKeyPairHandle.call(this.keyPairHandle);
+    async spec(options?: OperationOptions): Promise<KeyPairSpec> {
+        return await abortable(options, (token) =>
+            this.keyPairHandle.spec(token),
+        );
     }
 
-    async spec(): Promise<KeyPairSpec> {
-        return await specForKeyPairHandle.call(this.keyPairHandle);
+    /** Returns the spec without a round trip through the event loop. */
+    specSync(): KeyPairSpec {
+        return this.keyPairHandle.specSync();
     }
 
-    async startDhExchange(): Promise<DHExchange> {
-        return await startDhExchangeForKeyPairHandle.call(this.keyPairHandle);
+    async startDhExchange(
+        options?: OperationOptions,
+    ): Promise<NodeDHExchange> {
+        return new NodeDHExchange(
+            await abortable(options, (token) =>
+                this.keyPairHandle.startDhExchange(token),
+            ),
+        );
     }
 }
 
-class NodeDHExchange implements DHExchange {
+export class NodeDHExchange implements DHExchange {
     private dhExchange: BareDHExchange;
 
     constructor(bareDHExchange: BareDHExchange) {
         this.dhExchange = bareDHExchange;
     }
 
-    async getPublicKey(): Promise<Uint8Array> {
-        return await getPublicKeyForDHExchange.call(this.dhExchange);
+    toString(): string {
+        return this.dhExchange.toString();
+    }
+
+    [inspect.custom](): string {
+        return this.dhExchange.toString();
+    }
+
+    async getPublicKey(options?: OperationOptions): Promise<Uint8Array> {
+        return await abortable(options, (token) =>
+            this.dhExchange.getPublicKey(token),
+        );
     }
     /* async addExternal(externalKey: Uint8Array): Promise<Uint8Array> {
-        return await addExternalForDHExchange.call(
-            this.dhExchange,
+        return await this.dhExchange.addExternal(
             externalKey
         );
     }
-    async addExternalFinal(externalKey: Uint8Array): Promise<KeyHandle> {
+    async addExternalFinal(externalKey: Uint8Array): Promise<NodeKeyHandle> {
         return new NodeKeyHandle(
-            await addExternalFinalForDHExchange.call(
-                this.dhExchange,
+            await this.dhExchange.addExternalFinal(
                 externalKey
             )
         );
     } */
     async deriveClientSessionKeys(
         serverPk: Uint8Array,
+        options?: OperationOptions,
     ): Promise<[Uint8Array, Uint8Array]> {
-        return await deriveClientSessionKeys.call(this.dhExchange, serverPk);
+        return await abortable(options, (token) =>
+            this.dhExchange.deriveClientSessionKeys(serverPk, token),
+        );
     }
     async deriveServerSessionKeys(
         clientPk: Uint8Array,
+        options?: OperationOptions,
     ): Promise<[Uint8Array, Uint8Array]> {
-        return await deriveServerSessionKeys.call(this.dhExchange, clientPk);
+        return await abortable(options, (token) =>
+            this.dhExchange.deriveServerSessionKeys(clientPk, token),
+        );
     }
     async deriveClientKeyHandles(
         serverPk: Uint8Array,
+        options?: OperationOptions,
     ): Promise<[KeyHandle, KeyHandle]> {
-        const [rx, tx] = await deriveClientKeyHandles.call(
-            this.dhExchange,
-            serverPk,
+        const [rx, tx] = await abortable(options, (token) =>
+            this.dhExchange.deriveClientKeyHandles(serverPk, token),
         );
         return [new NodeKeyHandle(rx), new NodeKeyHandle(tx)];
     }
     async deriveServerKeyHandles(
         clientPk: Uint8Array,
+        options?: OperationOptions,
     ): Promise<[KeyHandle, KeyHandle]> {
-        const [rx, tx] = await deriveServerKeyHandles.call(
-            this.dhExchange,
-            clientPk,
+        const [rx, tx] = await abortable(options, (token) =>
+            this.dhExchange.deriveServerKeyHandles(clientPk, token),
         );
         return [new NodeKeyHandle(rx), new NodeKeyHandle(tx)];
     }
 }
 
+export async function getAllProviders(
+    options?: OperationOptions,
+): Promise<string[]> {
+    return await abortable(options, (token) => getAllBareProviders(token));
+}
+
+export async function getProviderCapabilities(
+    providerImplConfig: NodeProviderImplConfig,
+    options?: OperationOptions,
+): Promise<[string, ProviderConfig][]> {
+    return await abortable(options, (token) =>
+        getBareProviderCapabilities(providerImplConfig, token),
+    );
+}
+
 export async function createProvider(
     config: ProviderConfig,
-    impl_config: ProviderImplConfig,
-): Promise<Provider | undefined> {
-    const provider = await createBareProvider(config, impl_config);
+    impl_config: NodeProviderImplConfig,
+    options?: CreateProviderOptions,
+): Promise<NodeProvider | undefined> {
+    const explain = options?.explain ?? false;
+    const provider = await abortable(options, (token) =>
+        createBareProvider(config, impl_config, { explain }, token),
+    );
     if (!provider) {
         return undefined;
     }
     return new NodeProvider(provider);
 }
 
+/**
+ * Evaluates every provider against the `requirements`.
+ *
+ * Matching providers come first, followed by the providers missing the fewest requirements.
+ * Providers not initializable with `providerImplConfig` come last.
+ */
+export async function selectProviders(
+    requirements: ProviderConfig,
+    providerImplConfig: NodeProviderImplConfig,
+    options?: OperationOptions,
+): Promise<ProviderEvaluation[]> {
+    return await abortable(options, (token) =>
+        selectBareProviders(requirements, providerImplConfig, token),
+    );
+}
+
+/**
+ * Copies the keys of `source` matching `options.filter` with their metadata into `target`.
+ *
+ * Ids are not preserved: copies get new ids, which `ids` of the report maps from the ids of the source keys.
+ * Repeating a migration, e.g. after it was interrupted, only copies the keys, which were not copied before.
+ * Ephemeral and non exportable keys cannot be migrated.
+ * Non exportable keys have no wrapping path, as crypto-layer offers no way to wrap a key for transport.
+ */
+export async function migrateKeys(
+    source: NodeProvider,
+    target: NodeProvider,
+    options?: MigrateKeysOptions,
+): Promise<MigrationReport> {
+    return await source.migrateKeysTo(target, options);
+}
+
+/**
+ * Creates the provider `name`.
+ *
+ * Providers with a `FileStoreConfig` are cached per process by their `db_dir`:
+ * Calls with the same `db_dir` share one provider as long as it was not
+ * garbage collected. They must pass the same `name` and storage keys,
+ * otherwise the call rejects, as the database cannot be opened twice.
+ *
+ * The key metadata file next to the database is protected with the storage key.
+ * The call rejects, if the file was modified or belongs to another storage key.
+ */
 export async function createProviderFromName(
     name: string,
-    impl_config: ProviderImplConfig,
-): Promise<Provider | undefined> {
-    const provider = await createBareProviderFromName(name, impl_config);
+    impl_config: NodeProviderImplConfig,
+    options?: OperationOptions,
+): Promise<NodeProvider | undefined> {
+    const provider = await abortable(options, (token) =>
+        createBareProviderFromName(name, impl_config, token),
+    );
     if (!provider) {
         return undefined;
     }
@@ -1,4 +1,6 @@
 import { test, expect, describe } from "@jest/globals";
+import { existsSync, readFileSync, writeFileSync } from "node:fs";
+import { join } from "node:path";
 
 import {
     KeyPairSpec,
@@ -11,6 +13,13 @@ import {
     getAllProviders,
     createProviderFromName,
     getProviderCapabilities,
+    configureRuntime,
+    getRuntimeMetrics,
+    INVALID_ARGUMENT_ERROR_CODE,
+    NO_MATCHING_PROVIDER_ERROR_CODE,
+    NodeProviderImplConfig,
+    selectProviders,
+    UNSUPPORTED_ERROR_CODE,
 } from "../lib/index.cjs";
 
 import {
@@ -63,6 +72,67 @@ describe("test provider factory methods", () => {
         expect(provider_arr).toContain(SOFTWARE_PROVIDER_NAME);
     });
 
+    test("configure runtime and reject when queue is full", async () => {
+        configureRuntime({ workerThreads: 1, maxQueued: 0 });
+        try {
+            const metricsBefore = getRuntimeMetrics();
+            expect(metricsBefore.workerThreads).toEqual(1);
+            expect(metricsBefore.maxQueued).toEqual(0);
+
+            // The idle worker accepts the first call, the second would have to wait.
+            const first = getAllProviders();
+            const second = getAllProviders();
+            await expect(first).resolves.toContain(SOFTWARE_PROVIDER_NAME);
+            await expect(second).rejects.toThrow();
+            expect(getRuntimeMetrics().rejected).toEqual(
+                metricsBefore.rejected + 1,
+            );
+        } finally {
+            configureRuntime({});
+        }
+        expect(getRuntimeMetrics().maxQueued).toBeNull();
+        await expect(getAllProviders()).resolves.toContain(
+            SOFTWARE_PROVIDER_NAME,
+        );
+    });
+
+    test("aborted queued call releases its queue slot", async () => {
+        const provider = await createProviderFromName(SOFTWARE_PROVIDER_NAME, {
+            additional_config: [],
+        });
+        assertProvider(provider);
+
+        configureRuntime({ workerThreads: 1, maxQueued: 1 });
+        try {
+            // Keeps the only worker busy.
+            const busy = provider!.deriveKeyFromPassword(
+                "password1234",
+                new Uint8Array(16),
+                {
+                    cipher: "AesGcm256",
+                    signing_hash: "Sha2_256",
+                    ephemeral: true,
+                    non_exportable: true,
+                },
+                { Argon2d: { memory: 65536, iterations: 4, parallelism: 1 } },
+            );
+
+            const controller = new AbortController();
+            const queued = provider!.getRandom(16, {
+                signal: controller.signal,
+            });
+            controller.abort();
+            await expect(queued).rejects.toThrow();
+
+            // Would be rejected, if the aborted call still held the slot.
+            const next = provider!.getRandom(16);
+            await expect(busy).resolves.toBeTruthy();
+            await expect(next).resolves.toHaveLength(16);
+        } finally {
+            configureRuntime({});
+        }
+    });
+
     test("create provider from config with file store", async () => {
         const providerImplConfigWithFileStore: ProviderImplConfig = {
             additional_config: [{ FileStoreConfig: { db_dir: dbDirPath! } }],
@@ -99,6 +169,69 @@ describe("test provider factory methods", () => {
         );
     });
 
+    test("share providers with the same file store", async () => {
+        const implConfig: ProviderImplConfig = {
+            additional_config: [{ FileStoreConfig: { db_dir: dbDirPath! } }],
+        };
+        const first = await createProviderFromName(
+            SOFTWARE_PROVIDER_NAME,
+            implConfig,
+        );
+        const second = await createProviderFromName(
+            SOFTWARE_PROVIDER_NAME,
+            implConfig,
+        );
+        if (!first || !second) throw new Error("Failed creating providers.");
+
+        const key = await first.createKey({ cipher: "AesGcm256" });
+        const loadedKey = await second.loadKey(await key.id());
+        expect(await loadedKey.id()).toEqual(await key.id());
+
+        expect(second.release()).toEqual(1);
+        expect(first.release()).toEqual(0);
+        expect(first.release()).toEqual(0);
+    });
+
+    test("reject other storage keys for a cached file store", async () => {
+        const implConfig: ProviderImplConfig = {
+            additional_config: [{ FileStoreConfig: { db_dir: dbDirPath! } }],
+        };
+        const provider = await createProviderFromName(
+            SOFTWARE_PROVIDER_NAME,
+            implConfig,
+        );
+        assertProvider(provider);
+        expect(provider!.release()).toEqual(0);
+
+        await expect(
+            createProviderFromName(SOFTWARE_PROVIDER_NAME, {
+                additional_config: [
+                    {
+                        StoragePassword: {
+                            password: "another password",
+                            kdf: {
+                                Argon2id: {
+                                    memory: 8192,
+                                    iterations: 1,
+                                    parallelism: 1,
+                                },
+                            },
+                            salt_path: join(dbDirPath!, "storage.salt"),
+                        },
+                    },
+                    { FileStoreConfig: { db_dir: dbDirPath! } },
+                ],
+            }),
+        ).rejects.toThrow();
+
+        // Released, but still referenced, thus still shared.
+        const again = await createProviderFromName(
+            SOFTWARE_PROVIDER_NAME,
+            implConfig,
+        );
+        expect(again!.release()).toEqual(0);
+    });
+
     test("test get provider capabilities", async () => {
         const emptyProviderConfig: ProviderImplConfig = {
             additional_config: [],
@@ -115,6 +248,50 @@ describe("test provider factory methods", () => {
         }
     });
 
+    test("select providers", async () => {
+        const evaluations = await selectProviders(providerConfig, {
+            additional_config: [],
+        });
+
+        expect(evaluations.length).toEqual((await getAllProviders()).length);
+        const software = evaluations.find(
+            (evaluation) => evaluation.name === SOFTWARE_PROVIDER_NAME,
+        );
+        expect(software).toMatchObject({ matches: true, unmet: [] });
+        assertProviderConfig(software?.capabilities);
+        expect(evaluations[0].matches).toBe(true);
+    });
+
+    test("explain why no provider matches", async () => {
+        const impossibleConfig: ProviderConfig = {
+            ...providerConfig,
+            max_security_level: "Hardware",
+            min_security_level: "Hardware",
+        };
+        const implConfig: ProviderImplConfig = { additional_config: [] };
+
+        const evaluations = await selectProviders(impossibleConfig, implConfig);
+        const software = evaluations.find(
+            (evaluation) => evaluation.name === SOFTWARE_PROVIDER_NAME,
+        );
+        expect(software?.matches).toBe(false);
+        expect(software?.unmet.map((unmet) => unmet.requirement)).toContain(
+            "security_level",
+        );
+
+        if (evaluations.some((evaluation) => evaluation.matches)) return;
+        await expect(
+            createProvider(impossibleConfig, implConfig, { explain: true }),
+        ).rejects.toMatchObject({
+            name: "ProviderSelectionError",
+            code: NO_MATCHING_PROVIDER_ERROR_CODE,
+            evaluations,
+        });
+        await expect(
+            createProvider(impossibleConfig, implConfig),
+        ).resolves.toBeUndefined();
+    });
+
     test("create software provider secured via a key handle", async () => {
         const temporaryProviderConfig: ProviderImplConfig = {
             additional_config: [],
@@ -238,4 +415,109 @@ describe("test provider factory methods", () => {
             assertKeyHandle(keyHandle);
         }
     });
+
+    test("create software provider secured via a storage password", async () => {
+        const saltPath = join(dbDirPath!, "storage.salt");
+        const storagePassword = {
+            password: "correct horse battery staple",
+            kdf: { Argon2id: { memory: 8192, iterations: 1, parallelism: 1 } },
+            salt_path: saltPath,
+        };
+        const implConfig: NodeProviderImplConfig = {
+            additional_config: [
+                { StoragePassword: storagePassword },
+                { FileStoreConfig: { db_dir: dbDirPath! } },
+            ],
+        };
+
+        const securedProvider = await createProviderFromName(
+            SOFTWARE_PROVIDER_NAME,
+            implConfig,
+        );
+        if (!securedProvider)
+            throw new Error("Failed creating a secured software provider.");
+        expect(existsSync(saltPath)).toBe(true);
+        expect(readFileSync(saltPath).length).toEqual(16);
+
+        const keyHandle = await securedProvider.createKey({
+            cipher: "AesGcm256",
+            signing_hash: "Sha2_512",
+            ephemeral: false,
+            non_exportable: true,
+        });
+        const id = await keyHandle.id();
+        assertKeyHandle(await securedProvider.loadKey(id));
+
+        await expect(
+            createProviderFromName(SOFTWARE_PROVIDER_NAME, {
+                additional_config: [
+                    { StoragePassword: storagePassword },
+                    { StoragePassword: storagePassword },
+                ],
+            }),
+        ).rejects.toMatchObject({
+            code: INVALID_ARGUMENT_ERROR_CODE,
+            path: "impl_config.additional_config[1]",
+        });
+    });
+
+    test("reject a salt file of the wrong length", async () => {
+        const saltPath = join(dbDirPath!, "storage.salt");
+        writeFileSync(saltPath, Buffer.from([1, 2, 3]));
+
+        await expect(
+            createProviderFromName(SOFTWARE_PROVIDER_NAME, {
+                additional_config: [
+                    {
+                        StoragePassword: {
+                            password: "correct horse battery staple",
+                            salt_path: saltPath,
+                        },
+                    },
+                    { FileStoreConfig: { db_dir: dbDirPath! } },
+                ],
+            }),
+        ).rejects.toThrow("holds 3 bytes instead of 16");
+    });
+
+    test("reject a key metadata file failing verification", async () => {
+        writeFileSync(
+            join(dbDirPath!, "crypto-layer-node-key-metadata.json"),
+            JSON.stringify({ forged: { usages: ["Encrypt"] } }),
+        );
+
+        await expect(
+            createProviderFromName(SOFTWARE_PROVIDER_NAME, {
+                additional_config: [
+                    {
+                        StoragePassword: {
+                            password: "correct horse battery staple",
+                            kdf: {
+                                Argon2id: {
+                                    memory: 8192,
+                                    iterations: 1,
+                                    parallelism: 1,
+                                },
+                            },
+                            salt_path: join(dbDirPath!, "storage.salt"),
+                        },
+                    },
+                    { FileStoreConfig: { db_dir: dbDirPath! } },
+                ],
+            }),
+        ).rejects.toThrow(/integrity check/);
+    });
+
+    test("reject key value store config", async () => {
+        const implConfig = {
+            additional_config: [{ KVStoreConfig: {} }],
+        } as unknown as ProviderImplConfig;
+
+        await expect(
+            createProviderFromName(SOFTWARE_PROVIDER_NAME, implConfig),
+        ).rejects.toMatchObject({
+            code: UNSUPPORTED_ERROR_CODE,
+            path: "impl_config.additional_config[0].KVStoreConfig",
+        });
+    });
 });
@@ -1,7 +1,20 @@
 import { test, expect, describe } from "@jest/globals";
+import { inspect } from "node:util";
 
-import { ProviderImplConfig, Provider, KeySpec } from "@nmshd/rs-crypto-types";
-import { createProviderFromName } from "../lib/index.cjs";
+import { ProviderImplConfig, KeySpec } from "@nmshd/rs-crypto-types";
+import {
+    BareKeyHandle,
+    BareKeyPairHandle,
+    createProviderFromName,
+    ENCRYPTION_LIMIT_ERROR_CODE,
+    ENCRYPTION_LIMIT_WARNING_CODE,
+    isIvReusedError,
+    isKeyDeletedError,
+    isKeyUsageError,
+    KEY_DELETED_ERROR_CODE,
+    KEY_USAGE_ERROR_CODE,
+    NodeProvider,
+} from "../lib/index.cjs";
 
 import {
     gcAllAndWait,
@@ -12,7 +25,7 @@ import {
 import { assertKeyHandle } from "@nmshd/rs-crypto-types/checks";
 
 describe("test key handle methods", () => {
-    let provider: Provider;
+    let provider: NodeProvider;
     let dbDirPath: string;
 
     beforeAll(async () => {
@@ -52,6 +65,35 @@ describe("test key handle methods", () => {
         expect(typeof id).toBe("string");
     });
 
+    test("idSync and specSync", async () => {
+        const key = await provider.createKey(spec);
+        expect(key.idSync()).toEqual(await key.id());
+        expect(key.specSync()).toEqual(await key.spec());
+    });
+
+    test("native class", async () => {
+        const key = await provider.createKey(spec);
+        const id = await key.id();
+
+        expect(key.keyHandle).toBeInstanceOf(BareKeyHandle);
+        expect(key.keyHandle).not.toBeInstanceOf(BareKeyPairHandle);
+        expect(key.toString()).toContain(id);
+        expect(inspect(key)).toContain(id);
+        expect(inspect(key)).toContain("AesGcm256");
+    });
+
+    test("brand checks", async () => {
+        const key = await provider.createKey(spec);
+
+        expect(
+            () => new (BareKeyHandle as unknown as new () => object)(),
+        ).toThrow(TypeError);
+        expect(() => BareKeyHandle.prototype.id.call({})).toThrow(TypeError);
+        expect(() =>
+            BareKeyPairHandle.prototype.id.call(key.keyHandle),
+        ).toThrow(/KeyPairHandle/);
+    });
+
     test("delete", async () => {
         const key = await provider.createKey(spec);
         const id = await key.id();
@@ -59,6 +101,109 @@ describe("test key handle methods", () => {
         expect(provider.loadKey(id)).rejects.toThrow();
     });
 
+    test("deleted handles reject further use", async () => {
+        const key = await provider.createKey(spec);
+        const id = await key.id();
+        const otherKey = await provider.loadKey(id);
+
+        await key.delete();
+
+        await expect(key.id()).rejects.toMatchObject({
+            name: "KeyDeletedError",
+            code: KEY_DELETED_ERROR_CODE,
+            keyId: id,
+        });
+        expect(() => key.idSync()).toThrow(
+            expect.objectContaining({ code: KEY_DELETED_ERROR_CODE }),
+        );
+        const otherError = await otherKey.id().catch((error) => error);
+        expect(isKeyDeletedError(otherError)).toBe(true);
+        await expect(key.delete()).rejects.toMatchObject({
+            code: KEY_DELETED_ERROR_CODE,
+        });
+        expect(inspect(key)).toContain("deleted: true");
+    });
+
+    test("refuse operations not among the usages", async () => {
+        const key = await provider.createKey(spec, { usages: ["encrypt"] });
+        const nonce = await provider.getRandom(12);
+        const encryptedData = await key.encryptData(Buffer.from("Hi"), nonce);
+
+        await expect(key.decryptData(...encryptedData)).rejects.toMatchObject({
+            name: "KeyUsageError",
+            code: KEY_USAGE_ERROR_CODE,
+            keyId: await key.id(),
+            operation: "decrypt",
+            usages: ["encrypt"],
+        });
+        expect((await key.metadata())?.usages).toEqual(["encrypt"]);
+
+        const loaded = await provider.loadKey(await key.id());
+        const err = await loaded.decryptData(...encryptedData).catch((e) => e);
+        expect(isKeyUsageError(err)).toBe(true);
+    });
+
+    test("refuse encryptions beyond the limit", async () => {
+        const warnings: Error[] = [];
+        const onWarning = (warning: Error) => warnings.push(warning);
+        process.on("warning", onWarning);
+
+        const key = await provider.createKey(spec, {
+            encryptionLimits: { maxEncryptions: 3, warnAt: 2 },
+        });
+        await key.encryptMany([Buffer.from("a"), Buffer.from("b")]);
+        await key.encrypt(Buffer.from("c"));
+        await new Promise((resolve) => setImmediate(resolve));
+        process.off("warning", onWarning);
+
+        await expect(key.encrypt(Buffer.from("d"))).rejects.toMatchObject({
+            name: "EncryptionLimitError",
+            code: ENCRYPTION_LIMIT_ERROR_CODE,
+            keyId: await key.id(),
+            maxEncryptions: 3,
+        });
+        expect(warnings).toContainEqual(
+            expect.objectContaining({ code: ENCRYPTION_LIMIT_WARNING_CODE }),
+        );
+    });
+
+    test("refuse reused ivs in strict mode", async () => {
+        const key = await provider.createKey(spec, {
+            encryptionLimits: { strictIvCheck: true },
+        });
+        const nonce = await provider.getRandom(12);
+
+        await key.encryptWithIv(Buffer.from("Hello World!"), nonce);
+        const err = await key
+            .encryptWithIv(Buffer.from("Hello World!"), nonce)
+            .catch((e) => e);
+        expect(isIvReusedError(err)).toBe(true);
+
+        const loaded = await provider.loadKey(await key.id());
+        await expect(
+            loaded.encryptData(Buffer.from("Hi"), nonce),
+        ).rejects.toMatchObject({ name: "IvReusedError" });
+    });
+
+    test("encrypt with key commitment", async () => {
+        const [key, otherKey] = await Promise.all([
+            provider.createKey(spec),
+            provider.createKey(spec),
+        ]);
+        const data = Buffer.from("Hello World!");
+
+        const [ciphertext, iv] = await key.encrypt(data, { committing: true });
+        const decrypted = await key.decryptData(ciphertext, iv, {
+            committing: true,
+        });
+        expect(Buffer.from(decrypted)).toEqual(data);
+
+        await expect(
+            otherKey.decryptData(ciphertext, iv, { committing: true }),
+        ).rejects.toThrow("not encrypted with this key");
+        await expect(key.decryptData(ciphertext, iv)).rejects.toThrow();
+    });
+
     test("encrypt data and decrypt data", async () => {
         const [key, nonce] = await Promise.all([
             provider.createKey(spec),
@@ -130,6 +275,73 @@ describe("test key handle methods", () => {
         );
     });
 
+    test("encrypt into and decrypt into caller supplied buffers", async () => {
+        const key = await provider.createKey(spec);
+        const helloMsg: Uint8Array = Buffer.from("Hello World!");
+
+        const cipherBuffer = new Uint8Array(helloMsg.length + 64);
+        const [cipherLength, iv] = await key.encryptInto(
+            helloMsg,
+            cipherBuffer,
+        );
+        expect(cipherLength).toBeGreaterThan(helloMsg.length);
+        expect(iv).toBeInstanceOf(Uint8Array);
+
+        const plainBuffer = new Uint8Array(cipherLength);
+        const plainLength = await key.decryptInto(
+            cipherBuffer.subarray(0, cipherLength),
+            iv,
+            plainBuffer,
+        );
+        expect(plainLength).toEqual(helloMsg.length);
+        expect(
+            Buffer.from(plainBuffer.subarray(0, plainLength)).toString("utf8"),
+        ).toEqual("Hello World!");
+
+        const tooSmall = new Uint8Array(helloMsg.length);
+        await expect(key.encryptInto(helloMsg, tooSmall)).rejects.toThrow(
+            RangeError,
+        );
+        expect(tooSmall).toEqual(new Uint8Array(helloMsg.length));
+        await expect(
+            key.decryptInto(
+                cipherBuffer.subarray(0, cipherLength),
+                iv,
+                new Uint8Array(1),
+            ),
+        ).rejects.toThrow(RangeError);
+    });
+
+    test("encrypt many and decrypt many", async () => {
+        const key = await provider.createKey(spec);
+        const messages = ["a", "b", "c"].map((m) => Buffer.from(m));
+
+        const encrypted = await key.encryptMany(messages);
+        expect(encrypted.length).toEqual(messages.length);
+
+        const items = encrypted.map((result) => {
+            if (!result.ok) {
+                throw result.error;
+            }
+            return result.value;
+        });
+        items.push([Uint8Array.from([1, 2, 3]), items[0][1]]);
+
+        const decrypted = await key.decryptMany(items);
+        expect(
+            decrypted
+                .slice(0, 3)
+                .map((result) =>
+                    result.ok ? Buffer.from(result.value).toString("utf8") : "",
+                ),
+        ).toEqual(["a", "b", "c"]);
+        expect(decrypted[3]).toMatchObject({
+            ok: false,
+            error: { kind: expect.any(String) },
+        });
+        expect((decrypted[3] as { error: Error }).error).toBeInstanceOf(Error);
+    });
+
     test("spec", async () => {
         const key = await provider.createKey(spec);
         expect(key.spec()).resolves.toEqual(spec);
@@ -1,11 +1,12 @@
 import { test, expect, describe } from "@jest/globals";
 
+import { ProviderImplConfig, KeyPairSpec } from "@nmshd/rs-crypto-types";
 import {
-    ProviderImplConfig,
-    Provider,
-    KeyPairSpec,
-} from "@nmshd/rs-crypto-types";
-import { createProviderFromName } from "../lib/index.cjs";
+    createProviderFromName,
+    isKeyNotValidError,
+    KEY_NOT_VALID_ERROR_CODE,
+    NodeProvider,
+} from "../lib/index.cjs";
 
 import {
     gcAllAndWait,
@@ -15,7 +16,7 @@ import {
 } from "./common";
 
 describe("test key pair handle methods", () => {
-    let provider: Provider;
+    let provider: NodeProvider;
     let dbDirPath: string;
 
     beforeAll(async () => {
@@ -56,14 +57,46 @@ describe("test key pair handle methods", () => {
         expect(typeof id).toBe("string");
     });
 
+    test("idSync and specSync", async () => {
+        const keyPair = await provider.createKeyPair(spec);
+        expect(keyPair.idSync()).toEqual(await keyPair.id());
+        expect(keyPair.specSync()).toEqual(spec);
+    });
+
     test("delete", async () => {
         const keyPair = await provider.createKeyPair(spec);
         const id = await keyPair.id();
         await keyPair.delete();
-        // expect(await key_pair.id()).rejects.toThrow() // TODO: Fix delete functionality.
+        await expect(keyPair.id()).rejects.toThrow();
         expect(provider.loadKeyPair(id)).rejects.toThrow();
     });
 
+    test("sign many and verify many", async () => {
+        const keyPair = await provider.createKeyPair(spec);
+        const messages = [1, 2, 3].map((i) => Uint8Array.from([i, i, i]));
+
+        const signatures = await keyPair.signMany(messages);
+        expect(signatures.length).toEqual(messages.length);
+
+        const items: [Uint8Array, Uint8Array][] = signatures.map(
+            (result, i) => {
+                if (!result.ok) {
+                    throw result.error;
+                }
+                return [messages[i], result.value];
+            },
+        );
+        items.push([Uint8Array.from([4]), items[0][1]]);
+
+        const verified = await keyPair.verifyMany(items);
+        expect(verified.slice(0, 3)).toEqual([
+            { ok: true, value: true },
+            { ok: true, value: true },
+            { ok: true, value: true },
+        ]);
+        expect(verified[3]).not.toEqual({ ok: true, value: true });
+    });
+
     test("spec", async () => {
         const keyPair = await provider.createKeyPair(spec);
         expect(keyPair.spec()).resolves.toEqual(spec);
@@ -93,6 +126,62 @@ describe("test key pair handle methods", () => {
         expect(keyPair.verifySignature(data, signature)).resolves.toBe(true);
     });
 
+    test("refuse signing outside of the validity period", async () => {
+        const data = Uint8Array.from([1, 2, 3, 4]);
+
+        const notYetValid = await provider.createKeyPair(spec, {
+            validity: { notBefore: Date.now() + 60_000 },
+        });
+        await expect(notYetValid.signData(data)).rejects.toMatchObject({
+            name: "KeyNotValidError",
+            code: KEY_NOT_VALID_ERROR_CODE,
+            keyId: await notYetValid.id(),
+            operation: "sign",
+        });
+
+        const notAfter = new Date(Date.now() + 500);
+        const expiring = await provider.createKeyPair(spec, {
+            validity: { notAfter },
+        });
+        const signature = await expiring.signData(data);
+        await new Promise((resolve) => setTimeout(resolve, 600));
+
+        const err = await expiring.signData(data).catch((e) => e);
+        expect(isKeyNotValidError(err)).toBe(true);
+        expect(err.notAfter).toEqual(notAfter);
+        // Without grace period verification is refused as well.
+        await expect(
+            expiring.verifySignature(data, signature),
+        ).rejects.toMatchObject({
+            code: KEY_NOT_VALID_ERROR_CODE,
+            operation: "verify",
+        });
+        expect((await expiring.metadata())?.validity).toEqual({
+            notBefore: null,
+            notAfter,
+            gracePeriod: null,
+        });
+    });
+
+    test("refuse verification after the grace period", async () => {
+        const data = Uint8Array.from([1, 2, 3, 4]);
+        const keyPair = await provider.createKeyPair(spec, {
+            validity: { notAfter: Date.now() + 500, gracePeriod: 200 },
+        });
+        const signature = await keyPair.signData(data);
+
+        await new Promise((resolve) => setTimeout(resolve, 600));
+        expect(await keyPair.verifySignature(data, signature)).toBe(true);
+
+        await new Promise((resolve) => setTimeout(resolve, 300));
+        await expect(
+            keyPair.verifySignature(data, signature),
+        ).rejects.toMatchObject({
+            code: KEY_NOT_VALID_ERROR_CODE,
+            operation: "verify",
+        });
+    });
+
     // TODO: not yet implemented for software provider.
     /* test("encrypt and decrypt data", () => {
         let key = provider.createKeyPair(spec);
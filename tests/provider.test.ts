@@ -2,13 +2,25 @@ import { test, expect, describe } from "@jest/globals";
 
 import {
     ProviderImplConfig,
-    Provider,
     KeySpec,
     KeyPairSpec,
     KDF,
 } from "@nmshd/rs-crypto-types";
 
-import { createProviderFromName } from "../lib/index.cjs";
+import {
+    BACKUP_AUTHENTICATION_ERROR_CODE,
+    BACKUP_CONFLICT_ERROR_CODE,
+    createProviderFromName,
+    INVALID_ARGUMENT_ERROR_CODE,
+    KEY_METADATA_UNAVAILABLE_ERROR_CODE,
+    KEY_USAGE_ERROR_CODE,
+    migrateKeys,
+    NodeProvider,
+    UNSUPPORTED_ERROR_CODE,
+} from "../lib/index.cjs";
+
+import { existsSync, readFileSync, writeFileSync } from "node:fs";
+import { join } from "node:path";
 
 import {
     gcAllAndWait,
@@ -24,7 +36,7 @@ import {
 } from "@nmshd/rs-crypto-types/checks";
 
 describe("test provider methods", () => {
-    let provider: Provider;
+    let provider: NodeProvider;
     let dbDirPath: string;
 
     beforeAll(async () => {
@@ -151,6 +163,93 @@ describe("test provider methods", () => {
         expect(typeof keyPair.verifySignature).toBe("function");
     });
 
+    test("create key pair with invalid spec", async () => {
+        const spec = {
+            asym_spec: "P257",
+            cipher: null,
+            signing_hash: "Sha2_256",
+            ephemeral: false,
+            non_exportable: false,
+        } as unknown as KeyPairSpec;
+
+        await expect(provider.createKeyPair(spec)).rejects.toMatchObject({
+            name: "TypeError",
+            code: INVALID_ARGUMENT_ERROR_CODE,
+            path: "spec.asym_spec",
+            received: 'string "P257"',
+            accepted: expect.arrayContaining(["P256"]),
+        });
+    });
+
+    test("create key with missing spec field", async () => {
+        const spec = {
+            cipher: "AesGcm256",
+            ephemeral: false,
+            non_exportable: false,
+        } as unknown as KeySpec;
+
+        await expect(provider.createKey(spec)).rejects.toMatchObject({
+            code: INVALID_ARGUMENT_ERROR_CODE,
+            path: "spec.signing_hash",
+            received: "undefined",
+        });
+    });
+
+    test("complete partial specs", async () => {
+        const caps = await provider.getCapabilities();
+
+        const keySpec = await provider.completeSpec({
+            KeySpec: { cipher: "AesGcm256" },
+        });
+        expect(keySpec).toEqual({
+            KeySpec: {
+                cipher: "AesGcm256",
+                signing_hash: expect.any(String),
+                ephemeral: false,
+                non_exportable: expect.any(Boolean),
+            },
+        });
+        if (!("KeySpec" in keySpec)) throw new Error("Expected a KeySpec.");
+        expect(caps?.supported_hashes).toContain(keySpec.KeySpec.signing_hash);
+
+        const keyPairSpec = await provider.completeSpec({ KeyPairSpec: {} });
+        if (!("KeyPairSpec" in keyPairSpec))
+            throw new Error("Expected a KeyPairSpec.");
+        expect(caps?.supported_asym_spec).toContain(
+            keyPairSpec.KeyPairSpec.asym_spec,
+        );
+        expect(keyPairSpec.KeyPairSpec.cipher).toBeNull();
+    });
+
+    test("create key and key pair from partial specs", async () => {
+        const key = await provider.createKey({ ephemeral: true });
+        expect((await key.spec()).ephemeral).toBe(true);
+
+        const keyPair = await provider.createKeyPair({ asym_spec: "P256" });
+        expect((await keyPair.spec()).asym_spec).toEqual("P256");
+    });
+
+    test("reject ciphers the provider does not support", async () => {
+        const caps = await provider.getCapabilities();
+        const unsupported = [
+            "AesCbc128",
+            "AesCbc256",
+            "AesGcm128",
+            "AesGcm256",
+            "ChaCha20Poly1305",
+            "XChaCha20Poly1305",
+        ].find((cipher) => !caps?.supported_ciphers.includes(cipher as any));
+        if (!unsupported) return;
+
+        await expect(
+            provider.createKey({ cipher: unsupported as any }),
+        ).rejects.toMatchObject({
+            code: UNSUPPORTED_ERROR_CODE,
+            path: "spec.cipher",
+            accepted: caps?.supported_ciphers.slice().sort(),
+        });
+    });
+
     test("create P256 key pair and load", async () => {
         const spec: KeyPairSpec = {
             asym_spec: "P256",
@@ -255,12 +354,35 @@ describe("test provider methods", () => {
         expect(importedKeyPair.spec()).resolves.toEqual(spec);
     });
 
+    test("import key and zeroize source", async () => {
+        const spec: KeySpec = {
+            cipher: "AesGcm256",
+            signing_hash: "Sha2_256",
+            ephemeral: true,
+            non_exportable: false,
+        };
+
+        const key = await provider.createKey(spec);
+        const rawKey = await key.extractKey();
+        const rawKeyCopy = Uint8Array.from(rawKey);
+
+        const importedKey = await provider.importKey(spec, rawKey, {
+            zeroizeSource: true,
+        });
+        expect(rawKey.every((byte) => byte === 0)).toBe(true);
+        expect(await importedKey.extractKey()).toEqual(rawKeyCopy);
+    });
+
     test("get provider name", async () => {
         expect(provider.providerName()).resolves.toEqual(
             SOFTWARE_PROVIDER_NAME,
         );
     });
 
+    test("get provider name synchronously", () => {
+        expect(provider.nameSync()).toEqual(SOFTWARE_PROVIDER_NAME);
+    });
+
     test("get provider capabilities", async () => {
         const caps = await provider.getCapabilities();
         expect(caps).toBeDefined();
@@ -306,12 +428,57 @@ describe("test provider methods", () => {
         expect(keyHandle.spec()).resolves.toEqual(spec);
     });
 
+    test("native state is not reachable through properties", () => {
+        // @ts-expect-error Accesses the bare provider, which is private.
+        const bareProvider: object = provider.provider;
+        expect(Reflect.ownKeys(bareProvider)).toHaveLength(0);
+    });
+
     test("get random", async () => {
         const randomBytes = await provider.getRandom(256);
         expect(randomBytes).toBeInstanceOf(Uint8Array);
         expect(randomBytes.length).toEqual(256);
     });
 
+    test("get random with aborted signal", async () => {
+        const controller = new AbortController();
+        controller.abort();
+
+        await expect(
+            provider.getRandom(256, { signal: controller.signal }),
+        ).rejects.toThrow();
+    });
+
+    test("abort derive key from password", async () => {
+        const spec: KeySpec = {
+            cipher: "AesGcm256",
+            signing_hash: "Sha2_256",
+            ephemeral: true,
+            non_exportable: true,
+        };
+
+        const kdf: KDF = {
+            Argon2d: {
+                memory: 65536,
+                iterations: 4,
+                parallelism: 1,
+            },
+        };
+
+        const controller = new AbortController();
+        const reason = new Error("aborted by test");
+        const keyHandle = provider.deriveKeyFromPassword(
+            "password1234",
+            new Uint8Array(16),
+            spec,
+            kdf,
+            { signal: controller.signal },
+        );
+        controller.abort(reason);
+
+        await expect(keyHandle).rejects.toBe(reason);
+    });
+
     test("hash data", async () => {
         const data = Uint8Array.from([1, 2, 3, 4, 5, 6, 7, 8]);
         const hash = await provider.hash(data, "Sha2_256");
@@ -350,4 +517,433 @@ describe("test provider methods", () => {
             expect(isKeyHandle(key) || isKeyPairHandle(key)).toBe(true);
         }
     });
+
+    test("store metadata with keys", async () => {
+        const expiresAt = new Date(Date.now() + 60_000);
+        const before = Date.now();
+        const key = await provider.createKey(
+            { cipher: "AesGcm256", signing_hash: "Sha2_256" },
+            { metadata: { label: "session", tags: ["a", "b"], expiresAt } },
+        );
+        const keyPair = await provider.createKeyPair({ asym_spec: "P256" });
+
+        const metadata = await key.metadata();
+        expect(metadata).toMatchObject({
+            label: "session",
+            tags: ["a", "b"],
+            expiresAt,
+        });
+        expect(metadata!.createdAt.getTime()).toBeGreaterThanOrEqual(before);
+        expect(await keyPair.metadata()).toBeNull();
+
+        const keys = await provider.getAllKeys({ metadata: true });
+        const id = await key.id();
+        expect(keys.find(([keyId]) => keyId === id)?.[2]).toEqual(metadata);
+        const keyPairId = await keyPair.id();
+        expect(keys.find(([keyId]) => keyId === keyPairId)?.[2]).toBeNull();
+
+        const loaded = await provider.loadKey(id);
+        expect(await loaded.metadata()).toEqual(metadata);
+
+        await key.delete();
+        const keysAfterDelete = await provider.getAllKeys({ metadata: true });
+        expect(keysAfterDelete.find(([keyId]) => keyId === id)).toBeUndefined();
+    });
+
+    test("list keys page by page", async () => {
+        const tag = `listing-${Date.now()}`;
+        const ids = new Set<string>();
+        for (let i = 0; i < 5; i++) {
+            const key = await provider.createKey(
+                { cipher: "AesGcm256", signing_hash: "Sha2_256" },
+                { metadata: { tags: [tag] } },
+            );
+            ids.add(await key.id());
+        }
+        await provider.createKeyPair(
+            { asym_spec: "P256" },
+            { metadata: { tags: [tag] } },
+        );
+
+        const filter = { kind: "Key", tag } as const;
+        expect(await provider.countKeys(filter)).toBe(5);
+
+        const listed: string[] = [];
+        let cursor: string | undefined;
+        do {
+            const page = await provider.listKeys({ filter, cursor, limit: 2 });
+            expect(page.keys.length).toBeLessThanOrEqual(2);
+            for (const [id, spec] of page.keys) {
+                expect("KeySpec" in spec).toBe(true);
+                listed.push(id);
+            }
+            cursor = page.cursor ?? undefined;
+        } while (cursor);
+
+        expect(new Set(listed)).toEqual(ids);
+        expect(listed).toEqual([...listed].sort());
+        expect(await provider.countKeys({ kind: "KeyPair", tag })).toBe(1);
+    });
+
+    test("reject invalid list options", async () => {
+        await expect(provider.listKeys({ limit: 0 })).rejects.toMatchObject({
+            code: INVALID_ARGUMENT_ERROR_CODE,
+            path: "options.limit",
+        });
+        await expect(
+            provider.countKeys({ kind: "Keys" as "Key" }),
+        ).rejects.toMatchObject({
+            code: INVALID_ARGUMENT_ERROR_CODE,
+            path: "filter.kind",
+            accepted: ["Key", "KeyPair"],
+        });
+    });
+
+    test("rotate keys and rewrap ciphertexts", async () => {
+        const first = await provider.createKey(
+            { cipher: "AesGcm256", signing_hash: "Sha2_256" },
+            { metadata: { label: "documents" } },
+        );
+        const familyId = await first.id();
+        const data = Buffer.from("Hello World!");
+
+        const second = await provider.rotateKey(familyId);
+        const sealed = await provider.encryptForFamily(familyId, data);
+        expect(await second.metadata()).toMatchObject({
+            label: "documents",
+            family: { id: familyId, version: 2 },
+        });
+        expect(await first.metadata()).toMatchObject({
+            usages: ["decrypt"],
+            family: { id: familyId, version: 1 },
+        });
+        await expect(first.encrypt(data)).rejects.toMatchObject({
+            code: KEY_USAGE_ERROR_CODE,
+        });
+        await expect(provider.rotateKey(familyId)).rejects.toThrow(
+            "superseded by version 2",
+        );
+
+        const third = await provider.rotateKey(await second.id());
+        const decrypted = await provider.decryptForFamily(familyId, sealed);
+        expect(Buffer.from(decrypted)).toEqual(data);
+
+        const rewrapped = await provider.rewrap(sealed);
+        expect(Buffer.from(rewrapped).includes(await third.id())).toBe(true);
+        expect(
+            Buffer.from(await provider.decryptForFamily(familyId, rewrapped)),
+        ).toEqual(data);
+        await expect(
+            provider.decryptForFamily(await third.id(), rewrapped),
+        ).rejects.toThrow("does not belong");
+    });
+
+    test("export and import encrypted backups", async () => {
+        const first = await provider.createKey(
+            { cipher: "AesGcm256", signing_hash: "Sha2_256" },
+            { metadata: { label: "backup" } },
+        );
+        const second = await provider.rotateKey(await first.id());
+        const data = Buffer.from("Hello World!");
+        const [ciphertext, iv] = await second.encrypt(data);
+        const kdf: KDF = {
+            Argon2id: { memory: 8192, iterations: 1, parallelism: 1 },
+        };
+
+        const { archive, skipped } = await provider.exportBackup("secret", {
+            kdf,
+        });
+        expect(skipped).not.toContain(await second.id());
+        await expect(
+            provider.importBackup(archive, "wrong"),
+        ).rejects.toMatchObject({ code: BACKUP_AUTHENTICATION_ERROR_CODE });
+        await expect(
+            provider.importBackup(archive, "secret"),
+        ).rejects.toMatchObject({ code: BACKUP_CONFLICT_ERROR_CODE });
+        const tampered = Uint8Array.from(archive);
+        tampered[8] ^= 1;
+        await expect(
+            provider.importBackup(tampered, "secret"),
+        ).rejects.toMatchObject({ code: BACKUP_AUTHENTICATION_ERROR_CODE });
+        const expensive = Uint8Array.from(archive);
+        expensive[7] = 0xff;
+        await expect(
+            provider.importBackup(expensive, "secret"),
+        ).rejects.toThrow("kdf parameters exceed the limits");
+
+        const restoreDbDirPath = await setupDbDir();
+        try {
+            const target = await createProviderFromName(
+                SOFTWARE_PROVIDER_NAME,
+                {
+                    additional_config: [
+                        { FileStoreConfig: { db_dir: restoreDbDirPath } },
+                    ],
+                },
+            );
+            if (!target) throw new Error("Failed creating provider.");
+
+            const { restored } = await target.importBackup(archive, "secret");
+            const ids = new Map(
+                restored.map(({ originalId, id }) => [originalId, id]),
+            );
+            const restoredFirstId = ids.get(await first.id())!;
+            const restoredSecond = await target.loadKey(
+                ids.get(await second.id())!,
+            );
+            expect(
+                Buffer.from(await restoredSecond.decryptData(ciphertext, iv)),
+            ).toEqual(data);
+            expect(await restoredSecond.metadata()).toMatchObject({
+                label: "backup",
+                family: { id: restoredFirstId, version: 2 },
+            });
+
+            await expect(
+                target.importBackup(archive, "secret"),
+            ).rejects.toMatchObject({ code: BACKUP_CONFLICT_ERROR_CODE });
+            const again = await target.importBackup(archive, "secret", {
+                onConflict: "skip",
+            });
+            expect(again.restored).toEqual([]);
+            expect(again.skipped).toContainEqual({
+                originalId: await second.id(),
+                id: await restoredSecond.id(),
+            });
+        } finally {
+            await gcAllAndWait();
+            teardownDbDir(restoreDbDirPath);
+        }
+    });
+
+    test("migrate keys between providers", async () => {
+        const key = await provider.createKey(
+            { cipher: "AesGcm256", signing_hash: "Sha2_256" },
+            { metadata: { label: "migration", tags: ["migration"] } },
+        );
+        const id = await key.id();
+        const data = Buffer.from("Hello World!");
+        const [ciphertext, iv] = await key.encrypt(data);
+
+        const targetDbDirPath = await setupDbDir();
+        try {
+            const target = await createProviderFromName(
+                SOFTWARE_PROVIDER_NAME,
+                {
+                    additional_config: [
+                        { FileStoreConfig: { db_dir: targetDbDirPath } },
+                    ],
+                },
+            );
+            if (!target) throw new Error("Failed creating provider.");
+            const filter = { tag: "migration" };
+
+            const report = await migrateKeys(provider, target, { filter });
+            const [migration] = report.keys;
+            expect(report.ids).toEqual({ [id]: migration.newId });
+            expect(migration).toMatchObject({
+                id,
+                status: "migrated",
+                sourceDeleted: false,
+                error: null,
+            });
+            const copy = await target.loadKey(migration.newId!);
+            expect(Buffer.from(await copy.decryptData(ciphertext, iv))).toEqual(
+                data,
+            );
+            expect(await copy.metadata()).toMatchObject({
+                label: "migration",
+                migratedFrom: id,
+            });
+
+            const resumed = await migrateKeys(provider, target, {
+                filter,
+                deleteSource: true,
+            });
+            expect(resumed).toEqual({
+                keys: [
+                    {
+                        id,
+                        status: "alreadyMigrated",
+                        newId: migration.newId,
+                        sourceDeleted: true,
+                        error: null,
+                    },
+                ],
+                ids: { [id]: migration.newId },
+            });
+            await expect(provider.loadKey(id)).rejects.toThrow();
+            expect(await migrateKeys(provider, target, { filter })).toEqual({
+                keys: [],
+                ids: {},
+            });
+        } finally {
+            await gcAllAndWait();
+            teardownDbDir(targetDbDirPath);
+        }
+    });
+
+    test("verify and repair the metadata store", async () => {
+        const repairDbDirPath = await setupDbDir();
+        try {
+            writeFileSync(
+                join(repairDbDirPath, "crypto-layer-node-key-metadata.json"),
+                JSON.stringify({
+                    orphan: { createdAt: 1 },
+                    corrupt: { createdAt: "yesterday" },
+                }),
+            );
+            const repairProvider = await createProviderFromName(
+                SOFTWARE_PROVIDER_NAME,
+                {
+                    additional_config: [
+                        { FileStoreConfig: { db_dir: repairDbDirPath } },
+                    ],
+                },
+            );
+            if (!repairProvider) throw new Error("Failed creating provider.");
+            await repairProvider.createKey({
+                cipher: "AesGcm256",
+                signing_hash: "Sha2_256",
+            });
+            const ephemeral = await repairProvider.createKey(
+                {
+                    cipher: "AesGcm256",
+                    signing_hash: "Sha2_256",
+                    ephemeral: true,
+                },
+                { metadata: { label: "ephemeral" } },
+            );
+
+            const report = await repairProvider.verifyStore();
+            expect(report).toMatchObject({
+                checked: 1,
+                unreadable: [],
+                orphaned: ["orphan"],
+                corrupted: [{ id: "corrupt" }],
+                withoutMetadata: [],
+                metadataFileError: null,
+                repaired: [],
+            });
+
+            const repaired = await repairProvider.repairStore({
+                quarantine: true,
+            });
+            expect(repaired.repaired).toEqual(["orphan", "corrupt"]);
+            expect(await ephemeral.metadata()).toMatchObject({
+                label: "ephemeral",
+            });
+            expect(
+                existsSync(
+                    join(
+                        repairDbDirPath,
+                        "crypto-layer-node-key-metadata.quarantine.json",
+                    ),
+                ),
+            ).toBe(true);
+            expect(await repairProvider.verifyStore()).toMatchObject({
+                orphaned: [],
+                corrupted: [],
+            });
+        } finally {
+            await gcAllAndWait();
+            teardownDbDir(repairDbDirPath);
+        }
+    });
+
+    test("report keys without metadata and refuse unreadable metadata", async () => {
+        const policyDbDirPath = await setupDbDir();
+        const metadataPath = join(
+            policyDbDirPath,
+            "crypto-layer-node-key-metadata.json",
+        );
+        const implConfig: ProviderImplConfig = {
+            additional_config: [
+                { FileStoreConfig: { db_dir: policyDbDirPath } },
+            ],
+        };
+        try {
+            let id: string;
+            {
+                const policyProvider = await createProviderFromName(
+                    SOFTWARE_PROVIDER_NAME,
+                    implConfig,
+                );
+                if (!policyProvider)
+                    throw new Error("Failed creating provider.");
+                const keyPair = await policyProvider.createKeyPair(
+                    {
+                        asym_spec: "P256",
+                        signing_hash: "Sha2_256",
+                        ephemeral: false,
+                    },
+                    { usages: ["verify"] },
+                );
+                id = await keyPair.id();
+                policyProvider.release();
+            }
+            await gcAllAndWait();
+
+            const entries = JSON.parse(readFileSync(metadataPath, "utf8"));
+            delete entries[id];
+            writeFileSync(metadataPath, JSON.stringify(entries));
+
+            {
+                const policyProvider = await createProviderFromName(
+                    SOFTWARE_PROVIDER_NAME,
+                    implConfig,
+                );
+                if (!policyProvider)
+                    throw new Error("Failed creating provider.");
+                expect(await policyProvider.verifyStore()).toMatchObject({
+                    withoutMetadata: [id],
+                });
+                const keyPair = await policyProvider.loadKeyPair(id);
+                await keyPair.signData(Uint8Array.from([1, 2, 3]));
+                policyProvider.release();
+            }
+            await gcAllAndWait();
+
+            entries[id] = { createdAt: "yesterday" };
+            writeFileSync(metadataPath, JSON.stringify(entries));
+            const corruptProvider = await createProviderFromName(
+                SOFTWARE_PROVIDER_NAME,
+                implConfig,
+            );
+            if (!corruptProvider) throw new Error("Failed creating provider.");
+            const corruptKeyPair = await corruptProvider.loadKeyPair(id);
+            await expect(
+                corruptKeyPair.signData(Uint8Array.from([1, 2, 3])),
+            ).rejects.toMatchObject({
+                name: "KeyMetadataUnavailableError",
+                code: KEY_METADATA_UNAVAILABLE_ERROR_CODE,
+                keyId: id,
+                operation: "sign",
+            });
+        } finally {
+            await gcAllAndWait();
+            teardownDbDir(policyDbDirPath);
+        }
+    });
+
+    test("reject invalid metadata", async () => {
+        await expect(
+            provider.createKey(
+                { cipher: "AesGcm256", signing_hash: "Sha2_256" },
+                { metadata: { tags: ["a", 1 as unknown as string] } },
+            ),
+        ).rejects.toMatchObject({
+            code: INVALID_ARGUMENT_ERROR_CODE,
+            path: "options.metadata.tags[1]",
+        });
+        await expect(
+            provider.createKey(
+                { cipher: "AesGcm256", signing_hash: "Sha2_256" },
+                { usages: ["encrypt", "wrap" as "encrypt"] },
+            ),
+        ).rejects.toMatchObject({
+            code: INVALID_ARGUMENT_ERROR_CODE,
+            path: "options.usages[1]",
+        });
+    });
 }); // end describe